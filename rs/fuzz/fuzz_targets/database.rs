@@ -0,0 +1,24 @@
+//! Database file content, which may be truncated, edited by hand or copied from another machine.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use slam::database::{Database, LayoutById};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = slam::database::parse_layouts(data);
+
+    // load_or_empty() also reads the backup of an invalid file, so use a directory per process
+    let dir = std::env::temp_dir().join(format!("slam-fuzz-database-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("database.json");
+    std::fs::write(&path, data).unwrap();
+    if let Ok(database) = Database::load_or_empty(path, None) {
+        let _ = database.known_relations();
+        for layout in database.layouts().chain(database.saved_layouts()) {
+            let outputs = LayoutById(layout.clone());
+            let _ = database.closest_layout(&outputs);
+            let _ = database.preferred_layout(&outputs);
+            let _ = layout.adjacent_relations();
+            let _ = serde_json::to_vec(layout).unwrap();
+        }
+    }
+});
@@ -0,0 +1,12 @@
+//! Raw EDID bytes, as read from X properties, DRM blobs or sysfs of any connected monitor.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use slam::layout::{edid_physical_size, Edid, EdidInfo};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Edid::try_from(data);
+    if let Ok(info) = EdidInfo::parse(data) {
+        let _ = info.to_string();
+    }
+    let _ = edid_physical_size(data);
+});
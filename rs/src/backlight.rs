@@ -0,0 +1,152 @@
+use crate::layout::OutputId;
+use crate::Backend;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Directory of kernel backlight devices.
+const SYSFS_BACKLIGHT: &str = "/sys/class/backlight";
+
+/// Internal panels, whose backlight is the one of sysfs devices.
+pub(crate) fn is_internal_panel(name: &str) -> bool {
+    ["eDP", "LVDS", "DSI"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Preferred sysfs backlight device : firmware interfaces first, then platform drivers, then raw GPU registers.
+/// This is the order used by systemd and desktop environments.
+fn sysfs_device(dir: &Path) -> Option<PathBuf> {
+    let rank = |device: &Path| match std::fs::read_to_string(device.join("type")) {
+        Ok(kind) => match kind.trim() {
+            "firmware" => 0,
+            "platform" => 1,
+            _ => 2,
+        },
+        Err(_) => 3,
+    };
+    let devices = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| Some(e.ok()?.path()));
+    devices.min_by_key(|device| rank(device))
+}
+
+fn read_value(path: &Path) -> Result<u32, anyhow::Error> {
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?;
+    text.trim()
+        .parse()
+        .with_context(|| format!("invalid value in {}", path.display()))
+}
+
+/// Brightness of a sysfs backlight device in percent.
+pub fn sysfs_brightness(device: &Path) -> Result<u8, anyhow::Error> {
+    let max = read_value(&device.join("max_brightness"))?;
+    let value = read_value(&device.join("brightness"))?;
+    Ok(to_percent(value, max))
+}
+
+/// Set the brightness of a sysfs backlight device.
+/// Writing requires permissions usually given by udev rules to the `video` group.
+pub fn set_sysfs_brightness(device: &Path, percent: u8) -> Result<(), anyhow::Error> {
+    let max = read_value(&device.join("max_brightness"))?;
+    let path = device.join("brightness");
+    std::fs::write(&path, from_percent(percent, max).to_string())
+        .with_context(|| format!("cannot write {}", path.display()))
+}
+
+/// Percent of `max`, rounded.
+pub fn to_percent(value: u32, max: u32) -> u8 {
+    match max {
+        0 => 0,
+        max => ((u64::from(value.min(max)) * 100 + u64::from(max) / 2) / u64::from(max)) as u8,
+    }
+}
+
+/// Value for `percent` of `max`, rounded. A non zero percentage keeps the backlight on.
+pub fn from_percent(percent: u8, max: u32) -> u32 {
+    let value = (u64::from(percent.min(100)) * u64::from(max) + 50) / 100;
+    match (value, percent) {
+        (0, 1..) => 1.min(max),
+        (value, _) => value as u32,
+    }
+}
+
+/// Brightness of connected outputs in percent, from the backend or the sysfs device for internal panels.
+pub fn output_brightness(backend: &dyn Backend) -> HashMap<OutputId, u8> {
+    let mut brightness = backend.brightness();
+    let device = sysfs_device(Path::new(SYSFS_BACKLIGHT));
+    for (id, name) in backend.output_names() {
+        if let (Some(device), false) = (&device, brightness.contains_key(&id)) {
+            if is_internal_panel(&name) {
+                match sysfs_brightness(device) {
+                    Ok(percent) => {
+                        brightness.insert(id, percent);
+                    }
+                    Err(e) => log::debug!("{}: {:#}", name, e),
+                }
+            }
+        }
+    }
+    brightness
+}
+
+/// Set the brightness of an output with the backend, or the sysfs device for internal panels.
+/// Backend errors are hard errors, others are returned as errors with context.
+pub fn set_output_brightness(
+    backend: &mut dyn Backend,
+    output: &OutputId,
+    percent: u8,
+) -> Result<(), anyhow::Error> {
+    if backend.set_brightness(output, percent)? {
+        return Ok(());
+    }
+    let name = backend.output_names().remove(output);
+    let name = name.unwrap_or_else(|| output.to_string());
+    let device = match sysfs_device(Path::new(SYSFS_BACKLIGHT)) {
+        Some(device) if is_internal_panel(&name) => device,
+        _ => {
+            return Err(anyhow::Error::msg(format!(
+                "{}: no backlight control",
+                name
+            )))
+        }
+    };
+    set_sysfs_brightness(&device, percent)
+        .with_context(|| format!("{}: cannot set brightness", name))?;
+    log::info!("{}: brightness set to {}%", name, percent);
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_sysfs_backlight() {
+    assert_eq!(to_percent(0, 0), 0);
+    assert_eq!(to_percent(937, 937), 100);
+    assert_eq!(to_percent(469, 937), 50);
+    assert_eq!(from_percent(50, 937), 469);
+    assert_eq!(from_percent(1, 7), 1);
+    assert_eq!(from_percent(0, 7), 0);
+    assert_eq!(from_percent(200, 7), 7);
+
+    let dir = std::env::temp_dir().join(format!("slam-test-backlight-{}", std::process::id()));
+    let device = |name: &str, kind: &str| {
+        let device = dir.join(name);
+        std::fs::create_dir_all(&device).unwrap();
+        std::fs::write(device.join("type"), format!("{}\n", kind)).unwrap();
+        std::fs::write(device.join("max_brightness"), "255\n").unwrap();
+        std::fs::write(device.join("brightness"), "255\n").unwrap();
+        device
+    };
+    device("intel_backlight", "raw");
+    let firmware = device("acpi_video0", "firmware");
+    assert_eq!(sysfs_device(&dir), Some(firmware.clone()));
+    set_sysfs_brightness(&firmware, 40).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(firmware.join("brightness")).unwrap(),
+        "102"
+    );
+    assert_eq!(sysfs_brightness(&firmware).unwrap(), 40);
+    assert!(is_internal_panel("eDP-1") && !is_internal_panel("DP-1"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,159 @@
+use crate::database::DatabaseFormat;
+use crate::encryption::EncryptionConfig;
+use crate::hooks::HooksConfig;
+use crate::idle::IdleConfig;
+use crate::layout::auto::{OutputDefaults, RelationPreference};
+use crate::layout::bezel::BezelConfig;
+use crate::layout::modeline::CustomMode;
+use crate::layout::primary_policy::PrimaryRule;
+use crate::logging::{LogFormat, LogTarget};
+use crate::profile::{self, Profile};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// User configuration of the daemon, read from a toml file, or a json file for configurations
+/// written before toml support.
+/// All fields are optional, and missing ones use defaults.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub hooks: HooksConfig,
+    /// Send desktop notifications on layout events.
+    pub notifications: bool,
+    /// Log a drawing of auto-generated layouts, also sent as notification if enabled.
+    pub autolayout_preview: bool,
+    /// Set `Xft.dpi` from output densities when applying layouts without font DPI.
+    pub auto_font_dpi: bool,
+    /// Power down external outputs when the user is idle.
+    pub idle_power_down: Option<IdleConfig>,
+    /// Settings of outputs enabled by automatic layouts, by output name or id.
+    pub output_defaults: HashMap<String, OutputDefaults>,
+    /// Relations between outputs for automatic layouts, preferred over the relations of stored layouts.
+    pub relations: Vec<RelationPreference>,
+    /// Gaps between adjacent outputs compensating for bezels.
+    pub bezels: Vec<BezelConfig>,
+    /// Snap outputs of layouts made by the user that are at most this many pixels from being adjacent,
+    /// instead of ignoring these layouts as unsupported.
+    pub snap_tolerance: Option<u32>,
+    /// Pixels of gap or overlap accepted between adjacent outputs, so that layouts made by the user
+    /// that are slightly off are still stored as they are.
+    pub layout_tolerance: Option<u32>,
+    /// Custom modes for outputs with incomplete mode lists.
+    pub modes: Vec<CustomMode>,
+    /// Rules selecting the primary output of layouts without one, in order of precedence.
+    pub primary: Vec<PrimaryRule>,
+    /// Database file. Overridden by `--database` and `SLAM_DATABASE`.
+    pub database: Option<PathBuf>,
+    /// Encrypt the database file.
+    pub database_encryption: Option<EncryptionConfig>,
+    /// Write the database file as compact json with checksum, or indented json to edit by hand.
+    pub database_format: DatabaseFormat,
+    /// Maximum number of learned layouts in the database, the least recently used being evicted. Unlimited by default.
+    pub database_max_layouts: Option<usize>,
+    /// Overridden by `--log-level` and `SLAM_LOG_LEVEL`.
+    pub log_level: Option<log::Level>,
+    /// `text`, `json` or `journald`. Overridden by `--log-format` and `SLAM_LOG_FORMAT`.
+    pub log_format: Option<LogFormat>,
+    /// `stderr`, `syslog` or `file`. Overridden by `--log-target` and `SLAM_LOG_TARGET`.
+    pub log_target: Option<LogTarget>,
+    /// Seconds to wait for other daemons to react. Overridden by `--reaction-delay` and `SLAM_REACTION_DELAY`.
+    pub reaction_delay: Option<u64>,
+    /// Milliseconds to wait after events of each kind, instead of `reaction_delay`.
+    pub reaction_delays: ReactionDelaysConfig,
+    /// Profiles from the config file, followed by those of the `profiles.d` directory.
+    pub profiles: Vec<Profile>,
+}
+
+/// Reaction delays in milliseconds by kind of backend event, for instance longer for hotplugs.
+/// Missing kinds use `reaction_delay`.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReactionDelaysConfig {
+    pub hotplug: Option<u64>,
+    pub crtc: Option<u64>,
+    pub property: Option<u64>,
+}
+
+impl Config {
+    /// Load config from file, or use defaults if the file does not exist.
+    /// Files with a `.json` extension are json, others toml.
+    /// Unlike the database, an invalid or unreadable file is an error, as it was written by the user.
+    /// Profiles are also loaded from the `profiles.d` directory next to the file.
+    pub fn load_or_default(path: &Path) -> Result<Config, anyhow::Error> {
+        let mut config: Config = match std::fs::read(path) {
+            Ok(file_content) => match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => serde_json::from_slice(&file_content).map_err(anyhow::Error::from),
+                _ => toml::from_slice(&file_content).map_err(anyhow::Error::from),
+            }
+            .with_context(|| format!("error parsing config {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!("no config file at {}, using defaults", path.display());
+                Config::default()
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("cannot read config {}", path.display()))
+            }
+        };
+        if let Some(dir) = path.parent() {
+            let profiles = profile::load_profile_dir(&dir.join("profiles.d"))?;
+            config.profiles.extend(profiles)
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_load_config() {
+    use crate::geometry::Direction;
+    let dir = std::env::temp_dir().join(format!("slam-test-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let toml_path = dir.join("config.toml");
+    std::fs::write(
+        &toml_path,
+        r#"
+notifications = true
+log_level = "info"
+primary = ["external", { name = "eDP.*" }]
+bezels = [{ outputs = ["DP-1", "DP-2"], millimeters = 12 }]
+relations = [{ outputs = ["DP-1", "eDP-1"], direction = "right_of" }]
+
+[hooks]
+postswitch = ["restart-bar.sh"]
+
+[output_defaults.DP-3]
+disabled = true
+
+[reaction_delays]
+hotplug = 2000
+"#,
+    )
+    .unwrap();
+    let json_path = dir.join("config.json");
+    std::fs::write(
+        &json_path,
+        r#"{"notifications": true, "snap_tolerance": 4}"#,
+    )
+    .unwrap();
+    let invalid_path = dir.join("invalid.toml");
+    std::fs::write(&invalid_path, "notifications = 1").unwrap();
+    let from_toml = Config::load_or_default(&toml_path);
+    let from_json = Config::load_or_default(&json_path);
+    let invalid = Config::load_or_default(&invalid_path);
+    let missing = Config::load_or_default(&dir.join("missing.toml"));
+    let _ = std::fs::remove_dir_all(&dir);
+    let config = from_toml.unwrap();
+    assert!(config.notifications);
+    assert_eq!(config.log_level, Some(log::Level::Info));
+    assert_eq!(config.primary.len(), 2);
+    assert_eq!(config.bezels.len(), 1);
+    assert_eq!(config.relations[0].direction, Direction::RightOf);
+    assert_eq!(config.hooks.postswitch, ["restart-bar.sh"]);
+    assert!(config.output_defaults["DP-3"].disabled);
+    assert_eq!(config.reaction_delays.hotplug, Some(2000));
+    let config = from_json.unwrap();
+    assert_eq!(config.snap_tolerance, Some(4));
+    assert!(invalid.is_err());
+    assert!(!missing.unwrap().notifications);
+}
@@ -0,0 +1,290 @@
+use anyhow::Context;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Maximum time to exchange a request and its reply, so that a stuck client cannot block the daemon.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request sent to the daemon, as one text line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Print the current layout and daemon state.
+    Status,
+    /// Counters of the daemon since it started, as json, see [`crate::stats::DaemonStats`].
+    Stats,
+    /// Apply a saved layout by name, or a layout spec.
+    Apply(String),
+    /// Save the current layout under a name.
+    Save(String),
+    /// Read the database file again, after an edit or an import by another process.
+    Reload,
+    /// Stop storing and applying layouts until [`Request::Resume`].
+    Pause,
+    Resume,
+    /// Keep the connection open and receive daemon events as json lines, see [`publish`].
+    Watch,
+}
+
+impl std::str::FromStr for Request {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Request, String> {
+        let (command, argument) = match s.trim().split_once(' ') {
+            Some((command, argument)) => (command, Some(argument.trim().to_string())),
+            None => (s.trim(), None),
+        };
+        match (command, argument) {
+            ("status", None) => Ok(Request::Status),
+            ("stats", None) => Ok(Request::Stats),
+            ("apply", Some(layout)) => Ok(Request::Apply(layout)),
+            ("save", Some(name)) => Ok(Request::Save(name)),
+            ("reload", None) => Ok(Request::Reload),
+            ("pause", None) => Ok(Request::Pause),
+            ("resume", None) => Ok(Request::Resume),
+            ("watch", None) => Ok(Request::Watch),
+            _ => Err(format!("invalid request: {}", s.trim())),
+        }
+    }
+}
+
+impl std::fmt::Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Request::Status => write!(f, "status"),
+            Request::Stats => write!(f, "stats"),
+            Request::Apply(layout) => write!(f, "apply {}", layout),
+            Request::Save(name) => write!(f, "save {}", name),
+            Request::Reload => write!(f, "reload"),
+            Request::Pause => write!(f, "pause"),
+            Request::Resume => write!(f, "resume"),
+            Request::Watch => write!(f, "watch"),
+        }
+    }
+}
+
+/// Listening socket of the daemon. The socket file is removed on drop.
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listen on `path`. A leftover socket file from a dead daemon is replaced,
+    /// but it is an error if another daemon still answers on it.
+    pub fn bind(path: PathBuf) -> Result<ControlSocket, anyhow::Error> {
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(anyhow::Error::msg(format!(
+                    "control socket {} is used by another daemon",
+                    path.display()
+                )));
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("cannot remove stale socket {}", path.display()))?
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("cannot create directory {}", parent.display()))?
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("cannot listen on {}", path.display()))?;
+        listener
+            .set_nonblocking(true)
+            .context("cannot set control socket non blocking")?;
+        Ok(ControlSocket { listener, path })
+    }
+
+    /// Next pending client, without blocking. Accept failures are logged.
+    pub fn accept(&self) -> Option<Connection> {
+        match self.listener.accept() {
+            Ok((stream, _)) => Some(Connection { stream }),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => None,
+            Err(e) => {
+                log::error!("control socket: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Readable when clients are pending, for the daemon to wait on it.
+impl AsRawFd for ControlSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Client connection, answering exactly one request.
+pub struct Connection {
+    stream: UnixStream,
+}
+
+impl Connection {
+    pub fn read_request(&mut self) -> Result<Request, anyhow::Error> {
+        self.stream.set_nonblocking(false)?;
+        self.stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        self.stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new(&self.stream)
+            .read_line(&mut line)
+            .context("cannot read control request")?;
+        line.parse().map_err(anyhow::Error::msg)
+    }
+
+    /// Reply with `ok` and the text, or `error:` and the message. Failures are only logged.
+    pub fn reply(mut self, result: Result<String, anyhow::Error>) {
+        let reply = match result {
+            Ok(text) => format!("ok\n{}", text),
+            Err(e) => format!("error: {:#}\n", e),
+        };
+        if let Err(e) = self.stream.write_all(reply.as_bytes()) {
+            log::warn!("cannot send control reply: {}", e)
+        }
+    }
+
+    /// Reply with `ok` and keep the connection to send it later events with [`publish`].
+    pub fn watch(mut self) {
+        match self.stream.write_all(b"ok\n") {
+            Ok(()) => WATCHERS.lock().unwrap().push(self.stream),
+            Err(e) => log::warn!("cannot send control reply: {}", e),
+        }
+    }
+}
+
+/// Connections of [`Request::Watch`] clients, until they disconnect.
+static WATCHERS: Mutex<Vec<UnixStream>> = Mutex::new(Vec::new());
+
+/// Whether any client watches events, to avoid building events nobody reads.
+pub fn has_watchers() -> bool {
+    !WATCHERS.lock().unwrap().is_empty()
+}
+
+/// Send an event as one json line to watching clients.
+/// Clients which are gone or do not read within the timeout are dropped.
+pub fn publish(event: &serde_json::Value) {
+    let line = format!("{}\n", event);
+    WATCHERS.lock().unwrap().retain_mut(|stream| {
+        let sent = stream.write_all(line.as_bytes());
+        if let Err(e) = &sent {
+            log::info!("dropping event watcher: {}", e)
+        }
+        sent.is_ok()
+    })
+}
+
+/// Send `request` to the daemon listening on `path`, and return the text of its reply.
+pub fn send(path: &Path, request: &Request) -> Result<String, anyhow::Error> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("cannot connect to daemon on {}", path.display()))?;
+    stream.write_all(format!("{}\n", request).as_bytes())?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .context("cannot read daemon reply")?;
+    match reply.split_once('\n') {
+        Some(("ok", text)) => Ok(text.to_string()),
+        Some((error, _)) if error.starts_with("error: ") => Err(anyhow::Error::msg(
+            error.trim_start_matches("error: ").to_string(),
+        )),
+        _ => Err(anyhow::Error::msg(format!(
+            "invalid daemon reply: {}",
+            reply
+        ))),
+    }
+}
+
+/// Send a [`Request::Watch`] to the daemon listening on `path`, and return the event lines it sends.
+pub fn watch(
+    path: &Path,
+) -> Result<impl Iterator<Item = Result<String, anyhow::Error>>, anyhow::Error> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("cannot connect to daemon on {}", path.display()))?;
+    stream.write_all(format!("{}\n", Request::Watch).as_bytes())?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut reader = BufReader::new(stream);
+    let mut reply = String::new();
+    reader
+        .read_line(&mut reply)
+        .context("cannot read daemon reply")?;
+    match reply.trim_end() {
+        "ok" => Ok(reader
+            .lines()
+            .map(|line| line.context("cannot read daemon event"))),
+        error if error.starts_with("error: ") => Err(anyhow::Error::msg(
+            error.trim_start_matches("error: ").to_string(),
+        )),
+        _ => Err(anyhow::Error::msg(format!(
+            "invalid daemon reply: {}",
+            reply
+        ))),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_control_socket() {
+    for request in [
+        Request::Status,
+        Request::Stats,
+        Request::Apply("eDP-1:preferred@0,0 DP-1:off".into()),
+        Request::Save("desk".into()),
+        Request::Reload,
+        Request::Pause,
+        Request::Resume,
+        Request::Watch,
+    ] {
+        assert_eq!(request.to_string().parse(), Ok(request));
+    }
+    assert!("apply".parse::<Request>().is_err());
+    assert!("status now".parse::<Request>().is_err());
+
+    let path = std::env::temp_dir().join(format!("slam-test-control-{}.sock", std::process::id()));
+    let socket = ControlSocket::bind(path.clone()).unwrap();
+    let client = std::thread::spawn({
+        let path = path.clone();
+        move || send(&path, &Request::Save("desk".into()))
+    });
+    let mut connection = loop {
+        match socket.accept() {
+            Some(connection) => break connection,
+            None => std::thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    assert_eq!(
+        connection.read_request().unwrap(),
+        Request::Save("desk".into())
+    );
+    connection.reply(Ok("saved\n".into()));
+    assert_eq!(client.join().unwrap().unwrap(), "saved\n");
+
+    let watcher = std::thread::spawn({
+        let path = path.clone();
+        move || watch(&path).unwrap().next().unwrap().unwrap()
+    });
+    let mut connection = loop {
+        match socket.accept() {
+            Some(connection) => break connection,
+            None => std::thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    assert_eq!(connection.read_request().unwrap(), Request::Watch);
+    connection.watch();
+    assert!(has_watchers());
+    publish(&serde_json::json!({"event": "stored"}));
+    assert_eq!(watcher.join().unwrap(), r#"{"event":"stored"}"#);
+    publish(&serde_json::json!({"event": "applied"}));
+    assert!(!has_watchers());
+    assert!(ControlSocket::bind(path.clone()).is_err());
+    drop(socket);
+    assert!(!path.exists());
+}
@@ -0,0 +1,119 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Log lines kept for crash reports.
+const RECENT_LOG_LINES: usize = 200;
+
+/// Recent log lines, and state updated by the daemon.
+struct CrashState {
+    log_lines: VecDeque<String>,
+    sections: BTreeMap<&'static str, String>,
+    database: Option<PathBuf>,
+}
+
+static STATE: Mutex<CrashState> = Mutex::new(CrashState {
+    log_lines: VecDeque::new(),
+    sections: BTreeMap::new(),
+    database: None,
+});
+
+fn with_state<T>(f: impl FnOnce(&mut CrashState) -> T) -> T {
+    // A panic while holding the lock must not prevent writing the report
+    let mut state = STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&mut state)
+}
+
+/// Keep a log line for crash reports, dropping the oldest ones.
+pub(crate) fn keep_log_line(line: String) {
+    with_state(|state| {
+        if state.log_lines.len() == RECENT_LOG_LINES {
+            state.log_lines.pop_front();
+        }
+        state.log_lines.push_back(line)
+    })
+}
+
+/// Set a named section of crash reports, replacing its previous content.
+pub fn set_section(name: &'static str, content: String) {
+    with_state(|state| state.sections.insert(name, content));
+}
+
+/// Database file described in crash reports, with its size and checksum.
+pub fn set_database_path(path: PathBuf) {
+    with_state(|state| state.database = Some(path))
+}
+
+/// Write a report with `reason`, state sections, database file summary and recent log lines to `dir`.
+/// Returns the path of the report.
+pub fn write_report(dir: &Path, reason: &str) -> Result<PathBuf, std::io::Error> {
+    let now = time::OffsetDateTime::now_utc();
+    let path = dir.join(format!(
+        "crash-{}.txt",
+        now.unix_timestamp_nanos() / 1_000_000
+    ));
+    std::fs::create_dir_all(dir)?;
+    let mut file = std::fs::File::create(&path)?;
+    writeln!(file, "slam {} crash report", env!("CARGO_PKG_VERSION"))?;
+    writeln!(file, "reason: {}", reason)?;
+    let (sections, database, log_lines) = with_state(|state| {
+        let CrashState {
+            log_lines,
+            sections,
+            database,
+        } = state;
+        (sections.clone(), database.clone(), log_lines.clone())
+    });
+    for (name, content) in &sections {
+        writeln!(file, "\n[{}]\n{}", name, content.trim_end())?
+    }
+    if let Some(database) = database {
+        writeln!(file, "\n[database]\n{}", database.display())?;
+        match std::fs::read(&database) {
+            Ok(bytes) => writeln!(
+                file,
+                "{} bytes, crc32 {:08x}",
+                bytes.len(),
+                crate::database::crc32(&bytes)
+            )?,
+            Err(e) => writeln!(file, "unreadable: {}", e)?,
+        }
+    }
+    writeln!(file, "\n[log]")?;
+    for line in log_lines {
+        writeln!(file, "{}", line)?
+    }
+    Ok(path)
+}
+
+/// Write a report to `dir` on panic, then run the default panic hook.
+pub fn install_panic_hook(dir: PathBuf) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match write_report(&dir, &info.to_string()) {
+            Ok(path) => eprintln!("crash report written to {}", path.display()),
+            Err(e) => eprintln!("cannot write crash report to {}: {}", dir.display(), e),
+        }
+        default_hook(info)
+    }))
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_report() {
+    let dir = std::env::temp_dir().join(format!("slam-test-crash-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("database.json"), b"[]").unwrap();
+    // Other tests run the daemon, which sets the backend section
+    set_section("test", "test section\n".into());
+    set_database_path(dir.join("database.json"));
+    let report = write_report(&dir, "test failure").map(std::fs::read_to_string);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let report = report.unwrap().unwrap();
+    assert!(report.contains("reason: test failure\n"));
+    assert!(report.contains("[test]\ntest section\n"));
+    assert!(report.contains("2 bytes, crc32 "));
+}
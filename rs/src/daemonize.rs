@@ -0,0 +1,140 @@
+use anyhow::Context;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Detach the current process from the terminal and continue in the background.
+///
+/// Uses the classic double fork : the calling process exits, and the remaining process is not a session leader.
+/// Standard input is redirected from `/dev/null`, standard and error outputs to `log_file` (appended), which receives the logs.
+/// The pid of the background process is written to `pid_file`, which is removed when the returned [`PidFile`] is dropped.
+/// Fails if the pid file belongs to a running process.
+///
+/// Must be called before any thread is started.
+pub fn daemonize(log_file: &Path, pid_file: &Path) -> Result<PidFile, anyhow::Error> {
+    // Open files before forking to report errors in the terminal
+    for path in [log_file, pid_file] {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("cannot create directory {}", parent.display()))?
+        }
+    }
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .with_context(|| format!("cannot open log file {}", log_file.display()))?;
+    let null = std::fs::File::open("/dev/null").context("cannot open /dev/null")?;
+    if let Some(pid) = running_pid(pid_file) {
+        return Err(anyhow::Error::msg(format!(
+            "already running with pid {} (from {})",
+            pid,
+            pid_file.display()
+        )));
+    }
+
+    fork_and_exit_parent()?;
+    if unsafe { libc::setsid() } < 0 {
+        return Err(std::io::Error::last_os_error()).context("setsid failed");
+    }
+    fork_and_exit_parent()?;
+
+    std::env::set_current_dir("/").context("cannot change directory to /")?;
+    for (file, target) in [
+        (null.as_raw_fd(), libc::STDIN_FILENO),
+        (log.as_raw_fd(), libc::STDOUT_FILENO),
+        (log.as_raw_fd(), libc::STDERR_FILENO),
+    ] {
+        if unsafe { libc::dup2(file, target) } < 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("cannot redirect standard streams");
+        }
+    }
+    PidFile::create(pid_file)
+}
+
+/// Pid file of the current process, removed on drop if it was not replaced by another process.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    fn create(path: &Path) -> Result<PidFile, anyhow::Error> {
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("cannot write pid file {}", path.display()))?;
+        Ok(PidFile {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if read_pid(&self.path) == Some(std::process::id()) {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                log::warn!("cannot remove pid file {}: {}", self.path.display(), e)
+            }
+        }
+    }
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Pid from `path` if it is a running process. Pid files left by a crash are stale.
+/// Pid 0 would check the process group, and a stale pid may have been reused by the current process.
+fn running_pid(path: &Path) -> Option<u32> {
+    let pid = read_pid(path)?;
+    if pid == 0 || pid == std::process::id() {
+        return None;
+    }
+    let raw = libc::pid_t::try_from(pid).ok()?;
+    // Signal 0 only checks the process exists : EPERM means it runs as another user
+    let exists = unsafe { libc::kill(raw, 0) } == 0
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    exists.then_some(pid)
+}
+
+fn fork_and_exit_parent() -> Result<(), anyhow::Error> {
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()).context("fork failed"),
+        0 => Ok(()),
+        // Parent : leave without running any destructor of shared resources
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_pid_file() {
+    let dir = std::env::temp_dir().join(format!("slam-test-pid-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("slam.pid");
+    let pid_file = PidFile::create(&path).unwrap();
+    let own = running_pid(&path);
+    drop(pid_file);
+    let removed = !path.exists();
+    std::fs::write(&path, "0\n").unwrap();
+    let zero = running_pid(&path);
+    // Pid of a child is running until it is killed and reaped
+    let mut child = std::process::Command::new("sleep")
+        .arg("60")
+        .spawn()
+        .unwrap();
+    let child_pid = child.id();
+    std::fs::write(&path, format!("{}\n", child_pid)).unwrap();
+    let running = running_pid(&path);
+    child.kill().unwrap();
+    child.wait().unwrap();
+    let stale = running_pid(&path);
+    let replaced = PidFile { path: path.clone() };
+    drop(replaced);
+    let kept = path.exists();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(own, None);
+    assert!(removed);
+    assert_eq!(running, Some(child_pid));
+    assert_eq!(zero, None);
+    assert_eq!(stale, None);
+    assert!(kept);
+}
@@ -1,8 +1,9 @@
-use crate::layout::Layout;
+use crate::encryption::{self, EncryptionConfig};
+use crate::layout::{Layout, LayoutUsage, OutputId, OutputRelation};
 use anyhow::Context;
-use std::collections::HashSet;
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 /// Provide [`Eq`]+[`std::hash::Hash`] on the sorted ids of layout.
 /// [`serde_json`] flattens *newtypes* so this layer has no impact on serialization format.
@@ -23,36 +24,257 @@ impl std::hash::Hash for LayoutById {
     }
 }
 
+/// Content format of the database file. Both formats are read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseFormat {
+    /// Single line json followed by a checksum line.
+    #[default]
+    Compact,
+    /// Indented json without checksum, to edit by hand.
+    Pretty,
+}
+
 /// Database of known layouts, stored in memory with a file backing using [`serde_json`].
+/// The file is optionally encrypted.
+///
+/// Layouts learned by the daemon are keyed by their outputs.
+/// Layouts saved by the user are keyed by their name, and stored in the same file list.
 pub struct Database {
     layouts: HashSet<LayoutById>,
+    saved: BTreeMap<String, Layout>,
     path: PathBuf,
+    encryption: Option<EncryptionConfig>,
+    format: DatabaseFormat,
+    /// Maximum number of learned layouts, the least recently used being evicted.
+    max_layouts: Option<usize>,
+    /// Modification time of the file when last read or written, to detect external edits.
+    modified: Option<SystemTime>,
 }
 
 impl Database {
     /// Load database from file, or use an empty one if it cannot be read.
-    /// Only generate an error if the database is invalid / corrupted.
-    pub fn load_or_empty(path: PathBuf) -> Result<Database, anyhow::Error> {
-        let layouts = match std::fs::read(&path) {
-            Ok(file_content) => serde_json::from_slice(&file_content)
-                .with_context(|| format!("error parsing database {}", path.display()))?,
+    /// Only generate an error if the database is invalid / corrupted and its backup is unusable.
+    ///
+    /// With `encryption`, a plaintext database is still accepted, and will be encrypted on the next write.
+    /// An encrypted database without `encryption` is an error.
+    ///
+    /// The database is read under a shared [`FileLock`], so that it is never read while being replaced.
+    pub fn load_or_empty(
+        path: PathBuf,
+        encryption: Option<EncryptionConfig>,
+    ) -> Result<Database, anyhow::Error> {
+        // A missing directory has no database to protect
+        let _lock = match FileLock::acquire(&path, false) {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                log::debug!("{:#} ; reading database without lock", e);
+                None
+            }
+        };
+        let modified = modification_time(&path);
+        let content = match std::fs::read(&path) {
+            Ok(file_content) => match parse_file(&path, file_content, encryption.as_ref()) {
+                Ok(layouts) => layouts,
+                Err(e) => {
+                    let backup = backup_path(&path);
+                    log::error!("{:#} ; trying backup {}", e, backup.display());
+                    let from_backup = std::fs::read(&backup)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|content| parse_file(&backup, content, encryption.as_ref()));
+                    match from_backup {
+                        Ok(layouts) => layouts,
+                        Err(backup_error) => {
+                            log::error!("unusable backup: {:#}", backup_error);
+                            return Err(e);
+                        }
+                    }
+                }
+            },
             Err(e) => {
                 log::warn!(
                     "cannot read database {}: {} ; using an empty database instead",
                     path.display(),
                     e
                 );
-                HashSet::new()
+                Vec::new()
             }
         };
-        Ok(Database { layouts, path })
+        let mut database = Database {
+            layouts: HashSet::new(),
+            saved: BTreeMap::new(),
+            path,
+            encryption,
+            format: DatabaseFormat::default(),
+            max_layouts: None,
+            modified,
+        };
+        for layout in content {
+            database.insert(layout);
+        }
+        Ok(database)
+    }
+
+    /// Read the database file again, replacing layouts in memory. On error, the database is left unchanged.
+    pub fn reload(&mut self) -> Result<(), anyhow::Error> {
+        let (format, max_layouts) = (self.format, self.max_layouts);
+        *self = Database::load_or_empty(self.path.clone(), self.encryption.clone())?;
+        self.format = format;
+        self.max_layouts = max_layouts;
+        Ok(())
+    }
+
+    /// Set the format of the next writes. The file is not rewritten.
+    pub fn set_format(&mut self, format: DatabaseFormat) {
+        self.format = format
+    }
+
+    /// Set the maximum number of learned layouts, applied on the next store or import.
+    /// Layouts saved by name are never evicted.
+    pub fn set_max_layouts(&mut self, max_layouts: Option<usize>) {
+        self.max_layouts = max_layouts
+    }
+
+    /// Evict the least recently used learned layouts above the maximum, except `keep`.
+    fn prune(&mut self, keep: Option<&LayoutById>) {
+        let max = match self.max_layouts {
+            Some(max) if self.layouts.len() > max => max,
+            _ => return,
+        };
+        let last_used =
+            |layout: &Layout| layout.usage().map(|u| u.last_applied.unwrap_or(u.created));
+        let mut candidates = Vec::from_iter(
+            (self.layouts.iter())
+                .filter(|layout| Some(*layout) != keep)
+                .map(|layout| (last_used(&layout.0), LayoutById(layout.0.clone()))),
+        );
+        candidates.sort_by_key(|(last_used, _)| *last_used);
+        let excess = self.layouts.len() - max;
+        for (_, evicted) in candidates.into_iter().take(excess) {
+            self.layouts.remove(&evicted);
+        }
+        log::info!("database: evicted {} least recently used layouts", excess)
     }
 
     /// Store a layout, and update the file database.
     /// To avoid breaking an existing database if the serialization fails in the middle,
     /// the database is serialized to a temporary file, then moved on success.
-    pub fn store_layout(&mut self, layout: Layout) -> Result<(), anyhow::Error> {
+    /// The previous database is kept as a backup.
+    ///
+    /// Stored layouts are in use, so they count as applied now. The creation time and apply count are kept.
+    pub fn store_layout(&mut self, mut layout: Layout) -> Result<(), anyhow::Error> {
+        layout.set_name(None);
+        layout.record_relations();
+        let key = LayoutById(layout.clone());
+        let previous = self.layouts.get(&key).and_then(|l| l.0.usage());
+        let now = unix_time();
+        layout.set_usage(Some(LayoutUsage {
+            last_applied: Some(now),
+            ..previous.unwrap_or(LayoutUsage {
+                created: now,
+                ..LayoutUsage::default()
+            })
+        }));
         self.layouts.replace(LayoutById(layout));
+        self.prune(Some(&key));
+        self.write()
+    }
+
+    /// Save a layout under `name`, replacing any saved layout with the same name but keeping its usage.
+    pub fn save_layout(&mut self, name: String, mut layout: Layout) -> Result<(), anyhow::Error> {
+        layout.set_name(Some(name.clone()));
+        layout.record_relations();
+        let previous = self.saved.get(&name).and_then(Layout::usage);
+        layout.set_usage(Some(previous.unwrap_or(LayoutUsage {
+            created: unix_time(),
+            ..LayoutUsage::default()
+        })));
+        self.saved.insert(name, layout);
+        self.write()
+    }
+
+    /// Count an apply of `layout` if it is stored : saved under its name, or learned for its outputs.
+    /// The file is only written if usage changed.
+    pub fn record_applied(&mut self, layout: &Layout) -> Result<(), anyhow::Error> {
+        let now = unix_time();
+        let update = |stored: &mut Layout| {
+            let mut usage = stored.usage().unwrap_or(LayoutUsage {
+                created: now,
+                ..LayoutUsage::default()
+            });
+            usage.last_applied = Some(now);
+            usage.apply_count = usage.apply_count.saturating_add(1);
+            stored.set_usage(Some(usage))
+        };
+        let updated = match layout.name() {
+            Some(name) => match self.saved.get_mut(name) {
+                Some(stored) if stored == layout => {
+                    update(stored);
+                    true
+                }
+                _ => false,
+            },
+            None => match self.layouts.take(&LayoutById(layout.clone())) {
+                Some(LayoutById(mut stored)) => {
+                    let same = stored == *layout;
+                    if same {
+                        update(&mut stored)
+                    }
+                    self.layouts.insert(LayoutById(stored));
+                    same
+                }
+                None => false,
+            },
+        };
+        match updated {
+            true => self.write(),
+            false => Ok(()),
+        }
+    }
+
+    /// Delete the saved layout `name`. Returns false if there is none.
+    pub fn delete_saved_layout(&mut self, name: &str) -> Result<bool, anyhow::Error> {
+        match self.saved.remove(name) {
+            Some(_) => self.write().map(|()| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Add a layout to the learned or saved ones depending on its name. Returns true if it changed the database.
+    fn insert(&mut self, layout: Layout) -> bool {
+        match layout.name() {
+            Some(name) => {
+                let changed = self.saved.get(name) != Some(&layout);
+                self.saved.insert(name.to_string(), layout);
+                changed
+            }
+            None => {
+                let layout = LayoutById(layout);
+                let changed = self.layouts.get(&layout).map(|l| &l.0) != Some(&layout.0);
+                self.layouts.replace(layout);
+                changed
+            }
+        }
+    }
+
+    /// Store layouts replacing those of the same outputs (or same name for saved layouts), and update the file database once.
+    /// Returns the number of new or changed layouts ; the file is not written if there are none.
+    /// Imported layouts without usage are the first evicted if there are too many learned layouts.
+    pub fn import_layouts(&mut self, layouts: Vec<Layout>) -> Result<usize, anyhow::Error> {
+        let mut changed = 0;
+        for layout in layouts {
+            if self.insert(layout) {
+                changed += 1
+            }
+        }
+        if changed > 0 {
+            self.prune(None);
+            self.write()?
+        }
+        Ok(changed)
+    }
+
+    fn write(&mut self) -> Result<(), anyhow::Error> {
         // Write db to tmp file
         let mut tmp_path = self.path.clone();
         tmp_path.set_extension("json.tmp"); // same dir, just change extension
@@ -64,11 +286,29 @@ impl Database {
                 )
             })?
         }
-        let tmp_file = std::fs::File::create(&tmp_path).with_context(|| {
-            format!("cannot open temporary database file {}", tmp_path.display())
-        })?;
-        serde_json::to_writer(BufWriter::new(tmp_file), &self.layouts)
+        // Other slam instances wait until the database is replaced
+        let _lock = FileLock::acquire(&self.path, true)?;
+        let layouts = Vec::from_iter(self.layouts().chain(self.saved.values()));
+        let mut content = match self.format {
+            DatabaseFormat::Compact => serde_json::to_vec(&layouts),
+            DatabaseFormat::Pretty => serde_json::to_vec_pretty(&layouts),
+        }
+        .context("cannot serialize database")?;
+        match self.format {
+            DatabaseFormat::Compact => append_checksum(&mut content),
+            DatabaseFormat::Pretty => content.push(b'\n'),
+        }
+        if let Some(encryption) = &self.encryption {
+            content = encryption.encrypt(&content)?
+        }
+        std::fs::write(&tmp_path, content)
             .with_context(|| format!("cannot write database to {}", tmp_path.display()))?;
+        if self.path.exists() {
+            let backup = backup_path(&self.path);
+            if let Err(e) = std::fs::copy(&self.path, &backup) {
+                log::warn!("cannot backup database to {}: {}", backup.display(), e)
+            }
+        }
         // On success, atomically replace existing db with new one
         std::fs::rename(&tmp_path, &self.path).with_context(|| {
             format!(
@@ -76,14 +316,447 @@ impl Database {
                 self.path.display(),
                 tmp_path.display()
             )
-        })
+        })?;
+        self.modified = modification_time(&self.path);
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file was modified by another process since it was last read or written.
+    pub fn changed_on_disk(&self) -> bool {
+        modification_time(&self.path) != self.modified
+    }
+
+    /// All learned layouts, in no particular order.
+    pub fn layouts(&self) -> impl Iterator<Item = &Layout> {
+        self.layouts.iter().map(|l| &l.0)
+    }
+
+    /// Layouts saved by the user, sorted by name.
+    pub fn saved_layouts(&self) -> impl Iterator<Item = &Layout> {
+        self.saved.values()
+    }
+
+    /// Get the layout saved under `name`.
+    pub fn saved_layout(&self, name: &str) -> Option<&Layout> {
+        self.saved.get(name)
     }
 
     /// Get stored layout for given output ids.
-    /// 
+    ///
     /// It does not seem possible to use the alternate key type mode as [`std::borrow::Borrow`] returns a reference to an existing object.
     /// To represent the set of connected outputs in non copy mode we need a reference struct (iterator, etc).
     pub fn get_layout<'db>(&'db self, output_ids: &LayoutById) -> Option<&'db Layout> {
         self.layouts.get(output_ids).map(|l| &l.0)
     }
+
+    /// Most recently applied layout for given output ids, among the learned one and saved ones.
+    /// The learned layout is preferred if none was applied more recently.
+    pub fn preferred_layout<'db>(&'db self, output_ids: &LayoutById) -> Option<&'db Layout> {
+        let same_outputs = |layout: &&Layout| {
+            Iterator::eq(layout.connected_outputs(), output_ids.0.connected_outputs())
+        };
+        let saved = self.saved.values().filter(same_outputs);
+        let last_applied = |layout: &&Layout| layout.usage().and_then(|u| u.last_applied);
+        let learned = self.get_layout(output_ids);
+        // max_by_key returns the last maximum
+        Iterator::chain(saved, learned).max_by_key(last_applied)
+    }
+}
+
+/// Score of a stored output set for the `connected` outputs, higher is better : the number of common outputs,
+/// then the opposite of the number of other outputs. Both sets are sorted.
+///
+/// Returns [`None`] unless one set contains the other and they have a common output.
+pub fn subset_match_score(stored: &[&OutputId], connected: &[&OutputId]) -> Option<(usize, isize)> {
+    let common = stored.iter().filter(|id| connected.contains(id)).count();
+    let others = stored.len() + connected.len() - 2 * common;
+    let nested = common == stored.len().min(connected.len());
+    (nested && common > 0 && others > 0).then_some((common, -(others as isize)))
+}
+
+impl Database {
+    /// Learned layout with the best [`subset_match_score`] for given output ids, for output sets without stored layout.
+    /// Ties are broken by most recent use.
+    pub fn closest_layout<'db>(&'db self, output_ids: &LayoutById) -> Option<&'db Layout> {
+        let connected = Vec::from_iter(output_ids.0.connected_outputs());
+        let scored = self.layouts().filter_map(|layout| {
+            let stored = Vec::from_iter(layout.connected_outputs());
+            let score = subset_match_score(&stored, &connected)?;
+            let last_used = layout.usage().map(|u| u.last_applied.unwrap_or(u.created));
+            Some(((score, last_used), layout))
+        });
+        scored.max_by_key(|(key, _)| *key).map(|(_, layout)| layout)
+    }
+
+    /// Relations between outputs in learned layouts, like "projector right of laptop", for automatic layouts.
+    /// A pair of outputs gets its relation from the most recently used layout.
+    /// Layouts stored before relations were recorded use the adjacency of their rects.
+    pub fn known_relations(&self) -> Vec<OutputRelation> {
+        let mut layouts = Vec::from_iter(self.layouts());
+        layouts.sort_by_key(|layout| {
+            let last_used = layout.usage().map(|u| u.last_applied.unwrap_or(u.created));
+            std::cmp::Reverse(last_used)
+        });
+        let mut relations: Vec<OutputRelation> = Vec::new();
+        for layout in layouts {
+            let recorded = match layout.relations().is_empty() {
+                true => layout.adjacent_relations(),
+                false => layout.relations().to_vec(),
+            };
+            for relation in recorded {
+                let same_pair = |r: &OutputRelation| r.lhs == relation.lhs && r.rhs == relation.rhs;
+                if !relations.iter().any(same_pair) {
+                    relations.push(relation)
+                }
+            }
+        }
+        relations
+    }
+}
+
+/// Current time in seconds since the unix epoch, for layout usage.
+fn unix_time() -> u64 {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+    now.map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+/// Advisory `flock` on `<database>.lock`, released on drop.
+/// The database file itself cannot be locked, as writes replace it with another file.
+struct FileLock {
+    _file: std::fs::File,
+}
+
+impl FileLock {
+    /// Wait for a shared or `exclusive` lock for the database at `path`.
+    fn acquire(path: &Path, exclusive: bool) -> Result<FileLock, anyhow::Error> {
+        use std::os::unix::io::AsRawFd;
+        let mut lock_path = path.to_path_buf();
+        lock_path.set_extension("json.lock");
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("cannot open lock file {}", lock_path.display()))?;
+        let operation = match exclusive {
+            true => libc::LOCK_EX,
+            false => libc::LOCK_SH,
+        };
+        // Retry if interrupted by a signal
+        while unsafe { libc::flock(file.as_raw_fd(), operation) } < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() != std::io::ErrorKind::Interrupted {
+                return Err(e).with_context(|| format!("cannot lock {}", lock_path.display()));
+            }
+        }
+        Ok(FileLock { _file: file })
+    }
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.to_path_buf();
+    backup.set_extension("json.bak");
+    backup
+}
+
+/// Decrypt, check and parse database file content.
+fn parse_file(
+    path: &Path,
+    content: Vec<u8>,
+    encryption: Option<&EncryptionConfig>,
+) -> Result<Vec<Layout>, anyhow::Error> {
+    let content = match (encryption::is_encrypted(&content), encryption) {
+        (false, _) => content,
+        (true, Some(encryption)) => encryption.decrypt(&content)?,
+        (true, None) => {
+            return Err(anyhow::Error::msg(format!(
+                "database {} is encrypted but no encryption is configured",
+                path.display()
+            )))
+        }
+    };
+    let (json, checksum) = split_checksum(&content);
+    let parsed = serde_json::from_slice(json);
+    match (checksum, &parsed) {
+        (Checksum::Valid, _) => (),
+        (Checksum::Missing, Ok(_)) => log::info!("database {} has no checksum", path.display()),
+        (Checksum::Mismatch, Ok(_)) => log::warn!(
+            "database {} was edited outside of slam (checksum mismatch)",
+            path.display()
+        ),
+        (Checksum::Missing | Checksum::Mismatch, Err(_)) => {
+            return Err(anyhow::Error::msg(format!(
+                "database {} is corrupted or truncated",
+                path.display()
+            )))
+        }
+    }
+    parsed.with_context(|| format!("error parsing database {}", path.display()))
+}
+
+/// Parse layouts in the database file format, like a database copied from another machine.
+/// The checksum line is optional, but must be valid if present. Encrypted content is not supported.
+pub fn parse_layouts(content: &[u8]) -> Result<Vec<Layout>, anyhow::Error> {
+    let (json, checksum) = split_checksum(content);
+    if checksum == Checksum::Mismatch {
+        return Err(anyhow::Error::msg(
+            "layouts are corrupted (checksum mismatch)",
+        ));
+    }
+    serde_json::from_slice(json).context("error parsing layouts")
+}
+
+/// Database content is followed by a checksum line : `\n#crc32:<hex>\n`.
+const CHECKSUM_PREFIX: &[u8] = b"\n#crc32:";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Checksum {
+    Valid,
+    /// Hand written or older database, or truncated file.
+    Missing,
+    /// Edited or corrupted.
+    Mismatch,
+}
+
+fn append_checksum(content: &mut Vec<u8>) {
+    let checksum = crc32(content);
+    content.extend_from_slice(CHECKSUM_PREFIX);
+    content.extend_from_slice(format!("{:08x}\n", checksum).as_bytes());
+}
+
+/// Split content and checksum line, and verify it.
+fn split_checksum(content: &[u8]) -> (&[u8], Checksum) {
+    let trailer_start = content
+        .windows(CHECKSUM_PREFIX.len())
+        .rposition(|window| window == CHECKSUM_PREFIX);
+    let trailer_start = match trailer_start {
+        Some(start) => start,
+        None => return (content, Checksum::Missing),
+    };
+    let (json, trailer) = content.split_at(trailer_start);
+    let checksum = std::str::from_utf8(&trailer[CHECKSUM_PREFIX.len()..])
+        .ok()
+        .and_then(|hex| u32::from_str_radix(hex.trim_end(), 16).ok());
+    match checksum == Some(crc32(json)) {
+        true => (json, Checksum::Valid),
+        false => (json, Checksum::Mismatch),
+    }
+}
+
+/// CRC-32 (IEEE), bitwise : databases are small.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+#[test]
+fn test_checksum() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    let mut content = b"[]".to_vec();
+    append_checksum(&mut content);
+    assert_eq!(split_checksum(&content), (&b"[]"[..], Checksum::Valid));
+    assert_eq!(split_checksum(b"[]"), (&b"[]"[..], Checksum::Missing));
+    let mut edited = b"[ ]".to_vec();
+    edited.extend_from_slice(&content[2..]);
+    assert_eq!(split_checksum(&edited), (&b"[ ]"[..], Checksum::Mismatch));
+    let truncated = &content[..content.len() - 3];
+    assert_eq!(split_checksum(truncated).1, Checksum::Mismatch);
+}
+
+#[cfg(test)]
+#[test]
+fn test_import_layouts() {
+    use crate::layout::{LayoutInfo, OutputEntry, OutputId, OutputState};
+    let layout = |name: &str| {
+        let entry = OutputEntry {
+            id: OutputId::Name(name.into()),
+            state: OutputState::Disabled { reason: None },
+        };
+        LayoutInfo::from_iter([entry], None).layout
+    };
+    let mut content = serde_json::to_vec(&[layout("a"), layout("b")]).unwrap();
+    assert_eq!(parse_layouts(&content).unwrap().len(), 2);
+    append_checksum(&mut content);
+    assert_eq!(parse_layouts(&content).unwrap().len(), 2);
+    content[1] = b' ';
+    assert!(parse_layouts(&content).is_err());
+
+    let dir = std::env::temp_dir().join(format!("slam-test-import-{}", std::process::id()));
+    let mut database = Database::load_or_empty(dir.join("database.json"), None).unwrap();
+    let first = database.import_layouts(vec![layout("a"), layout("b")]);
+    let again = database.import_layouts(vec![layout("a")]);
+    let saved = database.save_layout("desk".into(), layout("a"));
+    let reloaded = Database::load_or_empty(dir.join("database.json"), None);
+    let deleted = database.delete_saved_layout("desk");
+    let deleted_again = database.delete_saved_layout("desk");
+    database.set_format(DatabaseFormat::Pretty);
+    let pretty = (database.save_layout("desk".into(), layout("b")))
+        .and_then(|()| Ok(std::fs::read(dir.join("database.json"))?));
+    let pretty_reloaded = Database::load_or_empty(dir.join("database.json"), None);
+    let locked = {
+        use std::os::unix::io::AsRawFd;
+        let _lock = FileLock::acquire(&dir.join("database.json"), true).unwrap();
+        let other = std::fs::File::open(dir.join("database.json.lock")).unwrap();
+        let operation = libc::LOCK_SH | libc::LOCK_NB;
+        unsafe { libc::flock(other.as_raw_fd(), operation) < 0 }
+    };
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(first.unwrap(), 2);
+    assert_eq!(again.unwrap(), 0);
+    saved.unwrap();
+    let reloaded = reloaded.unwrap();
+    assert_eq!(reloaded.layouts().count(), 2);
+    let desk = reloaded.saved_layout("desk");
+    assert_eq!(desk.and_then(Layout::name), Some("desk"));
+    assert!(deleted.unwrap());
+    assert!(!deleted_again.unwrap());
+    let pretty = pretty.unwrap();
+    assert!(pretty.starts_with(b"[\n") && split_checksum(&pretty).1 == Checksum::Missing);
+    let pretty_reloaded = pretty_reloaded.unwrap();
+    assert_eq!(pretty_reloaded.layouts().count(), 2);
+    assert!(pretty_reloaded.saved_layout("desk").is_some());
+    assert!(locked);
+}
+
+#[cfg(test)]
+#[test]
+fn test_subset_match_score() {
+    let id = |name: &str| OutputId::Name(name.into());
+    let (laptop, a, b) = (id("eDP-1"), id("DP-1"), id("DP-2"));
+    let score =
+        |stored: &[&OutputId], connected: &[&OutputId]| subset_match_score(stored, connected);
+    assert_eq!(score(&[&a, &laptop], &[&a, &b, &laptop]), Some((2, -1)));
+    assert_eq!(score(&[&a, &b, &laptop], &[&a, &laptop]), Some((2, -1)));
+    assert_eq!(score(&[&laptop], &[&a, &b, &laptop]), Some((1, -2)));
+    assert_eq!(score(&[&a, &laptop], &[&b, &laptop]), None);
+    assert_eq!(score(&[&a, &laptop], &[&a, &laptop]), None);
+    assert_eq!(score(&[&a], &[&b]), None);
+}
+
+#[cfg(test)]
+#[test]
+fn test_layout_usage() {
+    use crate::geometry::Vec2d;
+    use crate::layout::{LayoutInfo, OutputEntry, OutputState};
+    let layout = |x| {
+        let entry = OutputEntry::enabled_for_test("a", Vec2d::new(x, 1080), Vec2d::new(0, 0));
+        LayoutInfo::from_iter([entry], None).layout
+    };
+    let dir = std::env::temp_dir().join(format!("slam-test-usage-{}", std::process::id()));
+    let mut database = Database::load_or_empty(dir.join("database.json"), None).unwrap();
+    let stored = database.store_layout(layout(1920));
+    let saved = database.save_layout("tv".into(), layout(1280));
+    let key = LayoutById(layout(1920));
+    let learned_preferred = database.preferred_layout(&key).cloned();
+    let mut tv = database.saved_layout("tv").unwrap().clone();
+    let applied = database.record_applied(&tv);
+    let other = database.record_applied(&layout(800));
+    let reloaded = Database::load_or_empty(dir.join("database.json"), None);
+    let _ = std::fs::remove_dir_all(&dir);
+    stored.unwrap();
+    saved.unwrap();
+    applied.unwrap();
+    other.unwrap();
+    let learned_preferred = learned_preferred.unwrap();
+    assert_eq!(learned_preferred.name(), None);
+    let learned = learned_preferred.usage().unwrap();
+    assert_eq!(
+        (learned.last_applied, learned.apply_count),
+        (Some(learned.created), 0)
+    );
+    let reloaded = reloaded.unwrap();
+    let tv_usage = reloaded.saved_layout("tv").and_then(Layout::usage).unwrap();
+    assert_eq!(tv_usage.apply_count, 1);
+    // Ties in last apply time go to the learned layout : make the saved one more recent
+    tv.set_usage(Some(LayoutUsage {
+        last_applied: Some(tv_usage.last_applied.unwrap() + 1),
+        ..tv_usage
+    }));
+    let mut database = reloaded;
+    database.saved.insert("tv".into(), tv);
+    assert_eq!(
+        database.preferred_layout(&key).and_then(Layout::name),
+        Some("tv")
+    );
+    assert_eq!(
+        database.get_layout(&key).and_then(Layout::usage),
+        Some(learned)
+    );
+
+    // Least recently used learned layouts are evicted, never saved ones
+    let two = |x: u32| {
+        let mut layout = layout(x).output_entries().to_vec();
+        layout.push(OutputEntry {
+            id: OutputId::Name("b".into()),
+            state: OutputState::Disabled { reason: None },
+        });
+        LayoutInfo::from_iter(layout, None).layout
+    };
+    let usage = |last_applied| LayoutUsage {
+        created: 0,
+        last_applied,
+        apply_count: 0,
+    };
+    let mut old = two(1920);
+    old.set_usage(Some(usage(Some(10))));
+    let mut unused = layout(1920);
+    unused.set_usage(Some(usage(None)));
+    database.layouts.replace(LayoutById(old));
+    database.layouts.replace(LayoutById(unused));
+    database.set_max_layouts(Some(1));
+    database.prune(None);
+    assert_eq!(Vec::from_iter(database.layouts()), [&two(1920)]);
+    assert_eq!(database.saved_layouts().count(), 1);
+
+    // Known relations come from the most recently used layout with both outputs
+    let side_by_side = |b_x: i32, last_applied| {
+        let mut entries = layout(1920).output_entries().to_vec();
+        let mut b = entries[0].clone();
+        b.id = OutputId::Name("b".into());
+        if let OutputState::Enabled { bottom_left, .. } = &mut b.state {
+            *bottom_left = Vec2d::new(b_x, 0)
+        }
+        entries.push(b);
+        let mut layout = LayoutInfo::from_iter(entries, None).layout;
+        layout.set_usage(Some(usage(Some(last_applied))));
+        layout
+    };
+    database.layouts.clear();
+    database.layouts.insert(LayoutById(side_by_side(1920, 20)));
+    // Another output set, as learned layouts are unique per output set
+    let three = Vec::from_iter(
+        side_by_side(-1920, 30)
+            .output_entries()
+            .iter()
+            .cloned()
+            .chain([OutputEntry {
+                id: OutputId::Name("c".into()),
+                state: OutputState::Disabled { reason: None },
+            }]),
+    );
+    let mut recorded = LayoutInfo::from_iter(three, None).layout;
+    recorded.set_usage(Some(usage(Some(30))));
+    recorded.record_relations();
+    database.layouts.insert(LayoutById(recorded));
+    let known = database.known_relations();
+    assert_eq!(known.len(), 1);
+    assert_eq!(known[0].direction, crate::geometry::Direction::RightOf);
 }
@@ -0,0 +1,1434 @@
+use crate::geometry::{Rotation, Transform, Vec2d};
+use crate::layout::{self, Edid};
+use crate::{Backend, ReactionDelays};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Backend for consoles without display server, using DRM/KMS ioctls with atomic modesetting.
+/// Useful documentation : <https://docs.kernel.org/gpu/drm-kms.html> and `drm_mode.h`.
+///
+/// Like X, all enabled outputs show parts of one shared framebuffer, sized to the layout.
+/// Each _connector_ is driven by a _crtc_, whose _primary plane_ scans out the framebuffer at the output position.
+/// The framebuffer is black : applications draw on their own planes or framebuffers.
+///
+/// Things that are not handled :
+/// - changes by other DRM clients are not notified : only hotplug events from udev trigger a reload.
+/// - applying requires being the DRM master, so it fails while a display server runs on the device.
+/// - scaling, the primary output and output properties.
+pub struct DrmBackend {
+    device: Device,
+    uevents: UeventSocket,
+    state: DeviceState,
+    /// Framebuffer scanned out by enabled crtcs, replaced by each apply.
+    framebuffer: Option<Framebuffer>,
+}
+
+impl DrmBackend {
+    /// Use the first `/dev/dri/card*` device with connected outputs.
+    pub fn start() -> Result<Self, anyhow::Error> {
+        let mut paths = Vec::from_iter(
+            std::fs::read_dir("/dev/dri")
+                .with_context(|| "cannot list /dev/dri")?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .is_some_and(|n| n.to_string_lossy().starts_with("card"))
+                }),
+        );
+        paths.sort();
+        let uevents = UeventSocket::open().with_context(|| "cannot listen to udev events")?;
+        for path in paths {
+            let device = match Device::open(&path) {
+                Ok(device) => device,
+                Err(e) => {
+                    log::debug!("{}: {:#}", path.display(), e);
+                    continue;
+                }
+            };
+            let state = DeviceState::query(&device)?;
+            if state.connectors.iter().any(|c| c.connected) {
+                return Ok(DrmBackend {
+                    device,
+                    uevents,
+                    state,
+                    framebuffer: None,
+                });
+            }
+        }
+        Err(anyhow::Error::msg("no DRM device with connected outputs"))
+    }
+
+    fn reload_after_hotplug(
+        &mut self,
+        reaction_delays: ReactionDelays,
+    ) -> Result<(), anyhow::Error> {
+        // If delay is requested, also flush all events during the delay
+        if let Some(delay) = reaction_delays.hotplug {
+            std::thread::sleep(delay);
+            while self.uevents.wait_for_hotplug(Some(Duration::ZERO))? {}
+        }
+        self.state = DeviceState::query(&self.device)?;
+        Ok(())
+    }
+
+    /// Replace the framebuffer, releasing the previous one.
+    fn set_framebuffer(&mut self, framebuffer: Option<Framebuffer>) {
+        if let Some(previous) = std::mem::replace(&mut self.framebuffer, framebuffer) {
+            previous.release(&self.device)
+        }
+    }
+}
+
+impl Backend for DrmBackend {
+    fn current_layout(&self) -> layout::LayoutInfo {
+        let state = &self.state;
+        let entries = state
+            .connectors
+            .iter()
+            .filter(|c| c.connected)
+            .map(|connector| {
+                let crtc = connector
+                    .crtc
+                    .and_then(|id| state.crtcs.iter().find(|crtc| crtc.id == id));
+                let state = match crtc {
+                    Some(
+                        crtc @ Crtc {
+                            mode: Some(mode), ..
+                        },
+                    ) => {
+                        let rotation = state
+                            .primary_plane_of(crtc.id)
+                            .and_then(|plane| plane.props.get("rotation"))
+                            .map_or(ROTATE_0, |(_, value)| *value);
+                        layout::OutputState::Enabled {
+                            mode: mode.to_mode(),
+                            transform: transform_from_drm(rotation),
+                            bottom_left: crtc.position,
+                            scale: layout::Scale::ONE,
+                            panning: None,
+                        }
+                    }
+                    _ => layout::OutputState::Disabled { reason: None },
+                };
+                layout::OutputEntry {
+                    id: connector.id(),
+                    state,
+                }
+            });
+        layout::LayoutInfo::from_iter(entries, None)
+            .with_metadata(&self.output_names(), &self.preferred_modes())
+    }
+
+    fn refresh(&mut self) -> Result<(), anyhow::Error> {
+        self.state = DeviceState::query(&self.device)?;
+        Ok(())
+    }
+
+    fn can_verify_apply(&self) -> bool {
+        true
+    }
+
+    fn wait_for_change(&mut self, reaction_delays: ReactionDelays) -> Result<(), anyhow::Error> {
+        while !self.uevents.wait_for_hotplug(None)? {}
+        self.reload_after_hotplug(reaction_delays)
+    }
+
+    fn wait_for_change_until(
+        &mut self,
+        reaction_delays: ReactionDelays,
+        timeout: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if self.uevents.wait_for_hotplug(Some(remaining))? {
+                self.reload_after_hotplug(reaction_delays)?;
+                return Ok(true);
+            }
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+        }
+    }
+
+    fn event_fds(&self) -> Vec<RawFd> {
+        vec![self.uevents.fd.as_raw_fd()]
+    }
+
+    fn apply_layout(&mut self, layout: &layout::Layout) -> Result<(), anyhow::Error> {
+        let state = &self.state;
+        // Resolve outputs, modes and crtcs first : nothing is changed if any is missing.
+        let mut enabled = Vec::new();
+        for entry in layout.output_entries() {
+            let connector = match state
+                .connectors
+                .iter()
+                .find(|c| c.connected && c.id() == entry.id)
+            {
+                Some(connector) => connector,
+                None => {
+                    log::warn!("could not apply layout: output {} not found", entry.id);
+                    return Ok(());
+                }
+            };
+            if let layout::OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                ..
+            } = &entry.state
+            {
+                if !scale.is_one() {
+                    log::warn!(
+                        "could not apply layout: scaling is not supported by the drm backend"
+                    );
+                    return Ok(());
+                }
+                let mode_info = match connector.mode(mode) {
+                    Some(mode_info) => mode_info,
+                    None => {
+                        log::warn!(
+                            "could not apply layout: no mode matching {} found in output {}",
+                            mode,
+                            connector.name
+                        );
+                        return Ok(());
+                    }
+                };
+                let size = entry.state.rect().expect("enabled").size;
+                enabled.push((connector, mode_info, transform.clone(), *bottom_left, size))
+            }
+        }
+        let crtc_mapping = match state.allocate_crtcs(enabled.iter().map(|(c, ..)| *c)) {
+            Some(mapping) => mapping,
+            None => {
+                log::warn!("could not apply layout: cannot allocate crtcs");
+                return Ok(());
+            }
+        };
+        if !layout.backend_properties().is_empty() {
+            log::warn!("output properties are not supported by the drm backend")
+        }
+
+        let screen_size = layout.bounding_rect_size().map(|i| i.max(1));
+        let framebuffer = Framebuffer::create(&self.device, screen_size)?;
+        let mut request = AtomicRequest::default();
+        let mut blobs = Vec::new();
+        let mut build = || -> Result<(), anyhow::Error> {
+            for connector in &state.connectors {
+                let crtc = crtc_mapping.get(&connector.connector_id).copied();
+                request.set(
+                    connector.connector_id,
+                    &connector.props,
+                    "CRTC_ID",
+                    u64::from(crtc.unwrap_or(0)),
+                )?;
+            }
+            let mut used_planes = Vec::new();
+            for crtc in &state.crtcs {
+                let output = enabled.iter().find(|(connector, ..)| {
+                    crtc_mapping.get(&connector.connector_id) == Some(&crtc.id)
+                });
+                let (_, mode_info, transform, position, size) = match output {
+                    Some(output) => output,
+                    None => {
+                        request.set(crtc.id, &crtc.props, "ACTIVE", 0)?;
+                        request.set(crtc.id, &crtc.props, "MODE_ID", 0)?;
+                        continue;
+                    }
+                };
+                let blob = self.device.create_blob(mode_info.as_bytes())?;
+                blobs.push(blob);
+                request.set(crtc.id, &crtc.props, "ACTIVE", 1)?;
+                request.set(crtc.id, &crtc.props, "MODE_ID", u64::from(blob))?;
+                let plane = state.primary_plane_for(crtc.index).ok_or_else(|| {
+                    anyhow::Error::msg(format!("no primary plane for crtc {}", crtc.id))
+                })?;
+                used_planes.push(plane.id);
+                let props = &plane.props;
+                let fixed = |v: u32| u64::from(v) << 16;
+                request.set(plane.id, props, "FB_ID", u64::from(framebuffer.fb_id))?;
+                request.set(plane.id, props, "CRTC_ID", u64::from(crtc.id))?;
+                request.set(plane.id, props, "SRC_X", fixed(position.x as u32))?;
+                request.set(plane.id, props, "SRC_Y", fixed(position.y as u32))?;
+                request.set(plane.id, props, "SRC_W", fixed(size.x))?;
+                request.set(plane.id, props, "SRC_H", fixed(size.y))?;
+                request.set(plane.id, props, "CRTC_X", 0)?;
+                request.set(plane.id, props, "CRTC_Y", 0)?;
+                request.set(plane.id, props, "CRTC_W", u64::from(mode_info.hdisplay))?;
+                request.set(plane.id, props, "CRTC_H", u64::from(mode_info.vdisplay))?;
+                match (transform_to_drm(transform), props.contains_key("rotation")) {
+                    (ROTATE_0, false) => (),
+                    (rotation, true) => request.set(plane.id, props, "rotation", rotation)?,
+                    (_, false) => {
+                        return Err(anyhow::Error::msg(
+                            "rotations are not supported by the device",
+                        ))
+                    }
+                }
+            }
+            // Detach all other planes from crtcs
+            for plane in &state.planes {
+                if plane.crtc.is_some() && !used_planes.contains(&plane.id) {
+                    request.set(plane.id, &plane.props, "FB_ID", 0)?;
+                    request.set(plane.id, &plane.props, "CRTC_ID", 0)?;
+                }
+            }
+            Ok(())
+        };
+        let result = build().and_then(|()| {
+            // Atomic commits are all or nothing : test first to report invalid configurations without changes.
+            match self
+                .device
+                .atomic_commit(&request, ATOMIC_TEST_ONLY | ATOMIC_ALLOW_MODESET)
+            {
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => Ok(false),
+                Err(e) => Err(e).with_context(|| "atomic commit test failed"),
+                Ok(()) => Ok(true),
+            }
+        });
+        let result = result.and_then(|valid| match valid {
+            false => Ok(false),
+            true => self
+                .device
+                .atomic_commit(&request, ATOMIC_ALLOW_MODESET)
+                .map(|()| true)
+                .with_context(|| "atomic commit failed (another display server is running ?)"),
+        });
+        // The kernel keeps its own reference to mode blobs
+        for blob in blobs {
+            self.device.destroy_blob(blob)
+        }
+        match result {
+            Ok(true) => {
+                self.set_framebuffer(Some(framebuffer));
+                self.state = DeviceState::query(&self.device)?;
+                Ok(())
+            }
+            Ok(false) => {
+                framebuffer.release(&self.device);
+                log::warn!("could not apply layout: rejected by driver");
+                Ok(())
+            }
+            Err(e) => {
+                framebuffer.release(&self.device);
+                Err(e)
+            }
+        }
+    }
+
+    fn physical_sizes(&self) -> HashMap<layout::OutputId, Vec2d<u32>> {
+        HashMap::from_iter(
+            self.state
+                .connectors
+                .iter()
+                .filter(|c| c.connected)
+                .filter_map(|connector| {
+                    let size = connector
+                        .edid
+                        .as_deref()
+                        .and_then(layout::edid_physical_size)
+                        .unwrap_or(connector.mm_size);
+                    match size.x > 0 && size.y > 0 {
+                        true => Some((connector.id(), size)),
+                        false => None,
+                    }
+                }),
+        )
+    }
+
+    fn output_names(&self) -> HashMap<layout::OutputId, String> {
+        HashMap::from_iter(
+            self.state
+                .connectors
+                .iter()
+                .filter(|c| c.connected)
+                .map(|connector| (connector.id(), connector.name.clone())),
+        )
+    }
+
+    fn output_descriptions(&self) -> HashMap<layout::OutputId, String> {
+        HashMap::from_iter(
+            self.state
+                .connectors
+                .iter()
+                .filter(|c| c.connected)
+                .filter_map(|connector| {
+                    let info = layout::EdidInfo::parse(connector.edid.as_deref()?).ok()?;
+                    Some((connector.id(), info.to_string()))
+                }),
+        )
+    }
+
+    fn preferred_modes(&self) -> HashMap<layout::OutputId, layout::Mode> {
+        HashMap::from_iter(
+            self.state
+                .connectors
+                .iter()
+                .filter(|c| c.connected)
+                .filter_map(|connector| {
+                    let preferred = connector
+                        .modes
+                        .iter()
+                        .find(|m| m.mode_type & MODE_TYPE_PREFERRED != 0)?;
+                    Some((connector.id(), preferred.to_mode()))
+                }),
+        )
+    }
+
+    fn available_modes(&self) -> HashMap<layout::OutputId, Vec<layout::Mode>> {
+        HashMap::from_iter(
+            self.state
+                .connectors
+                .iter()
+                .filter(|c| c.connected)
+                .map(|connector| {
+                    let modes = connector.modes.iter().map(|mode| mode.to_mode());
+                    (connector.id(), Vec::from_iter(modes))
+                }),
+        )
+    }
+
+    fn diagnostics(&self) -> String {
+        use std::fmt::Write;
+        let state = &self.state;
+        let mut summary = format!("drm backend, device {}\n", self.device.path.display());
+        for connector in &state.connectors {
+            let _ = writeln!(
+                summary,
+                "connector {} {} ({}): {}, crtc {:?}, {} modes, possible crtcs {:#b}",
+                connector.name,
+                connector.connector_id,
+                connector.id(),
+                match connector.connected {
+                    true => "connected",
+                    false => "disconnected",
+                },
+                connector.crtc,
+                connector.modes.len(),
+                connector.possible_crtcs
+            );
+        }
+        for crtc in &state.crtcs {
+            let _ = writeln!(
+                summary,
+                "crtc {}: {} at {}x{}",
+                crtc.id,
+                crtc.mode
+                    .map_or_else(|| "disabled".to_string(), |m| m.to_mode().to_string()),
+                crtc.position.x,
+                crtc.position.y
+            );
+        }
+        for plane in &state.planes {
+            let _ = writeln!(
+                summary,
+                "plane {}{}: crtc {:?}, possible crtcs {:#b}",
+                plane.id,
+                match plane.primary {
+                    true => " (primary)",
+                    false => "",
+                },
+                plane.crtc,
+                plane.possible_crtcs
+            );
+        }
+        summary
+    }
+}
+
+impl Drop for DrmBackend {
+    fn drop(&mut self) {
+        self.set_framebuffer(None)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Property ids and values of a DRM object, by property name.
+type Properties = HashMap<String, (u32, u64)>;
+
+#[derive(Debug)]
+struct DeviceState {
+    connectors: Vec<Connector>,
+    crtcs: Vec<Crtc>,
+    planes: Vec<Plane>,
+}
+
+#[derive(Debug)]
+struct Connector {
+    connector_id: u32,
+    /// Kernel name, like `HDMI-A-1`.
+    name: String,
+    connected: bool,
+    modes: Vec<ModeInfo>,
+    /// Bit mask of crtc indexes, from all encoders.
+    possible_crtcs: u32,
+    mm_size: Vec2d<u32>,
+    edid: Option<Vec<u8>>,
+    crtc: Option<u32>,
+    props: Properties,
+}
+
+#[derive(Debug)]
+struct Crtc {
+    id: u32,
+    /// Index in the device crtc list, used by bit masks.
+    index: usize,
+    mode: Option<ModeInfo>,
+    position: Vec2d<i32>,
+    props: Properties,
+}
+
+#[derive(Debug)]
+struct Plane {
+    id: u32,
+    crtc: Option<u32>,
+    possible_crtcs: u32,
+    primary: bool,
+    props: Properties,
+}
+
+impl DeviceState {
+    fn query(device: &Device) -> Result<DeviceState, anyhow::Error> {
+        let resources = device
+            .resources()
+            .with_context(|| "cannot get DRM resources")?;
+        let crtcs = Result::from_iter(resources.crtcs.iter().enumerate().map(|(index, &id)| {
+            let info = device.crtc(id)?;
+            Ok::<_, anyhow::Error>(Crtc {
+                id,
+                index,
+                mode: (info.mode_valid != 0).then_some(info.mode),
+                position: Vec2d::new(info.x as i32, info.y as i32),
+                props: device.properties(id, OBJECT_CRTC)?,
+            })
+        }))?;
+        let encoders: HashMap<u32, u32> = Result::from_iter(resources.encoders.iter().map(|&id| {
+            let encoder = device.encoder(id)?;
+            Ok::<_, std::io::Error>((id, encoder.possible_crtcs))
+        }))
+        .with_context(|| "cannot get DRM encoders")?;
+        let connectors = Result::from_iter(resources.connectors.iter().map(|&id| {
+            let info = device
+                .connector(id)
+                .with_context(|| format!("cannot get connector {}", id))?;
+            let props = device.properties(id, OBJECT_CONNECTOR)?;
+            let edid = match props.get("EDID") {
+                Some((_, blob)) if *blob != 0 => Some(device.blob(*blob as u32)?),
+                _ => None,
+            };
+            Ok::<_, anyhow::Error>(Connector {
+                connector_id: id,
+                name: connector_name(info.connector_type, info.connector_type_id),
+                connected: info.connection == CONNECTOR_CONNECTED && !info.modes.is_empty(),
+                possible_crtcs: info
+                    .encoders
+                    .iter()
+                    .filter_map(|e| encoders.get(e))
+                    .fold(0, |mask, possible| mask | possible),
+                modes: info.modes,
+                mm_size: Vec2d::new(info.mm_width, info.mm_height),
+                edid,
+                crtc: props
+                    .get("CRTC_ID")
+                    .map(|(_, v)| *v as u32)
+                    .filter(|id| *id != 0),
+                props,
+            })
+        }))?;
+        let planes = Result::from_iter(device.planes()?.into_iter().map(|id| {
+            let info = device.plane(id)?;
+            let props = device.properties(id, OBJECT_PLANE)?;
+            Ok::<_, anyhow::Error>(Plane {
+                id,
+                crtc: Some(info.crtc_id).filter(|id| *id != 0),
+                possible_crtcs: info.possible_crtcs,
+                primary: props.get("type").map(|(_, v)| *v) == Some(PLANE_TYPE_PRIMARY),
+                props,
+            })
+        }))?;
+        Ok(DeviceState {
+            connectors,
+            crtcs,
+            planes,
+        })
+    }
+
+    fn primary_plane_of(&self, crtc: u32) -> Option<&Plane> {
+        self.planes
+            .iter()
+            .find(|p| p.primary && p.crtc == Some(crtc))
+    }
+
+    /// Primary plane that can be used by the crtc at `index`.
+    /// Drivers have one primary plane per crtc, so they are not shared.
+    fn primary_plane_for(&self, index: usize) -> Option<&Plane> {
+        self.planes
+            .iter()
+            .find(|p| p.primary && p.possible_crtcs & (1 << index) != 0)
+    }
+
+    /// Crtc for each connector, keeping the current one if possible.
+    fn allocate_crtcs<'s>(
+        &self,
+        connectors: impl Iterator<Item = &'s Connector> + Clone,
+    ) -> Option<HashMap<u32, u32>> {
+        let mut mapping = HashMap::new();
+        let is_free =
+            |mapping: &HashMap<u32, u32>, crtc: &Crtc| !mapping.values().any(|c| *c == crtc.id);
+        let can_drive =
+            |connector: &Connector, crtc: &Crtc| connector.possible_crtcs & (1 << crtc.index) != 0;
+        for connector in connectors.clone() {
+            if let Some(crtc) = self
+                .crtcs
+                .iter()
+                .find(|crtc| Some(crtc.id) == connector.crtc)
+            {
+                if is_free(&mapping, crtc) && can_drive(connector, crtc) {
+                    mapping.insert(connector.connector_id, crtc.id);
+                }
+            }
+        }
+        for connector in connectors {
+            if mapping.contains_key(&connector.connector_id) {
+                continue;
+            }
+            let crtc = self.crtcs.iter().find(|crtc| {
+                is_free(&mapping, crtc)
+                    && can_drive(connector, crtc)
+                    && self.primary_plane_for(crtc.index).is_some()
+            })?;
+            mapping.insert(connector.connector_id, crtc.id);
+        }
+        Some(mapping)
+    }
+}
+
+impl Connector {
+    fn id(&self) -> layout::OutputId {
+        match self.edid.as_deref().map(Edid::try_from) {
+            Some(Ok(edid)) => layout::OutputId::Edid(edid),
+            _ => layout::OutputId::Name(self.name.clone()),
+        }
+    }
+
+    /// Closest mode matching `mode` (see [`layout::Mode::match_distance`]), the preferred one if several do.
+    fn mode(&self, mode: &layout::Mode) -> Option<ModeInfo> {
+        self.modes
+            .iter()
+            .filter_map(|m| {
+                let distance = mode.match_distance(&m.to_mode())?;
+                Some(((distance, m.mode_type & MODE_TYPE_PREFERRED == 0), m))
+            })
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, m)| *m)
+    }
+}
+
+/// Kernel connector names, as in `/sys/class/drm` and Wayland compositors.
+fn connector_name(connector_type: u32, type_id: u32) -> String {
+    const NAMES: [&str; 21] = [
+        "Unknown",
+        "VGA",
+        "DVI-I",
+        "DVI-D",
+        "DVI-A",
+        "Composite",
+        "SVIDEO",
+        "LVDS",
+        "Component",
+        "DIN",
+        "DP",
+        "HDMI-A",
+        "HDMI-B",
+        "TV",
+        "eDP",
+        "Virtual",
+        "DSI",
+        "DPI",
+        "Writeback",
+        "SPI",
+        "USB",
+    ];
+    let kind = NAMES.get(connector_type as usize).unwrap_or(&"Unknown");
+    format!("{}-{}", kind, type_id)
+}
+
+/// DRM rotation property : counter-clockwise rotation bit, and reflection bits applied before it.
+fn transform_from_drm(rotation: u64) -> Transform {
+    let mut transform = Transform::default();
+    if rotation & REFLECT_X != 0 {
+        transform = transform.reflect_x();
+    }
+    if rotation & REFLECT_Y != 0 {
+        transform = transform.reflect_y();
+    }
+    let rotation = match rotation & 0xF {
+        ROTATE_90 => Rotation::R90,
+        ROTATE_180 => Rotation::R180,
+        ROTATE_270 => Rotation::R270,
+        _ => Rotation::R0,
+    };
+    transform.rotate(rotation)
+}
+
+fn transform_to_drm(transform: &Transform) -> u64 {
+    let rotation = match transform.rotation {
+        Rotation::R0 => ROTATE_0,
+        Rotation::R90 => ROTATE_90,
+        Rotation::R180 => ROTATE_180,
+        Rotation::R270 => ROTATE_270,
+    };
+    match transform.reflect {
+        true => rotation | REFLECT_X,
+        false => rotation,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Kernel interface, from `drm.h` and `drm_mode.h`.
+
+const OBJECT_CRTC: u32 = 0xcccccccc;
+const OBJECT_CONNECTOR: u32 = 0xc0c0c0c0;
+const OBJECT_PLANE: u32 = 0xeeeeeeee;
+
+const CLIENT_CAP_UNIVERSAL_PLANES: u64 = 2;
+const CLIENT_CAP_ATOMIC: u64 = 3;
+const CONNECTOR_CONNECTED: u32 = 1;
+const MODE_TYPE_PREFERRED: u32 = 1 << 3;
+const PLANE_TYPE_PRIMARY: u64 = 1;
+const ATOMIC_TEST_ONLY: u32 = 0x100;
+const ATOMIC_ALLOW_MODESET: u32 = 0x400;
+const FORMAT_XRGB8888: u32 = u32::from_le_bytes(*b"XR24");
+
+const ROTATE_0: u64 = 1 << 0;
+const ROTATE_90: u64 = 1 << 1;
+const ROTATE_180: u64 = 1 << 2;
+const ROTATE_270: u64 = 1 << 3;
+const REFLECT_X: u64 = 1 << 4;
+const REFLECT_Y: u64 = 1 << 5;
+
+/// `_IOWR('d', nr, T)`, or `_IOW` if not `read`.
+const fn ioctl_request<T>(nr: u32, read: bool) -> u32 {
+    let direction: u32 = if read { 3 } else { 1 };
+    (direction << 30) | ((std::mem::size_of::<T>() as u32) << 16) | ((b'd' as u32) << 8) | nr
+}
+
+const IOCTL_SET_CLIENT_CAP: u32 = ioctl_request::<SetClientCap>(0x0D, false);
+const IOCTL_MODE_GETRESOURCES: u32 = ioctl_request::<CardRes>(0xA0, true);
+const IOCTL_MODE_GETCRTC: u32 = ioctl_request::<GetCrtc>(0xA1, true);
+const IOCTL_MODE_GETENCODER: u32 = ioctl_request::<GetEncoder>(0xA6, true);
+const IOCTL_MODE_GETCONNECTOR: u32 = ioctl_request::<GetConnector>(0xA7, true);
+const IOCTL_MODE_GETPROPERTY: u32 = ioctl_request::<GetProperty>(0xAA, true);
+const IOCTL_MODE_GETPROPBLOB: u32 = ioctl_request::<GetBlob>(0xAC, true);
+const IOCTL_MODE_RMFB: u32 = ioctl_request::<u32>(0xAF, true);
+const IOCTL_MODE_CREATE_DUMB: u32 = ioctl_request::<CreateDumb>(0xB2, true);
+const IOCTL_MODE_DESTROY_DUMB: u32 = ioctl_request::<u32>(0xB4, true);
+const IOCTL_MODE_GETPLANERESOURCES: u32 = ioctl_request::<GetPlaneRes>(0xB5, true);
+const IOCTL_MODE_GETPLANE: u32 = ioctl_request::<GetPlane>(0xB6, true);
+const IOCTL_MODE_ADDFB2: u32 = ioctl_request::<FbCmd2>(0xB8, true);
+const IOCTL_MODE_OBJ_GETPROPERTIES: u32 = ioctl_request::<ObjGetProperties>(0xB9, true);
+const IOCTL_MODE_ATOMIC: u32 = ioctl_request::<Atomic>(0xBC, true);
+const IOCTL_MODE_CREATEPROPBLOB: u32 = ioctl_request::<CreateBlob>(0xBD, true);
+const IOCTL_MODE_DESTROYPROPBLOB: u32 = ioctl_request::<u32>(0xBE, true);
+
+#[repr(C)]
+#[derive(Default)]
+struct SetClientCap {
+    capability: u64,
+    value: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CardRes {
+    fb_id_ptr: u64,
+    crtc_id_ptr: u64,
+    connector_id_ptr: u64,
+    encoder_id_ptr: u64,
+    count_fbs: u32,
+    count_crtcs: u32,
+    count_connectors: u32,
+    count_encoders: u32,
+    min_width: u32,
+    max_width: u32,
+    min_height: u32,
+    max_height: u32,
+}
+
+/// `drm_mode_modeinfo`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct ModeInfo {
+    clock: u32,
+    hdisplay: u16,
+    hsync_start: u16,
+    hsync_end: u16,
+    htotal: u16,
+    hskew: u16,
+    vdisplay: u16,
+    vsync_start: u16,
+    vsync_end: u16,
+    vtotal: u16,
+    vscan: u16,
+    vrefresh: u32,
+    flags: u32,
+    mode_type: u32,
+    name: [u8; 32],
+}
+
+impl ModeInfo {
+    /// Frequency from timings, like the xcb backend (clock is in kHz).
+    fn to_mode(self) -> layout::Mode {
+        let dots = u64::from(self.htotal) * u64::from(self.vtotal);
+        let millihertz = match dots {
+            0 => self.vrefresh.saturating_mul(1000),
+            _ => ((u64::from(self.clock) * 1_000_000 + dots / 2) / dots) as u32,
+        };
+        layout::Mode::from_refresh(
+            Vec2d::new(self.hdisplay.into(), self.vdisplay.into()),
+            millihertz,
+        )
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        // Plain data without padding
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const ModeInfo as *const u8,
+                std::mem::size_of::<ModeInfo>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetCrtc {
+    set_connectors_ptr: u64,
+    count_connectors: u32,
+    crtc_id: u32,
+    fb_id: u32,
+    x: u32,
+    y: u32,
+    gamma_size: u32,
+    mode_valid: u32,
+    mode: ModeInfo,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetEncoder {
+    encoder_id: u32,
+    encoder_type: u32,
+    crtc_id: u32,
+    possible_crtcs: u32,
+    possible_clones: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetConnector {
+    encoders_ptr: u64,
+    modes_ptr: u64,
+    props_ptr: u64,
+    prop_values_ptr: u64,
+    count_modes: u32,
+    count_props: u32,
+    count_encoders: u32,
+    encoder_id: u32,
+    connector_id: u32,
+    connector_type: u32,
+    connector_type_id: u32,
+    connection: u32,
+    mm_width: u32,
+    mm_height: u32,
+    subpixel: u32,
+    pad: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetProperty {
+    values_ptr: u64,
+    enum_blob_ptr: u64,
+    prop_id: u32,
+    flags: u32,
+    name: [u8; 32],
+    count_values: u32,
+    count_enum_blobs: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetBlob {
+    blob_id: u32,
+    length: u32,
+    data: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CreateBlob {
+    data: u64,
+    length: u32,
+    blob_id: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CreateDumb {
+    height: u32,
+    width: u32,
+    bpp: u32,
+    flags: u32,
+    handle: u32,
+    pitch: u32,
+    size: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetPlaneRes {
+    plane_id_ptr: u64,
+    count_planes: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetPlane {
+    plane_id: u32,
+    crtc_id: u32,
+    fb_id: u32,
+    possible_crtcs: u32,
+    gamma_size: u32,
+    count_format_types: u32,
+    format_type_ptr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FbCmd2 {
+    fb_id: u32,
+    width: u32,
+    height: u32,
+    pixel_format: u32,
+    flags: u32,
+    handles: [u32; 4],
+    pitches: [u32; 4],
+    offsets: [u32; 4],
+    modifier: [u64; 4],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ObjGetProperties {
+    props_ptr: u64,
+    prop_values_ptr: u64,
+    count_props: u32,
+    obj_id: u32,
+    obj_type: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Atomic {
+    flags: u32,
+    count_objs: u32,
+    objs_ptr: u64,
+    count_props_ptr: u64,
+    props_ptr: u64,
+    prop_values_ptr: u64,
+    reserved: u64,
+    user_data: u64,
+}
+
+fn array_ptr<T>(array: &mut [T]) -> u64 {
+    array.as_mut_ptr() as u64
+}
+
+struct Resources {
+    crtcs: Vec<u32>,
+    connectors: Vec<u32>,
+    encoders: Vec<u32>,
+}
+
+struct ConnectorInfo {
+    connector_type: u32,
+    connector_type_id: u32,
+    connection: u32,
+    mm_width: u32,
+    mm_height: u32,
+    modes: Vec<ModeInfo>,
+    encoders: Vec<u32>,
+}
+
+/// Opened DRM device, with atomic modesetting enabled.
+struct Device {
+    file: File,
+    path: PathBuf,
+    /// Property names by id, which are constant.
+    property_names: std::cell::RefCell<HashMap<u32, String>>,
+}
+
+impl Device {
+    fn open(path: &Path) -> Result<Device, anyhow::Error> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("cannot open {}", path.display()))?;
+        let device = Device {
+            file,
+            path: path.to_path_buf(),
+            property_names: Default::default(),
+        };
+        for capability in [CLIENT_CAP_UNIVERSAL_PLANES, CLIENT_CAP_ATOMIC] {
+            let mut cap = SetClientCap {
+                capability,
+                value: 1,
+            };
+            device
+                .ioctl(IOCTL_SET_CLIENT_CAP, &mut cap)
+                .with_context(|| "atomic modesetting not supported")?;
+        }
+        Ok(device)
+    }
+
+    fn ioctl<T>(&self, request: u32, arg: &mut T) -> std::io::Result<()> {
+        loop {
+            let result = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg as *mut T) };
+            if result == 0 {
+                return Ok(());
+            }
+            let error = std::io::Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EINTR) | Some(libc::EAGAIN) => continue,
+                _ => return Err(error),
+            }
+        }
+    }
+
+    /// Resource arrays are read with a first call for counts, and retried if counts changed in between.
+    fn resources(&self) -> std::io::Result<Resources> {
+        loop {
+            let mut counts = CardRes::default();
+            self.ioctl(IOCTL_MODE_GETRESOURCES, &mut counts)?;
+            let mut fbs = vec![0u32; counts.count_fbs as usize];
+            let mut crtcs = vec![0u32; counts.count_crtcs as usize];
+            let mut connectors = vec![0u32; counts.count_connectors as usize];
+            let mut encoders = vec![0u32; counts.count_encoders as usize];
+            let mut res = CardRes {
+                fb_id_ptr: array_ptr(&mut fbs),
+                crtc_id_ptr: array_ptr(&mut crtcs),
+                connector_id_ptr: array_ptr(&mut connectors),
+                encoder_id_ptr: array_ptr(&mut encoders),
+                ..counts
+            };
+            self.ioctl(IOCTL_MODE_GETRESOURCES, &mut res)?;
+            if (
+                res.count_fbs,
+                res.count_crtcs,
+                res.count_connectors,
+                res.count_encoders,
+            ) == (
+                counts.count_fbs,
+                counts.count_crtcs,
+                counts.count_connectors,
+                counts.count_encoders,
+            ) {
+                return Ok(Resources {
+                    crtcs,
+                    connectors,
+                    encoders,
+                });
+            }
+        }
+    }
+
+    fn crtc(&self, crtc_id: u32) -> std::io::Result<GetCrtc> {
+        let mut crtc = GetCrtc {
+            crtc_id,
+            ..Default::default()
+        };
+        self.ioctl(IOCTL_MODE_GETCRTC, &mut crtc)?;
+        Ok(crtc)
+    }
+
+    fn encoder(&self, encoder_id: u32) -> std::io::Result<GetEncoder> {
+        let mut encoder = GetEncoder {
+            encoder_id,
+            ..Default::default()
+        };
+        self.ioctl(IOCTL_MODE_GETENCODER, &mut encoder)?;
+        Ok(encoder)
+    }
+
+    fn connector(&self, connector_id: u32) -> std::io::Result<ConnectorInfo> {
+        loop {
+            // Properties are read with ObjGetProperties instead
+            let mut counts = GetConnector {
+                connector_id,
+                ..Default::default()
+            };
+            self.ioctl(IOCTL_MODE_GETCONNECTOR, &mut counts)?;
+            let mut modes = vec![ModeInfo::default(); counts.count_modes as usize];
+            let mut encoders = vec![0u32; counts.count_encoders as usize];
+            let mut info = GetConnector {
+                connector_id,
+                modes_ptr: array_ptr(&mut modes),
+                count_modes: counts.count_modes,
+                encoders_ptr: array_ptr(&mut encoders),
+                count_encoders: counts.count_encoders,
+                ..Default::default()
+            };
+            self.ioctl(IOCTL_MODE_GETCONNECTOR, &mut info)?;
+            if (info.count_modes, info.count_encoders)
+                == (counts.count_modes, counts.count_encoders)
+            {
+                return Ok(ConnectorInfo {
+                    connector_type: info.connector_type,
+                    connector_type_id: info.connector_type_id,
+                    connection: info.connection,
+                    mm_width: info.mm_width,
+                    mm_height: info.mm_height,
+                    modes,
+                    encoders,
+                });
+            }
+        }
+    }
+
+    fn planes(&self) -> std::io::Result<Vec<u32>> {
+        let mut counts = GetPlaneRes::default();
+        self.ioctl(IOCTL_MODE_GETPLANERESOURCES, &mut counts)?;
+        let mut planes = vec![0u32; counts.count_planes as usize];
+        let mut res = GetPlaneRes {
+            plane_id_ptr: array_ptr(&mut planes),
+            count_planes: counts.count_planes,
+        };
+        self.ioctl(IOCTL_MODE_GETPLANERESOURCES, &mut res)?;
+        planes.truncate(res.count_planes as usize);
+        Ok(planes)
+    }
+
+    fn plane(&self, plane_id: u32) -> std::io::Result<GetPlane> {
+        let mut plane = GetPlane {
+            plane_id,
+            ..Default::default()
+        };
+        self.ioctl(IOCTL_MODE_GETPLANE, &mut plane)?;
+        Ok(plane)
+    }
+
+    fn properties(&self, obj_id: u32, obj_type: u32) -> Result<Properties, anyhow::Error> {
+        let context = || format!("cannot get properties of DRM object {}", obj_id);
+        let mut counts = ObjGetProperties {
+            obj_id,
+            obj_type,
+            ..Default::default()
+        };
+        self.ioctl(IOCTL_MODE_OBJ_GETPROPERTIES, &mut counts)
+            .with_context(context)?;
+        let mut ids = vec![0u32; counts.count_props as usize];
+        let mut values = vec![0u64; counts.count_props as usize];
+        let mut props = ObjGetProperties {
+            props_ptr: array_ptr(&mut ids),
+            prop_values_ptr: array_ptr(&mut values),
+            ..counts
+        };
+        self.ioctl(IOCTL_MODE_OBJ_GETPROPERTIES, &mut props)
+            .with_context(context)?;
+        let count = (props.count_props as usize).min(ids.len());
+        let mut properties = HashMap::new();
+        for (id, value) in Iterator::zip(ids[..count].iter(), values.iter()) {
+            properties.insert(
+                self.property_name(*id).with_context(context)?,
+                (*id, *value),
+            );
+        }
+        Ok(properties)
+    }
+
+    fn property_name(&self, prop_id: u32) -> std::io::Result<String> {
+        if let Some(name) = self.property_names.borrow().get(&prop_id) {
+            return Ok(name.clone());
+        }
+        let mut property = GetProperty {
+            prop_id,
+            ..Default::default()
+        };
+        self.ioctl(IOCTL_MODE_GETPROPERTY, &mut property)?;
+        let length = property
+            .name
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(property.name.len());
+        let name = String::from_utf8_lossy(&property.name[..length]).to_string();
+        self.property_names
+            .borrow_mut()
+            .insert(prop_id, name.clone());
+        Ok(name)
+    }
+
+    fn blob(&self, blob_id: u32) -> std::io::Result<Vec<u8>> {
+        let mut counts = GetBlob {
+            blob_id,
+            ..Default::default()
+        };
+        self.ioctl(IOCTL_MODE_GETPROPBLOB, &mut counts)?;
+        let mut data = vec![0u8; counts.length as usize];
+        let mut blob = GetBlob {
+            blob_id,
+            length: counts.length,
+            data: array_ptr(&mut data),
+        };
+        self.ioctl(IOCTL_MODE_GETPROPBLOB, &mut blob)?;
+        Ok(data)
+    }
+
+    fn create_blob(&self, data: &[u8]) -> Result<u32, anyhow::Error> {
+        let mut blob = CreateBlob {
+            data: data.as_ptr() as u64,
+            length: data.len() as u32,
+            blob_id: 0,
+        };
+        self.ioctl(IOCTL_MODE_CREATEPROPBLOB, &mut blob)
+            .with_context(|| "cannot create DRM property blob")?;
+        Ok(blob.blob_id)
+    }
+
+    fn destroy_blob(&self, mut blob_id: u32) {
+        if let Err(e) = self.ioctl(IOCTL_MODE_DESTROYPROPBLOB, &mut blob_id) {
+            log::warn!("cannot destroy DRM property blob {}: {}", blob_id, e)
+        }
+    }
+
+    fn atomic_commit(&self, request: &AtomicRequest, flags: u32) -> std::io::Result<()> {
+        let mut entries = request.entries.clone();
+        entries.sort_by_key(|(object, ..)| *object);
+        let mut objects = Vec::new();
+        let mut counts = Vec::new();
+        for (object, ..) in &entries {
+            match objects.last() {
+                Some(last) if last == object => *counts.last_mut().unwrap() += 1,
+                _ => {
+                    objects.push(*object);
+                    counts.push(1u32)
+                }
+            }
+        }
+        let mut props = Vec::from_iter(entries.iter().map(|(_, prop, _)| *prop));
+        let mut values = Vec::from_iter(entries.iter().map(|(.., value)| *value));
+        let mut atomic = Atomic {
+            flags,
+            count_objs: objects.len() as u32,
+            objs_ptr: array_ptr(&mut objects),
+            count_props_ptr: array_ptr(&mut counts),
+            props_ptr: array_ptr(&mut props),
+            prop_values_ptr: array_ptr(&mut values),
+            ..Default::default()
+        };
+        self.ioctl(IOCTL_MODE_ATOMIC, &mut atomic)
+    }
+}
+
+/// Property changes of an atomic commit.
+#[derive(Default)]
+struct AtomicRequest {
+    entries: Vec<(u32, u32, u64)>,
+}
+
+impl AtomicRequest {
+    fn set(
+        &mut self,
+        object: u32,
+        props: &Properties,
+        name: &str,
+        value: u64,
+    ) -> Result<(), anyhow::Error> {
+        let (prop, _) = props.get(name).ok_or_else(|| {
+            anyhow::Error::msg(format!("DRM object {} has no property {}", object, name))
+        })?;
+        self.entries.push((object, *prop, value));
+        Ok(())
+    }
+}
+
+/// Black XRGB framebuffer in a dumb buffer.
+struct Framebuffer {
+    fb_id: u32,
+    handle: u32,
+}
+
+impl Framebuffer {
+    fn create(device: &Device, size: Vec2d<u32>) -> Result<Framebuffer, anyhow::Error> {
+        let mut dumb = CreateDumb {
+            width: size.x,
+            height: size.y,
+            bpp: 32,
+            ..Default::default()
+        };
+        device
+            .ioctl(IOCTL_MODE_CREATE_DUMB, &mut dumb)
+            .with_context(|| format!("cannot create {}x{} buffer", size.x, size.y))?;
+        let mut fb = FbCmd2 {
+            width: size.x,
+            height: size.y,
+            pixel_format: FORMAT_XRGB8888,
+            handles: [dumb.handle, 0, 0, 0],
+            pitches: [dumb.pitch, 0, 0, 0],
+            ..Default::default()
+        };
+        if let Err(e) = device.ioctl(IOCTL_MODE_ADDFB2, &mut fb) {
+            let mut handle = dumb.handle;
+            let _ = device.ioctl(IOCTL_MODE_DESTROY_DUMB, &mut handle);
+            return Err(e).with_context(|| "cannot create framebuffer");
+        }
+        Ok(Framebuffer {
+            fb_id: fb.fb_id,
+            handle: dumb.handle,
+        })
+    }
+
+    fn release(self, device: &Device) {
+        let (mut fb_id, mut handle) = (self.fb_id, self.handle);
+        if let Err(e) = device.ioctl(IOCTL_MODE_RMFB, &mut fb_id) {
+            log::warn!("cannot remove framebuffer {}: {}", self.fb_id, e)
+        }
+        if let Err(e) = device.ioctl(IOCTL_MODE_DESTROY_DUMB, &mut handle) {
+            log::warn!("cannot destroy buffer {}: {}", self.handle, e)
+        }
+    }
+}
+
+/// Netlink socket receiving kernel uevents, for connector hotplug.
+struct UeventSocket {
+    fd: OwnedFd,
+}
+
+impl UeventSocket {
+    fn open() -> std::io::Result<UeventSocket> {
+        let raw = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if raw < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = 1; // kernel events
+        let result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(UeventSocket { fd })
+    }
+
+    /// Wait for one uevent, up to `timeout` if any. Returns true for a DRM hotplug event.
+    fn wait_for_hotplug(&self, timeout: Option<Duration>) -> Result<bool, anyhow::Error> {
+        let mut fd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        match unsafe { libc::poll(&mut fd, 1, timeout_ms) } {
+            0 => return Ok(false),
+            n if n < 0 => {
+                let error = std::io::Error::last_os_error();
+                return match error.kind() {
+                    std::io::ErrorKind::Interrupted => Ok(false),
+                    _ => Err(error).with_context(|| "poll on uevent socket failed"),
+                };
+            }
+            _ => (),
+        }
+        let mut buffer = [0u8; 8192];
+        let size = unsafe {
+            libc::recv(
+                self.fd.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                0,
+            )
+        };
+        if size < 0 {
+            return Err(std::io::Error::last_os_error()).with_context(|| "cannot read uevent");
+        }
+        Ok(is_drm_hotplug(&buffer[..size as usize]))
+    }
+}
+
+/// Uevents are a header then `KEY=value` fields, separated by nul bytes.
+fn is_drm_hotplug(uevent: &[u8]) -> bool {
+    let (mut drm, mut hotplug) = (false, false);
+    for field in uevent.split(|b| *b == 0) {
+        drm |= field == b"SUBSYSTEM=drm";
+        hotplug |= field == b"HOTPLUG=1";
+    }
+    drm && hotplug
+}
+
+#[cfg(test)]
+#[test]
+fn test_drm_interface() {
+    // Sizes of kernel structures, which are part of ioctl numbers
+    assert_eq!(std::mem::size_of::<ModeInfo>(), 68);
+    assert_eq!(std::mem::size_of::<GetCrtc>(), 104);
+    assert_eq!(std::mem::size_of::<GetConnector>(), 80);
+    assert_eq!(std::mem::size_of::<GetProperty>(), 64);
+    assert_eq!(std::mem::size_of::<FbCmd2>(), 104);
+    assert_eq!(std::mem::size_of::<Atomic>(), 56);
+    assert_eq!(IOCTL_MODE_GETRESOURCES, 0xC04064A0);
+    assert_eq!(IOCTL_MODE_ATOMIC, 0xC03864BC);
+
+    let mode = ModeInfo {
+        clock: 148500,
+        hdisplay: 1920,
+        htotal: 2200,
+        vdisplay: 1080,
+        vtotal: 1125,
+        ..Default::default()
+    };
+    assert_eq!(
+        mode.to_mode(),
+        layout::Mode {
+            size: Vec2d::new(1920, 1080),
+            frequency: 60,
+            refresh: None,
+        }
+    );
+    assert_eq!(mode.to_mode().refresh, Some(60000));
+    assert_eq!(connector_name(11, 1), "HDMI-A-1");
+    for transform in [
+        Transform::default(),
+        Transform::default().rotate(Rotation::R90),
+        Transform::default().reflect_x().rotate(Rotation::R270),
+    ] {
+        assert_eq!(transform_from_drm(transform_to_drm(&transform)), transform)
+    }
+    assert!(is_drm_hotplug(
+        b"change@/devices/card0\0ACTION=change\0SUBSYSTEM=drm\0HOTPLUG=1\0"
+    ));
+    assert!(!is_drm_hotplug(
+        b"add@/devices/usb1\0ACTION=add\0SUBSYSTEM=usb\0"
+    ));
+}
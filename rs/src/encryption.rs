@@ -0,0 +1,69 @@
+use anyhow::Context;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Encryption at rest of the database, using the [`age`](https://age-encryption.org) command line tool.
+/// The database is encrypted to the recipient of the identity file, so no passphrase prompt is needed for the daemon.
+/// Identities can be generated with `age-keygen -o <file>`.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// age identity file (private key).
+    pub identity: PathBuf,
+}
+
+impl EncryptionConfig {
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        run_age(&["--encrypt", "--identity"], &self.identity, plaintext)
+            .context("database encryption failed")
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        run_age(&["--decrypt", "--identity"], &self.identity, ciphertext)
+            .context("database decryption failed")
+    }
+}
+
+/// Detect binary and armored age files.
+pub fn is_encrypted(content: &[u8]) -> bool {
+    content.starts_with(b"age-encryption.org/")
+        || content.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----")
+}
+
+/// Run age with `input` on stdin, and return its output.
+fn run_age(args: &[&str], identity: &Path, input: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut child = Command::new("age")
+        .args(args)
+        .arg(identity)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("cannot run age")?;
+    // Write from another thread to avoid a deadlock if age fills the output pipe
+    let mut stdin = child.stdin.take().expect("piped stdin");
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().context("cannot run age")?;
+    writer
+        .join()
+        .expect("writer thread does not panic")
+        .context("cannot write to age")?;
+    if !output.status.success() {
+        return Err(anyhow::Error::msg(format!(
+            "age failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+#[test]
+fn test_is_encrypted() {
+    assert!(is_encrypted(b"age-encryption.org/v1\n-> X25519 abcd\n"));
+    assert!(is_encrypted(b"-----BEGIN AGE ENCRYPTED FILE-----\nYWdl\n"));
+    assert!(!is_encrypted(b"[]"));
+}
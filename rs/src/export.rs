@@ -0,0 +1,398 @@
+use crate::geometry::{Rotation, Transform};
+use crate::layout::{Layout, OutputId, OutputState};
+use std::collections::HashMap;
+
+/// Configuration formats of other tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// `output` commands for sway config files.
+    Sway,
+    /// `monitor` lines for Hyprland config files.
+    Hyprland,
+    /// KDE kscreen control file (json).
+    Kscreen,
+    /// `Monitor` and `Screen` sections for `xorg.conf.d`.
+    Xorg,
+    /// `profile` blocks for kanshi config files.
+    Kanshi,
+    /// `xrandr` command line.
+    Xrandr,
+}
+
+impl ExportFormat {
+    /// Prefix of comment lines, if the format supports comments.
+    pub fn comment_prefix(self) -> Option<&'static str> {
+        match self {
+            ExportFormat::Sway
+            | ExportFormat::Hyprland
+            | ExportFormat::Xorg
+            | ExportFormat::Kanshi
+            | ExportFormat::Xrandr => Some("#"),
+            ExportFormat::Kscreen => None,
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ExportFormat, String> {
+        match s {
+            "sway" => Ok(ExportFormat::Sway),
+            "hyprland" => Ok(ExportFormat::Hyprland),
+            "kscreen" => Ok(ExportFormat::Kscreen),
+            "xorg" => Ok(ExportFormat::Xorg),
+            "kanshi" => Ok(ExportFormat::Kanshi),
+            "xrandr" => Ok(ExportFormat::Xrandr),
+            _ => Err(format!("unknown export format: {}", s)),
+        }
+    }
+}
+
+/// Render `layout` in `format`.
+/// Outputs are named using `names` (usually from a backend), or their [`OutputId`] representation if missing.
+pub fn export(layout: &Layout, format: ExportFormat, names: &HashMap<OutputId, String>) -> String {
+    let name = |id: &OutputId| match names.get(id) {
+        Some(name) => name.clone(),
+        None => id.to_string(),
+    };
+    let lines = match format {
+        ExportFormat::Sway => sway_lines(layout, &name),
+        ExportFormat::Hyprland => hyprland_lines(layout, &name),
+        ExportFormat::Kscreen => return kscreen_json(layout, &name) + "\n",
+        ExportFormat::Xorg => xorg_lines(layout, &name),
+        ExportFormat::Kanshi => kanshi_lines(layout, &name),
+        ExportFormat::Xrandr => vec![xrandr_command(layout, names)],
+    };
+    lines.into_iter().map(|line| line + "\n").collect()
+}
+
+/// Value of `wl_output.transform` : `0-3` for rotations of `90 * value`, `4-7` for the same with reflection.
+/// Wayland compositor configurations use clockwise rotations, and reflect before rotation like [`Transform`].
+fn wayland_transform(transform: &Transform) -> u8 {
+    let rotation = match transform.rotation {
+        Rotation::R0 => 0,
+        Rotation::R90 => 3,
+        Rotation::R180 => 2,
+        Rotation::R270 => 1,
+    };
+    match transform.reflect {
+        false => rotation,
+        true => 4 + rotation,
+    }
+}
+
+/// [`Transform`] of a `wl_output.transform` value, inverse of [`wayland_transform`].
+pub(crate) fn transform_from_wayland(value: u8) -> Option<Transform> {
+    let rotation = match value % 4 {
+        0 => Rotation::R0,
+        1 => Rotation::R270,
+        2 => Rotation::R180,
+        _ => Rotation::R90,
+    };
+    match value {
+        0..=3 => Some(Transform::default().rotate(rotation)),
+        4..=7 => Some(Transform::default().reflect_x().rotate(rotation)),
+        _ => None,
+    }
+}
+
+/// Sway (and kanshi) names of [`wayland_transform`] values.
+pub(crate) const SWAY_TRANSFORMS: [&str; 8] = [
+    "normal",
+    "90",
+    "180",
+    "270",
+    "flipped",
+    "flipped-90",
+    "flipped-180",
+    "flipped-270",
+];
+
+fn sway_lines(layout: &Layout, name: &dyn Fn(&OutputId) -> String) -> Vec<String> {
+    Vec::from_iter(layout.output_entries().iter().map(|entry| {
+        let name = name(&entry.id);
+        match &entry.state {
+            OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                ..
+            } => {
+                let mut line = format!(
+                    "output \"{}\" mode {}x{}@{}Hz position {},{} transform {}",
+                    name,
+                    mode.size.x,
+                    mode.size.y,
+                    mode.frequency,
+                    bottom_left.x,
+                    bottom_left.y,
+                    SWAY_TRANSFORMS[usize::from(wayland_transform(transform))]
+                );
+                if !scale.is_one() {
+                    line += &format!(" scale {}", scale)
+                }
+                line
+            }
+            OutputState::Disabled { .. } => format!("output \"{}\" disable", name),
+        }
+    }))
+}
+
+/// One profile for the layout, named like the layout if it is a saved one.
+/// Outputs are designated by name : kanshi also accepts descriptions (make, model, serial), which are not stored.
+fn kanshi_lines(layout: &Layout, name: &dyn Fn(&OutputId) -> String) -> Vec<String> {
+    let mut lines = vec![match layout.name() {
+        Some(profile) => format!("profile {} {{", profile),
+        None => "profile {".to_string(),
+    }];
+    for entry in layout.output_entries() {
+        let name = name(&entry.id);
+        lines.push(match &entry.state {
+            OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                ..
+            } => format!(
+                "    output \"{}\" enable mode {}x{}@{}Hz position {},{} scale {} transform {}",
+                name,
+                mode.size.x,
+                mode.size.y,
+                mode.frequency,
+                bottom_left.x,
+                bottom_left.y,
+                scale,
+                SWAY_TRANSFORMS[usize::from(wayland_transform(transform))]
+            ),
+            OutputState::Disabled { .. } => format!("    output \"{}\" disable", name),
+        })
+    }
+    lines.push("}".to_string());
+    lines
+}
+
+/// Arguments are quoted for `sh` when needed.
+fn xrandr_command(layout: &Layout, names: &HashMap<OutputId, String>) -> String {
+    let quote = |arg: String| {
+        let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_.,:/=@+".contains(c);
+        match !arg.is_empty() && arg.chars().all(is_plain) {
+            true => arg,
+            false => format!("'{}'", arg.replace('\'', "'\\''")),
+        }
+    };
+    let args = layout.to_xrandr_args(names).into_iter().map(quote);
+    std::iter::once("xrandr".to_string())
+        .chain(args)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn hyprland_lines(layout: &Layout, name: &dyn Fn(&OutputId) -> String) -> Vec<String> {
+    Vec::from_iter(layout.output_entries().iter().map(|entry| {
+        let name = name(&entry.id);
+        match &entry.state {
+            OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                ..
+            } => format!(
+                "monitor={},{}x{}@{},{}x{},{},transform,{}",
+                name,
+                mode.size.x,
+                mode.size.y,
+                mode.frequency,
+                bottom_left.x,
+                bottom_left.y,
+                scale,
+                wayland_transform(transform)
+            ),
+            OutputState::Disabled { .. } => format!("monitor={},disable", name),
+        }
+    }))
+}
+
+/// Array of outputs like in `~/.local/share/kscreen/<hash>`.
+/// kscreen identifies outputs by a hash of the EDID, which is not available here.
+/// Thus `id` is the [`OutputId`] representation, and the output name is given in `metadata`.
+/// kscreen does not support reflections, they are dropped.
+fn kscreen_json(layout: &Layout, name: &dyn Fn(&OutputId) -> String) -> String {
+    let outputs = Vec::from_iter(layout.output_entries().iter().map(|entry| {
+        let mut output = serde_json::json!({
+            "id": entry.id.to_string(),
+            "metadata": { "name": name(&entry.id) },
+            "enabled": entry.state.rect().is_some(),
+            "primary": layout.primary() == Some(&entry.id),
+        });
+        if let OutputState::Enabled {
+            mode,
+            transform,
+            bottom_left,
+            scale,
+            ..
+        } = &entry.state
+        {
+            // KScreen::Output::Rotation : None = 1, Left = 2, Inverted = 4, Right = 8
+            let rotation = match transform.rotation {
+                Rotation::R0 => 1,
+                Rotation::R90 => 2,
+                Rotation::R180 => 4,
+                Rotation::R270 => 8,
+            };
+            output["mode"] = serde_json::json!({
+                "refresh": mode.frequency,
+                "size": { "width": mode.size.x, "height": mode.size.y },
+            });
+            output["pos"] = serde_json::json!({ "x": bottom_left.x, "y": bottom_left.y });
+            output["rotation"] = rotation.into();
+            output["scale"] = scale.to_f64().into();
+        }
+        output
+    }));
+    serde_json::to_string_pretty(&outputs).expect("json values serialize")
+}
+
+/// Xorg uses the `Monitor` section named like an output for it, unless a `Monitor-<output>` device option says otherwise.
+/// Reflections and scales have no static equivalent and are dropped.
+/// The `Screen` section only sets the virtual screen size, and must be merged with an existing one if any.
+fn xorg_lines(layout: &Layout, name: &dyn Fn(&OutputId) -> String) -> Vec<String> {
+    let mut lines = Vec::new();
+    for entry in layout.output_entries() {
+        lines.push("Section \"Monitor\"".to_string());
+        lines.push(format!("    Identifier \"{}\"", name(&entry.id)));
+        match &entry.state {
+            OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                ..
+            } => {
+                let rotate = match transform.rotation {
+                    Rotation::R0 => "normal",
+                    Rotation::R90 => "left",
+                    Rotation::R180 => "inverted",
+                    Rotation::R270 => "right",
+                };
+                lines.push(format!(
+                    "    Option \"PreferredMode\" \"{}x{}\"",
+                    mode.size.x, mode.size.y
+                ));
+                lines.push(format!(
+                    "    Option \"Position\" \"{} {}\"",
+                    bottom_left.x, bottom_left.y
+                ));
+                lines.push(format!("    Option \"Rotate\" \"{}\"", rotate));
+                if layout.primary() == Some(&entry.id) {
+                    lines.push("    Option \"Primary\" \"true\"".to_string());
+                }
+            }
+            OutputState::Disabled { .. } => {
+                lines.push("    Option \"Enable\" \"false\"".to_string())
+            }
+        }
+        lines.push("EndSection".to_string());
+    }
+    let size = layout.bounding_rect_size();
+    lines.push("Section \"Screen\"".to_string());
+    lines.push("    Identifier \"slam\"".to_string());
+    lines.push("    SubSection \"Display\"".to_string());
+    lines.push(format!("        Virtual {} {}", size.x, size.y));
+    lines.push("    EndSubSection".to_string());
+    lines.push("EndSection".to_string());
+    lines
+}
+
+#[cfg(test)]
+#[test]
+fn test_export() {
+    use crate::geometry::Vec2d;
+    use crate::layout::{Edid, LayoutInfo, Mode, OutputEntry, Scale};
+    let info = LayoutInfo::from_iter(
+        [
+            OutputEntry {
+                id: OutputId::Name("DP-1".into()),
+                state: OutputState::Enabled {
+                    mode: Mode {
+                        size: Vec2d::new(2560, 1440),
+                        frequency: 60,
+                        refresh: None,
+                    },
+                    transform: Transform::default().rotate(Rotation::R90),
+                    bottom_left: Vec2d::new(1280, 0),
+                    scale: Scale::ONE,
+                    panning: None,
+                },
+            },
+            OutputEntry {
+                id: OutputId::Edid(Edid::from(0x1234)),
+                state: OutputState::Enabled {
+                    mode: Mode {
+                        size: Vec2d::new(2560, 1600),
+                        frequency: 120,
+                        refresh: None,
+                    },
+                    transform: Transform::default(),
+                    bottom_left: Vec2d::new(0, 0),
+                    scale: Scale::from_f64(2.).unwrap(),
+                    panning: None,
+                },
+            },
+            OutputEntry {
+                id: OutputId::Name("HDMI-1".into()),
+                state: OutputState::Disabled { reason: None },
+            },
+        ],
+        None,
+    );
+    assert!(info.unsupported_causes.is_empty());
+    let names = HashMap::from([(OutputId::Edid(Edid::from(0x1234)), "eDP-1".to_string())]);
+    assert_eq!(
+        export(&info.layout, ExportFormat::Sway, &names),
+        "output \"eDP-1\" mode 2560x1600@120Hz position 0,0 transform normal scale 2\n\
+         output \"DP-1\" mode 2560x1440@60Hz position 1280,0 transform 270\n\
+         output \"HDMI-1\" disable\n"
+    );
+    assert_eq!(
+        export(&info.layout, ExportFormat::Hyprland, &names),
+        "monitor=eDP-1,2560x1600@120,0x0,2,transform,0\n\
+         monitor=DP-1,2560x1440@60,1280x0,1,transform,3\n\
+         monitor=HDMI-1,disable\n"
+    );
+    let kscreen: serde_json::Value =
+        serde_json::from_str(&export(&info.layout, ExportFormat::Kscreen, &names)).unwrap();
+    assert_eq!(kscreen[0]["metadata"]["name"], "eDP-1");
+    assert_eq!(kscreen[0]["scale"], 2.);
+    assert_eq!(kscreen[1]["rotation"], 2);
+    assert_eq!(kscreen[1]["pos"], serde_json::json!({"x": 1280, "y": 0}));
+    assert_eq!(kscreen[2]["enabled"], false);
+    let xorg = export(&info.layout, ExportFormat::Xorg, &names);
+    assert!(xorg.contains(
+        "Section \"Monitor\"\n    Identifier \"DP-1\"\n    Option \"PreferredMode\" \"2560x1440\"\n    \
+         Option \"Position\" \"1280 0\"\n    Option \"Rotate\" \"left\"\nEndSection\n"
+    ));
+    assert!(xorg.contains("    Identifier \"HDMI-1\"\n    Option \"Enable\" \"false\"\n"));
+    assert!(xorg.contains("        Virtual 2720 2560\n"));
+    let mut named = info.layout.clone();
+    named.set_name(Some("desk".into()));
+    assert_eq!(
+        export(&named, ExportFormat::Kanshi, &names),
+        "profile desk {\n    \
+         output \"eDP-1\" enable mode 2560x1600@120Hz position 0,0 scale 2 transform normal\n    \
+         output \"DP-1\" enable mode 2560x1440@60Hz position 1280,0 scale 1 transform 270\n    \
+         output \"HDMI-1\" disable\n\
+         }\n"
+    );
+    assert_eq!(
+        export(&info.layout, ExportFormat::Xrandr, &names),
+        "xrandr --output eDP-1 --mode 2560x1600 --rate 120 --pos 0x0 --rotate normal --reflect normal --scale 0.5x0.5 \
+         --output DP-1 --mode 2560x1440 --rate 60 --pos 1280x0 --rotate left --reflect normal \
+         --output HDMI-1 --off\n"
+    );
+    for value in 0..8 {
+        let transform = transform_from_wayland(value).unwrap();
+        assert_eq!(wayland_transform(&transform), value);
+    }
+}
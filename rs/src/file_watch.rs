@@ -0,0 +1,113 @@
+use anyhow::Context;
+use std::ffi::{CString, OsStr, OsString};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Watch of a file with inotify, without blocking.
+///
+/// The parent directory is watched, as files written atomically are replaced by a rename,
+/// which would end a watch on the file itself.
+pub struct FileWatch {
+    fd: OwnedFd,
+    name: OsString,
+}
+
+impl FileWatch {
+    pub fn new(path: &Path) -> Result<FileWatch, anyhow::Error> {
+        let (dir, name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => (dir, name),
+            _ => {
+                return Err(anyhow::Error::msg(format!(
+                    "cannot watch {}: not a file path",
+                    path.display()
+                )))
+            }
+        };
+        let dir = match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        };
+        let raw = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if raw < 0 {
+            return Err(std::io::Error::last_os_error()).context("cannot create inotify instance");
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+        let c_dir = CString::new(dir.as_os_str().as_bytes()).context("invalid path")?;
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_dir.as_ptr(), mask) } < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("cannot watch directory {}", dir.display()));
+        }
+        Ok(FileWatch {
+            fd,
+            name: name.to_os_string(),
+        })
+    }
+
+    /// Whether the file was written or replaced since the last call.
+    pub fn changed(&self) -> Result<bool, anyhow::Error> {
+        let mut changed = false;
+        let mut buffer = [0_u8; 4096];
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+            if read < 0 {
+                let error = std::io::Error::last_os_error();
+                return match error.kind() {
+                    std::io::ErrorKind::WouldBlock => Ok(changed),
+                    std::io::ErrorKind::Interrupted => continue,
+                    _ => Err(error).context("cannot read inotify events"),
+                };
+            }
+            changed |= event_names(&buffer[..read as usize]).any(|name| name == self.name);
+        }
+    }
+}
+
+/// Readable when the watched directory changed, for the daemon to wait on it.
+impl AsRawFd for FileWatch {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Names in a buffer of `inotify_event` structs, which are followed by their nul padded name.
+fn event_names(mut buffer: &[u8]) -> impl Iterator<Item = &OsStr> {
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+    std::iter::from_fn(move || {
+        let len_bytes = buffer.get(HEADER - 4..HEADER)?;
+        let len = u32::from_ne_bytes(len_bytes.try_into().ok()?) as usize;
+        let name = buffer.get(HEADER..HEADER + len)?;
+        buffer = &buffer[HEADER + len..];
+        let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        Some(OsStr::from_bytes(&name[..end]))
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn test_file_watch() {
+    let dir = std::env::temp_dir().join(format!("slam-test-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("database.json");
+    let watch = FileWatch::new(&path).unwrap();
+    let unchanged = watch.changed().unwrap();
+    std::fs::write(dir.join("other.json"), "[]").unwrap();
+    let other = watch.changed().unwrap();
+    std::fs::write(dir.join("database.json.tmp"), "[]").unwrap();
+    std::fs::rename(dir.join("database.json.tmp"), &path).unwrap();
+    let replaced = watch.changed().unwrap();
+    std::fs::write(&path, "[ ]").unwrap();
+    let written = watch.changed().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(
+        (unchanged, other, replaced, written),
+        (false, false, true, true)
+    );
+}
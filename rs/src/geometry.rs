@@ -1,9 +1,12 @@
 use crate::relation::InvertibleRelation;
-use std::ops::{Add, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
 
 /// Trigonometric orientation (anti-clockwise)
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[derive(
+    Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
 pub enum Rotation {
+    #[default]
     R0 = 0,
     R90 = 1,
     R180 = 2,
@@ -15,11 +18,6 @@ impl std::fmt::Debug for Rotation {
         ((*self as isize) * 90).fmt(f)
     }
 }
-impl Default for Rotation {
-    fn default() -> Rotation {
-        Rotation::R0
-    }
-}
 impl Rotation {
     fn rotate(&self, r: Rotation) -> Rotation {
         // Using mod 4 arithmetic for efficiency
@@ -92,6 +90,54 @@ impl Transform {
     }
 }
 
+/// xrandr names : `normal`, `left`, `inverted`, `right` for rotations, and `reflect-x`, `reflect-y`.
+/// Names are separated by commas, like `left,reflect-x`.
+/// As with xrandr `--rotate` and `--reflect`, reflections are applied before the rotation whatever the order.
+impl std::str::FromStr for Transform {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Transform, String> {
+        let mut rotation = None;
+        let mut transform = Transform::default();
+        for name in s.split(',') {
+            let r = match name {
+                "normal" => Rotation::R0,
+                "left" => Rotation::R90,
+                "inverted" => Rotation::R180,
+                "right" => Rotation::R270,
+                "reflect-x" => {
+                    transform = transform.reflect_x();
+                    continue;
+                }
+                "reflect-y" => {
+                    transform = transform.reflect_y();
+                    continue;
+                }
+                _ => return Err(format!("invalid transform: {}", name)),
+            };
+            if rotation.replace(r).is_some() {
+                return Err(format!("multiple rotations: {}", s));
+            }
+        }
+        Ok(transform.rotate(rotation.unwrap_or_default()))
+    }
+}
+
+/// xrandr names, parsed back by [`std::str::FromStr`] : `normal`, `left,reflect-x`, ...
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self.rotation {
+            Rotation::R0 => "normal",
+            Rotation::R90 => "left",
+            Rotation::R180 => "inverted",
+            Rotation::R270 => "right",
+        })?;
+        if self.reflect {
+            f.write_str(",reflect-x")?
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 #[test]
 fn test_transform() {
@@ -105,12 +151,37 @@ fn test_transform() {
         Transform::default().rotate(Rotation::R90).reflect_y(),
         Transform::default().rotate(Rotation::R270).reflect_x()
     );
+    // xrandr names
+    let parse = |s: &str| s.parse::<Transform>();
+    assert_eq!(parse("normal"), Ok(Transform::default()));
+    assert_eq!(
+        parse("left"),
+        Ok(Transform::default().rotate(Rotation::R90))
+    );
+    assert_eq!(parse("reflect-x,left"), parse("left,reflect-x"));
+    assert_eq!(
+        parse("reflect-x,reflect-y"),
+        Ok(Transform::default().rotate(Rotation::R180))
+    );
+    assert!(parse("left,right").is_err());
+    assert!(parse("upside-down").is_err());
+    for rotation in [Rotation::R0, Rotation::R90, Rotation::R180, Rotation::R270] {
+        for transform in [
+            Transform::default().rotate(rotation),
+            Transform::default().reflect_x().rotate(rotation),
+        ] {
+            assert_eq!(parse(&transform.to_string()), Ok(transform))
+        }
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 
 /// Tag for relative positionning of monitor outputs.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
 pub enum Direction {
     LeftOf,
     RightOf,
@@ -129,6 +200,23 @@ impl InvertibleRelation for Direction {
     }
 }
 
+/// Relation between the rects of two outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Adjacent(Direction),
+    /// Occupies the same rect, like cloned outputs.
+    SameAs,
+}
+
+impl InvertibleRelation for Relation {
+    fn inverse(&self) -> Relation {
+        match self {
+            Relation::Adjacent(direction) => Relation::Adjacent(direction.inverse()),
+            Relation::SameAs => Relation::SameAs,
+        }
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 
 /// Generic pair type.
@@ -153,6 +241,9 @@ pub struct Vec2d<T> {
     pub y: T,
 }
 
+/// Signed integer vector, used for pixel coordinates.
+pub type Vec2di = Vec2d<i32>;
+
 impl<T> Vec2d<T> {
     pub fn new(x: T, y: T) -> Self {
         Vec2d { x, y }
@@ -165,12 +256,22 @@ impl<T> Vec2d<T> {
         }
     }
 
+    /// Apply `f` to both components, like a conversion `v.map(f64::from)`.
     pub fn map<U, F: Fn(T) -> U>(self, f: F) -> Vec2d<U> {
         Vec2d {
             x: f(self.x),
             y: f(self.y),
         }
     }
+
+    /// Apply a fallible conversion to both components, like `v.try_map(u16::try_from)`.
+    /// Fails with the first error, instead of panicking on a cast.
+    pub fn try_map<U, E, F: Fn(T) -> Result<U, E>>(self, f: F) -> Result<Vec2d<U>, E> {
+        Ok(Vec2d {
+            x: f(self.x)?,
+            y: f(self.y)?,
+        })
+    }
 }
 
 impl<T: Add> Add for Vec2d<T> {
@@ -193,6 +294,13 @@ impl<T: Sub> Sub for Vec2d<T> {
     }
 }
 
+impl<T: AddAssign> AddAssign for Vec2d<T> {
+    fn add_assign(&mut self, rhs: Vec2d<T>) {
+        self.x += rhs.x;
+        self.y += rhs.y
+    }
+}
+
 impl<T: SubAssign> SubAssign for Vec2d<T> {
     fn sub_assign(&mut self, rhs: Vec2d<T>) {
         self.x -= rhs.x;
@@ -200,6 +308,28 @@ impl<T: SubAssign> SubAssign for Vec2d<T> {
     }
 }
 
+/// Scalar multiplication.
+impl<T: Mul + Copy> Mul<T> for Vec2d<T> {
+    type Output = Vec2d<T::Output>;
+    fn mul(self, rhs: T) -> Self::Output {
+        Vec2d {
+            x: self.x * rhs,
+            y: self.y * rhs,
+        }
+    }
+}
+
+/// Scalar division.
+impl<T: Div + Copy> Div<T> for Vec2d<T> {
+    type Output = Vec2d<T::Output>;
+    fn div(self, rhs: T) -> Self::Output {
+        Vec2d {
+            x: self.x / rhs,
+            y: self.y / rhs,
+        }
+    }
+}
+
 impl<T: Ord> Vec2d<T> {
     /// Component-wise min
     pub fn cwise_min(self, rhs: Vec2d<T>) -> Vec2d<T> {
@@ -222,7 +352,7 @@ impl<T: Ord> Vec2d<T> {
 /// `x` axis is from left to right. `y` axis is from bottom to top.
 /// The rectangle covers pixels in `[bl.x, bl.x+size.x[ X [bl.y, bl.y+size.y[`.
 /// Top and right sides are excluded.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub struct Rect {
     pub bottom_left: Vec2d<i32>,
     pub size: Vec2d<u32>,
@@ -252,13 +382,49 @@ impl Rect {
         self.bottom_left + Vec2d::new(self.size.x as i32, (self.size.y / 2) as i32)
     }
 
-    fn offset(&self, delta: Vec2d<i32>) -> Rect {
+    /// Same size, translated by `delta`.
+    pub fn offset(&self, delta: Vec2d<i32>) -> Rect {
         Rect {
             bottom_left: self.bottom_left + delta,
             size: self.size,
         }
     }
 
+    /// Number of covered pixels.
+    pub fn area(&self) -> u64 {
+        u64::from(self.size.x) * u64::from(self.size.y)
+    }
+
+    /// Is `point` a pixel covered by `self` ? Top and right sides are excluded.
+    pub fn contains_point(&self, point: Vec2d<i32>) -> bool {
+        let top_right = self.top_right();
+        (self.bottom_left.x..top_right.x).contains(&point.x)
+            && (self.bottom_left.y..top_right.y).contains(&point.y)
+    }
+
+    /// Pixels covered by both `self` and `other`, or [`None`] if they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let bottom_left = Vec2d::cwise_max(self.bottom_left, other.bottom_left);
+        let top_right = Vec2d::cwise_min(self.top_right(), other.top_right());
+        match bottom_left.x < top_right.x && bottom_left.y < top_right.y {
+            true => Some(Rect {
+                bottom_left,
+                size: (top_right - bottom_left).map(|i| i as u32),
+            }),
+            false => None,
+        }
+    }
+
+    /// Smallest rect covering both `self` and `other`.
+    pub fn union_bounds(&self, other: &Rect) -> Rect {
+        let bottom_left = Vec2d::cwise_min(self.bottom_left, other.bottom_left);
+        let top_right = Vec2d::cwise_max(self.top_right(), other.top_right());
+        Rect {
+            bottom_left,
+            size: (top_right - bottom_left).map(|i| i as u32),
+        }
+    }
+
     /// Does `self` overlaps `other` ?
     pub fn overlaps(&self, other: &Rect) -> bool {
         // It is easier to determine if there is NO overlap : the other rect must be entirely on one side.
@@ -275,10 +441,12 @@ impl Rect {
     pub fn adjacent_direction(&self, rhs: &Rect) -> Option<Direction> {
         let lhs = self;
         let size_max = Vec2d::cwise_max(lhs.size, rhs.size);
-        let is_adjacent_x =
-            |l: Vec2d<i32>, r: Vec2d<i32>| l.x == r.x && 2 * (l.y - r.y).abs() as u32 <= size_max.y;
-        let is_adjacent_y =
-            |l: Vec2d<i32>, r: Vec2d<i32>| l.y == r.y && 2 * (l.x - r.x).abs() as u32 <= size_max.x;
+        let is_adjacent_x = |l: Vec2d<i32>, r: Vec2d<i32>| {
+            l.x == r.x && 2 * (l.y - r.y).unsigned_abs() <= size_max.y
+        };
+        let is_adjacent_y = |l: Vec2d<i32>, r: Vec2d<i32>| {
+            l.y == r.y && 2 * (l.x - r.x).unsigned_abs() <= size_max.x
+        };
         if is_adjacent_x(lhs.center_right(), rhs.center_left()) {
             return Some(Direction::LeftOf);
         }
@@ -293,6 +461,68 @@ impl Rect {
         }
         None
     }
+
+    /// Relation of `lhs` to `rhs` : same rect, or adjacent (see [`Rect::adjacent_direction`]).
+    pub fn relation(&self, rhs: &Rect) -> Option<Relation> {
+        match self == rhs {
+            true => Some(Relation::SameAs),
+            false => self.adjacent_direction(rhs).map(Relation::Adjacent),
+        }
+    }
+
+    /// Like [`Rect::adjacent_direction`], but rects must be separated by exactly `gap` instead of touching.
+    /// A negative `gap` is an overlap of the rects along the adjacent edge.
+    pub fn adjacent_direction_with_gap(&self, rhs: &Rect, gap: i32) -> Option<Direction> {
+        [
+            (Direction::LeftOf, Vec2d::new(-gap, 0)),
+            (Direction::RightOf, Vec2d::new(gap, 0)),
+            (Direction::Under, Vec2d::new(0, -gap)),
+            (Direction::Above, Vec2d::new(0, gap)),
+        ]
+        .into_iter()
+        .find(|(direction, shift)| self.adjacent_direction(&rhs.offset(*shift)) == Some(*direction))
+        .map(|(direction, _)| direction)
+    }
+
+    /// Like [`Rect::adjacent_direction_with_gap`], if `rhs` is at most `tolerance` pixels off across the common edge.
+    /// Also returns the smallest shift of `rhs` making them exactly adjacent.
+    pub fn adjacent_direction_with_tolerance(
+        &self,
+        rhs: &Rect,
+        gap: i32,
+        tolerance: u32,
+    ) -> Option<(Direction, Vec2d<i32>)> {
+        let (lhs_min, lhs_max) = (self.bottom_left, self.top_right());
+        let (rhs_min, rhs_max) = (rhs.bottom_left, rhs.top_right());
+        let shifts = [
+            Vec2d::new(lhs_max.x + gap - rhs_min.x, 0),
+            Vec2d::new(lhs_min.x - gap - rhs_max.x, 0),
+            Vec2d::new(0, lhs_max.y + gap - rhs_min.y),
+            Vec2d::new(0, lhs_min.y - gap - rhs_max.y),
+        ];
+        let length = |shift: &Vec2d<i32>| shift.x.unsigned_abs() + shift.y.unsigned_abs();
+        (shifts.into_iter())
+            .filter(|shift| length(shift) <= tolerance)
+            .filter_map(|shift| {
+                let direction = self.adjacent_direction_with_gap(&rhs.offset(shift), gap)?;
+                Some((direction, shift))
+            })
+            .min_by_key(|(_, shift)| length(shift))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_vec2d() {
+    let v = Vec2d::new(1920_i32, 1080);
+    assert_eq!(v * 2, Vec2d::new(3840, 2160));
+    assert_eq!(v / 2, Vec2d::new(960, 540));
+    assert_eq!(v.map(f64::from) * 0.5, Vec2d::new(960., 540.));
+    let mut sum = v;
+    sum += Vec2d::new(1, 2);
+    assert_eq!(sum, Vec2d::new(1921, 1082));
+    assert_eq!(v.try_map(u16::try_from), Ok(Vec2d::new(1920_u16, 1080)));
+    assert!(Vec2d::new(-1, 40000).try_map(u16::try_from).is_err());
 }
 
 #[cfg(test)]
@@ -319,7 +549,21 @@ fn test_overlaps() {
     assert!(main.overlaps(&main.offset(Vec2d::new(-1919, 0))));
     assert!(main.overlaps(&main.offset(Vec2d::new(200, 0))));
     assert!(main.overlaps(&main.offset(Vec2d::new(0, 1079))));
-    assert!(main.overlaps(&main))
+    assert!(main.overlaps(&main));
+    // Intersection and bounds
+    let shifted = main.offset(Vec2d::new(1000, 500));
+    let common = main.intersection(&shifted).unwrap();
+    assert_eq!(common.bottom_left, Vec2d::new(1000, 500));
+    assert_eq!(common.size, Vec2d::new(920, 580));
+    assert_eq!(common.area(), 920 * 580);
+    assert_eq!(main.intersection(&main.offset(Vec2d::new(1920, 0))), None);
+    let bounds = main.union_bounds(&shifted);
+    assert_eq!(bounds.bottom_left, Vec2d::new(0, 0));
+    assert_eq!(bounds.size, Vec2d::new(2920, 1580));
+    assert!(main.contains_point(Vec2d::new(0, 0)));
+    assert!(main.contains_point(Vec2d::new(1919, 1079)));
+    assert!(!main.contains_point(Vec2d::new(1920, 0)));
+    assert!(!main.contains_point(Vec2d::new(-1, 0)))
 }
 
 #[cfg(test)]
@@ -369,4 +613,11 @@ fn test_direction() {
         Some(Direction::Above)
     );
     assert_eq!(Rect::adjacent_direction(&at_right, &smaller_below), None);
+    // Clones
+    assert_eq!(Rect::relation(&primary, &primary), Some(Relation::SameAs));
+    assert_eq!(
+        Rect::relation(&primary, &at_right),
+        Some(Relation::Adjacent(Direction::LeftOf))
+    );
+    assert_eq!(Relation::SameAs.inverse(), Relation::SameAs);
 }
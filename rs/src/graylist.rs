@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Tracks consecutive failures of items (outputs), to stop using flaky ones for a while.
+/// After `max_failures` consecutive failures, an item is *gray-listed* for `cooldown`.
+/// A success resets the failure count.
+#[derive(Debug)]
+pub struct GrayList<K> {
+    max_failures: u32,
+    cooldown: Duration,
+    entries: HashMap<K, FailureState>,
+}
+
+#[derive(Debug, Default)]
+struct FailureState {
+    consecutive_failures: u32,
+    grayed_until: Option<Instant>,
+}
+
+impl<K: Hash + Eq> GrayList<K> {
+    pub fn new(max_failures: u32, cooldown: Duration) -> Self {
+        assert!(max_failures > 0);
+        GrayList {
+            max_failures,
+            cooldown,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns true if this failure caused `key` to be gray-listed.
+    pub fn record_failure(&mut self, key: K, now: Instant) -> bool {
+        let state = self.entries.entry(key).or_default();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.max_failures {
+            state.consecutive_failures = 0;
+            state.grayed_until = Some(now + self.cooldown);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn record_success(&mut self, key: &K) {
+        self.entries.remove(key);
+    }
+
+    pub fn is_grayed(&self, key: &K, now: Instant) -> bool {
+        match self.entries.get(key).and_then(|s| s.grayed_until) {
+            Some(until) => now < until,
+            None => false,
+        }
+    }
+
+    pub fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_gray_list() {
+    let start = Instant::now();
+    let cooldown = Duration::from_secs(60);
+    let mut list = GrayList::new(3, cooldown);
+    assert!(!list.record_failure("a", start));
+    assert!(!list.record_failure("a", start));
+    // success resets count
+    list.record_success(&"a");
+    assert!(!list.record_failure("a", start));
+    assert!(!list.record_failure("a", start));
+    assert!(!list.is_grayed(&"a", start));
+    assert!(list.record_failure("a", start));
+    assert!(list.is_grayed(&"a", start));
+    assert!(!list.is_grayed(&"b", start));
+    // cooldown expiration
+    assert!(list.is_grayed(&"a", start + cooldown / 2));
+    assert!(!list.is_grayed(&"a", start + cooldown));
+}
@@ -0,0 +1,231 @@
+use crate::layout::{Layout, OutputState};
+use anyhow::Context;
+use std::process::Command;
+
+/// Layout changes handled by the daemon, reported to hooks and notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutEvent {
+    /// A layout from the database was applied to a new set of outputs.
+    Applied,
+    /// A layout change made by another tool was stored in the database.
+    Stored,
+}
+
+impl LayoutEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LayoutEvent::Applied => "applied",
+            LayoutEvent::Stored => "stored",
+        }
+    }
+}
+
+impl std::fmt::Display for LayoutEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+impl std::str::FromStr for LayoutEvent {
+    type Err = String;
+    fn from_str(s: &str) -> Result<LayoutEvent, String> {
+        match s {
+            "applied" => Ok(LayoutEvent::Applied),
+            "stored" => Ok(LayoutEvent::Stored),
+            _ => Err(format!("unknown event '{}', expected: applied stored", s)),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// User commands run on layout events, with `sh -c`.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Run before the daemon applies a layout, with the layout to apply. Failures do not prevent the switch.
+    pub preswitch: Vec<String>,
+    /// Run after the daemon switched to or stored a layout.
+    pub postswitch: Vec<String>,
+}
+
+impl HooksConfig {
+    pub fn preswitch_invocations(&self, layout: &Layout) -> Vec<HookInvocation> {
+        invocations(&self.preswitch, LayoutEvent::Applied, layout)
+    }
+
+    pub fn postswitch_invocations(
+        &self,
+        event: LayoutEvent,
+        layout: &Layout,
+    ) -> Vec<HookInvocation> {
+        invocations(&self.postswitch, event, layout)
+    }
+}
+
+fn invocations(commands: &[String], event: LayoutEvent, layout: &Layout) -> Vec<HookInvocation> {
+    let environment = event_environment(event, layout);
+    Vec::from_iter(commands.iter().map(|command| HookInvocation {
+        command: command.clone(),
+        environment: environment.clone(),
+    }))
+}
+
+/// A hook command with the environment describing the event.
+#[derive(Debug, Clone)]
+pub struct HookInvocation {
+    pub command: String,
+    pub environment: Vec<(&'static str, String)>,
+}
+
+impl HookInvocation {
+    /// Run the command to completion. Non-zero exit codes are errors.
+    pub fn run(&self) -> Result<(), anyhow::Error> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .envs(self.environment.iter().map(|(k, v)| (k, v)))
+            .status()
+            .with_context(|| format!("cannot start hook '{}'", self.command))?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(anyhow::Error::msg(format!(
+                "hook '{}' failed: {}",
+                self.command, status
+            ))),
+        }
+    }
+}
+
+/// Shell-like representation, useful to replay the hook by hand.
+impl std::fmt::Display for HookInvocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in &self.environment {
+            write!(f, "{}={} ", key, shell_quote(value))?
+        }
+        write!(f, "sh -c {}", shell_quote(&self.command))
+    }
+}
+
+/// Single quote `s` for `sh`, where `'` must end the quoted string : `it's` becomes `'it'\''s'`.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Variables given to hook commands:
+/// - `SLAM_EVENT` : event name (see [`LayoutEvent`]).
+/// - `SLAM_OUTPUTS` : space separated list of connected output ids.
+/// - `SLAM_ENABLED_OUTPUTS` : same, only for enabled outputs.
+/// - `SLAM_PRIMARY` : primary output id, if any.
+/// - `SLAM_RESOLUTION` : size of the screen containing all enabled outputs, as `WxH`.
+/// - `SLAM_FONT_DPI` : font DPI of the layout, if set.
+fn event_environment(event: LayoutEvent, layout: &Layout) -> Vec<(&'static str, String)> {
+    let ids_to_string = |enabled_only: bool| {
+        let ids = layout
+            .output_entries()
+            .iter()
+            .filter(|entry| !enabled_only || matches!(entry.state, OutputState::Enabled { .. }))
+            .map(|entry| entry.id.to_string());
+        Vec::from_iter(ids).join(" ")
+    };
+    let mut environment = vec![
+        ("SLAM_EVENT", event.name().to_string()),
+        ("SLAM_OUTPUTS", ids_to_string(false)),
+        ("SLAM_ENABLED_OUTPUTS", ids_to_string(true)),
+    ];
+    if let Some(primary) = layout.primary() {
+        environment.push(("SLAM_PRIMARY", primary.to_string()))
+    }
+    let size = layout.bounding_rect_size();
+    environment.push(("SLAM_RESOLUTION", format!("{}x{}", size.x, size.y)));
+    if let Some(dpi) = layout.font_dpi() {
+        environment.push(("SLAM_FONT_DPI", dpi.to_string()))
+    }
+    environment
+}
+
+#[cfg(test)]
+#[test]
+fn test_postswitch_invocations() {
+    use crate::geometry::Vec2d;
+    use crate::layout::{LayoutInfo, OutputEntry, OutputId};
+    let layout = LayoutInfo::from_iter(
+        [
+            OutputEntry::enabled_for_test("a", Vec2d::new(1920, 1080), Vec2d::new(0, 0)),
+            OutputEntry {
+                id: OutputId::Name("b".into()),
+                state: OutputState::Disabled { reason: None },
+            },
+        ],
+        None,
+    )
+    .layout;
+    let config = HooksConfig {
+        preswitch: Vec::new(),
+        postswitch: vec!["true".into(), "exit 3".into()],
+    };
+    let hooks = config.postswitch_invocations(LayoutEvent::Stored, &layout);
+    assert_eq!(hooks.len(), 2);
+    assert_eq!(
+        hooks[0].environment[..3],
+        [
+            ("SLAM_EVENT", "stored".to_string()),
+            ("SLAM_OUTPUTS", "a b".to_string()),
+            ("SLAM_ENABLED_OUTPUTS", "a".to_string()),
+        ]
+    );
+    assert!(hooks[0].run().is_ok());
+    assert!(hooks[1].run().is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_event_environment() {
+    use crate::geometry::Vec2d;
+    use crate::layout::{LayoutInfo, OutputEntry, OutputId};
+    let enabled = |name: &str, x| {
+        OutputEntry::enabled_for_test(name, Vec2d::new(1920, 1080), Vec2d::new(x, 0))
+    };
+    let layout = LayoutInfo::from_iter(
+        [
+            enabled("a", 0),
+            enabled("b", 1920),
+            OutputEntry {
+                id: OutputId::Name("c".into()),
+                state: OutputState::Disabled { reason: None },
+            },
+        ],
+        Some(OutputId::Name("a".into())),
+    )
+    .layout;
+    let config = HooksConfig {
+        preswitch: vec!["true".into()],
+        postswitch: Vec::new(),
+    };
+    let hooks = config.preswitch_invocations(&layout);
+    let variable = |name| {
+        let (_, value) = hooks[0].environment.iter().find(|(k, _)| *k == name)?;
+        Some(value.as_str())
+    };
+    assert_eq!(variable("SLAM_EVENT"), Some("applied"));
+    assert_eq!(variable("SLAM_ENABLED_OUTPUTS"), Some("a b"));
+    assert_eq!(variable("SLAM_PRIMARY"), Some("a"));
+    assert_eq!(variable("SLAM_RESOLUTION"), Some("3840x1080"));
+    assert_eq!(variable("SLAM_FONT_DPI"), None);
+}
+
+#[cfg(test)]
+#[test]
+fn test_invocation_display() {
+    let hook = HookInvocation {
+        command: "notify-send 'layout changed'".into(),
+        environment: vec![
+            ("SLAM_EVENT", "applied".into()),
+            ("SLAM_PRIMARY", "it's".into()),
+        ],
+    };
+    assert_eq!(
+        hook.to_string(),
+        r"SLAM_EVENT='applied' SLAM_PRIMARY='it'\''s' sh -c 'notify-send '\''layout changed'\'''"
+    );
+}
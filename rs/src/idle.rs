@@ -0,0 +1,219 @@
+use crate::layout::{DisabledReason, Layout, LayoutInfo, OutputId, OutputState};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::process::Command;
+use std::time::Duration;
+
+/// Idle time check period while outputs are powered down, to restore them quickly on activity.
+const ACTIVITY_POLL_PERIOD: Duration = Duration::from_secs(1);
+
+/// Power-down of external outputs when the user is idle.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdleConfig {
+    /// Idle time in seconds before powering down.
+    pub after: u64,
+    #[serde(default)]
+    pub method: IdleMethod,
+    /// Names of outputs kept enabled by [`IdleMethod::Disable`]. Default is internal panels (`eDP`, `LVDS`, `DSI`).
+    #[serde(default)]
+    pub keep: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleMethod {
+    /// Disable outputs in the layout. The layout is restored on activity.
+    #[default]
+    Disable,
+    /// Turn all monitors off with `xset dpms force off`. The X server turns them back on.
+    Dpms,
+}
+
+/// Idle time of the X session, from the `xprintidle` command.
+/// This avoids a dependency on the xcb screensaver extension, and works without the xcb backend.
+pub fn idle_time() -> Result<Duration, anyhow::Error> {
+    let output = Command::new("xprintidle")
+        .output()
+        .with_context(|| "cannot run xprintidle")?;
+    if !output.status.success() {
+        return Err(anyhow::Error::msg(format!(
+            "xprintidle failed: {}",
+            output.status
+        )));
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let milliseconds: u64 = text
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid xprintidle output: {}", text.trim()))?;
+    Ok(Duration::from_millis(milliseconds))
+}
+
+/// `layout` with enabled outputs disabled for [`DisabledReason::PowerSaving`], except those named in `keep`.
+/// Returns [`None`] if no output would be disabled, or if all would be.
+pub fn powered_down_layout(
+    layout: &Layout,
+    names: &HashMap<OutputId, String>,
+    keep: &[String],
+) -> Option<Layout> {
+    let is_kept = |id: &OutputId| match names.get(id) {
+        Some(name) if keep.is_empty() => ["eDP", "LVDS", "DSI"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix)),
+        Some(name) => keep.contains(name),
+        None => false,
+    };
+    let mut entries = layout.output_entries().to_vec();
+    let (mut disabled, mut kept) = (0, 0);
+    for entry in entries.iter_mut() {
+        if let OutputState::Enabled { .. } = entry.state {
+            match is_kept(&entry.id) {
+                true => kept += 1,
+                false => {
+                    entry.state = OutputState::Disabled {
+                        reason: Some(DisabledReason::PowerSaving),
+                    };
+                    disabled += 1
+                }
+            }
+        }
+    }
+    if disabled == 0 || kept == 0 {
+        return None;
+    }
+    let primary = layout.primary().filter(|id| is_kept(id)).cloned();
+    let info = LayoutInfo::from(entries, primary).with_bezels(layout.bezels().to_vec());
+    let mut powered_down = info.layout;
+    powered_down.copy_output_settings(layout);
+    Some(powered_down)
+}
+
+/// Idle power-down state of the daemon.
+pub struct IdlePowerDown<'c> {
+    config: &'c IdleConfig,
+    /// Layout to restore on activity, when powered down with [`IdleMethod::Disable`].
+    saved_layout: Option<Layout>,
+    powered_down: bool,
+    last_idle_time: Duration,
+    /// Disabled after an idle time query failure, to avoid repeating errors.
+    failed: bool,
+}
+
+impl<'c> IdlePowerDown<'c> {
+    pub fn new(config: &'c IdleConfig) -> Self {
+        IdlePowerDown {
+            config,
+            saved_layout: None,
+            powered_down: false,
+            last_idle_time: Duration::ZERO,
+            failed: false,
+        }
+    }
+
+    pub fn is_powered_down(&self) -> bool {
+        self.powered_down
+    }
+
+    /// Time until the next call to [`IdlePowerDown::check`] is needed.
+    pub fn timeout(&self) -> Option<Duration> {
+        let threshold = Duration::from_secs(self.config.after);
+        match (self.failed, self.powered_down) {
+            (true, _) => None,
+            (false, true) => Some(ACTIVITY_POLL_PERIOD),
+            (false, false) => Some(
+                threshold
+                    .saturating_sub(self.last_idle_time)
+                    .max(ACTIVITY_POLL_PERIOD),
+            ),
+        }
+    }
+
+    /// Forget the power-down state after a layout change by the user or hotplug.
+    pub fn forget(&mut self) {
+        self.saved_layout = None;
+        self.powered_down = false
+    }
+
+    /// Layout to restore when the daemon stops while outputs are powered down.
+    pub fn restore(&mut self) -> Option<Layout> {
+        self.powered_down = false;
+        self.saved_layout.take()
+    }
+
+    /// Query idle time and power down or restore outputs.
+    /// Returns a layout to apply : the powered down one, or the saved one on activity.
+    pub fn check(&mut self, layout: &Layout, names: &HashMap<OutputId, String>) -> Option<Layout> {
+        let idle_time = match idle_time() {
+            Ok(idle_time) => idle_time,
+            Err(e) => {
+                log::error!("{:#}, idle power-down disabled", e);
+                self.failed = true;
+                return None;
+            }
+        };
+        let had_activity = idle_time < self.last_idle_time;
+        self.last_idle_time = idle_time;
+        if self.powered_down {
+            if !had_activity {
+                return None;
+            }
+            log::info!("activity after idle power-down: restoring outputs");
+            self.powered_down = false;
+            return self.saved_layout.take();
+        }
+        if idle_time < Duration::from_secs(self.config.after) {
+            return None;
+        }
+        match self.config.method {
+            IdleMethod::Disable => {
+                let powered_down = powered_down_layout(layout, names, &self.config.keep)?;
+                log::info!("idle: disabling external outputs");
+                self.saved_layout = Some(layout.clone());
+                self.powered_down = true;
+                Some(powered_down)
+            }
+            IdleMethod::Dpms => {
+                log::info!("idle: turning monitors off");
+                let status = Command::new("xset").args(["dpms", "force", "off"]).status();
+                match status {
+                    Ok(status) if status.success() => self.powered_down = true,
+                    Ok(status) => log::error!("xset failed: {}", status),
+                    Err(e) => log::error!("cannot run xset: {}", e),
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_powered_down_layout() {
+    use crate::geometry::Vec2d;
+    use crate::layout::OutputEntry;
+    let enabled = |name: &str, x| {
+        OutputEntry::enabled_for_test(name, Vec2d::new(1920, 1080), Vec2d::new(x, 0))
+    };
+    let (laptop, external) = (OutputId::Name("a".into()), OutputId::Name("b".into()));
+    let info = LayoutInfo::from_iter(
+        [enabled("a", 0), enabled("b", 1920)],
+        Some(external.clone()),
+    );
+    let names = HashMap::from([
+        (laptop.clone(), "eDP-1".to_string()),
+        (external.clone(), "DP-1".to_string()),
+    ]);
+    let powered_down = powered_down_layout(&info.layout, &names, &[]).unwrap();
+    let states = powered_down.output_entries();
+    assert!(states[0].state.rect().is_some());
+    assert_eq!(
+        states[1].state.disabled_reason(),
+        Some(DisabledReason::PowerSaving)
+    );
+    assert_eq!(powered_down.primary(), None);
+    // Explicit kept outputs, never disable everything
+    let kept = powered_down_layout(&info.layout, &names, &["DP-1".into()]).unwrap();
+    assert!(kept.output_entries()[1].state.rect().is_some());
+    assert!(powered_down_layout(&info.layout, &HashMap::new(), &[]).is_none());
+}
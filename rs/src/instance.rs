@@ -0,0 +1,74 @@
+use anyhow::Context;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Display served by the daemon : `$DISPLAY` for X, `$WAYLAND_DISPLAY` for Wayland, or the console for DRM.
+pub fn current_display() -> String {
+    std::env::var("WAYLAND_DISPLAY")
+        .or_else(|_| std::env::var("DISPLAY"))
+        .unwrap_or_else(|_| "console".into())
+}
+
+/// Exclusive `flock` held by the only daemon of a display, released when the process exits.
+/// Two daemons on the same display would fight over layouts and write the database twice.
+pub struct InstanceLock {
+    _file: std::fs::File,
+}
+
+impl InstanceLock {
+    /// Lock `slam-<display>.lock` in `dir`, failing immediately if another daemon holds it.
+    pub fn acquire(dir: &Path, display: &str) -> Result<InstanceLock, anyhow::Error> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("cannot create directory {}", dir.display()))?;
+        let path = lock_path(dir, display);
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("cannot open lock file {}", path.display()))?;
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+                return Ok(InstanceLock { _file: file });
+            }
+            let e = std::io::Error::last_os_error();
+            match e.kind() {
+                std::io::ErrorKind::Interrupted => continue,
+                std::io::ErrorKind::WouldBlock => {
+                    return Err(anyhow::Error::msg(format!(
+                        "another slam daemon is running for display {} (lock {})",
+                        display,
+                        path.display()
+                    )))
+                }
+                _ => return Err(e).with_context(|| format!("cannot lock {}", path.display())),
+            }
+        }
+    }
+}
+
+/// Wayland display names may be absolute socket paths.
+fn lock_path(dir: &Path, display: &str) -> PathBuf {
+    dir.join(format!("slam-{}.lock", display.replace('/', "_")))
+}
+
+#[cfg(test)]
+#[test]
+fn test_instance_lock() {
+    let dir = std::env::temp_dir().join(format!("slam-test-instance-{}", std::process::id()));
+    assert_eq!(
+        lock_path(&dir, "/run/user/1000/wayland-1"),
+        dir.join("slam-_run_user_1000_wayland-1.lock")
+    );
+    let first = InstanceLock::acquire(&dir, ":0");
+    let second = InstanceLock::acquire(&dir, ":0");
+    let other_display = InstanceLock::acquire(&dir, ":1");
+    let first_locked = first.is_ok();
+    drop(first);
+    let after_release = InstanceLock::acquire(&dir, ":0");
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(first_locked);
+    assert!(second.is_err());
+    assert!(other_display.is_ok());
+    assert!(after_release.is_ok());
+}
@@ -0,0 +1,274 @@
+use crate::export::{transform_from_wayland, SWAY_TRANSFORMS};
+use crate::geometry::{Transform, Vec2d};
+use crate::layout::{Layout, LayoutInfo, Mode, OutputEntry, OutputId, OutputState, Scale};
+
+/// Default location of the kanshi configuration, in the user config directory.
+pub const DEFAULT_CONFIG: &str = "kanshi/config";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+    Newline,
+}
+
+/// Split kanshi configuration text into words, quoted strings, braces and newlines. Comments are dropped.
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => tokens.push(Token::Newline),
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '#' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quoted string".into()),
+                    }
+                }
+                tokens.push(Token::Word(word))
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{}#\"".contains(*c)) {
+                    word.push(c)
+                }
+                tokens.push(Token::Word(word))
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Profile as written in the configuration, before conversion to a [`Layout`].
+#[derive(Debug)]
+struct Profile {
+    name: Option<String>,
+    /// Words of each `output` directive, without the directive name.
+    outputs: Vec<Vec<String>>,
+}
+
+fn parse_profiles(tokens: &[Token]) -> Result<Vec<Profile>, String> {
+    let mut profiles = Vec::new();
+    let mut tokens = tokens.iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Newline => (),
+            Token::Word(word) if word == "profile" => {
+                let name = match tokens.next() {
+                    Some(Token::Word(name)) => match tokens.next() {
+                        Some(Token::Open) => Some(name.clone()),
+                        _ => return Err(format!("expected '{{' after profile {}", name)),
+                    },
+                    Some(Token::Open) => None,
+                    _ => return Err("expected profile name or '{'".into()),
+                };
+                let mut profile = Profile {
+                    name,
+                    outputs: Vec::new(),
+                };
+                loop {
+                    let mut directive = Vec::new();
+                    let closed = loop {
+                        match tokens.next() {
+                            Some(Token::Word(word)) => directive.push(word.clone()),
+                            Some(Token::Newline) => break false,
+                            Some(Token::Close) => break true,
+                            Some(Token::Open) => return Err("unexpected '{' in profile".into()),
+                            None => return Err("unterminated profile".into()),
+                        }
+                    };
+                    match directive.split_first() {
+                        Some((kind, arguments)) if kind == "output" => {
+                            profile.outputs.push(arguments.to_vec())
+                        }
+                        Some((kind, _)) if kind == "exec" => (),
+                        Some((kind, _)) => {
+                            return Err(format!("unknown profile directive {}", kind))
+                        }
+                        None => (),
+                    }
+                    if closed {
+                        break;
+                    }
+                }
+                profiles.push(profile)
+            }
+            Token::Word(word) if word == "output" || word == "include" => {
+                log::warn!(
+                    "kanshi: {} directives outside of profiles are ignored",
+                    word
+                );
+                while tokens.next_if(|t| **t != Token::Newline).is_some() {}
+            }
+            token => return Err(format!("unexpected {:?}", token)),
+        }
+    }
+    Ok(profiles)
+}
+
+/// Output entry of an `output` directive : criteria then settings.
+fn parse_output(words: &[String]) -> Result<OutputEntry, String> {
+    let (criteria, settings) = words.split_first().ok_or("output without criteria")?;
+    if criteria == "*" {
+        return Err("wildcard output criteria are not supported".into());
+    }
+    let mut enabled = true;
+    let mut mode = None;
+    let mut position = Vec2d::new(0, 0);
+    let mut scale = Scale::ONE;
+    let mut transform = Transform::default();
+    let mut settings = settings.iter();
+    let invalid = |what: &str, value: Option<&String>| {
+        format!("output {}: invalid {} {:?}", criteria, what, value)
+    };
+    while let Some(setting) = settings.next() {
+        match setting.as_str() {
+            "enable" => enabled = true,
+            "disable" => enabled = false,
+            "mode" => {
+                let mut value = settings.next();
+                if value.is_some_and(|v| v == "--custom") {
+                    value = settings.next()
+                }
+                mode = Some(parse_mode(value.ok_or_else(|| invalid("mode", None))?)?)
+            }
+            "position" => {
+                let value = settings.next();
+                let (x, y) = value
+                    .and_then(|v| v.split_once(','))
+                    .ok_or_else(|| invalid("position", value))?;
+                position = match (x.parse(), y.parse()) {
+                    (Ok(x), Ok(y)) => Vec2d::new(x, y),
+                    _ => return Err(invalid("position", value)),
+                }
+            }
+            "scale" => {
+                let value = settings.next();
+                scale = value
+                    .and_then(|v| v.parse().ok())
+                    .and_then(Scale::from_f64)
+                    .ok_or_else(|| invalid("scale", value))?
+            }
+            "transform" => {
+                let value = settings.next();
+                let index = SWAY_TRANSFORMS
+                    .iter()
+                    .position(|name| Some(*name) == value.map(String::as_str));
+                transform = index
+                    .and_then(|i| transform_from_wayland(i as u8))
+                    .ok_or_else(|| invalid("transform", value))?
+            }
+            "adaptive_sync" | "alias" => {
+                settings.next();
+            }
+            _ => return Err(format!("output {}: unknown setting {}", criteria, setting)),
+        }
+    }
+    let state = match (enabled, mode) {
+        (false, _) => OutputState::Disabled { reason: None },
+        (true, Some(mode)) => OutputState::Enabled {
+            mode,
+            transform,
+            bottom_left: position,
+            scale,
+            panning: None,
+        },
+        (true, None) => return Err(format!("output {}: enabled without mode", criteria)),
+    };
+    Ok(OutputEntry {
+        id: OutputId::Name(criteria.clone()),
+        state,
+    })
+}
+
+/// `WxH@R` or `WxH@RHz`, with a possibly fractional refresh rate (kept to the mHz).
+fn parse_mode(text: &str) -> Result<Mode, String> {
+    let invalid = || format!("invalid mode {} (WxH@RHz expected)", text);
+    let (size, refresh) = text.split_once('@').ok_or_else(invalid)?;
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let refresh: f64 = refresh
+        .trim_end_matches("Hz")
+        .parse()
+        .map_err(|_| invalid())?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if refresh > 0. => Ok(Mode::from_refresh(
+            Vec2d::new(width, height),
+            (refresh * 1000.).round() as u32,
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+/// Layouts of the profiles of a kanshi configuration.
+///
+/// Named profiles become saved layouts with the same name, others are learned layouts.
+/// Outputs are designated by the criteria string, which matches output names.
+/// Profiles which cannot be represented (wildcards, missing modes, overlaps) are skipped with a warning.
+/// Syntax errors are errors.
+pub fn parse_config(text: &str) -> Result<Vec<Layout>, anyhow::Error> {
+    let profiles = tokenize(text)
+        .and_then(|tokens| parse_profiles(&tokens))
+        .map_err(|e| anyhow::Error::msg(format!("invalid kanshi configuration: {}", e)))?;
+    let mut layouts = Vec::new();
+    for profile in profiles {
+        let profile_name = profile.name.as_deref().unwrap_or("<anonymous>");
+        let entries: Result<Vec<_>, _> = profile.outputs.iter().map(|o| parse_output(o)).collect();
+        let info = match entries {
+            Ok(entries) => LayoutInfo::from_iter(entries, None),
+            Err(e) => {
+                log::warn!("kanshi: profile {} skipped: {}", profile_name, e);
+                continue;
+            }
+        };
+        if !info.unsupported_causes.is_empty() {
+            log::warn!(
+                "kanshi: profile {} skipped: unsupported layout: {:?}",
+                profile_name,
+                info.unsupported_causes
+            );
+            continue;
+        }
+        let mut layout = info.layout;
+        layout.set_name(profile.name);
+        layouts.push(layout)
+    }
+    Ok(layouts)
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_config() {
+    use crate::export::{export, ExportFormat};
+    use std::collections::HashMap;
+    let config = "# comment\n\
+        profile docked {\n\
+        \toutput eDP-1 disable\n\
+        \toutput \"DP-1\" mode 2560x1440@59.951Hz position 1280,0 transform 90 # rotated\n\
+        \toutput HDMI-A-1 enable mode 1920x1080@60 scale 1.5\n\
+        \texec notify-send \"docked {}\"\n\
+        }\n\
+        profile {\n\
+        \toutput * enable\n\
+        }\n\
+        profile { output eDP-1 mode 1920x1080@60Hz }\n";
+    let layouts = parse_config(config).unwrap();
+    assert_eq!(layouts.len(), 2);
+    assert_eq!(layouts[0].name(), Some("docked"));
+    assert_eq!(layouts[0].output_entries().len(), 3);
+    assert_eq!(layouts[1].name(), None);
+    let exported = export(&layouts[0], ExportFormat::Kanshi, &HashMap::new());
+    let reimported = parse_config(&exported).unwrap();
+    assert_eq!(reimported[0], layouts[0]);
+    assert!(parse_config("profile {\n output eDP-1 disable\n").is_err());
+    let without_refresh = parse_config("profile { output eDP-1 mode 1920x1080 }");
+    assert!(without_refresh.unwrap().is_empty());
+}
@@ -1,5 +1,25 @@
-use crate::geometry::{Rect, Transform, Vec2d};
+use crate::geometry::{Direction, Rect, Relation, Rotation, Transform, Vec2d};
 use crate::relation::RelationMatrix;
+use std::collections::HashMap;
+
+/// Automatic layouts for output sets without stored layout.
+pub mod auto;
+/// Gaps between outputs compensating for bezels.
+pub mod bezel;
+/// Output position optimization, as a quadratic problem.
+mod compute_rects;
+/// Mode timings : X modelines and CVT computation.
+pub mod modeline;
+/// Physical (millimeter) representation of layouts.
+pub mod physical;
+/// Text drawings of layouts.
+pub mod preview;
+/// Rules selecting the primary output of layouts without one.
+pub mod primary_policy;
+/// Compact one-line layout descriptions.
+pub mod spec;
+
+use bezel::Bezel;
 
 ///////////////////////////////////////////////////////////////////////////////
 
@@ -24,7 +44,7 @@ impl std::fmt::Debug for Edid {
 impl<'a> TryFrom<&'a [u8]> for Edid {
     type Error = &'static str;
     fn try_from(edid_entry_bytes: &'a [u8]) -> Result<Edid, &'static str> {
-        if !(edid_entry_bytes.len() >= 16) {
+        if edid_entry_bytes.len() < 16 {
             // Very permissive here as we only need the bytes 8-15.
             // EDID standard has at least 128 bytes from 1.0 upwards.
             return Err("Edid: bad length");
@@ -37,6 +57,109 @@ impl<'a> TryFrom<&'a [u8]> for Edid {
     }
 }
 
+impl Edid {
+    /// Identifier from the monitor identification strings, for backends without raw EDID (Wayland).
+    /// This is a hash, so it differs from the identifier of the same monitor built from raw EDID.
+    pub fn from_identification(make: &str, model: &str, serial_number: &str) -> Edid {
+        // FNV-1a : unlike std hashers, stable across releases
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in [make, model, serial_number].join("\0").bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3)
+        }
+        Edid(hash)
+    }
+}
+
+/// Monitor identification decoded from raw EDID data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdidInfo {
+    /// Three letter PNP id of the manufacturer, like `DEL`.
+    pub manufacturer: String,
+    pub product_code: u16,
+    /// Numeric serial number, 0 if unused.
+    pub serial_number: u32,
+    /// Serial number string from a display descriptor.
+    pub serial: Option<String>,
+    /// Model name from a display descriptor, like `DELL U2720Q`.
+    pub model_name: Option<String>,
+}
+
+impl EdidInfo {
+    /// Decode the vendor and product block, and the text display descriptors.
+    pub fn parse(edid_bytes: &[u8]) -> Result<EdidInfo, &'static str> {
+        if edid_bytes.len() < 128 {
+            return Err("Edid: bad length");
+        }
+        if edid_bytes[0..8] != [0x0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x0] {
+            return Err("Edid: missing constant header pattern");
+        }
+        // 3 letters of 5 bits, 1 is 'A'
+        let packed = u16::from_be_bytes([edid_bytes[8], edid_bytes[9]]);
+        let letter = |shift: u16| char::from(b'@' + ((packed >> shift) & 0x1F) as u8);
+        let mut info = EdidInfo {
+            manufacturer: String::from_iter([letter(10), letter(5), letter(0)]),
+            product_code: u16::from_le_bytes([edid_bytes[10], edid_bytes[11]]),
+            serial_number: u32::from_le_bytes(edid_bytes[12..16].try_into().unwrap()),
+            serial: None,
+            model_name: None,
+        };
+        // Display descriptors have a zero pixel clock, then a tag at byte 3 and text at bytes 5..18
+        for descriptor in edid_bytes[54..126].chunks_exact(18) {
+            if descriptor[0..3] != [0, 0, 0] {
+                continue;
+            }
+            let text = String::from_utf8_lossy(&descriptor[5..18]);
+            let text = text
+                .split('\n')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            match descriptor[3] {
+                0xFF => info.serial = Some(text),
+                0xFC => info.model_name = Some(text),
+                _ => (),
+            }
+        }
+        Ok(info)
+    }
+}
+
+/// Model name if available, or manufacturer and product code.
+impl std::fmt::Display for EdidInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.model_name {
+            Some(name) if !name.is_empty() => name.fmt(f),
+            _ => write!(f, "{} {:04X}", self.manufacturer, self.product_code),
+        }
+    }
+}
+
+/// Physical image size in millimeters from raw EDID data, if specified.
+/// Uses the preferred detailed timing descriptor (mm precision), or the basic display parameters (cm precision).
+pub fn edid_physical_size(edid_bytes: &[u8]) -> Option<Vec2d<u32>> {
+    let non_zero = |size: Vec2d<u32>| match size.x > 0 && size.y > 0 {
+        true => Some(size),
+        false => None,
+    };
+    // First descriptor at bytes 54..72 is a timing if pixel clock (54-55) is not 0.
+    let from_timing = edid_bytes.get(54..72).and_then(|dtd| {
+        if dtd[0] == 0 && dtd[1] == 0 {
+            return None;
+        }
+        non_zero(Vec2d::new(
+            u32::from(dtd[12]) | (u32::from(dtd[14] & 0xF0) << 4),
+            u32::from(dtd[13]) | (u32::from(dtd[14] & 0x0F) << 8),
+        ))
+    });
+    let from_basic_params = || {
+        let cm = edid_bytes.get(21..23)?;
+        non_zero(Vec2d::new(u32::from(cm[0]) * 10, u32::from(cm[1]) * 10))
+    };
+    from_timing.or_else(from_basic_params)
+}
+
 // For tests only
 impl From<u64> for Edid {
     fn from(raw: u64) -> Edid {
@@ -46,10 +169,33 @@ impl From<u64> for Edid {
 
 ///////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+/// Modes compare by size and frequency rounded to the nearest Hz, as backends may report slightly different
+/// refresh rates for the same mode. The exact refresh only selects among matching modes (see [`Mode::match_distance`]).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Mode {
     pub size: Vec2d<u32>,
     pub frequency: u32,
+    /// Exact refresh rate in mHz, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh: Option<u32>,
+}
+
+impl PartialEq for Mode {
+    fn eq(&self, other: &Mode) -> bool {
+        (self.size, self.frequency) == (other.size, other.frequency)
+    }
+}
+impl Eq for Mode {}
+
+impl PartialOrd for Mode {
+    fn partial_cmp(&self, other: &Mode) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Mode {
+    fn cmp(&self, other: &Mode) -> std::cmp::Ordering {
+        (self.size, self.frequency).cmp(&(other.size, other.frequency))
+    }
 }
 
 impl std::fmt::Display for Mode {
@@ -58,6 +204,175 @@ impl std::fmt::Display for Mode {
     }
 }
 
+/// Maximum frequency difference in Hz between a requested mode and the one applied.
+/// This absorbs frequency rounding changes between driver versions (59 or 60 Hz for the same mode).
+pub const MODE_FREQUENCY_TOLERANCE: u32 = 1;
+
+impl Mode {
+    /// Mode with an exact refresh rate in mHz, rounded to the nearest Hz for `frequency`.
+    pub fn from_refresh(size: Vec2d<u32>, millihertz: u32) -> Mode {
+        Mode {
+            size,
+            frequency: millihertz / 1000 + u32::from(millihertz % 1000 >= 500),
+            refresh: Some(millihertz),
+        }
+    }
+
+    /// Refresh rate in mHz, exact if known.
+    pub fn millihertz(&self) -> u32 {
+        self.refresh
+            .unwrap_or_else(|| self.frequency.saturating_mul(1000))
+    }
+
+    /// Distance of `candidate` to this requested mode, or [`None`] if it does not match.
+    /// Modes match with the same size and rounded frequencies within [`MODE_FREQUENCY_TOLERANCE`],
+    /// and the distance is the refresh rate difference in mHz so that `59.94` and `60` Hz select their exact mode.
+    /// Backends select the closest matching mode.
+    pub fn match_distance(&self, candidate: &Mode) -> Option<u32> {
+        let matches = self.size == candidate.size
+            && self.frequency.abs_diff(candidate.frequency) <= MODE_FREQUENCY_TOLERANCE;
+        matches.then(|| self.millihertz().abs_diff(candidate.millihertz()))
+    }
+
+    /// Closest mode to this one among `candidates`, for outputs which no longer advertise it :
+    /// same aspect ratio first, then nearest size in pixels, then nearest frequency.
+    pub fn closest<'m>(&self, candidates: impl IntoIterator<Item = &'m Mode>) -> Option<&'m Mode> {
+        let area = |mode: &Mode| u64::from(mode.size.x) * u64::from(mode.size.y);
+        let same_aspect = |mode: &Mode| {
+            u64::from(mode.size.x) * u64::from(self.size.y)
+                == u64::from(self.size.x) * u64::from(mode.size.y)
+        };
+        candidates.into_iter().min_by_key(|mode| {
+            (
+                !same_aspect(mode),
+                area(mode).abs_diff(area(self)),
+                mode.millihertz().abs_diff(self.millihertz()),
+            )
+        })
+    }
+
+    /// Parse a mode size : `1920x1080`.
+    pub fn parse_size(s: &str) -> Result<Vec2d<u32>, String> {
+        let dimension = |v: &str| match v.parse::<u32>() {
+            Ok(0) | Err(_) => Err(format!("invalid mode size: {} (expected WxH)", s)),
+            Ok(v) => Ok(v),
+        };
+        let (x, y) = s
+            .split_once('x')
+            .ok_or_else(|| format!("invalid mode size: {} (expected WxH)", s))?;
+        Ok(Vec2d::new(dimension(x)?, dimension(y)?))
+    }
+
+    /// Parse a frequency in Hz with up to 3 decimals : `60`, `59.94`. Returns the refresh rate in mHz.
+    pub fn parse_frequency(s: &str) -> Result<u32, String> {
+        let invalid = || format!("invalid frequency: {} (expected HZ like 60 or 59.94)", s);
+        let (integer, decimals) = s.split_once('.').unwrap_or((s, ""));
+        let all_digits = |v: &str| v.bytes().all(|b| b.is_ascii_digit());
+        if integer.is_empty() || !all_digits(integer) || !all_digits(decimals) {
+            return Err(invalid());
+        }
+        if decimals.len() > 3 {
+            return Err(format!("frequency too precise: {} (at most mHz)", s));
+        }
+        let hz: u32 = integer.parse().map_err(|_| invalid())?;
+        let mhz = format!("{:0<3}", decimals).parse::<u32>().unwrap_or(0);
+        hz.checked_mul(1000)
+            .and_then(|v| v.checked_add(mhz))
+            .filter(|v| *v > 0)
+            .ok_or_else(invalid)
+    }
+
+    /// Format a refresh rate in mHz as parsed by [`Mode::parse_frequency`] : `60`, `59.94`.
+    pub fn format_frequency(millihertz: u32) -> String {
+        let decimals = format!("{:03}", millihertz % 1000);
+        match decimals.trim_end_matches('0') {
+            "" => (millihertz / 1000).to_string(),
+            decimals => format!("{}.{}", millihertz / 1000, decimals),
+        }
+    }
+}
+
+/// `WxH@HZ`, like `1920x1080@60` or `1920x1080@59.94` (see [`Mode::parse_frequency`]).
+impl std::str::FromStr for Mode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Mode, String> {
+        let (size, frequency) = s
+            .split_once('@')
+            .ok_or_else(|| format!("missing frequency in mode: {} (expected WxH@HZ)", s))?;
+        Ok(Mode::from_refresh(
+            Mode::parse_size(size)?,
+            Mode::parse_frequency(frequency)?,
+        ))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+
+/// Output scale factor, with the Wayland semantics : content is `scale` times bigger.
+/// The output covers a *logical* area of `size / scale` in the layout space.
+///
+/// Stored as a fixed point multiple of `1/120` like the Wayland fractional scale protocol.
+/// This keeps [`Eq`] and [`Ord`], and every valid Wayland scale is representable.
+/// Serialized as a float.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Scale(u32);
+
+impl Scale {
+    const DENOMINATOR: u32 = 120;
+    pub const ONE: Scale = Scale(Self::DENOMINATOR);
+
+    /// Closest representable scale, or [`None`] if not strictly positive.
+    pub fn from_f64(scale: f64) -> Option<Scale> {
+        let numerator = (scale * f64::from(Self::DENOMINATOR)).round();
+        match numerator >= 1. && numerator <= f64::from(u32::MAX) {
+            true => Some(Scale(numerator as u32)),
+            false => None,
+        }
+    }
+    pub fn to_f64(self) -> f64 {
+        f64::from(self.0) / f64::from(Self::DENOMINATOR)
+    }
+    pub fn is_one(&self) -> bool {
+        *self == Scale::ONE
+    }
+
+    /// Logical size of an output of `size` pixels.
+    /// Rounding rule : each component is rounded to the nearest integer, half up.
+    /// Sizes that would round to 0 are kept at 1 pixel.
+    pub fn logical_size(self, size: Vec2d<u32>) -> Vec2d<u32> {
+        size.map(|pixels| {
+            let scaled = (u64::from(pixels) * u64::from(Self::DENOMINATOR) + u64::from(self.0 / 2))
+                / u64::from(self.0);
+            u32::try_from(scaled).unwrap_or(u32::MAX).max(1)
+        })
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Scale {
+        Scale::ONE
+    }
+}
+
+impl std::fmt::Display for Scale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_f64().fmt(f)
+    }
+}
+
+impl serde::Serialize for Scale {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+impl<'de> serde::Deserialize<'de> for Scale {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Scale, D::Error> {
+        let float = f64::deserialize(deserializer)?;
+        Scale::from_f64(float)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid scale: {}", float)))
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 
 /// Identifier for an output
@@ -71,29 +386,337 @@ pub enum OutputId {
     Name(String),
 }
 
+impl std::fmt::Display for OutputId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputId::Edid(edid) => write!(f, "{:016x}", edid.0),
+            OutputId::Name(name) => name.fmt(f),
+        }
+    }
+}
+
+/// Why an output is disabled, if known.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum DisabledReason {
+    /// Disabled by the user with any layout tool.
+    UserDisabled,
+    /// Laptop lid is closed.
+    LidClosed,
+    /// Output is ignored by configuration.
+    Blacklisted,
+    /// Disabled to save power.
+    PowerSaving,
+}
+
+impl DisabledReason {
+    /// Outputs disabled for transient reasons should be re-enabled when conditions change.
+    /// Others stay disabled until the user decides otherwise.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            DisabledReason::UserDisabled | DisabledReason::Blacklisted => true,
+            DisabledReason::LidClosed | DisabledReason::PowerSaving => false,
+        }
+    }
+}
+
+impl std::fmt::Display for DisabledReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DisabledReason::UserDisabled => "disabled by user",
+            DisabledReason::LidClosed => "lid closed",
+            DisabledReason::Blacklisted => "blacklisted",
+            DisabledReason::PowerSaving => "power saving",
+        })
+    }
+}
+
+/// Panning of an output over a larger area (`xrandr --panning`) : the output shows a viewport following the mouse.
+/// The panned area starts at the output position and replaces the output rect in the layout.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct Panning {
+    /// Size of the panned area, at least the output logical size.
+    pub size: Vec2d<u32>,
+    /// Area where the mouse moves the viewport, relative to the output position. The panned area if [`None`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking: Option<Rect>,
+    /// Distances to the viewport edges where panning starts : left, top, right, bottom.
+    #[serde(default)]
+    pub border: [i16; 4],
+}
+
+/// `WxH[+X+Y/TWxTH+TX+TY[/L/T/R/B]]` like `xrandr --panning`, with the area position omitted as it is the output position.
+impl std::fmt::Display for Panning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.size.x, self.size.y)?;
+        if self.tracking.is_none() && self.border == [0; 4] {
+            return Ok(());
+        }
+        let tracking = self.tracking.clone().unwrap_or(Rect {
+            bottom_left: Vec2d::new(0, 0),
+            size: self.size,
+        });
+        write!(
+            f,
+            "/{}x{}{:+}{:+}",
+            tracking.size.x, tracking.size.y, tracking.bottom_left.x, tracking.bottom_left.y
+        )?;
+        if self.border != [0; 4] {
+            let [left, top, right, bottom] = self.border;
+            write!(f, "/{}/{}/{}/{}", left, top, right, bottom)?
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Panning {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Panning, String> {
+        let invalid = || format!("invalid panning: {}", s);
+        let parse_rect = |text: &str| -> Option<Rect> {
+            let sign = text.find(['+', '-'])?;
+            let (size, offset) = text.split_at(sign);
+            let sign = offset[1..].find(['+', '-'])? + 1;
+            let (x, y) = offset.split_at(sign);
+            Some(Rect {
+                bottom_left: Vec2d::new(x.parse().ok()?, y.parse().ok()?),
+                size: Mode::parse_size(size).ok()?,
+            })
+        };
+        let mut parts = s.split('/');
+        let size = Mode::parse_size(parts.next().ok_or_else(invalid)?).map_err(|_| invalid())?;
+        let tracking = match parts.next() {
+            Some(text) => Some(parse_rect(text).ok_or_else(invalid)?),
+            None => None,
+        };
+        let border = match Vec::from_iter(parts).as_slice() {
+            [] => [0; 4],
+            [left, top, right, bottom] => {
+                let parse = |v: &str| v.parse::<i16>().map_err(|_| invalid());
+                [parse(left)?, parse(top)?, parse(right)?, parse(bottom)?]
+            }
+            _ => return Err(invalid()),
+        };
+        let tracking = tracking.filter(|t| {
+            *t != Rect {
+                bottom_left: Vec2d::new(0, 0),
+                size,
+            }
+        });
+        Ok(Panning {
+            size,
+            tracking,
+            border,
+        })
+    }
+}
+
+/// The disabled reason is informative and ignored by comparisons ([`Eq`], [`Ord`]).
+/// Backends cannot report it, so a layout read from a backend must still match the stored one.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(from = "SerializedOutputState")]
 pub enum OutputState {
     Enabled {
         mode: Mode,
         transform: Transform,
         bottom_left: Vec2d<i32>,
+        scale: Scale,
+        panning: Option<Panning>,
+    },
+    Disabled {
+        reason: Option<DisabledReason>,
     },
-    Disabled,
 }
 
 impl OutputState {
-    /// Rect occupied by monitor in abstract 2D space (X11 screen)
-    fn rect(&self) -> Option<Rect> {
+    /// Rect occupied by monitor in abstract 2D space (X11 screen), in logical coordinates.
+    /// This is the panned area if the output pans.
+    pub fn rect(&self) -> Option<Rect> {
+        match self {
+            Self::Disabled { .. } => None,
+            Self::Enabled {
+                bottom_left,
+                mode,
+                transform,
+                scale,
+                panning,
+            } => {
+                let size = scale.logical_size(mode.size.apply(transform));
+                Some(Rect {
+                    bottom_left: *bottom_left,
+                    size: match panning {
+                        Some(panning) => size.cwise_max(panning.size),
+                        None => size,
+                    },
+                })
+            }
+        }
+    }
+
+    pub fn disabled_reason(&self) -> Option<DisabledReason> {
+        match self {
+            Self::Enabled { .. } => None,
+            Self::Disabled { reason } => *reason,
+        }
+    }
+
+    /// Enabled state data used for comparisons, or [`None`] if disabled.
+    #[allow(clippy::type_complexity)]
+    fn comparison_key(&self) -> Option<(&Mode, &Transform, &Vec2d<i32>, &Scale, &Option<Panning>)> {
         match self {
-            Self::Disabled => None,
             Self::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                panning,
+            } => Some((mode, transform, bottom_left, scale, panning)),
+            Self::Disabled { .. } => None,
+        }
+    }
+}
+
+/// Short description : `2560x1440x60Hz+1920+0 R90 scale 1.5 panning 3840x2160` or `disabled (lid closed)`.
+impl std::fmt::Display for OutputState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                panning,
+            } => {
+                write!(f, "{}+{}+{}", mode, bottom_left.x, bottom_left.y)?;
+                if *transform != Transform::default() {
+                    write!(f, " {:?}", transform)?
+                }
+                if !scale.is_one() {
+                    write!(f, " scale {}", scale)?
+                }
+                if let Some(panning) = panning {
+                    write!(f, " panning {}", panning)?
+                }
+                Ok(())
+            }
+            Self::Disabled { reason: None } => f.write_str("disabled"),
+            Self::Disabled {
+                reason: Some(reason),
+            } => write!(f, "disabled ({})", reason),
+        }
+    }
+}
+
+impl PartialEq for OutputState {
+    fn eq(&self, other: &Self) -> bool {
+        self.comparison_key() == other.comparison_key()
+    }
+}
+impl Eq for OutputState {}
+impl PartialOrd for OutputState {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for OutputState {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        Ord::cmp(&self.comparison_key(), &other.comparison_key())
+    }
+}
+
+/// Fields with default values are omitted, and disabled outputs without reason use the unit variant
+/// `"Disabled"` : databases stay readable by versions predating these fields.
+impl serde::Serialize for OutputState {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStructVariant;
+        match self {
+            OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                panning,
+            } => {
+                let len = 3 + usize::from(!scale.is_one()) + usize::from(panning.is_some());
+                let mut state =
+                    serializer.serialize_struct_variant("OutputState", 0, "Enabled", len)?;
+                state.serialize_field("mode", mode)?;
+                state.serialize_field("transform", transform)?;
+                state.serialize_field("bottom_left", bottom_left)?;
+                if !scale.is_one() {
+                    state.serialize_field("scale", scale)?
+                }
+                if let Some(panning) = panning {
+                    state.serialize_field("panning", panning)?
+                }
+                state.end()
+            }
+            OutputState::Disabled { reason: None } => {
+                serializer.serialize_unit_variant("OutputState", 1, "Disabled")
+            }
+            OutputState::Disabled {
+                reason: Some(reason),
+            } => {
+                let mut state =
+                    serializer.serialize_struct_variant("OutputState", 1, "Disabled", 1)?;
+                state.serialize_field("reason", reason)?;
+                state.end()
+            }
+        }
+    }
+}
+
+/// Databases written before [`DisabledReason`] store disabled outputs as a unit variant.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum SerializedOutputState {
+    Current(CurrentOutputState),
+    Legacy(LegacyOutputState),
+}
+#[derive(serde::Deserialize)]
+enum CurrentOutputState {
+    Enabled {
+        mode: Mode,
+        transform: Transform,
+        bottom_left: Vec2d<i32>,
+        #[serde(default)]
+        scale: Scale,
+        #[serde(default)]
+        panning: Option<Panning>,
+    },
+    Disabled {
+        #[serde(default)]
+        reason: Option<DisabledReason>,
+    },
+}
+#[derive(serde::Deserialize)]
+enum LegacyOutputState {
+    Disabled,
+}
+
+impl From<SerializedOutputState> for OutputState {
+    fn from(state: SerializedOutputState) -> OutputState {
+        match state {
+            SerializedOutputState::Current(CurrentOutputState::Enabled {
+                mode,
+                transform,
                 bottom_left,
+                scale,
+                panning,
+            }) => OutputState::Enabled {
                 mode,
                 transform,
-            } => Some(Rect {
-                bottom_left: bottom_left.clone(),
-                size: mode.size.clone().apply(transform),
-            }),
+                bottom_left,
+                scale,
+                panning,
+            },
+            SerializedOutputState::Current(CurrentOutputState::Disabled { reason }) => {
+                OutputState::Disabled { reason }
+            }
+            SerializedOutputState::Legacy(LegacyOutputState::Disabled) => {
+                OutputState::Disabled { reason: None }
+            }
         }
     }
 }
@@ -105,58 +728,481 @@ pub struct OutputEntry {
     pub state: OutputState,
 }
 
+#[cfg(test)]
+impl OutputEntry {
+    /// Enabled output named `name`, with a 60Hz mode and no transform.
+    pub fn enabled_for_test(name: &str, size: Vec2d<u32>, bottom_left: Vec2d<i32>) -> OutputEntry {
+        OutputEntry {
+            id: OutputId::Name(name.into()),
+            state: OutputState::Enabled {
+                mode: Mode {
+                    size,
+                    frequency: 60,
+                    refresh: None,
+                },
+                transform: Transform::default(),
+                bottom_left,
+                scale: Scale::ONE,
+                panning: None,
+            },
+        }
+    }
+}
+
+/// Backend specific output property set when applying a layout, like RandR `audio` or `Broadcast RGB`.
+/// The value uses the textual representation of `xrandr --set` ; the backend converts it to the property type.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct OutputProperty {
+    pub output: OutputId,
+    pub name: String,
+    pub value: String,
+}
+
+/// RGB quantization range of the video signal. TVs often default to the limited range, washing out colors.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum BroadcastRgb {
+    Automatic,
+    Full,
+    Limited,
+}
+
+/// HDMI content type hint, used by TVs to select a picture mode (low latency for `Game`).
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentType {
+    NoData,
+    Graphics,
+    Photo,
+    Cinema,
+    Game,
+}
+
+/// Video signal settings of an output, applied as the standard kernel connector properties.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct OutputSignal {
+    pub output: OutputId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub broadcast_rgb: Option<BroadcastRgb>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<ContentType>,
+}
+
+impl OutputSignal {
+    /// Equivalent output properties, with names and values of the kernel (`Broadcast RGB`, `content type`).
+    pub fn to_properties(&self) -> Vec<OutputProperty> {
+        let property = |name: &str, value: &str| OutputProperty {
+            output: self.output.clone(),
+            name: name.into(),
+            value: value.into(),
+        };
+        let broadcast_rgb = self.broadcast_rgb.map(|range| {
+            let value = match range {
+                BroadcastRgb::Automatic => "Automatic",
+                BroadcastRgb::Full => "Full",
+                BroadcastRgb::Limited => "Limited 16:235",
+            };
+            property("Broadcast RGB", value)
+        });
+        let content_type = self.content_type.map(|content_type| {
+            let value = match content_type {
+                ContentType::NoData => "No Data",
+                ContentType::Graphics => "Graphics",
+                ContentType::Photo => "Photo",
+                ContentType::Cinema => "Cinema",
+                ContentType::Game => "Game",
+            };
+            property("content type", value)
+        });
+        Vec::from_iter(broadcast_rgb.into_iter().chain(content_type))
+    }
+}
+
+/// Preferred backlight brightness of an output, in percent of the maximum.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct OutputBrightness {
+    pub output: OutputId,
+    pub percent: u8,
+}
+
+/// Adjacency of two enabled outputs in a stored layout : `lhs` is at `direction` of `rhs`, with `lhs < rhs`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct OutputRelation {
+    pub lhs: OutputId,
+    pub rhs: OutputId,
+    pub direction: Direction,
+}
+
+/// Use of a layout stored in the database, in seconds since the unix epoch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LayoutUsage {
+    pub created: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_applied: Option<u64>,
+    #[serde(default)]
+    pub apply_count: u32,
+}
+
 /// State of a set of screen outputs and their positionning.
 /// Intended to be stored in the database.
 /// Lists all connected outputs of a system.
 /// Positions are defined by coordinates of the bottom left corner, starting at `(0,0)`.
-#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+///
+/// Output properties, signals and brightness are only set on apply, and backends do not report them.
+/// Like [`DisabledReason`], they are ignored by comparisons, as are the name and usage of database layouts.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "UncheckedLayout")]
 pub struct Layout {
     /// Sorted by [`OutputId`].
-    #[serde(deserialize_with = "deserialize_layout_entries")]
     outputs: Box<[OutputEntry]>,
     /// Primary output if used / supported. Not in Wayland apparently.
     /// Used by some window manager to choose where to place tray icons, etc.
     primary: Option<OutputId>,
+    /// Gaps between adjacent enabled outputs, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    bezels: Vec<Bezel>,
+    /// Pixels of gap or overlap accepted between nearly adjacent enabled outputs.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    tolerance: u32,
+    /// Properties of connected outputs, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<OutputProperty>,
+    /// Signal settings of connected outputs, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    signals: Vec<OutputSignal>,
+    /// Backlight brightness of connected outputs, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    brightness: Vec<OutputBrightness>,
+    /// Font DPI of the session (`Xft.dpi`) with this layout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    font_dpi: Option<u32>,
+    /// Name given by the user to a saved layout. Learned layouts have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// Set for layouts stored in the database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    usage: Option<LayoutUsage>,
+    /// Adjacent enabled outputs when stored in the database, sorted.
+    /// Automatic layouts for other sets of outputs reuse them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    relations: Vec<OutputRelation>,
+}
+
+impl PartialEq for Layout {
+    fn eq(&self, other: &Self) -> bool {
+        (&self.outputs, &self.primary, &self.bezels)
+            == (&other.outputs, &other.primary, &other.bezels)
+    }
 }
+impl Eq for Layout {}
 
 impl Layout {
     /// Return the list of outputs ids, sorted.
-    pub fn connected_outputs<'l>(
-        &'l self,
-    ) -> impl Iterator<Item = &'l OutputId> + ExactSizeIterator + DoubleEndedIterator {
+    pub fn connected_outputs(
+        &self,
+    ) -> impl ExactSizeIterator<Item = &OutputId> + DoubleEndedIterator {
         self.outputs.iter().map(|o| &o.id)
     }
 
+    /// Short fingerprint of the set of connected outputs, stable across versions and runs.
+    /// 64 bit FNV-1a hash of output id representations, in hexadecimal.
+    pub fn fingerprint(&self) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for id in self.connected_outputs() {
+            for byte in id.to_string().bytes().chain(std::iter::once(b'\n')) {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        format!("{:016x}", hash)
+    }
+
+    /// Arguments of an `xrandr` command line setting this layout.
+    /// Outputs are designated by their name from `names`, or by their [`OutputId`].
+    /// Scales are converted to the inverse `--scale` of xrandr, which multiplies the screen area used by an output.
+    pub fn to_xrandr_args(&self, names: &HashMap<OutputId, String>) -> Vec<String> {
+        let mut args = Vec::new();
+        let properties = self.backend_properties();
+        for entry in self.outputs.iter() {
+            args.push("--output".to_string());
+            args.push(
+                names
+                    .get(&entry.id)
+                    .cloned()
+                    .unwrap_or_else(|| entry.id.to_string()),
+            );
+            match &entry.state {
+                OutputState::Enabled {
+                    mode,
+                    transform,
+                    bottom_left,
+                    scale,
+                    panning,
+                } => {
+                    let rotate = match transform.rotation {
+                        Rotation::R0 => "normal",
+                        Rotation::R90 => "left",
+                        Rotation::R180 => "inverted",
+                        Rotation::R270 => "right",
+                    };
+                    args.extend([
+                        "--mode".to_string(),
+                        format!("{}x{}", mode.size.x, mode.size.y),
+                        "--rate".to_string(),
+                        mode.frequency.to_string(),
+                        "--pos".to_string(),
+                        format!("{}x{}", bottom_left.x, bottom_left.y),
+                        "--rotate".to_string(),
+                        rotate.to_string(),
+                        "--reflect".to_string(),
+                        if transform.reflect { "x" } else { "normal" }.to_string(),
+                    ]);
+                    if !scale.is_one() {
+                        let inverse = 1. / scale.to_f64();
+                        args.push("--scale".to_string());
+                        args.push(format!("{}x{}", inverse, inverse))
+                    }
+                    if let Some(panning) = panning {
+                        // xrandr uses absolute positions for the panned and tracking areas
+                        let mut text = format!(
+                            "{}x{}{:+}{:+}",
+                            panning.size.x, panning.size.y, bottom_left.x, bottom_left.y
+                        );
+                        if let Some(tracking) = &panning.tracking {
+                            let position = *bottom_left + tracking.bottom_left;
+                            text += &format!(
+                                "/{}x{}{:+}{:+}",
+                                tracking.size.x, tracking.size.y, position.x, position.y
+                            )
+                        }
+                        if panning.border != [0; 4] {
+                            if panning.tracking.is_none() {
+                                text += &format!("/{}", text.clone())
+                            }
+                            let [left, top, right, bottom] = panning.border;
+                            text += &format!("/{}/{}/{}/{}", left, top, right, bottom)
+                        }
+                        args.extend(["--panning".to_string(), text])
+                    }
+                    if self.primary.as_ref() == Some(&entry.id) {
+                        args.push("--primary".to_string())
+                    }
+                    for property in properties.iter().filter(|p| p.output == entry.id) {
+                        args.extend(["--set".to_string(), property.name.clone()]);
+                        args.push(property.value.clone())
+                    }
+                }
+                OutputState::Disabled { .. } => args.push("--off".to_string()),
+            }
+        }
+        args
+    }
+
     /// Returns sorted entries.
-    pub fn output_entries<'l>(&'l self) -> &'l [OutputEntry] {
+    pub fn output_entries(&self) -> &[OutputEntry] {
         &self.outputs
     }
 
-    /// Size of minimal rectangle containing all outputs: `Rect{(0,0), size}`
+    /// Size of minimal rectangle containing all outputs: `Rect{(0,0), size}`.
+    /// Uses logical output sizes, with rotations and scale applied.
     pub fn bounding_rect_size(&self) -> Vec2d<u32> {
         self.outputs
             .iter()
-            .fold(Vec2d::default(), |max, output| match &output.state {
-                OutputState::Enabled {
-                    bottom_left, mode, ..
-                } => Vec2d::cwise_max(
-                    max,
-                    bottom_left.map(|i| u32::try_from(i).unwrap()) + mode.size,
-                ),
-                OutputState::Disabled => max,
+            .filter_map(|output| output.state.rect())
+            .fold(Vec2d::default(), |max, rect| {
+                Vec2d::cwise_max(max, rect.top_right().map(|i| i.max(0) as u32))
             })
     }
 
     pub fn primary(&self) -> Option<&OutputId> {
         self.primary.as_ref()
     }
+
+    /// `primary` is supposed to be a connected and enabled output (not checked).
+    pub fn set_primary(&mut self, primary: Option<OutputId>) {
+        self.primary = primary
+    }
+
+    /// Groups of enabled outputs sharing the same rect, which mirror each other, in [`OutputId`] order.
+    pub fn clone_groups(&self) -> Vec<Vec<&OutputId>> {
+        let mut groups: Vec<(Rect, Vec<&OutputId>)> = Vec::new();
+        for entry in self.outputs.iter() {
+            if let Some(rect) = entry.state.rect() {
+                match groups.iter_mut().find(|(r, _)| *r == rect) {
+                    Some((_, group)) => group.push(&entry.id),
+                    None => groups.push((rect, vec![&entry.id])),
+                }
+            }
+        }
+        Vec::from_iter(
+            groups
+                .into_iter()
+                .map(|(_, group)| group)
+                .filter(|group| group.len() > 1),
+        )
+    }
+
+    pub fn bezels(&self) -> &[Bezel] {
+        &self.bezels
+    }
+
+    pub fn properties(&self) -> &[OutputProperty] {
+        &self.properties
+    }
+
+    /// Replace output properties. Properties of outputs that are not connected are dropped.
+    pub fn set_properties(&mut self, mut properties: Vec<OutputProperty>) {
+        properties.retain(|p| self.outputs.iter().any(|o| o.id == p.output));
+        properties.sort();
+        properties.dedup();
+        self.properties = properties
+    }
+
+    pub fn signals(&self) -> &[OutputSignal] {
+        &self.signals
+    }
+
+    /// Replace output signal settings. Settings of outputs that are not connected are dropped.
+    pub fn set_signals(&mut self, mut signals: Vec<OutputSignal>) {
+        signals.retain(|s| self.outputs.iter().any(|o| o.id == s.output));
+        signals.sort();
+        signals.dedup();
+        self.signals = signals
+    }
+
+    /// All properties to set on apply : signal settings, then explicit properties which take precedence.
+    pub fn backend_properties(&self) -> Vec<OutputProperty> {
+        let signals = self.signals.iter().flat_map(OutputSignal::to_properties);
+        Vec::from_iter(signals.chain(self.properties.iter().cloned()))
+    }
+
+    pub fn brightness(&self) -> &[OutputBrightness] {
+        &self.brightness
+    }
+
+    /// Replace brightness settings, one per output and at most 100%. Settings of outputs that are not connected are dropped.
+    pub fn set_brightness(&mut self, mut brightness: Vec<OutputBrightness>) {
+        brightness.retain(|b| self.outputs.iter().any(|o| o.id == b.output));
+        for b in brightness.iter_mut() {
+            b.percent = b.percent.min(100)
+        }
+        brightness.sort();
+        brightness.dedup_by(|b, previous| b.output == previous.output);
+        self.brightness = brightness
+    }
+
+    pub fn font_dpi(&self) -> Option<u32> {
+        self.font_dpi
+    }
+
+    pub fn set_font_dpi(&mut self, font_dpi: Option<u32>) {
+        self.font_dpi = font_dpi
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.name = name
+    }
+
+    pub fn usage(&self) -> Option<LayoutUsage> {
+        self.usage
+    }
+
+    pub fn set_usage(&mut self, usage: Option<LayoutUsage>) {
+        self.usage = usage
+    }
+
+    /// Relations between outputs recorded when the layout was stored.
+    /// Pixels of gap or overlap accepted between nearly adjacent enabled outputs.
+    pub fn tolerance(&self) -> u32 {
+        self.tolerance
+    }
+
+    pub fn relations(&self) -> &[OutputRelation] {
+        &self.relations
+    }
+
+    /// Record the current adjacency of enabled outputs, separated by their bezels.
+    pub fn record_relations(&mut self) {
+        self.relations = self.adjacent_relations()
+    }
+
+    /// Adjacency of enabled outputs, separated by their bezels up to the tolerance, sorted.
+    pub fn adjacent_relations(&self) -> Vec<OutputRelation> {
+        let mut relations = Vec::new();
+        for (i, rhs) in self.outputs.iter().enumerate() {
+            for lhs in &self.outputs[..i] {
+                let gap = bezel::gap_between(&self.bezels, &lhs.id, &rhs.id);
+                let direction = match (lhs.state.rect(), rhs.state.rect()) {
+                    (Some(l), Some(r)) => l
+                        .adjacent_direction_with_tolerance(&r, gap, self.tolerance)
+                        .map(|(direction, _)| direction),
+                    _ => None,
+                };
+                if let Some(direction) = direction {
+                    relations.push(OutputRelation {
+                        lhs: lhs.id.clone(),
+                        rhs: rhs.id.clone(),
+                        direction,
+                    })
+                }
+            }
+        }
+        relations.sort();
+        relations
+    }
+
+    /// Copy properties, signal settings, brightness and font DPI from `other`, as they are not reported by backends.
+    pub fn copy_output_settings(&mut self, other: &Layout) {
+        self.set_properties(other.properties.clone());
+        self.set_signals(other.signals.clone());
+        self.set_brightness(other.brightness.clone());
+        self.font_dpi = other.font_dpi
+    }
+
+    /// Layout flipped from left to right : outputs on the left are moved to the right and conversely.
+    /// Adjacency relations are kept with left and right swapped. Output contents are not reflected.
+    pub fn mirrored_horizontally(&self) -> Layout {
+        let width = self.bounding_rect_size().x as i32;
+        let mut outputs = self.outputs.to_vec();
+        for entry in outputs.iter_mut() {
+            if let (Some(rect), OutputState::Enabled { bottom_left, .. }) =
+                (entry.state.rect(), &mut entry.state)
+            {
+                bottom_left.x = width - rect.top_right().x
+            }
+        }
+        let (primary, bezels) = (self.primary.clone(), self.bezels.clone());
+        let mut layout = LayoutInfo::from_parts(outputs, primary, bezels, self.tolerance).layout;
+        layout.properties = self.properties.clone();
+        layout.signals = self.signals.clone();
+        layout.brightness = self.brightness.clone();
+        layout.font_dpi = self.font_dpi;
+        layout
+    }
+
+    /// Set `reason` for disabled outputs without a known reason.
+    pub fn fill_disabled_reasons(&mut self, reason: DisabledReason) {
+        for entry in self.outputs.iter_mut() {
+            if let OutputState::Disabled { reason: r @ None } = &mut entry.state {
+                *r = Some(reason)
+            }
+        }
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 
 bitflags::bitflags! {
     pub struct UnsupportedCauses: u8 {
-        /// Some output rects overlap
+        /// Some output rects overlap, other than clones sharing the same rect
         const OVERLAPS = 0b00000001;
         /// Output rects are not all connected to each other
         const GAPS = 0b00000010;
@@ -165,13 +1211,36 @@ bitflags::bitflags! {
     }
 }
 
+/// Backend data about a connected output, which is not part of layouts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputMetadata {
+    /// System name, like `DP-1`.
+    pub name: Option<String>,
+    /// Connector type from the system name, like `DP`, `HDMI-A` or `eDP`.
+    pub connector: Option<String>,
+    /// Mode preferred by the monitor.
+    pub preferred_mode: Option<Mode>,
+}
+
+impl OutputMetadata {
+    /// Connector type of a system name, without its index : `HDMI-A` for `HDMI-A-1`, `LVDS` for `LVDS1`.
+    pub fn connector_type(name: &str) -> Option<&str> {
+        let connector = name
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+            .trim_end_matches('-');
+        Some(connector).filter(|c| !c.is_empty() && c.len() < name.len())
+    }
+}
+
 /// Result of trying to validate layout output entries.
 /// We need both the layout info and the error status, thus the choice of struct instead of [`Result`].
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LayoutInfo {
     pub layout: Layout,
     pub unsupported_causes: UnsupportedCauses,
-    // TODO it would be useful to store data for statistical mode, with output names
+    /// Backend data of connected outputs, so that users of the layout do not query the backend for each.
+    /// Empty for layouts not read from a backend.
+    pub metadata: HashMap<OutputId, OutputMetadata>,
 }
 
 impl LayoutInfo {
@@ -179,17 +1248,94 @@ impl LayoutInfo {
     /// A layout will always be returned even if unsupported :
     /// - this is needed to recognize changes later on
     /// - it should not be stored in the database
-    pub fn from(mut outputs: Vec<OutputEntry>, primary: Option<OutputId>) -> LayoutInfo {
+    pub fn from(outputs: Vec<OutputEntry>, primary: Option<OutputId>) -> LayoutInfo {
+        LayoutInfo::from_parts(outputs, primary, Vec::new(), 0)
+    }
+
+    /// Set metadata of connected outputs from backend names and preferred modes.
+    pub fn with_metadata(
+        mut self,
+        names: &HashMap<OutputId, String>,
+        preferred_modes: &HashMap<OutputId, Mode>,
+    ) -> LayoutInfo {
+        self.metadata = HashMap::from_iter(self.layout.connected_outputs().map(|id| {
+            let name = names.get(id).cloned();
+            let metadata = OutputMetadata {
+                connector: (name.as_deref())
+                    .and_then(OutputMetadata::connector_type)
+                    .map(String::from),
+                name,
+                preferred_mode: preferred_modes.get(id).cloned(),
+            };
+            (id.clone(), metadata)
+        }));
+        self
+    }
+
+    /// Replace bezels of the layout, and check it again with the new gaps.
+    pub fn with_bezels(self, bezels: Vec<Bezel>) -> LayoutInfo {
+        let tolerance = self.layout.tolerance;
+        self.rechecked(bezels, tolerance)
+    }
+
+    /// Replace the gap or overlap tolerance of the layout, and check it again.
+    pub fn with_tolerance(self, tolerance: u32) -> LayoutInfo {
+        let bezels = self.layout.bezels.clone();
+        self.rechecked(bezels, tolerance)
+    }
+
+    fn rechecked(self, bezels: Vec<Bezel>, tolerance: u32) -> LayoutInfo {
+        let Layout {
+            outputs,
+            primary,
+            properties,
+            signals,
+            brightness,
+            font_dpi,
+            name,
+            usage,
+            relations,
+            ..
+        } = self.layout;
+        let mut info = LayoutInfo::from_parts(outputs.into_vec(), primary, bezels, tolerance);
+        info.layout.properties = properties;
+        info.layout.signals = signals;
+        info.layout.brightness = brightness;
+        info.layout.font_dpi = font_dpi;
+        info.layout.name = name;
+        info.layout.usage = usage;
+        info.layout.relations = relations;
+        info.metadata = self.metadata;
+        info
+    }
+
+    fn from_parts(
+        mut outputs: Vec<OutputEntry>,
+        primary: Option<OutputId>,
+        mut bezels: Vec<Bezel>,
+        tolerance: u32,
+    ) -> LayoutInfo {
         outputs.sort();
+        bezels.sort();
         normalize_bottom_left_coordinates(&mut outputs);
-        let unsupported_causes = check_entries_for_unsupported_causes(&outputs);
+        let unsupported_causes = check_entries_for_unsupported_causes(&outputs, &bezels, tolerance);
         let layout = Layout {
             outputs: Vec::into_boxed_slice(outputs),
             primary,
+            bezels,
+            tolerance,
+            properties: Vec::new(),
+            signals: Vec::new(),
+            brightness: Vec::new(),
+            font_dpi: None,
+            name: None,
+            usage: None,
+            relations: Vec::new(),
         };
         LayoutInfo {
             layout,
             unsupported_causes,
+            metadata: HashMap::new(),
         }
     }
 
@@ -201,24 +1347,94 @@ impl LayoutInfo {
     }
 }
 
-/// Validate and normalize layout contents in deserialization case.
-fn deserialize_layout_entries<'de, D>(deserializer: D) -> Result<Box<[OutputEntry]>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let mut entries: Box<[OutputEntry]> = serde::Deserialize::deserialize(deserializer)?;
-    entries.sort();
-    normalize_bottom_left_coordinates(&mut entries);
-    let unsupported = check_entries_for_unsupported_causes(&entries);
-    if unsupported != UnsupportedCauses::empty() {
-        use serde::de::Error;
-        Err(D::Error::custom(format!(
-            "unsupported layout: {:?}",
-            unsupported
-        )))
-    } else {
-        Ok(entries)
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// Layout as read from the database, validated and normalized on conversion.
+#[derive(serde::Deserialize)]
+struct UncheckedLayout {
+    outputs: Vec<OutputEntry>,
+    primary: Option<OutputId>,
+    #[serde(default)]
+    bezels: Vec<Bezel>,
+    #[serde(default)]
+    tolerance: u32,
+    #[serde(default)]
+    properties: Vec<OutputProperty>,
+    #[serde(default)]
+    signals: Vec<OutputSignal>,
+    #[serde(default)]
+    brightness: Vec<OutputBrightness>,
+    #[serde(default)]
+    font_dpi: Option<u32>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    usage: Option<LayoutUsage>,
+    #[serde(default)]
+    relations: Vec<OutputRelation>,
+}
+
+impl TryFrom<UncheckedLayout> for Layout {
+    type Error = String;
+    fn try_from(unchecked: UncheckedLayout) -> Result<Layout, String> {
+        check_coordinate_range(&unchecked.outputs, &unchecked.bezels, unchecked.tolerance)?;
+        let info = LayoutInfo::from_parts(
+            unchecked.outputs,
+            unchecked.primary,
+            unchecked.bezels,
+            unchecked.tolerance,
+        );
+        if info.unsupported_causes != UnsupportedCauses::empty() {
+            Err(format!("unsupported layout: {:?}", info.unsupported_causes))
+        } else {
+            let mut layout = info.layout;
+            layout.set_properties(unchecked.properties);
+            layout.set_signals(unchecked.signals);
+            layout.set_brightness(unchecked.brightness);
+            layout.font_dpi = unchecked.font_dpi;
+            layout.name = unchecked.name;
+            layout.usage = unchecked.usage;
+            layout.relations = unchecked.relations;
+            layout.relations.sort();
+            Ok(layout)
+        }
+    }
+}
+
+/// Largest coordinate, size, bezel or tolerance of layouts read from files, in pixels.
+/// Far above real screens, and keeps sums and differences of coordinates within `i32`.
+const MAX_COORDINATE: u32 = 1 << 24;
+
+/// Reject layouts from corrupted or crafted files whose geometry would overflow.
+fn check_coordinate_range(
+    outputs: &[OutputEntry],
+    bezels: &[Bezel],
+    tolerance: u32,
+) -> Result<(), String> {
+    let in_range = |value: i32| value.unsigned_abs() <= MAX_COORDINATE;
+    for entry in outputs {
+        if let Some(rect) = entry.state.rect() {
+            let bottom_left = rect.bottom_left;
+            if !(in_range(bottom_left.x) && in_range(bottom_left.y))
+                || rect.size.x > MAX_COORDINATE
+                || rect.size.y > MAX_COORDINATE
+            {
+                return Err(format!(
+                    "output {} is out of range: {}",
+                    entry.id, entry.state
+                ));
+            }
+        }
+    }
+    if let Some(bezel) = bezels.iter().find(|bezel| !in_range(bezel.gap)) {
+        return Err(format!("bezel gap out of range: {}", bezel.gap));
     }
+    if tolerance > MAX_COORDINATE {
+        return Err(format!("tolerance out of range: {}", tolerance));
+    }
+    Ok(())
 }
 
 /// Renormalize coordinates to fit `Rect { (0, 0), (max_x, max_y) }`
@@ -226,8 +1442,8 @@ fn normalize_bottom_left_coordinates(outputs: &mut [OutputEntry]) {
     let min_coords = outputs
         .iter()
         .fold(Vec2d::default(), |min, output| match &output.state {
-            OutputState::Enabled { bottom_left, .. } => Vec2d::cwise_min(min, bottom_left.clone()),
-            OutputState::Disabled => min,
+            OutputState::Enabled { bottom_left, .. } => Vec2d::cwise_min(min, *bottom_left),
+            OutputState::Disabled { .. } => min,
         });
     for output in outputs {
         if let OutputState::Enabled { bottom_left, .. } = &mut output.state {
@@ -237,23 +1453,41 @@ fn normalize_bottom_left_coordinates(outputs: &mut [OutputEntry]) {
 }
 
 /// Check output entries for problems:
-/// - gaps and overlaps between enabled outputs rects
+/// - gaps and overlaps between enabled outputs rects, except gaps or overlaps matching `bezels` up to `tolerance` pixels,
+///   and clones with the same rect
 /// - EDID present more than once
-fn check_entries_for_unsupported_causes(outputs: &[OutputEntry]) -> UnsupportedCauses {
+fn check_entries_for_unsupported_causes(
+    outputs: &[OutputEntry],
+    bezels: &[Bezel],
+    tolerance: u32,
+) -> UnsupportedCauses {
     let mut unsupported_causes = UnsupportedCauses::empty();
 
     // Coordinate problems : gaps, overlap
-    let rects = Vec::from_iter(outputs.iter().filter_map(|o| o.state.rect()));
+    let rects = Vec::from_iter(
+        outputs
+            .iter()
+            .filter_map(|o| o.state.rect().map(|rect| (&o.id, rect))),
+    );
     let size = rects.len();
     let mut relations = RelationMatrix::new(size);
-    for rhs in 1..size {
-        let rhs_rect = &rects[rhs];
-        for lhs in 0..rhs {
-            let lhs_rect = &rects[lhs];
-            if lhs_rect.overlaps(rhs_rect) {
-                unsupported_causes |= UnsupportedCauses::OVERLAPS;
-            }
-            relations.set(lhs, rhs, Rect::adjacent_direction(lhs_rect, rhs_rect))
+    for (rhs, (rhs_id, rhs_rect)) in rects.iter().enumerate() {
+        for (lhs, (lhs_id, lhs_rect)) in rects[..rhs].iter().enumerate() {
+            let relation = match lhs_rect == rhs_rect {
+                true => Some(Relation::SameAs),
+                false => {
+                    let gap = bezel::gap_between(bezels, lhs_id, rhs_id);
+                    let direction = lhs_rect
+                        .adjacent_direction_with_tolerance(rhs_rect, gap, tolerance)
+                        .map(|(direction, _)| direction);
+                    // Adjacent outputs only overlap by a negative bezel or within tolerance
+                    if lhs_rect.overlaps(rhs_rect) && direction.is_none() {
+                        unsupported_causes |= UnsupportedCauses::OVERLAPS;
+                    }
+                    direction.map(Relation::Adjacent)
+                }
+            };
+            relations.set(lhs, rhs, relation)
         }
     }
     if !relations.is_single_connected_component() {
@@ -261,7 +1495,7 @@ fn check_entries_for_unsupported_causes(outputs: &[OutputEntry]) -> UnsupportedC
     }
 
     // Duplicate EDID
-    let mut entries = outputs.into_iter();
+    let mut entries = outputs.iter();
     if let Some(first_entry) = entries.next() {
         let mut prev_id = &first_entry.id;
         for entry in entries {
@@ -275,3 +1509,460 @@ fn check_entries_for_unsupported_causes(outputs: &[OutputEntry]) -> UnsupportedC
 
     unsupported_causes
 }
+
+/// Nudge enabled outputs that are nearly adjacent, a few pixels apart or overlapping as often left by GUI tools,
+/// so that they become exactly adjacent, separated by their `bezels`.
+/// Outputs are nearly adjacent if moving one by at most `tolerance` pixels across their common edge
+/// makes them adjacent, see [`Rect::adjacent_direction_with_tolerance`].
+///
+/// The biggest output stays in place, and others are snapped to an output they are nearly adjacent to,
+/// moving along with it. Returns whether outputs moved.
+pub fn snap(entries: &mut [OutputEntry], bezels: &[Bezel], tolerance: u32) -> bool {
+    let rects = Vec::from_iter(
+        (entries.iter().enumerate())
+            .filter_map(|(i, entry)| entry.state.rect().map(|rect| (i, entry.id.clone(), rect))),
+    );
+    // First of the biggest outputs
+    let biggest = match (0..rects.len()).rev().max_by_key(|k| rects[*k].2.area()) {
+        Some(biggest) => biggest,
+        None => return false,
+    };
+    // Breadth first traversal from the biggest output, with the shift of each reached output
+    let mut shifts: Vec<Option<Vec2d<i32>>> = vec![None; rects.len()];
+    shifts[biggest] = Some(Vec2d::new(0, 0));
+    let mut queue = std::collections::VecDeque::from([biggest]);
+    while let Some(lhs) = queue.pop_front() {
+        let lhs_shift = shifts[lhs].expect("reached");
+        let (_, lhs_id, lhs_rect) = &rects[lhs];
+        let lhs_rect = lhs_rect.offset(lhs_shift);
+        for rhs in 0..rects.len() {
+            if shifts[rhs].is_some() {
+                continue;
+            }
+            let (_, rhs_id, rhs_rect) = &rects[rhs];
+            let rhs_rect = rhs_rect.offset(lhs_shift);
+            let shift = match rhs_rect == lhs_rect {
+                true => Some(Vec2d::new(0, 0)),
+                false => {
+                    let gap = bezel::gap_between(bezels, lhs_id, rhs_id);
+                    (lhs_rect.adjacent_direction_with_tolerance(&rhs_rect, gap, tolerance))
+                        .map(|(_, shift)| shift)
+                }
+            };
+            if let Some(shift) = shift {
+                shifts[rhs] = Some(lhs_shift + shift);
+                queue.push_back(rhs)
+            }
+        }
+    }
+    let mut moved = false;
+    for ((i, _, _), shift) in Iterator::zip(rects.into_iter(), shifts) {
+        let shift = shift.unwrap_or(Vec2d::new(0, 0));
+        if let OutputState::Enabled { bottom_left, .. } = &mut entries[i].state {
+            moved |= shift != Vec2d::new(0, 0);
+            *bottom_left += shift
+        }
+    }
+    moved
+}
+
+#[cfg(test)]
+#[test]
+fn test_disabled_reason_serialization() {
+    let legacy: OutputState = serde_json::from_str(r#""Disabled""#).unwrap();
+    assert_eq!(legacy.disabled_reason(), None);
+    assert_eq!(serde_json::to_string(&legacy).unwrap(), r#""Disabled""#);
+    let with_reason = OutputState::Disabled {
+        reason: Some(DisabledReason::LidClosed),
+    };
+    let json = serde_json::to_string(&with_reason).unwrap();
+    let read_back: OutputState = serde_json::from_str(&json).unwrap();
+    assert_eq!(read_back.disabled_reason(), Some(DisabledReason::LidClosed));
+    // Reasons are ignored when comparing layouts
+    assert_eq!(legacy, read_back);
+}
+
+#[cfg(test)]
+#[test]
+fn test_mode_parsing() {
+    let mode = |x, y, frequency| Mode {
+        size: Vec2d::new(x, y),
+        frequency,
+        refresh: None,
+    };
+    assert_eq!("1920x1080@60".parse(), Ok(mode(1920, 1080, 60)));
+    assert_eq!("1920x1080@59.4".parse(), Ok(mode(1920, 1080, 59)));
+    assert_eq!("3840x2160@29.970".parse(), Ok(mode(3840, 2160, 30)));
+    let ntsc: Mode = "1920x1080@59.94".parse().unwrap();
+    assert_eq!((ntsc.frequency, ntsc.refresh), (60, Some(59940)));
+    assert_eq!(Mode::format_frequency(59940), "59.94");
+    assert_eq!(Mode::format_frequency(60000), "60");
+    assert_eq!(Mode::format_frequency(29970), "29.97");
+    assert_eq!(Mode::parse_size("1920x1080"), Ok(Vec2d::new(1920, 1080)));
+    for invalid in [
+        "1920x1080",
+        "1920@60",
+        "0x1080@60",
+        "1920x1080@",
+        "1920x1080@0",
+        "1920x1080@60hz",
+        "1920x1080@.5",
+        "1920x1080@59.9401",
+        "1920x1080@4294968",
+        "1920x1080@4294967.296",
+        "1920x1080@99999999999",
+    ] {
+        assert!(invalid.parse::<Mode>().is_err(), "{}", invalid)
+    }
+    // Driver updates may round frequencies differently
+    let stored = mode(1920, 1080, 60);
+    assert_eq!(stored.match_distance(&mode(1920, 1080, 60)), Some(0));
+    assert_eq!(stored.match_distance(&mode(1920, 1080, 59)), Some(1000));
+    assert_eq!(stored.match_distance(&mode(1920, 1080, 50)), None);
+    assert_eq!(stored.match_distance(&mode(1920, 1200, 60)), None);
+    // Modes rounded to the same frequency are told apart by their exact refresh
+    let size = Vec2d::new(1920, 1080);
+    let advertised = [
+        Mode::from_refresh(size, 60000),
+        Mode::from_refresh(size, 59940),
+    ];
+    let select = |requested: &Mode| {
+        let matching = advertised
+            .iter()
+            .filter_map(|m| Some((requested.match_distance(m)?, m)));
+        matching
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, m)| m.refresh)
+    };
+    assert_eq!(select(&ntsc), Some(Some(59940)));
+    assert_eq!(select(&"1920x1080@60".parse().unwrap()), Some(Some(60000)));
+    assert_eq!(select(&stored), Some(Some(60000)));
+    // Substitutes for modes no longer advertised
+    let available = [
+        mode(1920, 1200, 60),
+        mode(1280, 720, 60),
+        mode(2560, 1440, 60),
+        mode(2560, 1440, 144),
+    ];
+    assert_eq!(stored.closest(&available), Some(&available[1]));
+    assert_eq!(
+        mode(2560, 1440, 120).closest(&available),
+        Some(&available[3])
+    );
+    assert_eq!(
+        mode(1920, 1080, 60).closest(&available[..1]),
+        Some(&available[0])
+    );
+    assert_eq!(stored.closest(&[]), None);
+}
+
+#[cfg(test)]
+#[test]
+fn test_scale() {
+    let scale = |f| Scale::from_f64(f).unwrap();
+    assert_eq!(Scale::from_f64(0.), None);
+    assert_eq!(Scale::from_f64(-1.), None);
+    assert_eq!(scale(1.), Scale::ONE);
+    let size = Vec2d::new(2560, 1440);
+    assert_eq!(scale(2.).logical_size(size), Vec2d::new(1280, 720));
+    // 1706.67 rounded
+    assert_eq!(scale(1.5).logical_size(size), Vec2d::new(1707, 960));
+    // 1092.8, 614.4
+    assert_eq!(
+        scale(1.25).logical_size(Vec2d::new(1366, 768)),
+        Vec2d::new(1093, 614)
+    );
+    // Overlap and adjacency checks use logical sizes
+    let entry = |name: &str, x, output_scale| {
+        let mut entry = OutputEntry::enabled_for_test(name, size, Vec2d::new(x, 0));
+        if let OutputState::Enabled { scale, .. } = &mut entry.state {
+            *scale = output_scale
+        }
+        entry
+    };
+    let info = LayoutInfo::from_iter(
+        [entry("a", 0, scale(2.)), entry("b", 1280, Scale::ONE)],
+        None,
+    );
+    assert_eq!(info.unsupported_causes, UnsupportedCauses::empty());
+    assert_eq!(info.layout.bounding_rect_size(), Vec2d::new(3840, 1440));
+    let info = LayoutInfo::from_iter(
+        [entry("a", 0, Scale::ONE), entry("b", 1280, Scale::ONE)],
+        None,
+    );
+    assert!(info
+        .unsupported_causes
+        .contains(UnsupportedCauses::OVERLAPS));
+    // Clones share the same logical rect
+    let info = LayoutInfo::from_iter(
+        [
+            entry("a", 0, scale(2.)),
+            entry("b", 1280, Scale::ONE),
+            entry("c", 0, scale(2.)),
+        ],
+        None,
+    );
+    assert_eq!(info.unsupported_causes, UnsupportedCauses::empty());
+    let (a, c) = (OutputId::Name("a".into()), OutputId::Name("c".into()));
+    assert_eq!(info.layout.clone_groups(), [[&a, &c]]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_bezels() {
+    let entry = |name: &str, x| {
+        OutputEntry::enabled_for_test(name, Vec2d::new(1920, 1080), Vec2d::new(x, 0))
+    };
+    let (a, b) = (OutputId::Name("a".into()), OutputId::Name("b".into()));
+    let info = LayoutInfo::from_iter([entry("a", 0), entry("b", 1960)], None);
+    assert_eq!(info.unsupported_causes, UnsupportedCauses::GAPS);
+    let info = info.with_bezels(vec![Bezel::new(b.clone(), a.clone(), 40)]);
+    assert_eq!(info.unsupported_causes, UnsupportedCauses::empty());
+    // Stored with the layout
+    let json = serde_json::to_string(&info.layout).unwrap();
+    let read_back: Layout = serde_json::from_str(&json).unwrap();
+    assert_eq!(read_back, info.layout);
+    let wrong_gap = info.with_bezels(vec![Bezel::new(a.clone(), b.clone(), 30)]);
+    assert_eq!(wrong_gap.unsupported_causes, UnsupportedCauses::GAPS);
+    assert!(
+        serde_json::from_str::<Layout>(&serde_json::to_string(&wrong_gap.layout).unwrap()).is_err()
+    );
+    // Millimeter widths from configuration : 1920px / 480mm = 4 px/mm
+    let config: Vec<bezel::BezelConfig> =
+        serde_json::from_str(r#"[{"outputs": ["a", "b"], "millimeters": 10}]"#).unwrap();
+    let sizes = std::collections::HashMap::from([
+        (a.clone(), Vec2d::new(480, 270)),
+        (b.clone(), Vec2d::new(480, 270)),
+    ]);
+    let outputs = [entry("a", 0), entry("b", 1960)];
+    assert_eq!(
+        bezel::resolve_bezels(&config, &outputs, &sizes),
+        vec![Bezel::new(a.clone(), b.clone(), 40)]
+    );
+    assert!(bezel::resolve_bezels(&config, &outputs, &Default::default()).is_empty());
+    // Overlapping projectors
+    let info = LayoutInfo::from_iter([entry("a", 0), entry("b", 1880)], None);
+    assert_eq!(
+        info.unsupported_causes,
+        UnsupportedCauses::OVERLAPS | UnsupportedCauses::GAPS
+    );
+    let info = info.with_bezels(vec![Bezel::new(a.clone(), b.clone(), -40)]);
+    assert_eq!(info.unsupported_causes, UnsupportedCauses::empty());
+    let config: Vec<bezel::BezelConfig> = serde_json::from_str(
+        r#"[{"outputs": ["a", "b"], "pixels": -40}, {"outputs": ["b", "a"], "pixels": -1080}]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        bezel::resolve_bezels(&config, &outputs, &sizes),
+        vec![Bezel::new(a, b, -40)]
+    );
+    // Tolerance of slightly imperfect layouts
+    let info = LayoutInfo::from_iter([entry("a", 0), entry("b", 1923)], None);
+    assert_eq!(info.unsupported_causes, UnsupportedCauses::GAPS);
+    let info = info.with_tolerance(4);
+    assert_eq!(info.unsupported_causes, UnsupportedCauses::empty());
+    assert_eq!(info.layout.adjacent_relations().len(), 1);
+    let json = serde_json::to_string(&info.layout).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Layout>(&json).unwrap().tolerance(),
+        4
+    );
+    let info = LayoutInfo::from_iter([entry("a", 0), entry("b", 1917)], None).with_tolerance(4);
+    assert_eq!(info.unsupported_causes, UnsupportedCauses::empty());
+    let info = info.with_tolerance(2);
+    assert_eq!(
+        info.unsupported_causes,
+        UnsupportedCauses::OVERLAPS | UnsupportedCauses::GAPS
+    );
+    // Corrupted files with coordinates overflowing geometry computations
+    let json = serde_json::to_string(&info.with_tolerance(3).layout).unwrap();
+    for (valid, corrupted) in [
+        ("1917", "2147483647"),
+        (r#""tolerance":3"#, r#""tolerance":4294967295"#),
+    ] {
+        assert!(serde_json::from_str::<Layout>(&json.replace(valid, corrupted)).is_err());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_mirrored_horizontally() {
+    let entry =
+        |name: &str, x, y, size| OutputEntry::enabled_for_test(name, size, Vec2d::new(x, y));
+    // Laptop at the left, bottom aligned with a big monitor, and a small one above the big one.
+    let info = LayoutInfo::from_iter(
+        [
+            entry("laptop", 0, 0, Vec2d::new(1920, 1080)),
+            entry("big", 1920, 0, Vec2d::new(2560, 1440)),
+            entry("small", 2240, 1440, Vec2d::new(1280, 1024)),
+        ],
+        None,
+    );
+    assert!(info.unsupported_causes.is_empty());
+    let mirrored = info.layout.mirrored_horizontally();
+    let bottom_left =
+        |layout: &Layout, i: usize| layout.output_entries()[i].state.rect().unwrap().bottom_left;
+    // Sorted by name : big, laptop, small
+    assert_eq!(bottom_left(&mirrored, 0), Vec2d::new(0, 0));
+    assert_eq!(bottom_left(&mirrored, 1), Vec2d::new(2560, 0));
+    assert_eq!(bottom_left(&mirrored, 2), Vec2d::new(960, 1440));
+    assert_eq!(
+        mirrored.bounding_rect_size(),
+        info.layout.bounding_rect_size()
+    );
+    assert_eq!(mirrored.mirrored_horizontally(), info.layout);
+    // Same outputs
+    assert_eq!(mirrored.fingerprint(), info.layout.fingerprint());
+    assert_eq!(info.layout.fingerprint(), "e588e6220bba82ce");
+}
+
+#[cfg(test)]
+#[test]
+fn test_output_properties() {
+    let entry = |name: &str| OutputEntry {
+        id: OutputId::Name(name.into()),
+        state: OutputState::Disabled { reason: None },
+    };
+    let property = |output: &str, value: &str| OutputProperty {
+        output: OutputId::Name(output.into()),
+        name: "audio".into(),
+        value: value.into(),
+    };
+    let info = LayoutInfo::from_iter([entry("a")], None);
+    let mut layout = info.layout.clone();
+    layout.set_properties(vec![property("a", "on"), property("unconnected", "on")]);
+    assert_eq!(layout.properties(), [property("a", "on")]);
+    // Not reported by backends, so ignored by comparisons
+    assert_eq!(layout, info.layout);
+    let json = serde_json::to_string(&layout).unwrap();
+    let read_back: Layout = serde_json::from_str(&json).unwrap();
+    assert_eq!(read_back.properties(), layout.properties());
+    // Signal settings are converted to kernel properties, overridden by explicit ones
+    layout.set_signals(vec![OutputSignal {
+        output: OutputId::Name("a".into()),
+        broadcast_rgb: Some(BroadcastRgb::Limited),
+        content_type: Some(ContentType::Game),
+    }]);
+    let properties = layout.backend_properties();
+    assert_eq!(properties.len(), 3);
+    assert_eq!(
+        (properties[0].name.as_str(), properties[0].value.as_str()),
+        ("Broadcast RGB", "Limited 16:235")
+    );
+    assert_eq!(properties[1].value, "Game");
+    assert_eq!(properties[2], property("a", "on"));
+    // Font DPI and brightness are kept like properties
+    layout.set_font_dpi(Some(144));
+    let brightness = |output: &str, percent| OutputBrightness {
+        output: OutputId::Name(output.into()),
+        percent,
+    };
+    layout.set_brightness(vec![brightness("a", 150), brightness("a", 30)]);
+    assert_eq!(layout.brightness(), [brightness("a", 30)]);
+    layout.set_brightness(vec![brightness("a", 150), brightness("unconnected", 30)]);
+    assert_eq!(layout.brightness(), [brightness("a", 100)]);
+    let json = serde_json::to_string(&layout).unwrap();
+    let read_back: Layout = serde_json::from_str(&json).unwrap();
+    assert_eq!(read_back.font_dpi(), Some(144));
+    let mut copy = info.layout.clone();
+    copy.copy_output_settings(&read_back);
+    assert_eq!(copy.font_dpi(), Some(144));
+    assert_eq!(copy.brightness(), [brightness("a", 100)]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_output_metadata() {
+    let connector = OutputMetadata::connector_type;
+    assert_eq!(connector("DP-1"), Some("DP"));
+    assert_eq!(connector("HDMI-A-1"), Some("HDMI-A"));
+    assert_eq!(connector("DisplayPort-0"), Some("DisplayPort"));
+    assert_eq!(connector("LVDS1"), Some("LVDS"));
+    assert_eq!(connector("eDP"), None);
+    assert_eq!(connector("42"), None);
+
+    let id = |name: &str| OutputId::Name(name.into());
+    let disabled = |name: &str| OutputEntry {
+        id: id(name),
+        state: OutputState::Disabled { reason: None },
+    };
+    let mode = Mode {
+        size: Vec2d::new(1920, 1080),
+        frequency: 60,
+        refresh: None,
+    };
+    let info = LayoutInfo::from_iter([disabled("a"), disabled("b")], None).with_metadata(
+        &HashMap::from([(id("a"), "eDP-1".to_string())]),
+        &HashMap::from([(id("a"), mode.clone())]),
+    );
+    let info = info.with_bezels(Vec::new());
+    assert_eq!(
+        info.metadata[&id("a")],
+        OutputMetadata {
+            name: Some("eDP-1".into()),
+            connector: Some("eDP".into()),
+            preferred_mode: Some(mode),
+        }
+    );
+    assert_eq!(info.metadata[&id("b")], OutputMetadata::default());
+}
+
+#[cfg(test)]
+#[test]
+fn test_edid_info() {
+    let mut edid = vec![0u8; 128];
+    edid[0..8].copy_from_slice(&[0x0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x0]);
+    edid[8..10].copy_from_slice(&[0x10, 0xAC]); // DEL
+    edid[10..12].copy_from_slice(&0x41B5u16.to_le_bytes());
+    edid[12..16].copy_from_slice(&42u32.to_le_bytes());
+    edid[21..23].copy_from_slice(&[60, 34]);
+    // Timing descriptor with 597x336mm, then serial and name descriptors
+    edid[54..56].copy_from_slice(&[0x01, 0x1D]);
+    edid[66..69].copy_from_slice(&[0x55, 0x50, 0x21]);
+    edid[72..90].copy_from_slice(b"\0\0\0\xFF\0ABC123\n      ");
+    edid[90..108].copy_from_slice(b"\0\0\0\xFC\0DELL U2720Q\n ");
+    let info = EdidInfo::parse(&edid).unwrap();
+    assert_eq!(info.manufacturer, "DEL");
+    assert_eq!(info.product_code, 0x41B5);
+    assert_eq!(info.serial_number, 42);
+    assert_eq!(info.serial.as_deref(), Some("ABC123"));
+    assert_eq!(info.to_string(), "DELL U2720Q");
+    assert_eq!(edid_physical_size(&edid), Some(Vec2d::new(597, 336)));
+    edid[90..108].fill(0);
+    assert_eq!(EdidInfo::parse(&edid).unwrap().to_string(), "DEL 41B5");
+    assert!(EdidInfo::parse(&edid[..100]).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_snap() {
+    let entry = |name: &str, x, y| {
+        OutputEntry::enabled_for_test(name, Vec2d::new(1920, 1080), Vec2d::new(x, y))
+    };
+    let position = |entry: &OutputEntry| entry.state.rect().unwrap().bottom_left;
+    // b overlaps a by 3 pixels, and c is 5 pixels under b : c moves along with b
+    let mut entries = [
+        entry("a", 0, 0),
+        entry("b", 2557, 0),
+        entry("c", 2557, 1085),
+    ];
+    entries[0] = OutputEntry::enabled_for_test("a", Vec2d::new(2560, 1440), Vec2d::new(0, 0));
+    assert!(snap(&mut entries, &[], 8));
+    assert_eq!(position(&entries[0]), Vec2d::new(0, 0));
+    assert_eq!(position(&entries[1]), Vec2d::new(2560, 0));
+    assert_eq!(position(&entries[2]), Vec2d::new(2560, 1080));
+    let info = LayoutInfo::from_iter(entries.clone(), None);
+    assert!(info.unsupported_causes.is_empty());
+    // Already adjacent, or too far
+    assert!(!snap(&mut entries, &[], 8));
+    let mut far = [entry("a", 0, 0), entry("b", 1945, 0)];
+    assert!(!snap(&mut far, &[], 8));
+    // Bezels are kept
+    let bezels = [Bezel::new(
+        OutputId::Name("a".into()),
+        OutputId::Name("b".into()),
+        20,
+    )];
+    assert!(snap(&mut far, &bezels, 8));
+    assert_eq!(position(&far[1]), Vec2d::new(1940, 0));
+}
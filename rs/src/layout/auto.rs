@@ -1,22 +1,497 @@
-impl Layout {
-    pub fn compute_base_coords(&self, enabled_output_preferred_modes: &[Mode]) -> Vec<Vec2di> {
-        assert_eq!(
-            enabled_output_preferred_modes.len(),
-            self.enabled_outputs.len()
-        );
-        let output_sizes = Vec::from_iter(
-            Iterator::zip(
-                self.enabled_outputs.iter(),
-                enabled_output_preferred_modes.iter(),
+use super::bezel::{self, Bezel};
+use super::{
+    compute_rects, Layout, LayoutInfo, Mode, OutputEntry, OutputId, OutputRelation, OutputState,
+    Scale,
+};
+use crate::geometry::{Direction, Relation, Transform, Vec2d, Vec2di};
+use crate::relation::{InvertibleRelation, RelationMatrix};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Automatic layout for the outputs of `layout`, used for output sets without stored layout or profile.
+///
+/// Enabled outputs keep their mode, transform, and relations to each other.
+/// Disabled outputs are enabled with their preferred mode, next to an output they had a `known` relation with
+/// in a stored layout, or else in a row at the right of the others in [`OutputId`] order.
+/// Outputs with `defaults` use their mode and transform instead, or stay disabled if requested.
+/// Outputs disabled for a permanent reason, or without a known mode, stay disabled.
+/// Positions are computed by the rect solver, with adjacent outputs separated by bezels.
+/// `bezels` computes bezels for output entries, which are only meaningful for enabled outputs.
+///
+/// Returns [`None`] if no output can be enabled or the solver fails.
+pub fn auto_layout(
+    layout: &Layout,
+    preferred_modes: &HashMap<OutputId, Mode>,
+    defaults: &HashMap<OutputId, OutputDefaults>,
+    known: &[OutputRelation],
+    bezels: impl Fn(&[OutputEntry]) -> Vec<Bezel>,
+) -> Option<Layout> {
+    let mut entries = layout.output_entries().to_vec();
+    // Indexes of enabled entries, with flag for newly enabled ones
+    let mut enabled = Vec::new();
+    for (i, entry) in entries.iter_mut().enumerate() {
+        match &entry.state {
+            OutputState::Enabled { .. } => enabled.push((i, false)),
+            OutputState::Disabled { reason } => {
+                let permanent = reason.is_some_and(|r| r.is_permanent());
+                let defaults = defaults.get(&entry.id);
+                let disabled = permanent || defaults.is_some_and(|d| d.disabled);
+                let mode = defaults.and_then(|d| d.mode.as_ref());
+                if let (false, Some(mode)) = (disabled, mode.or(preferred_modes.get(&entry.id))) {
+                    entry.state = OutputState::Enabled {
+                        mode: mode.clone(),
+                        transform: defaults
+                            .map_or_else(Transform::default, |d| d.transform.clone()),
+                        bottom_left: Vec2d::default(),
+                        scale: Scale::ONE,
+                        panning: None,
+                    };
+                    enabled.push((i, true))
+                }
+            }
+        }
+    }
+    if enabled.is_empty() {
+        return None;
+    }
+    // Known relations of different output sets may conflict, like two outputs right of the same one
+    place_outputs(layout, entries.clone(), &enabled, known, &bezels)
+        .or_else(|| place_outputs(layout, entries, &enabled, &[], &bezels))
+}
+
+/// `layout` with enabled outputs using `modes` instead of their current mode.
+/// Outputs keep their transform and relations to each other, and are placed again by the rect solver.
+/// If the solver fails, outputs keep their position.
+pub fn with_modes(
+    layout: &Layout,
+    modes: &HashMap<OutputId, Mode>,
+    bezels: impl Fn(&[OutputEntry]) -> Vec<Bezel>,
+) -> Layout {
+    let mut entries = layout.output_entries().to_vec();
+    let mut enabled = Vec::new();
+    for (i, entry) in entries.iter_mut().enumerate() {
+        if let OutputState::Enabled { mode, .. } = &mut entry.state {
+            if let Some(new_mode) = modes.get(&entry.id) {
+                *mode = new_mode.clone()
+            }
+            enabled.push((i, false))
+        }
+    }
+    let mut placed = match place_outputs(layout, entries.clone(), &enabled, &[], bezels) {
+        Some(placed) => placed,
+        None => {
+            let primary = layout.primary().cloned();
+            let mut kept = LayoutInfo::from(entries, primary)
+                .with_bezels(layout.bezels().to_vec())
+                .with_tolerance(layout.tolerance())
+                .layout;
+            kept.copy_output_settings(layout);
+            kept
+        }
+    };
+    placed.set_name(layout.name().map(String::from));
+    placed
+}
+
+/// Place `enabled` outputs of `entries`, flagged if they are newly enabled.
+/// Others keep the relations of their rect in `layout`.
+/// New ones are placed by a `known` relation to a placed output, or else in a row at the right.
+fn place_outputs(
+    layout: &Layout,
+    mut entries: Vec<OutputEntry>,
+    enabled: &[(usize, bool)],
+    known: &[OutputRelation],
+    bezels: impl Fn(&[OutputEntry]) -> Vec<Bezel>,
+) -> Option<Layout> {
+    let bezels = bezels(&entries);
+    let rect = |i: usize| layout.output_entries()[i].state.rect().expect("enabled");
+
+    // Relations : kept between previously enabled outputs, new ones in a row at the right
+    let mut relations = RelationMatrix::new(enabled.len());
+    for (rhs, (rhs_entry, rhs_new)) in enabled.iter().enumerate() {
+        for (lhs, (lhs_entry, lhs_new)) in enabled[..rhs].iter().enumerate() {
+            if !lhs_new && !rhs_new {
+                let gap = bezel::gap_between(
+                    layout.bezels(),
+                    &entries[*lhs_entry].id,
+                    &entries[*rhs_entry].id,
+                );
+                let relation = match rect(*lhs_entry) == rect(*rhs_entry) {
+                    true => Some(Relation::SameAs),
+                    false => rect(*lhs_entry)
+                        .adjacent_direction_with_tolerance(
+                            &rect(*rhs_entry),
+                            gap,
+                            layout.tolerance(),
+                        )
+                        .map(|(direction, _)| Relation::Adjacent(direction)),
+                };
+                relations.set(lhs, rhs, relation)
+            }
+        }
+    }
+    let mut left = (0..enabled.len())
+        .filter(|k| !enabled[*k].1)
+        .max_by_key(|k| rect(enabled[*k].0).top_right().x);
+    // Relation of `lhs` to `rhs` in a stored layout
+    let known_direction = |lhs: usize, rhs: usize| {
+        let (lhs_id, rhs_id) = (&entries[enabled[lhs].0].id, &entries[enabled[rhs].0].id);
+        known.iter().find_map(
+            |relation| match (&relation.lhs == lhs_id, &relation.rhs == rhs_id) {
+                (true, true) => Some(relation.direction),
+                _ if &relation.lhs == rhs_id && &relation.rhs == lhs_id => {
+                    Some(relation.direction.inverse())
+                }
+                _ => None,
+            },
+        )
+    };
+    let chain_new_outputs = |relations: &mut RelationMatrix<Relation>, left: &mut Option<usize>| {
+        for k in (0..enabled.len()).filter(|k| enabled[*k].1) {
+            // Outputs that were already enabled, or new ones placed before
+            let mut placed = (0..enabled.len()).filter(|j| !enabled[*j].1 || *j < k);
+            if let Some((j, direction)) = placed.find_map(|j| Some((j, known_direction(j, k)?))) {
+                relations.set(j, k, Some(Relation::Adjacent(direction)));
+                continue;
+            }
+            if let Some(left) = left {
+                relations.set(*left, k, Some(Relation::Adjacent(Direction::LeftOf)))
+            }
+            *left = Some(k)
+        }
+    };
+    chain_new_outputs(&mut relations, &mut left);
+    if !relations.is_single_connected_component() {
+        // Previous layout was not connected : place all outputs in a row, from left to right
+        let mut order = Vec::from_iter((0..enabled.len()).filter(|k| !enabled[*k].1));
+        order.sort_by_key(|k| rect(enabled[*k].0).bottom_left.x);
+        relations = RelationMatrix::new(enabled.len());
+        for pair in order.windows(2) {
+            relations.set(
+                pair[0],
+                pair[1],
+                Some(Relation::Adjacent(Direction::LeftOf)),
             )
-            .map(|(output, preferred_mode)| output.transformed_size(preferred_mode)),
+        }
+        let mut left = order.last().copied();
+        chain_new_outputs(&mut relations, &mut left)
+    }
+
+    let sizes = Vec::from_iter(enabled.iter().map(|(i, _)| {
+        let size = entries[*i].state.rect().expect("enabled").size;
+        Vec2di::new(size.x as i32, size.y as i32)
+    }));
+    let mut gaps = HashMap::new();
+    for (rhs, (rhs_entry, _)) in enabled.iter().enumerate() {
+        for (lhs, (lhs_entry, _)) in enabled[..rhs].iter().enumerate() {
+            let gap = bezel::gap_between(&bezels, &entries[*lhs_entry].id, &entries[*rhs_entry].id);
+            if gap != 0 {
+                gaps.insert((lhs, rhs), gap);
+            }
+        }
+    }
+    // Relations of new outputs are defaults, dropped before those kept from the previous layout
+    let importance = |lhs: usize, rhs: usize| [lhs, rhs].iter().filter(|k| !enabled[**k].1).count();
+    let ids = Vec::from_iter(enabled.iter().map(|(i, _)| &entries[*i].id));
+    let positions = relaxed_positions(&sizes, relations, &gaps, importance, &ids)?;
+    for ((i, _), position) in Iterator::zip(enabled.iter(), positions) {
+        if let OutputState::Enabled { bottom_left, .. } = &mut entries[*i].state {
+            *bottom_left = position
+        }
+    }
+
+    let primary = layout
+        .primary()
+        .filter(|id| enabled.iter().any(|(i, _)| &entries[*i].id == *id))
+        .cloned();
+    let info = LayoutInfo::from(entries, primary).with_bezels(bezels);
+    if !info.unsupported_causes.is_empty() {
+        log::warn!(
+            "auto layout: unsupported result: {:?}",
+            info.unsupported_causes
         );
-        // TODO handle failure
-        // overlap -> add relations
-        // failure -> remove relations ?
-        compute_rects::compute_optimized_bottom_left_coords(&output_sizes, &self.relations).unwrap()
+        return None;
     }
+    let mut auto = info.layout;
+    auto.copy_output_settings(layout);
+    Some(auto)
 }
 
-/// Compute rects optimization problem code (lengthy).
-mod compute_rects;
+/// Positions of outputs with `relations`, removing relations until constraints are feasible.
+/// Constraints may conflict when outputs of a layout got other sizes, like a grid of outputs with a new mode.
+///
+/// Relations are removed by increasing `importance`, then from the last outputs.
+/// A relation is only removed if outputs stay connected : unrelated outputs could overlap,
+/// and a tree of relations is always feasible.
+fn relaxed_positions(
+    sizes: &[Vec2di],
+    mut relations: RelationMatrix<Relation>,
+    gaps: &HashMap<(usize, usize), i32>,
+    importance: impl Fn(usize, usize) -> usize,
+    ids: &[&OutputId],
+) -> Option<Vec<Vec2di>> {
+    let mut pairs = Vec::from_iter(
+        (0..sizes.len())
+            .flat_map(|rhs| (0..rhs).map(move |lhs| (lhs, rhs)))
+            .filter(|(lhs, rhs)| relations.get(*lhs, *rhs).is_some()),
+    );
+    pairs.sort_by_key(|(lhs, rhs)| (importance(*lhs, *rhs), Reverse(*rhs), Reverse(*lhs)));
+    let mut pairs = pairs.into_iter();
+    loop {
+        if let Ok(positions) =
+            compute_rects::compute_optimized_bottom_left_coords(sizes, &relations, gaps)
+        {
+            return Some(positions);
+        }
+        let removed = pairs.find(|(lhs, rhs)| {
+            let relation = relations.get(*lhs, *rhs);
+            relations.set(*lhs, *rhs, None);
+            let connected = relations.is_single_connected_component();
+            if !connected {
+                relations.set(*lhs, *rhs, relation)
+            }
+            connected
+        });
+        match removed {
+            Some((lhs, rhs)) => log::info!(
+                "auto layout: infeasible constraints, dropping relation between {} and {}",
+                ids[lhs],
+                ids[rhs]
+            ),
+            None => {
+                log::warn!("auto layout: infeasible constraints");
+                return None;
+            }
+        }
+    }
+}
+
+/// Settings of outputs enabled by automatic layouts, in configuration :
+/// `"output_defaults": {"HDMI-1": {"mode": "1920x1080@60", "rotate": "left"}, "DP-3": {"disabled": true}}`.
+/// Outputs are designated by their name or displayed [`OutputId`].
+/// This helps with monitors advertising a bad preferred mode, like TVs preferring 4K with a 1080p panel.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "OutputDefaultsConfig")]
+pub struct OutputDefaults {
+    /// Mode used instead of the preferred one.
+    pub mode: Option<Mode>,
+    pub transform: Transform,
+    /// Keep the output disabled.
+    pub disabled: bool,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputDefaultsConfig {
+    mode: Option<String>,
+    rotate: Option<String>,
+    #[serde(default)]
+    disabled: bool,
+}
+
+impl TryFrom<OutputDefaultsConfig> for OutputDefaults {
+    type Error = String;
+    fn try_from(config: OutputDefaultsConfig) -> Result<OutputDefaults, String> {
+        Ok(OutputDefaults {
+            mode: config.mode.map(|mode| mode.parse()).transpose()?,
+            transform: match config.rotate {
+                Some(transform) => transform.parse()?,
+                None => Transform::default(),
+            },
+            disabled: config.disabled,
+        })
+    }
+}
+
+/// Relation between two outputs preferred for automatic layouts, by output name or displayed [`OutputId`] :
+/// `{"outputs": ["DP-1", "eDP-1"], "direction": "right_of"}` places `DP-1` right of `eDP-1`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelationPreference {
+    pub outputs: [String; 2],
+    pub direction: Direction,
+}
+
+/// Relations of `config` between connected outputs of `layout`, designated by name (from `names`) or [`OutputId`].
+pub fn resolve_relation_preferences(
+    config: &[RelationPreference],
+    layout: &Layout,
+    names: &HashMap<OutputId, String>,
+) -> Vec<OutputRelation> {
+    let find = |designation: &str| {
+        layout.connected_outputs().find(|id| {
+            names.get(*id).map(String::as_str) == Some(designation) || id.to_string() == designation
+        })
+    };
+    Vec::from_iter(config.iter().filter_map(|preference| {
+        let lhs = find(&preference.outputs[0])?.clone();
+        let rhs = find(&preference.outputs[1])?.clone();
+        Some(match lhs < rhs {
+            true => OutputRelation {
+                lhs,
+                rhs,
+                direction: preference.direction,
+            },
+            false => OutputRelation {
+                lhs: rhs,
+                rhs: lhs,
+                direction: preference.direction.inverse(),
+            },
+        })
+    }))
+}
+
+/// Defaults of `config` for the outputs of `layout`, designated by name (from `names`) or [`OutputId`].
+pub fn resolve_output_defaults(
+    config: &HashMap<String, OutputDefaults>,
+    layout: &Layout,
+    names: &HashMap<OutputId, String>,
+) -> HashMap<OutputId, OutputDefaults> {
+    let find = |id: &OutputId| {
+        let by_name = names.get(id).and_then(|name| config.get(name));
+        by_name.or_else(|| config.get(&id.to_string())).cloned()
+    };
+    HashMap::from_iter(
+        layout
+            .connected_outputs()
+            .filter_map(|id| Some((id.clone(), find(id)?))),
+    )
+}
+
+#[cfg(test)]
+#[test]
+fn test_auto_layout() {
+    let mode = |x, y| Mode {
+        size: Vec2d::new(x, y),
+        frequency: 60,
+        refresh: None,
+    };
+    let id = |name: &str| OutputId::Name(name.into());
+    let entries = [
+        OutputEntry::enabled_for_test("a", Vec2d::new(1920, 1080), Vec2d::new(0, 0)),
+        OutputEntry {
+            id: id("b"),
+            state: OutputState::Disabled { reason: None },
+        },
+        OutputEntry {
+            id: id("c"),
+            state: OutputState::Disabled {
+                reason: Some(super::DisabledReason::UserDisabled),
+            },
+        },
+    ];
+    let layout = LayoutInfo::from_iter(entries, Some(id("a"))).layout;
+    let no_bezels = |_: &[OutputEntry]| Vec::new();
+    let preferred_modes = HashMap::from([
+        (id("a"), mode(1920, 1080)),
+        (id("b"), mode(2560, 1440)),
+        (id("c"), mode(1920, 1080)),
+    ]);
+    let auto = auto_layout(&layout, &preferred_modes, &HashMap::new(), &[], no_bezels).unwrap();
+    let rect = |i: usize| auto.output_entries()[i].state.rect();
+    // New output at the right, centered vertically ; permanently disabled one stays disabled
+    let (a, b) = (rect(0).unwrap(), rect(1).unwrap());
+    assert_eq!(a.top_right().x, b.bottom_left.x);
+    assert_eq!(2 * a.bottom_left.y + 1080, 2 * b.bottom_left.y + 1440);
+    assert_eq!(rect(2), None);
+    assert_eq!(auto.primary(), Some(&id("a")));
+    // Bezels separate adjacent outputs
+    let bezels = |_: &[OutputEntry]| vec![Bezel::new(id("a"), id("b"), 20)];
+    let auto = auto_layout(&layout, &preferred_modes, &HashMap::new(), &[], bezels).unwrap();
+    let (a, b) = (
+        auto.output_entries()[0].state.rect().unwrap(),
+        auto.output_entries()[1].state.rect().unwrap(),
+    );
+    assert_eq!(a.top_right().x + 20, b.bottom_left.x);
+    let overlap = |_: &[OutputEntry]| vec![Bezel::new(id("a"), id("b"), -20)];
+    let auto = auto_layout(&layout, &preferred_modes, &HashMap::new(), &[], overlap).unwrap();
+    let b = auto.output_entries()[1].state.rect().unwrap();
+    assert_eq!(a.top_right().x - 20, b.bottom_left.x);
+    // Known relations from stored layouts take precedence over the row
+    let known = [OutputRelation {
+        lhs: id("a"),
+        rhs: id("b"),
+        direction: Direction::Under,
+    }];
+    let auto = auto_layout(
+        &layout,
+        &preferred_modes,
+        &HashMap::new(),
+        &known,
+        no_bezels,
+    )
+    .unwrap();
+    let (a, b) = (
+        auto.output_entries()[0].state.rect().unwrap(),
+        auto.output_entries()[1].state.rect().unwrap(),
+    );
+    assert_eq!(a.top_right().y, b.bottom_left.y);
+    // Preferences from configuration, by name or id, in the stored orientation
+    let config: Vec<RelationPreference> = serde_json::from_str(
+        r#"[{"outputs": ["b", "a"], "direction": "above"}, {"outputs": ["x", "a"], "direction": "left_of"}]"#,
+    )
+    .unwrap();
+    let names = HashMap::from([(id("b"), "b".to_string())]);
+    assert_eq!(
+        resolve_relation_preferences(&config, &layout, &names),
+        known
+    );
+    // A single output needs no solver
+    let single = LayoutInfo::from_iter([layout.output_entries()[1].clone()], None).layout;
+    let auto = auto_layout(&single, &preferred_modes, &HashMap::new(), &[], no_bezels).unwrap();
+    assert_eq!(
+        auto.output_entries()[0].state.rect().unwrap().bottom_left,
+        Vec2d::new(0, 0)
+    );
+    // Nothing to enable
+    let none = HashMap::new();
+    let disabled = LayoutInfo::from_iter(
+        [OutputEntry {
+            id: id("b"),
+            state: OutputState::Disabled { reason: None },
+        }],
+        None,
+    );
+    assert!(auto_layout(&disabled.layout, &none, &HashMap::new(), &[], no_bezels).is_none());
+    // Defaults from configuration replace the preferred mode, or keep outputs disabled
+    let config: HashMap<String, OutputDefaults> = serde_json::from_str(
+        r#"{"b": {"mode": "1920x1080@60", "rotate": "left"}, "c": {"disabled": true}}"#,
+    )
+    .unwrap();
+    let names = HashMap::from([(id("b"), "b".to_string())]);
+    let defaults = resolve_output_defaults(&config, &layout, &names);
+    assert_eq!(defaults.len(), 2);
+    let auto = auto_layout(&layout, &preferred_modes, &defaults, &[], no_bezels).unwrap();
+    assert_eq!(
+        auto.output_entries()[1].state.rect().unwrap().size,
+        Vec2d::new(1080, 1920)
+    );
+    let new_output = LayoutInfo::from_iter([layout.output_entries()[1].clone()], None).layout;
+    let defaults = HashMap::from([(id("b"), config["c"].clone())]);
+    assert!(auto_layout(&new_output, &preferred_modes, &defaults, &[], no_bezels).is_none());
+    assert!(serde_json::from_str::<OutputDefaults>(r#"{"rotate": "upside"}"#).is_err());
+    // Substituted modes keep relations between outputs
+    let mut stored =
+        auto_layout(&layout, &preferred_modes, &HashMap::new(), &[], no_bezels).unwrap();
+    stored.set_name(Some("desk".into()));
+    let substituted = with_modes(
+        &stored,
+        &HashMap::from([(id("a"), mode(1280, 720))]),
+        no_bezels,
+    );
+    let rect = |i: usize| substituted.output_entries()[i].state.rect().unwrap();
+    let (a, b) = (rect(0), rect(1));
+    assert_eq!(a.size, Vec2d::new(1280, 720));
+    assert_eq!(a.top_right().x, b.bottom_left.x);
+    assert_eq!(substituted.name(), Some("desk"));
+    // Conflicting relations are dropped from the last outputs, keeping outputs connected
+    let sizes = [Vec2di::new(100, 100); 3];
+    let mut relations = RelationMatrix::new(3);
+    relations.set(0, 1, Some(Relation::Adjacent(Direction::LeftOf)));
+    relations.set(1, 2, Some(Relation::Adjacent(Direction::LeftOf)));
+    relations.set(2, 0, Some(Relation::Adjacent(Direction::LeftOf)));
+    let ids = [&id("a"), &id("b"), &id("c")];
+    let positions = relaxed_positions(&sizes, relations, &HashMap::new(), |_, _| 0, &ids).unwrap();
+    assert_eq!(positions[1].x - positions[0].x, 100);
+    assert_eq!(positions[0].x - positions[2].x, 100);
+}
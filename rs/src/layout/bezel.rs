@@ -0,0 +1,114 @@
+use super::{OutputEntry, OutputId, OutputState};
+use crate::geometry::Vec2d;
+use std::collections::HashMap;
+
+/// Gap between two adjacent outputs, compensating for their physical bezels.
+/// Outputs separated by exactly `gap` layout pixels are considered adjacent, so the layout stays supported.
+/// A negative gap is an overlap, like projectors blending their edges.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct Bezel {
+    /// Sorted pair.
+    pub outputs: (OutputId, OutputId),
+    pub gap: i32,
+}
+
+impl Bezel {
+    pub fn new(a: OutputId, b: OutputId, gap: i32) -> Bezel {
+        let outputs = match a <= b {
+            true => (a, b),
+            false => (b, a),
+        };
+        Bezel { outputs, gap }
+    }
+
+    pub fn is_between(&self, a: &OutputId, b: &OutputId) -> bool {
+        (&self.outputs.0, &self.outputs.1) == (a, b) || (&self.outputs.0, &self.outputs.1) == (b, a)
+    }
+}
+
+/// Gap between `a` and `b`, or 0 if none is defined.
+pub fn gap_between(bezels: &[Bezel], a: &OutputId, b: &OutputId) -> i32 {
+    bezels
+        .iter()
+        .find(|bezel| bezel.is_between(a, b))
+        .map_or(0, |bezel| bezel.gap)
+}
+
+/// Bezel width in configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BezelWidth {
+    /// Layout pixels, used as is. Negative widths overlap outputs.
+    Pixels(i32),
+    /// Physical width, converted using the densities of both outputs.
+    Millimeters(u32),
+}
+
+/// Bezel between two outputs in the configuration file : `{"outputs": ["DP-1", "DP-2"], "millimeters": 12}`.
+/// Outputs are designated by their displayed [`OutputId`] (EDID in hexadecimal, or output name).
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct BezelConfig {
+    pub outputs: [String; 2],
+    #[serde(flatten)]
+    pub width: BezelWidth,
+}
+
+/// Compute bezels in layout pixels for the enabled outputs of `outputs`.
+/// Bezels with unknown or disabled outputs are ignored, and overlaps covering a whole side of an output.
+/// Millimeter widths use `physical_sizes` (unrotated), and are ignored if a size is unknown.
+pub fn resolve_bezels(
+    config: &[BezelConfig],
+    outputs: &[OutputEntry],
+    physical_sizes: &HashMap<OutputId, Vec2d<u32>>,
+) -> Vec<Bezel> {
+    let find_enabled = |name: &str| {
+        outputs
+            .iter()
+            .find(|entry| entry.id.to_string() == name && entry.state.rect().is_some())
+    };
+    // Mean pixels per millimeter over both axes
+    let density = |entry: &OutputEntry| -> Option<f64> {
+        match (&entry.state, entry.state.rect()) {
+            (OutputState::Enabled { transform, .. }, Some(rect)) => {
+                let mm = physical_sizes.get(&entry.id)?.apply(transform);
+                match mm.x > 0 && mm.y > 0 {
+                    true => Some(
+                        0.5 * (f64::from(rect.size.x) / f64::from(mm.x)
+                            + f64::from(rect.size.y) / f64::from(mm.y)),
+                    ),
+                    false => None,
+                }
+            }
+            _ => None,
+        }
+    };
+    config
+        .iter()
+        .filter_map(|bezel| {
+            let a = find_enabled(&bezel.outputs[0])?;
+            let b = find_enabled(&bezel.outputs[1])?;
+            let gap = match bezel.width {
+                BezelWidth::Pixels(pixels) => pixels,
+                BezelWidth::Millimeters(mm) => {
+                    let density = 0.5 * (density(a)? + density(b)?);
+                    (f64::from(mm) * density).round() as i32
+                }
+            };
+            let min_side = [a, b]
+                .iter()
+                .filter_map(|entry| entry.state.rect())
+                .map(|rect| rect.size.x.min(rect.size.y))
+                .min()?;
+            if gap < 0 && gap.unsigned_abs() >= min_side {
+                log::warn!(
+                    "bezel between {} and {}: overlap of {} pixels covers an output",
+                    a.id,
+                    b.id,
+                    -gap
+                );
+                return None;
+            }
+            Some(Bezel::new(a.id.clone(), b.id.clone(), gap))
+        })
+        .collect()
+}
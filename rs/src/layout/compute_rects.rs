@@ -1,17 +1,24 @@
-use super::RelationMatrix;
-use crate::geometry::{Direction, InvertibleRelation, Rect, Vec2d, Vec2di};
+use crate::geometry::{Direction, Relation, Vec2d, Vec2di};
+use crate::relation::{InvertibleRelation, RelationMatrix};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::ops::{Add, AddAssign, Mul};
-use std::time::Duration;
+
+/// Quadratic problem solver used without the `osqp` feature.
+#[cfg(any(not(feature = "osqp"), test))]
+mod admm;
 
 #[derive(Debug)]
 pub struct Infeasible;
 
 /// Compute output `bottom_left` coords as an optimization problem with constraints coming from a [`RelationMatrix`].
+/// Adjacent outputs are separated by their `gaps` (bezels), indexed by `(lhs, rhs)` with `lhs < rhs`.
+/// [`Relation::SameAs`] outputs (clones) share both coordinates.
 /// May fail if constraints cannot be met.
 pub fn compute_optimized_bottom_left_coords(
     sizes: &[Vec2di],
-    relations: &RelationMatrix<Direction>,
+    relations: &RelationMatrix<Relation>,
+    gaps: &HashMap<(usize, usize), i32>,
 ) -> Result<Vec<Vec2di>, Infeasible> {
     let n_outputs = sizes.len();
     assert_eq!(n_outputs, relations.size());
@@ -41,22 +48,101 @@ pub fn compute_optimized_bottom_left_coords(
     for rhs in 0..n_outputs {
         for lhs in 0..rhs {
             if let Some(relation) = relations.get(lhs, rhs) {
+                let gap = gaps.get(&(lhs, rhs)).copied().unwrap_or(0);
                 match relation {
-                    Direction::LeftOf => add_leftof_relation(&mut problem, lhs, rhs, &sizes)?,
-                    Direction::RightOf => add_leftof_relation(&mut problem, rhs, lhs, &sizes)?,
-                    Direction::Under => add_under_relation(&mut problem, lhs, rhs, &sizes)?,
-                    Direction::Above => add_under_relation(&mut problem, rhs, lhs, &sizes)?,
+                    Relation::Adjacent(direction) => match direction {
+                        Direction::LeftOf => {
+                            add_leftof_relation(&mut problem, lhs, rhs, sizes, gap)?
+                        }
+                        Direction::RightOf => {
+                            add_leftof_relation(&mut problem, rhs, lhs, sizes, gap)?
+                        }
+                        Direction::Under => add_under_relation(&mut problem, lhs, rhs, sizes, gap)?,
+                        Direction::Above => add_under_relation(&mut problem, rhs, lhs, sizes, gap)?,
+                    },
+                    Relation::SameAs => add_same_as_relation(&mut problem, lhs, rhs)?,
                 }
             }
         }
     }
     // TODO maybe post simplify singleton constraints
-    let settings = osqp::Settings::default()
-        .verbose(false)
-        .time_limit(Some(Duration::from_secs(1)));
-    let mut qp_problem = create_qp_problem(&problem, sizes, &settings).map_err(|_| Infeasible)?;
+    if problem.nb_variables() == 0 {
+        // All coordinates are constants (single output, or fully constrained) : osqp rejects empty problems
+        return problem
+            .coordinate_definitions
+            .iter()
+            .map(|def| -> Result<Vec2di, Infeasible> {
+                Ok(Vec2di {
+                    x: def.x.evaluate(&[])?,
+                    y: def.y.evaluate(&[])?,
+                })
+            })
+            .collect();
+    }
+    let solution = solve_qp(qp_matrices(&problem, sizes))?;
+    // Extract results. For now just round floats into integers.
+    problem
+        .coordinate_definitions
+        .iter()
+        .map(|def| -> Result<Vec2di, Infeasible> {
+            Ok(Vec2di {
+                x: def.x.evaluate(&solution)?,
+                y: def.y.evaluate(&solution)?,
+            })
+        })
+        .collect()
+}
+
+/// Number of problems kept by a [`SolverCache`].
+const CACHE_SIZE: usize = 8;
+
+/// Solver states of recently solved problems, least recently used first.
+/// The same output sets come back on hotplug events : solvers skip their setup,
+/// and start from the previous solution instead of zero.
+struct SolverCache<T> {
+    entries: Vec<(QpMatrices, T)>,
+}
+
+impl<T> SolverCache<T> {
+    const fn new() -> Self {
+        SolverCache {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Remove the state of a problem with the same matrices, to be inserted back after solving.
+    fn take(&mut self, qp: &QpMatrices) -> Option<T> {
+        let index = self.entries.iter().position(|(cached, _)| cached == qp)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    fn insert(&mut self, qp: QpMatrices, state: T) {
+        if self.entries.len() >= CACHE_SIZE {
+            self.entries.remove(0);
+        }
+        self.entries.push((qp, state))
+    }
+}
+
+/// Solve with [`osqp`], which warm starts from the last solution of a cached problem.
+#[cfg(feature = "osqp")]
+fn solve_qp(qp: QpMatrices) -> Result<Vec<f64>, Infeasible> {
+    static CACHE: std::sync::Mutex<SolverCache<osqp::Problem>> =
+        std::sync::Mutex::new(SolverCache::new());
+    let cached = CACHE.lock().unwrap().take(&qp);
+    let mut qp_problem = match cached {
+        Some(qp_problem) => qp_problem,
+        None => {
+            let settings = osqp::Settings::default()
+                .verbose(false)
+                .time_limit(Some(std::time::Duration::from_secs(1)));
+            // osqp only reads the upper triangle of the symmetric matrix p, and rejects any other element
+            let p = osqp::CscMatrix::from(&qp.p).into_upper_tri();
+            osqp::Problem::new(p, &qp.q, &qp.a, &qp.l, &qp.u, &settings).map_err(|_| Infeasible)?
+        }
+    };
     let solution = match qp_problem.solve() {
-        osqp::Status::Solved(solution) => solution,
+        osqp::Status::Solved(solution) => solution.x().to_vec(),
         unsolved => {
             use osqp::Status::*;
             match unsolved {
@@ -73,17 +159,21 @@ pub fn compute_optimized_bottom_left_coords(
             return Err(Infeasible);
         }
     };
-    // Extract results. For now just round floats into integers.
-    problem
-        .coordinate_definitions
-        .iter()
-        .map(|def| -> Result<Vec2di, Infeasible> {
-            Ok(Vec2di {
-                x: def.x.evaluate(solution.x())?,
-                y: def.y.evaluate(solution.x())?,
-            })
-        })
-        .collect()
+    CACHE.lock().unwrap().insert(qp, qp_problem);
+    Ok(solution)
+}
+
+/// Solve with the pure Rust [`admm`] solver, for builds without a C toolchain.
+/// It starts from the last iterates of a cached problem.
+#[cfg(not(feature = "osqp"))]
+fn solve_qp(qp: QpMatrices) -> Result<Vec<f64>, Infeasible> {
+    static CACHE: std::sync::Mutex<SolverCache<admm::Iterates>> =
+        std::sync::Mutex::new(SolverCache::new());
+    let cached = CACHE.lock().unwrap().take(&qp);
+    let iterates = admm::solve(&qp, cached)?;
+    let solution = iterates.x.clone();
+    CACHE.lock().unwrap().insert(qp, iterates);
+    Ok(solution)
 }
 
 // Helpers that are used twice each (LeftOf+RightOf, Above+Under)
@@ -92,10 +182,11 @@ fn add_leftof_relation(
     left: usize,
     right: usize,
     sizes: &[Vec2di],
+    gap: i32,
 ) -> Result<(), Infeasible> {
-    // left.x + left.sx = right.x
+    // left.x + left.sx + gap = right.x
     problem.add_equality_constraint(
-        problem.coordinate_definitions[left].x.clone() + sizes[left].x,
+        problem.coordinate_definitions[left].x.clone() + (sizes[left].x + gap),
         problem.coordinate_definitions[right].x.clone(),
     )?;
     // left.y - right.sy <= right.y <= left.y + lhs.sy
@@ -110,10 +201,11 @@ fn add_under_relation(
     under: usize,
     above: usize,
     sizes: &[Vec2di],
+    gap: i32,
 ) -> Result<(), Infeasible> {
-    // under.y + under.sy = above.y
+    // under.y + under.sy + gap = above.y
     problem.add_equality_constraint(
-        problem.coordinate_definitions[under].y.clone() + sizes[under].y,
+        problem.coordinate_definitions[under].y.clone() + (sizes[under].y + gap),
         problem.coordinate_definitions[above].y.clone(),
     )?;
     // under.x - above.sx <= above.x <= under.x + under.sx
@@ -124,14 +216,37 @@ fn add_under_relation(
     )
 }
 
+fn add_same_as_relation(
+    problem: &mut QpProblemState,
+    lhs: usize,
+    rhs: usize,
+) -> Result<(), Infeasible> {
+    // lhs.x = rhs.x, lhs.y = rhs.y
+    problem.add_equality_constraint(
+        problem.coordinate_definitions[lhs].x.clone(),
+        problem.coordinate_definitions[rhs].x.clone(),
+    )?;
+    problem.add_equality_constraint(
+        problem.coordinate_definitions[lhs].y.clone(),
+        problem.coordinate_definitions[rhs].y.clone(),
+    )
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 
-/// Compute input matrices for an [`osqp`] problem and initialize it.
-fn create_qp_problem(
-    problem: &QpProblemState,
-    sizes: &[Vec2di],
-    settings: &osqp::Settings,
-) -> Result<osqp::Problem, osqp::SetupError> {
+/// Quadratic problem : minimize `1/2 x^T p x + q^T x` subject to `l <= a x <= u`.
+/// Matrices are dense, as problems have a few variables per output.
+#[derive(Debug, Clone, PartialEq)]
+struct QpMatrices {
+    p: RowMatrix<f64>,
+    q: Vec<f64>,
+    a: RowMatrix<f64>,
+    l: Vec<f64>,
+    u: Vec<f64>,
+}
+
+/// Compute input matrices of the quadratic problem.
+fn qp_matrices(problem: &QpProblemState, sizes: &[Vec2di]) -> QpMatrices {
     let n_var = problem.nb_variables();
     let n_coord = problem.coordinate_definitions.len();
     assert_eq!(n_coord, sizes.len());
@@ -152,9 +267,7 @@ fn create_qp_problem(
         let mut c_x = 0.;
         let mut c_array_y = vec![0.; n_var];
         let mut c_y = 0.;
-        for j in 0..n_coord {
-            let size_j = &sizes[j];
-            let coord_j = &problem.coordinate_definitions[j];
+        for (size_j, coord_j) in Iterator::zip(sizes.iter(), &problem.coordinate_definitions) {
             let a_j = f64::from(size_j.x) * f64::from(size_j.y);
             accumulate_carray_c(
                 &mut c_array_x,
@@ -199,7 +312,7 @@ fn create_qp_problem(
             }
         }
     }
-    osqp::Problem::new(&p, &q, &a, &l, &u, settings)
+    QpMatrices { p, q, a, l, u }
 }
 
 fn accumulate_carray_c(
@@ -225,7 +338,7 @@ fn add_a_times_array(acc: &mut [f64], a: f64, array: &[f64]) {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// Row-major ordered matrix.
 struct RowMatrix<T> {
     nrow: usize,
@@ -256,7 +369,8 @@ impl<T> RowMatrix<T> {
         assert!(col < self.ncol);
         (row * self.ncol) + col
     }
-    fn row_major_array<'a>(&'a self) -> &'a [T] {
+    #[cfg(feature = "osqp")]
+    fn row_major_array(&self) -> &[T] {
         &self.array
     }
     fn add_vt_v(&mut self, v: &[T])
@@ -279,12 +393,13 @@ impl<T> RowMatrix<T> {
     }
 }
 
+#[cfg(feature = "osqp")]
 impl<'m> From<&'m RowMatrix<f64>> for osqp::CscMatrix<'static> {
     fn from(matrix: &'m RowMatrix<f64>) -> Self {
         osqp::CscMatrix::from_row_iter_dense(
             matrix.nrow,
             matrix.ncol,
-            matrix.row_major_array().into_iter().cloned(),
+            matrix.row_major_array().iter().cloned(),
         )
     }
 }
@@ -575,7 +690,7 @@ impl Constraint {
         self.min <= value && value <= self.max
     }
     fn is_unconstrained(&self) -> bool {
-        self.min <= i32::MIN / 2 && self.max >= i32::MAX
+        self.min <= i32::MIN / 2 && self.max == i32::MAX
     }
 
     fn merge(&self, other: &Constraint) -> Result<Constraint, Infeasible> {
@@ -588,7 +703,7 @@ impl Constraint {
     }
 }
 
-impl<'a> Add<i32> for &'a Constraint {
+impl Add<i32> for &Constraint {
     type Output = Constraint;
     fn add(self, rhs: i32) -> Constraint {
         Constraint {
@@ -620,7 +735,7 @@ fn test_qp_problem_replace_with_const() {
     let coord1 = Vec2d::new(
         Expression {
             constant: 0,
-            variable: coord0.x.variable.clone(), // index 0 multi use
+            variable: coord0.x.variable, // index 0 multi use
         },
         Expression::constant(42),
     );
@@ -669,7 +784,7 @@ fn test_qp_problem_merge_variables() {
     let coord1 = Vec2d::new(
         Expression {
             constant: 0,
-            variable: coord0.x.variable.clone(), // index 0 multi use
+            variable: coord0.x.variable, // index 0 multi use
         },
         Expression::free_variable(&mut problem), // index 2
     );
@@ -747,3 +862,30 @@ fn test_qp_problem_merge_variables() {
 
     assert_eq!(problem.nb_variables(), problem.dual_constraints.size())
 }
+
+#[cfg(test)]
+#[test]
+fn test_solver_cache() {
+    let qp = |value: f64| QpMatrices {
+        p: RowMatrix::square(1, value),
+        q: vec![0.],
+        a: RowMatrix::empty(1),
+        l: Vec::new(),
+        u: Vec::new(),
+    };
+    let mut cache = SolverCache::new();
+    for i in 0..=CACHE_SIZE {
+        cache.insert(qp(i as f64), i)
+    }
+    // Oldest problem was evicted
+    assert_eq!(cache.take(&qp(0.)), None);
+    assert_eq!(cache.take(&qp(1.)), Some(1));
+    assert_eq!(cache.take(&qp(1.)), None);
+    // Solving again starts from the cached state, with the same result
+    let sizes = [Vec2di::new(1920, 1080), Vec2di::new(1280, 1024)];
+    let mut relations = RelationMatrix::new(2);
+    relations.set(0, 1, Some(Relation::Adjacent(Direction::LeftOf)));
+    let first = compute_optimized_bottom_left_coords(&sizes, &relations, &HashMap::new()).unwrap();
+    let again = compute_optimized_bottom_left_coords(&sizes, &relations, &HashMap::new()).unwrap();
+    assert_eq!(first, again);
+}
@@ -0,0 +1,174 @@
+use super::{Infeasible, QpMatrices, RowMatrix};
+
+/// Penalty of constraint violations in the augmented lagrangian, like osqp defaults.
+const RHO: f64 = 0.1;
+const SIGMA: f64 = 1e-6;
+/// Over-relaxation of iterates.
+const ALPHA: f64 = 1.6;
+const MAX_ITERATIONS: usize = 100_000;
+/// Solutions are rounded to integer coordinates : residuals must be well below a pixel.
+const EPSILON: f64 = 1e-4;
+
+/// Iterates of the solver : solution `x`, constrained values `z` of `a x`, and dual solution `y`.
+pub struct Iterates {
+    pub x: Vec<f64>,
+    z: Vec<f64>,
+    y: Vec<f64>,
+}
+
+/// Solve a quadratic problem with the ADMM iterations of osqp, without its scaling or infeasibility detection.
+/// Iterations start from `start`, the converged iterates of the same problem, or zero.
+/// Problems that do not converge are reported as [`Infeasible`].
+pub fn solve(qp: &QpMatrices, start: Option<Iterates>) -> Result<Iterates, Infeasible> {
+    let (n, m) = (qp.q.len(), qp.l.len());
+    let a = |row: usize, col: usize| qp.a.array[qp.a.linearized_index(row, col)];
+    // Objective weights are squared output areas : scaling them to 1 keeps the minimum
+    // and makes the objective comparable to constraints
+    let scale = (0..n)
+        .map(|i| qp.p.array[qp.p.linearized_index(i, i)])
+        .fold(1., f64::max);
+    let p = |row: usize, col: usize| qp.p.array[qp.p.linearized_index(row, col)] / scale;
+    let q = Vec::from_iter(qp.q.iter().map(|q| q / scale));
+
+    // Linear system of x updates : (p + sigma I + rho a^T a) x = rhs, factorized once
+    let mut system = RowMatrix::square(n, 0.);
+    for row in 0..n {
+        for col in 0..n {
+            let a_t_a: f64 = (0..m).map(|k| a(k, row) * a(k, col)).sum();
+            let index = system.linearized_index(row, col);
+            system.array[index] = p(row, col) + RHO * a_t_a + if row == col { SIGMA } else { 0. };
+        }
+    }
+    let cholesky = Cholesky::new(system)?;
+
+    let Iterates {
+        mut x,
+        mut z,
+        mut y,
+    } = start.unwrap_or(Iterates {
+        x: vec![0.; n],
+        z: vec![0.; m],
+        y: vec![0.; m],
+    });
+    let times_a = |x: &[f64]| {
+        Vec::from_iter((0..m).map(|row| (0..n).map(|col| a(row, col) * x[col]).sum::<f64>()))
+    };
+    for _ in 0..MAX_ITERATIONS {
+        let mut rhs = Vec::from_iter((0..n).map(|i| SIGMA * x[i] - q[i]));
+        for row in 0..m {
+            let weight = RHO * z[row] - y[row];
+            for (col, rhs) in rhs.iter_mut().enumerate() {
+                *rhs += a(row, col) * weight
+            }
+        }
+        let x_tilde = cholesky.solve(rhs);
+        let z_tilde = times_a(&x_tilde);
+        for (x, x_tilde) in Iterator::zip(x.iter_mut(), x_tilde) {
+            *x = ALPHA * x_tilde + (1. - ALPHA) * *x
+        }
+        for row in 0..m {
+            let relaxed = ALPHA * z_tilde[row] + (1. - ALPHA) * z[row];
+            z[row] = (relaxed + y[row] / RHO).clamp(qp.l[row], qp.u[row]);
+            y[row] += RHO * (relaxed - z[row]);
+        }
+
+        let primal_residual = Iterator::zip(times_a(&x).iter(), &z)
+            .map(|(ax, z)| (ax - z).abs())
+            .fold(0., f64::max);
+        let dual_residual = (0..n)
+            .map(|i| {
+                let px: f64 = (0..n).map(|j| p(i, j) * x[j]).sum();
+                let a_t_y: f64 = (0..m).map(|row| a(row, i) * y[row]).sum();
+                (px + q[i] + a_t_y).abs()
+            })
+            .fold(0., f64::max);
+        if primal_residual < EPSILON && dual_residual < EPSILON {
+            return Ok(Iterates { x, z, y });
+        }
+    }
+    Err(Infeasible)
+}
+
+/// Cholesky factorization `l l^T` of a symmetric positive definite matrix.
+struct Cholesky {
+    l: RowMatrix<f64>,
+}
+
+impl Cholesky {
+    fn new(matrix: RowMatrix<f64>) -> Result<Cholesky, Infeasible> {
+        let n = matrix.nrow;
+        let mut l = RowMatrix::square(n, 0.);
+        for row in 0..n {
+            for col in 0..=row {
+                let dot: f64 = (0..col)
+                    .map(|k| {
+                        l.array[l.linearized_index(row, k)] * l.array[l.linearized_index(col, k)]
+                    })
+                    .sum();
+                let value = matrix.array[matrix.linearized_index(row, col)] - dot;
+                let index = l.linearized_index(row, col);
+                l.array[index] = match row == col {
+                    true if value > 0. => value.sqrt(),
+                    true => return Err(Infeasible),
+                    false => value / l.array[l.linearized_index(col, col)],
+                };
+            }
+        }
+        Ok(Cholesky { l })
+    }
+
+    /// Solve `l l^T x = rhs`.
+    fn solve(&self, mut rhs: Vec<f64>) -> Vec<f64> {
+        let l = |row: usize, col: usize| self.l.array[self.l.linearized_index(row, col)];
+        let n = rhs.len();
+        for row in 0..n {
+            let dot: f64 = (0..row).map(|k| l(row, k) * rhs[k]).sum();
+            rhs[row] = (rhs[row] - dot) / l(row, row);
+        }
+        for row in (0..n).rev() {
+            let dot: f64 = (row + 1..n).map(|k| l(k, row) * rhs[k]).sum();
+            rhs[row] = (rhs[row] - dot) / l(row, row);
+        }
+        rhs
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_admm() {
+    // min (x0 - 3)^2 + (x1 + 1)^2 with -2 <= x1 - x0 <= 2, and x0 <= 10
+    let mut a = RowMatrix::empty(2);
+    a.add_row([-1., 1.].into_iter());
+    a.add_row([1., 0.].into_iter());
+    let mut p = RowMatrix::square(2, 0.);
+    p.add_vt_v(&[2_f64.sqrt(), 0.]);
+    p.add_vt_v(&[0., 2_f64.sqrt()]);
+    let qp = QpMatrices {
+        p,
+        q: vec![-6., 2.],
+        a,
+        l: vec![-2., f64::from(i32::MIN)],
+        u: vec![2., 10.],
+    };
+    let iterates = solve(&qp, None).unwrap();
+    let x = iterates.x.clone();
+    assert!(
+        (x[0] - 2.).abs() < 1e-3 && (x[1] - 0.).abs() < 1e-3,
+        "{:?}",
+        x
+    );
+    // Converged iterates are a fixed point
+    let warm = solve(&qp, Some(iterates)).unwrap();
+    assert!(Iterator::zip(x.iter(), &warm.x).all(|(x, warm)| (x - warm).abs() < 1e-3));
+    // x0 <= 10 and x0 >= 11
+    let mut a = RowMatrix::empty(2);
+    a.add_row([1., 0.].into_iter());
+    a.add_row([1., 0.].into_iter());
+    let infeasible = QpMatrices {
+        a,
+        l: vec![f64::from(i32::MIN), 11.],
+        u: vec![10., f64::from(i32::MAX)],
+        ..qp
+    };
+    assert!(solve(&infeasible, None).is_err());
+}
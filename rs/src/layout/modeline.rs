@@ -0,0 +1,287 @@
+use super::Mode;
+use crate::geometry::Vec2d;
+
+/// Sync pulse polarity of a modeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    Positive,
+    Negative,
+}
+
+/// Detailed timings of a mode, in X modeline format : `173.00 1920 2048 2248 2576 1080 1083 1088 1120 -hsync +vsync`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Modeline {
+    /// Pixel clock in kHz.
+    pub clock: u32,
+    /// Display, sync start, sync end and total pixels of lines.
+    pub horizontal: [u16; 4],
+    /// Display, sync start, sync end and total lines of frames.
+    pub vertical: [u16; 4],
+    pub hsync: Option<Polarity>,
+    pub vsync: Option<Polarity>,
+}
+
+impl Modeline {
+    /// Timings from the VESA Coordinated Video Timings 1.1 formulas, like the `cvt` tool.
+    /// Reduced blanking lowers the pixel clock, which helps with bandwidth-limited links, but is only supported by LCD monitors.
+    pub fn cvt(size: Vec2d<u32>, refresh: u32, reduced_blanking: bool) -> Modeline {
+        const H_GRANULARITY: u32 = 8;
+        const MIN_V_PORCH: u32 = 3;
+        const MIN_V_BPORCH: u32 = 6;
+        const CLOCK_STEP: u32 = 250;
+        let hdisplay = size.x - size.x % H_GRANULARITY;
+        let vdisplay = size.y;
+        // Vertical sync width encodes the aspect ratio
+        let aspect =
+            |num: u32, den: u32| vdisplay.is_multiple_of(den) && vdisplay * num / den == size.x;
+        let vsync = match () {
+            _ if aspect(4, 3) => 4,
+            _ if aspect(16, 9) => 5,
+            _ if aspect(16, 10) => 6,
+            _ if aspect(5, 4) || aspect(15, 9) => 7,
+            _ => 10,
+        };
+        let field_period = 1_000_000. / f64::from(refresh);
+
+        // Horizontal period in microseconds, and totals
+        let (hperiod, htotal, hsync_end, hsync_width, vtotal, polarity) = if reduced_blanking {
+            const MIN_VBLANK: f64 = 460.;
+            const H_SYNC: u32 = 32;
+            const H_BLANK: u32 = 160;
+            let hperiod = ((field_period - MIN_VBLANK) / f64::from(vdisplay)) as f32;
+            let vblank = (MIN_VBLANK / f64::from(hperiod)) as u32 + 1;
+            let vblank = vblank.max(MIN_V_PORCH + vsync + MIN_V_BPORCH);
+            let polarity = (Polarity::Positive, Polarity::Negative);
+            let htotal = hdisplay + H_BLANK;
+            (
+                hperiod,
+                htotal,
+                hdisplay + H_BLANK / 2,
+                H_SYNC,
+                vdisplay + vblank,
+                polarity,
+            )
+        } else {
+            const MIN_VSYNC_BP: f64 = 550.;
+            const HSYNC_PERCENTAGE: u32 = 8;
+            const C_PRIME: f32 = 30.;
+            const M_PRIME: f32 = 300.;
+            let hperiod =
+                ((field_period - MIN_VSYNC_BP) / f64::from(vdisplay + MIN_V_PORCH)) as f32;
+            let vsync_and_back_porch = (MIN_VSYNC_BP / f64::from(hperiod)) as u32 + 1;
+            let vsync_and_back_porch = vsync_and_back_porch.max(vsync + MIN_V_BPORCH);
+            let blank_percentage = (C_PRIME - M_PRIME * hperiod / 1000.).max(20.);
+            let hblank = (hdisplay as f32 * blank_percentage / (100. - blank_percentage)) as u32;
+            let hblank = hblank - hblank % (2 * H_GRANULARITY);
+            let htotal = hdisplay + hblank;
+            let hsync_width = htotal * HSYNC_PERCENTAGE / 100;
+            let hsync_width = hsync_width - hsync_width % H_GRANULARITY;
+            let polarity = (Polarity::Negative, Polarity::Positive);
+            let vtotal = vdisplay + vsync_and_back_porch + MIN_V_PORCH;
+            (
+                hperiod,
+                htotal,
+                hdisplay + hblank / 2,
+                hsync_width,
+                vtotal,
+                polarity,
+            )
+        };
+        let clock = (htotal as f32 * 1000. / hperiod) as u32;
+        let vsync_start = vdisplay + MIN_V_PORCH;
+        let timing = |v: u32| u16::try_from(v).unwrap_or(u16::MAX);
+        Modeline {
+            clock: clock - clock % CLOCK_STEP,
+            horizontal: [hdisplay, hsync_end - hsync_width, hsync_end, htotal].map(timing),
+            vertical: [vdisplay, vsync_start, vsync_start + vsync, vtotal].map(timing),
+            hsync: Some(polarity.0),
+            vsync: Some(polarity.1),
+        }
+    }
+
+    /// Mode with the exact refresh rate, like backend modes.
+    pub fn mode(&self) -> Mode {
+        let dots = u64::from(self.horizontal[3]) * u64::from(self.vertical[3]);
+        Mode::from_refresh(
+            Vec2d::new(self.horizontal[0].into(), self.vertical[0].into()),
+            ((u64::from(self.clock) * 1_000_000 + dots / 2) / dots) as u32,
+        )
+    }
+
+    /// Mode name in the style of the `cvt` tool : `1920x1080_60`.
+    pub fn name(&self) -> String {
+        let mode = self.mode();
+        format!("{}x{}_{}", mode.size.x, mode.size.y, mode.frequency)
+    }
+}
+
+impl std::fmt::Display for Modeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [h0, h1, h2, h3] = self.horizontal;
+        let [v0, v1, v2, v3] = self.vertical;
+        write!(f, "{}.{:02}", self.clock / 1000, self.clock % 1000 / 10)?;
+        write!(
+            f,
+            " {} {} {} {} {} {} {} {}",
+            h0, h1, h2, h3, v0, v1, v2, v3
+        )?;
+        let polarity = |p: Polarity| match p {
+            Polarity::Positive => '+',
+            Polarity::Negative => '-',
+        };
+        if let Some(hsync) = self.hsync {
+            write!(f, " {}hsync", polarity(hsync))?
+        }
+        if let Some(vsync) = self.vsync {
+            write!(f, " {}vsync", polarity(vsync))?
+        }
+        Ok(())
+    }
+}
+
+/// X modeline, with an optional quoted name before timings which is ignored.
+impl std::str::FromStr for Modeline {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Modeline, String> {
+        let invalid = |what: &str| format!("invalid modeline {}: {}", what, s);
+        let mut words = s.split_whitespace().peekable();
+        if words.peek().is_some_and(|w| w.starts_with('"')) {
+            // Names may contain spaces
+            while !words
+                .next()
+                .is_some_and(|w| w.ends_with('"') && w.len() > 1)
+            {
+                if words.peek().is_none() {
+                    return Err(invalid("name"));
+                }
+            }
+        }
+        let mhz: f64 = match words.next().map(str::parse) {
+            Some(Ok(mhz)) if mhz > 0. => mhz,
+            _ => return Err(invalid("clock")),
+        };
+        let mut timings = [0_u16; 8];
+        for timing in timings.iter_mut() {
+            *timing = match words.next().map(str::parse) {
+                Some(Ok(v)) => v,
+                _ => return Err(invalid("timings")),
+            }
+        }
+        let (horizontal, vertical) = (
+            [timings[0], timings[1], timings[2], timings[3]],
+            [timings[4], timings[5], timings[6], timings[7]],
+        );
+        let ordered = |t: [u16; 4]| t[0] > 0 && t[0] <= t[1] && t[1] <= t[2] && t[2] <= t[3];
+        if !ordered(horizontal) || !ordered(vertical) {
+            return Err(invalid("timings order"));
+        }
+        let (mut hsync, mut vsync) = (None, None);
+        for flag in words {
+            match flag.to_ascii_lowercase().as_str() {
+                "+hsync" => hsync = Some(Polarity::Positive),
+                "-hsync" => hsync = Some(Polarity::Negative),
+                "+vsync" => vsync = Some(Polarity::Positive),
+                "-vsync" => vsync = Some(Polarity::Negative),
+                _ => return Err(format!("unsupported modeline flag {}: {}", flag, s)),
+            }
+        }
+        Ok(Modeline {
+            clock: (mhz * 1000.).round() as u32,
+            horizontal,
+            vertical,
+            hsync,
+            vsync,
+        })
+    }
+}
+
+/// Custom mode for an output in configuration, with timings computed by CVT or given as modeline :
+/// `{"output": "DP-1", "cvt": "2560x1080@75"}` (with optional `"reduced_blanking": true`),
+/// or `{"output": "DP-1", "modeline": "173.00 1920 2048 2248 2576 1080 1083 1088 1120 -hsync +vsync"}`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "CustomModeConfig")]
+pub struct CustomMode {
+    /// Output name, like `DP-1`.
+    pub output: String,
+    pub modeline: Modeline,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CustomModeConfig {
+    output: String,
+    cvt: Option<String>,
+    #[serde(default)]
+    reduced_blanking: bool,
+    modeline: Option<String>,
+}
+
+impl TryFrom<CustomModeConfig> for CustomMode {
+    type Error = String;
+    fn try_from(config: CustomModeConfig) -> Result<CustomMode, String> {
+        let modeline = match (config.cvt, config.modeline) {
+            (Some(cvt), None) => {
+                let mode: Mode = cvt.parse()?;
+                Modeline::cvt(mode.size, mode.frequency, config.reduced_blanking)
+            }
+            (None, Some(modeline)) => modeline.parse()?,
+            _ => {
+                return Err(format!(
+                    "custom mode for {}: expected one of cvt or modeline",
+                    config.output
+                ))
+            }
+        };
+        Ok(CustomMode {
+            output: config.output,
+            modeline,
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_modeline() {
+    // Reference values from the cvt tool
+    let cvt = Modeline::cvt(Vec2d::new(1920, 1080), 60, false);
+    assert_eq!(
+        cvt.to_string(),
+        "173.00 1920 2048 2248 2576 1080 1083 1088 1120 -hsync +vsync"
+    );
+    assert_eq!(cvt.mode(), "1920x1080@60".parse().unwrap());
+    assert_eq!(cvt.name(), "1920x1080_60");
+    let reduced = Modeline::cvt(Vec2d::new(1920, 1080), 60, true);
+    assert_eq!(
+        reduced.to_string(),
+        "138.50 1920 1968 2000 2080 1080 1083 1088 1111 +hsync -vsync"
+    );
+    let ultrawide = Modeline::cvt(Vec2d::new(2560, 1080), 75, false);
+    assert_eq!(
+        ultrawide.to_string(),
+        "294.00 2560 2744 3016 3472 1080 1083 1093 1130 -hsync +vsync"
+    );
+
+    let parsed: Modeline =
+        "\"1920x1080 60\" 173.00 1920 2048 2248 2576 1080 1083 1088 1120 -HSync +VSync"
+            .parse()
+            .unwrap();
+    assert_eq!(parsed, cvt);
+    assert!("173 1920 2048 2248 2576 1080 1083 1088"
+        .parse::<Modeline>()
+        .is_err());
+    assert!("173 1920 1900 2248 2576 1080 1083 1088 1120"
+        .parse::<Modeline>()
+        .is_err());
+    assert!("173 1920 2048 2248 2576 1080 1083 1088 1120 interlace"
+        .parse::<Modeline>()
+        .is_err());
+
+    let config: Vec<CustomMode> = serde_json::from_str(
+        r#"[{"output": "DP-1", "cvt": "1920x1080@60", "reduced_blanking": true},
+            {"output": "DP-2", "modeline": "173 1920 2048 2248 2576 1080 1083 1088 1120 -hsync +vsync"}]"#,
+    )
+    .unwrap();
+    assert_eq!(config[0].modeline, reduced);
+    assert_eq!(config[1].modeline, cvt);
+    assert!(serde_json::from_str::<CustomMode>(r#"{"output": "DP-1"}"#).is_err());
+}
@@ -0,0 +1,232 @@
+use super::{Layout, LayoutInfo, OutputId, OutputState};
+use crate::geometry::{Direction, Rect, Vec2d};
+use std::collections::{HashMap, VecDeque};
+
+/// Layout of enabled outputs in physical space, in millimeters.
+///
+/// Pixel coordinates cannot represent physical positions on mixed-DPI setups : a pixel has a different size on each output.
+/// This representation places outputs as they sit on the desk.
+/// Conversions between the two spaces keep the adjacency relations (pixel layouts stay gap-free),
+/// and scale the offsets between adjacent outputs using their densities.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PhysicalLayout {
+    /// Output rects in millimeters, sorted by [`OutputId`] like [`Layout`].
+    pub outputs: Vec<(OutputId, Rect)>,
+}
+
+impl PhysicalLayout {
+    /// Build from a pixel layout and physical output sizes (unrotated, usually from EDID).
+    /// Fails if a size is unknown or if outputs are not all adjacent.
+    pub fn from_layout(
+        layout: &Layout,
+        physical_sizes: &HashMap<OutputId, Vec2d<u32>>,
+    ) -> Option<PhysicalLayout> {
+        let mut ids = Vec::new();
+        let mut pixel_rects = Vec::new();
+        let mut mm_sizes = Vec::new();
+        for entry in layout.output_entries() {
+            if let (Some(rect), OutputState::Enabled { transform, .. }) =
+                (entry.state.rect(), &entry.state)
+            {
+                ids.push(entry.id.clone());
+                pixel_rects.push(rect);
+                mm_sizes.push(physical_sizes.get(&entry.id)?.apply(transform));
+            }
+        }
+        let positions = transpose_placement(&pixel_rects, &mm_sizes)?;
+        let outputs = Iterator::zip(
+            ids.into_iter(),
+            Iterator::zip(positions.into_iter(), mm_sizes),
+        )
+        .map(|(id, (bottom_left, size))| (id, Rect { bottom_left, size }))
+        .collect();
+        Some(PhysicalLayout { outputs })
+    }
+
+    /// Compute pixel positions for the enabled outputs of `layout` from this physical arrangement.
+    /// Output modes, transforms and scales are taken from `layout`.
+    /// Fails if outputs of `layout` are missing or if physical rects are not all adjacent.
+    pub fn to_layout(&self, layout: &Layout) -> Option<LayoutInfo> {
+        let mut pixel_sizes = Vec::new();
+        let mut mm_rects = Vec::new();
+        for (id, mm_rect) in &self.outputs {
+            let entry = layout.output_entries().iter().find(|e| &e.id == id)?;
+            pixel_sizes.push(entry.state.rect()?.size);
+            mm_rects.push(mm_rect.clone());
+        }
+        let positions = transpose_placement(&mm_rects, &pixel_sizes)?;
+        let mut entries = layout.output_entries().to_vec();
+        for ((id, _), position) in Iterator::zip(self.outputs.iter(), positions) {
+            let entry = entries.iter_mut().find(|e| &e.id == id)?;
+            if let OutputState::Enabled { bottom_left, .. } = &mut entry.state {
+                *bottom_left = position
+            }
+        }
+        Some(
+            LayoutInfo::from(entries, layout.primary().cloned())
+                .with_bezels(layout.bezels().to_vec()),
+        )
+    }
+}
+
+/// Place rects of `target_sizes` with the adjacency relations of `source` rects.
+/// Adjacent rects are placed edge to edge.
+/// Along the shared edge, the offset between rect centers is scaled by the geometric mean of both rect densities.
+/// The density of a rect is its `target / source` size ratio.
+/// Using the geometric mean makes the conversion invertible (up to rounding), and keeps centered rects centered.
+///
+/// Rects are placed in breadth-first order of the adjacency graph, so cycles are not checked for consistency.
+/// Returns [`None`] if rects are not all connected.
+fn transpose_placement(source: &[Rect], target_sizes: &[Vec2d<u32>]) -> Option<Vec<Vec2d<i32>>> {
+    assert_eq!(source.len(), target_sizes.len());
+    if source.is_empty() {
+        return Some(Vec::new());
+    }
+    let density = |i: usize| -> Vec2d<f64> {
+        Vec2d::new(
+            f64::from(target_sizes[i].x) / f64::from(source[i].size.x.max(1)),
+            f64::from(target_sizes[i].y) / f64::from(source[i].size.y.max(1)),
+        )
+    };
+    let target_size = |i: usize| target_sizes[i].map(|v| v as i32);
+    // Center coordinates in f64
+    let center = |bottom_left: Vec2d<i32>, size: Vec2d<u32>| {
+        bottom_left.map(f64::from) + size.map(f64::from) * 0.5
+    };
+
+    let mut positions: Vec<Option<Vec2d<i32>>> = vec![None; source.len()];
+    positions[0] = Some(Vec2d::default());
+    let mut queue = VecDeque::from([0]);
+    while let Some(i) = queue.pop_front() {
+        let pos_i = positions[i].expect("queued rects are placed");
+        let (src_i, dens_i) = (&source[i], density(i));
+        for j in 0..source.len() {
+            if positions[j].is_some() {
+                continue;
+            }
+            let direction = match Rect::adjacent_direction(src_i, &source[j]) {
+                Some(direction) => direction,
+                None => continue,
+            };
+            let (src_j, dens_j) = (&source[j], density(j));
+            let src_offset =
+                center(src_j.bottom_left, src_j.size) - center(src_i.bottom_left, src_i.size);
+            let dst_center_i = center(pos_i, target_sizes[i]);
+            let half_size_j = target_sizes[j].map(|v| 0.5 * f64::from(v));
+            let along_y = || {
+                let offset = src_offset.y * f64::sqrt(dens_i.y * dens_j.y);
+                (dst_center_i.y + offset - half_size_j.y).round() as i32
+            };
+            let along_x = || {
+                let offset = src_offset.x * f64::sqrt(dens_i.x * dens_j.x);
+                (dst_center_i.x + offset - half_size_j.x).round() as i32
+            };
+            positions[j] = Some(match direction {
+                Direction::LeftOf => Vec2d::new(pos_i.x + target_size(i).x, along_y()),
+                Direction::RightOf => Vec2d::new(pos_i.x - target_size(j).x, along_y()),
+                Direction::Under => Vec2d::new(along_x(), pos_i.y + target_size(i).y),
+                Direction::Above => Vec2d::new(along_x(), pos_i.y - target_size(j).y),
+            });
+            queue.push_back(j)
+        }
+    }
+    positions.into_iter().collect()
+}
+
+pub const MM_PER_INCH: f64 = 25.4;
+
+/// Density range of real monitors. EDID of projectors and TVs often has aspect ratios (like 16x9cm) instead of sizes.
+const PLAUSIBLE_DPI: std::ops::RangeInclusive<f64> = 50.0..=600.0;
+
+/// Density of an output in dots per inch, from its mode size and physical size (both unrotated).
+/// Returns [`None`] for unknown or implausible physical sizes.
+pub fn output_dpi(mode_size: Vec2d<u32>, physical_size: Vec2d<u32>) -> Option<f64> {
+    if physical_size.x == 0 || physical_size.y == 0 {
+        return None;
+    }
+    let dpmm_x = f64::from(mode_size.x) / f64::from(physical_size.x);
+    let dpmm_y = f64::from(mode_size.y) / f64::from(physical_size.y);
+    let dpi = MM_PER_INCH * 0.5 * (dpmm_x + dpmm_y);
+    Some(dpi).filter(|dpi| PLAUSIBLE_DPI.contains(dpi))
+}
+
+/// Density of the whole screen : average of output densities weighted by their physical area.
+/// Returns [`None`] if no enabled output has a plausible physical size.
+pub fn screen_dpi(layout: &Layout, physical_sizes: &HashMap<OutputId, Vec2d<u32>>) -> Option<f64> {
+    let (mut weighted_sum, mut weight_sum) = (0., 0.);
+    for entry in layout.output_entries() {
+        if let OutputState::Enabled { mode, .. } = &entry.state {
+            let size = match physical_sizes.get(&entry.id) {
+                Some(size) => *size,
+                None => continue,
+            };
+            if let Some(dpi) = output_dpi(mode.size, size) {
+                let weight = f64::from(size.x) * f64::from(size.y);
+                weighted_sum += weight * dpi;
+                weight_sum += weight
+            }
+        }
+    }
+    match weight_sum > 0. {
+        true => Some(weighted_sum / weight_sum),
+        false => None,
+    }
+}
+
+/// Font DPI (`Xft.dpi`) for the screen density : multiples of 24 (quarters of 96) from 96, like usual scaling factors.
+pub fn font_dpi(layout: &Layout, physical_sizes: &HashMap<OutputId, Vec2d<u32>>) -> Option<u32> {
+    let dpi = screen_dpi(layout, physical_sizes)?;
+    Some(((dpi / 24.).round() as u32 * 24).max(96))
+}
+
+#[cfg(test)]
+#[test]
+fn test_physical_layout() {
+    use super::OutputEntry;
+    let enabled = |name: &str, x, size| OutputEntry::enabled_for_test(name, size, Vec2d::new(x, 0));
+    let (laptop, external) = (OutputId::Name("a".into()), OutputId::Name("b".into()));
+    // Laptop 2560x1600 @ 300x190mm, left of external 2560x1440 @ 600x340mm, bottoms aligned in pixels.
+    let info = LayoutInfo::from_iter(
+        [
+            enabled("a", 0, Vec2d::new(2560, 1600)),
+            enabled("b", 2560, Vec2d::new(2560, 1440)),
+        ],
+        None,
+    );
+    let sizes = HashMap::from([
+        (laptop.clone(), Vec2d::new(300, 190)),
+        (external.clone(), Vec2d::new(600, 340)),
+    ]);
+    let physical = PhysicalLayout::from_layout(&info.layout, &sizes).unwrap();
+    assert_eq!(physical.outputs[0].1.size, Vec2d::new(300, 190));
+    assert_eq!(physical.outputs[1].1.bottom_left.x, 300);
+    // Round trip, with rounding errors due to millimeter precision
+    let round_trip = physical.to_layout(&info.layout).unwrap();
+    assert!(round_trip.unsupported_causes.is_empty());
+    let rect = |info: &LayoutInfo, i: usize| info.layout.output_entries()[i].state.rect().unwrap();
+    assert_eq!(rect(&round_trip, 1).bottom_left.x, 2560);
+    assert!((rect(&round_trip, 1).bottom_left.y - rect(&info, 1).bottom_left.y).abs() <= 6);
+    // Physically align the bottom edges : 75mm between centers, mean density ~6 px/mm.
+    let mut desk = physical.clone();
+    desk.outputs[0].1.bottom_left = Vec2d::new(0, 0);
+    desk.outputs[1].1.bottom_left = Vec2d::new(300, 0);
+    let aligned = desk.to_layout(&info.layout).unwrap();
+    assert!(aligned.unsupported_causes.is_empty());
+    assert_eq!(rect(&aligned, 1).bottom_left.x, 2560);
+    assert_eq!(
+        rect(&aligned, 1).bottom_left.y - rect(&aligned, 0).bottom_left.y,
+        528
+    );
+    // Missing physical size
+    assert!(PhysicalLayout::from_layout(&info.layout, &HashMap::new()).is_none());
+    // Densities : laptop ~215 dpi, external ~108 dpi, weighted by area
+    assert_eq!(
+        output_dpi(Vec2d::new(2560, 1600), Vec2d::new(300, 190)).map(f64::round),
+        Some(215.)
+    );
+    assert_eq!(output_dpi(Vec2d::new(1920, 1080), Vec2d::new(16, 9)), None);
+    let dpi = screen_dpi(&info.layout, &sizes).unwrap();
+    assert!((dpi - 131.).abs() < 1.);
+    assert_eq!(font_dpi(&info.layout, &sizes), Some(120));
+    assert_eq!(font_dpi(&info.layout, &HashMap::new()), None);
+}
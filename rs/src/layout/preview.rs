@@ -0,0 +1,202 @@
+use super::{Layout, OutputId, OutputState};
+use crate::geometry::{Rect, Vec2d};
+use std::collections::HashMap;
+
+/// Maximum diagram width in characters.
+const MAX_COLUMNS: u32 = 60;
+
+/// Text drawing of the enabled outputs of `layout`, for logs and notifications.
+///
+/// Outputs are boxes labelled with their name from `names` (or [`OutputId`]), `*` marking the primary.
+/// A character cell is twice as high as wide, so proportions are roughly kept in a terminal.
+/// Disabled outputs are listed below the drawing.
+pub fn ascii_diagram(layout: &Layout, names: &HashMap<OutputId, String>) -> String {
+    let name_of = |id: &OutputId| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+    let mut boxes: Vec<(String, Rect)> = Vec::new();
+    let mut disabled = Vec::new();
+    for entry in layout.output_entries() {
+        match (&entry.state, entry.state.rect()) {
+            (OutputState::Enabled { .. }, Some(rect)) => {
+                let mut label = name_of(&entry.id);
+                if layout.primary() == Some(&entry.id) {
+                    label.push('*')
+                }
+                boxes.push((label, rect))
+            }
+            _ => disabled.push(name_of(&entry.id)),
+        }
+    }
+
+    let mut lines = Vec::new();
+    if let Some(min) = boxes
+        .iter()
+        .map(|(_, r)| r.bottom_left)
+        .reduce(|a, b| Vec2d::new(a.x.min(b.x), a.y.min(b.y)))
+    {
+        let max = boxes
+            .iter()
+            .map(|(_, r)| r.top_right())
+            .reduce(|a, b| Vec2d::new(a.x.max(b.x), a.y.max(b.y)))
+            .unwrap_or(min);
+        let extent = max - min;
+        // Pixels per column, and per row (twice as much)
+        let pixels = (extent.x.max(extent.y * 2) as u32 / MAX_COLUMNS).max(1) as i32;
+        let column = |x: i32| ((x - min.x) / pixels) as usize;
+        let row = |y: i32| ((y - min.y) / (2 * pixels)) as usize;
+        let cells = |(_, rect): &(String, Rect)| {
+            let (c0, r0) = (column(rect.bottom_left.x), row(rect.bottom_left.y));
+            let top_right = rect.top_right();
+            let (c1, r1) = (
+                column(top_right.x).max(c0 + 2),
+                row(top_right.y).max(r0 + 2),
+            );
+            (c0, r0, c1, r1)
+        };
+        let (mut width, mut height) = (0, 0);
+        for b in &boxes {
+            let (_, _, c1, r1) = cells(b);
+            width = width.max(c1 + 1);
+            height = height.max(r1 + 1)
+        }
+        let mut grid = vec![vec![' '; width]; height];
+        for b in &boxes {
+            let (c0, r0, c1, r1) = cells(b);
+            for r in [r0, r1] {
+                grid[r][c0..=c1].fill('-');
+                grid[r][c0] = '+';
+                grid[r][c1] = '+'
+            }
+            for line in &mut grid[r0 + 1..r1] {
+                line[c0] = '|';
+                line[c1] = '|'
+            }
+            let label = Vec::from_iter(b.0.chars().take(c1 - c0 - 1));
+            let start = c0 + 1 + (c1 - c0 - 1 - label.len()) / 2;
+            for (i, c) in label.into_iter().enumerate() {
+                grid[(r0 + r1) / 2][start + i] = c
+            }
+        }
+        lines.extend(
+            grid.into_iter()
+                .map(|row| String::from_iter(row).trim_end().to_string()),
+        )
+    }
+    if !disabled.is_empty() {
+        lines.push(format!("off: {}", disabled.join(", ")))
+    }
+    lines.join("\n")
+}
+
+/// Maximum width or height of png drawings, in pixels.
+#[cfg(feature = "preview")]
+const MAX_IMAGE_SIZE: u32 = 1024;
+
+/// Border of output rectangles in png drawings, in pixels. The primary output has a thicker one.
+#[cfg(feature = "preview")]
+const BORDER: f32 = 2.;
+
+/// Colors with evenly distributed hues.
+#[cfg(feature = "preview")]
+fn color_palette(n: usize) -> impl Iterator<Item = tiny_skia::Color> {
+    use palette::*;
+    let red: Srgb<f32> = named::RED.into_format();
+    let red = Hsl::from_color(red);
+    (0..n).map(move |i| {
+        let color: Hsl = red.shift_hue(360. * i as f32 / n as f32);
+        let color: Srgb<f32> = color.into_color();
+        tiny_skia::Color::from_rgba(color.red, color.green, color.blue, 1.).unwrap()
+    })
+}
+
+/// Png image of a layout, with the color of each drawn output as `#rrggbb` for a legend.
+#[cfg(feature = "preview")]
+pub struct PngDrawing {
+    pub png: Vec<u8>,
+    pub colors: Vec<(OutputId, String)>,
+}
+
+/// Png drawing of the enabled outputs of `layout`, scaled down to fit in [`MAX_IMAGE_SIZE`].
+///
+/// Outputs are rectangles of distinct colors, as the drawing has no text.
+/// Layout coordinates have y downwards like images, so the drawing looks like the screens.
+#[cfg(feature = "preview")]
+pub fn png_drawing(layout: &Layout) -> Result<PngDrawing, anyhow::Error> {
+    let outputs = Vec::from_iter(
+        (layout.output_entries().iter()).filter_map(|entry| Some((&entry.id, entry.state.rect()?))),
+    );
+    let bounds = (outputs.iter().map(|(_, r)| r.clone()))
+        .reduce(|a, b| a.union_bounds(&b))
+        .ok_or_else(|| anyhow::Error::msg("no enabled output to draw"))?;
+    let min = bounds.bottom_left;
+    let extent = bounds.size.map(|i| i.max(1));
+    let scale = (MAX_IMAGE_SIZE as f32 / extent.x.max(extent.y) as f32).min(1.);
+    let size = extent.map(|i| ((i as f32 * scale).ceil() as u32).max(1));
+    let mut image = tiny_skia::Pixmap::new(size.x, size.y)
+        .ok_or_else(|| anyhow::Error::msg(format!("invalid image size {:?}", size)))?;
+    image.fill(tiny_skia::Color::WHITE);
+    let image_rect = |rect: &Rect, inset: f32| {
+        let origin = (rect.bottom_left - min).map(|i| i as f32) * scale;
+        let size = rect.size.map(|i| i as f32) * scale;
+        tiny_skia::Rect::from_xywh(
+            origin.x + inset,
+            origin.y + inset,
+            size.x - 2. * inset,
+            size.y - 2. * inset,
+        )
+    };
+    let mut colors = Vec::new();
+    for ((id, rect), color) in Iterator::zip(outputs.iter(), color_palette(outputs.len())) {
+        let border = match layout.primary() == Some(*id) {
+            true => 3. * BORDER,
+            false => BORDER,
+        };
+        let mut paint = tiny_skia::Paint::default();
+        paint.set_color(tiny_skia::Color::BLACK);
+        if let Some(outer) = image_rect(rect, 0.) {
+            image.fill_rect(outer, &paint, tiny_skia::Transform::identity(), None);
+        }
+        paint.set_color(color);
+        if let Some(inner) = image_rect(rect, border) {
+            image.fill_rect(inner, &paint, tiny_skia::Transform::identity(), None);
+        }
+        let component = |c: f32| (c * 255.).round() as u8;
+        colors.push((
+            (*id).clone(),
+            format!(
+                "#{:02x}{:02x}{:02x}",
+                component(color.red()),
+                component(color.green()),
+                component(color.blue())
+            ),
+        ))
+    }
+    let png = image.encode_png().map_err(anyhow::Error::new)?;
+    Ok(PngDrawing { png, colors })
+}
+
+#[cfg(test)]
+#[test]
+fn test_ascii_diagram() {
+    use super::{LayoutInfo, OutputEntry};
+    let enabled = |name: &str, x| {
+        OutputEntry::enabled_for_test(name, Vec2d::new(1920, 1080), Vec2d::new(x, 0))
+    };
+    let info = LayoutInfo::from_iter(
+        [
+            enabled("a", 0),
+            enabled("b", 1920),
+            OutputEntry {
+                id: OutputId::Name("c".into()),
+                state: OutputState::Disabled { reason: None },
+            },
+        ],
+        Some(OutputId::Name("b".into())),
+    );
+    let names = HashMap::from([(OutputId::Name("a".into()), "eDP-1".to_string())]);
+    let diagram = ascii_diagram(&info.layout, &names);
+    let lines = Vec::from_iter(diagram.lines());
+    assert_eq!(lines.len(), 10);
+    assert_eq!(lines[0], format!("+{}+{}+", "-".repeat(29), "-".repeat(29)));
+    assert_eq!(lines[4], format!("|{:^29}|{:^29}|", "eDP-1", "b*"));
+    assert_eq!(lines[9], "off: c");
+}
@@ -0,0 +1,117 @@
+use super::{Layout, OutputId};
+use crate::backlight::is_internal_panel;
+use std::collections::HashMap;
+
+/// Rule selecting the primary output in configuration : `"primary": ["external", "largest", {"name": "eDP.*"}]`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrimaryRule {
+    /// Outputs which are not internal panels.
+    External,
+    /// Outputs with the most pixels.
+    Largest,
+    /// Outputs whose whole name matches a pattern : `.` is any character, `*` and `?` repeat the previous one.
+    Name(String),
+}
+
+/// Primary output among the enabled outputs of `layout`, selected by `rules`.
+/// Rules are applied in order, each one keeping the candidates that satisfy it, unless none does.
+/// Remaining ties are broken by [`OutputId`] order.
+///
+/// Returns [`None`] without rules or enabled outputs.
+pub fn select_primary(
+    rules: &[PrimaryRule],
+    layout: &Layout,
+    names: &HashMap<OutputId, String>,
+) -> Option<OutputId> {
+    if rules.is_empty() {
+        return None;
+    }
+    let mut candidates = Vec::from_iter(
+        layout
+            .output_entries()
+            .iter()
+            .filter_map(|entry| Some((&entry.id, entry.state.rect()?))),
+    );
+    for rule in rules {
+        let satisfied = Vec::from_iter(candidates.iter().filter(|(id, rect)| {
+            let name = names.get(id);
+            match rule {
+                PrimaryRule::External => name.is_some_and(|name| !is_internal_panel(name)),
+                PrimaryRule::Largest => candidates.iter().all(|(_, r)| r.area() <= rect.area()),
+                PrimaryRule::Name(pattern) => {
+                    name.is_some_and(|name| matches_pattern(pattern, name))
+                }
+            }
+        }));
+        if !satisfied.is_empty() {
+            candidates = Vec::from_iter(satisfied.into_iter().cloned())
+        }
+    }
+    candidates.into_iter().map(|(id, _)| id).min().cloned()
+}
+
+/// Match the whole `text` against a simple regular expression.
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        let first = |c: char| text.first().is_some_and(|t| c == '.' || c == *t);
+        match pattern {
+            [] => text.is_empty(),
+            [c, '*', rest @ ..] => {
+                matches(rest, text) || (first(*c) && matches(pattern, &text[1..]))
+            }
+            [c, '?', rest @ ..] => matches(rest, text) || (first(*c) && matches(rest, &text[1..])),
+            [c, rest @ ..] => first(*c) && matches(rest, &text[1..]),
+        }
+    }
+    let pattern = Vec::from_iter(pattern.chars());
+    let text = Vec::from_iter(text.chars());
+    matches(&pattern, &text)
+}
+
+#[cfg(test)]
+#[test]
+fn test_select_primary() {
+    use super::{LayoutInfo, OutputEntry, OutputState};
+    use crate::geometry::Vec2d;
+
+    assert!(matches_pattern("eDP.*", "eDP-1"));
+    assert!(matches_pattern("DP-1?", "DP-"));
+    assert!(!matches_pattern("eDP.*", "DP-1"));
+    assert!(!matches_pattern("DP", "DP-1"));
+
+    let id = |name: &str| OutputId::Name(name.into());
+    let enabled =
+        |name: &str, x, y| OutputEntry::enabled_for_test(name, Vec2d::new(x, y), Vec2d::new(0, 0));
+    let layout = LayoutInfo::from_iter(
+        [
+            enabled("DP-1", 1920, 1080),
+            enabled("DP-2", 2560, 1440),
+            enabled("eDP-1", 2560, 1600),
+            OutputEntry {
+                id: id("HDMI-1"),
+                state: OutputState::Disabled { reason: None },
+            },
+        ],
+        None,
+    )
+    .layout;
+    let names = HashMap::from_iter(
+        ["DP-1", "DP-2", "eDP-1", "HDMI-1"].map(|name| (id(name), name.to_string())),
+    );
+    let select = |rules: &[PrimaryRule]| select_primary(rules, &layout, &names);
+    assert_eq!(select(&[]), None);
+    assert_eq!(select(&[PrimaryRule::Largest]), Some(id("eDP-1")));
+    assert_eq!(
+        select(&[PrimaryRule::External, PrimaryRule::Largest]),
+        Some(id("DP-2"))
+    );
+    assert_eq!(select(&[PrimaryRule::External]), Some(id("DP-1")));
+    // Unsatisfied rules are skipped, disabled outputs are never selected
+    let name = |pattern: &str| PrimaryRule::Name(pattern.into());
+    assert_eq!(select(&[name("HDMI.*")]), Some(id("DP-1")));
+    assert_eq!(
+        select(&[name("eDP.*"), PrimaryRule::External]),
+        Some(id("eDP-1"))
+    );
+}
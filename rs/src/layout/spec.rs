@@ -0,0 +1,665 @@
+use super::{
+    BroadcastRgb, ContentType, Layout, LayoutInfo, Mode, OutputBrightness, OutputEntry, OutputId,
+    OutputProperty, OutputSignal, OutputState, Panning, Scale,
+};
+use crate::backlight::is_internal_panel;
+use crate::geometry::{Direction, Relation, Transform, Vec2d};
+use std::collections::HashMap;
+
+/// Class of outputs designated by a wildcard name : `Some(true)` for internal panels, `Some(false)` for others.
+fn wildcard_class(name: &str) -> Option<bool> {
+    match name {
+        "@internal" => Some(true),
+        "@external" => Some(false),
+        _ => None,
+    }
+}
+
+/// One-line layout description, for scripts and one-off arrangements from the command line.
+///
+/// Outputs are separated by spaces : `NAME:MODE[@X,Y][:OPTION]...`.
+/// - `NAME` is the output name (`DP-1`) or its [`OutputId`] representation.
+///   The wildcards `@internal` and `@external` designate any one laptop panel or other output, see [`LayoutSpec::has_wildcards`].
+/// - `MODE` is `preferred`, `WxH`, `WxH@HZ` or `off`. `WxH` uses the frequency of the preferred mode.
+///   `HZ` may have decimals (`59.94`) to choose between modes of nearly the same frequency.
+/// - `OPTION` is one of `left-of=NAME`, `right-of=NAME`, `above=NAME`, `below=NAME`, `same-as=NAME`, `primary`,
+///   `rotate=TRANSFORM`, `scale=FACTOR`, `panning=WxH[/TWxTH+TX+TY[/L/T/R/B]]`, `set=PROPERTY=VALUE`,
+///   `broadcast-rgb=auto|full|limited`, `content-type=none|graphics|photo|cinema|game`, `brightness=PERCENT`.
+/// - `panning` uses the `xrandr --panning` syntax without the position of the panned area, and a tracking area relative to the output.
+/// - `TRANSFORM` uses xrandr names, comma separated : `normal`, `left`, `inverted`, `right`, `reflect-x`, `reflect-y`.
+///
+/// `same-as` makes a clone of the other output, which should have the same logical size.
+/// Property names and values cannot contain spaces or `:`.
+/// Positions use the backend coordinates (`y` downward for X).
+/// Each enabled output needs a position or a relation, except if it is the only one.
+/// Connected outputs missing from the spec are disabled.
+///
+/// Example : `eDP-1:preferred@0,0 DP-1:2560x1440@60:right-of=eDP-1:primary`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutSpec {
+    outputs: Vec<OutputSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct OutputSpec {
+    name: String,
+    mode: ModeSpec,
+    position: Option<Vec2d<i32>>,
+    /// `self relation other`
+    relation: Option<(Relation, String)>,
+    primary: bool,
+    transform: Transform,
+    scale: Scale,
+    panning: Option<Panning>,
+    /// Backend output properties : `(name, value)`.
+    properties: Vec<(String, String)>,
+    broadcast_rgb: Option<BroadcastRgb>,
+    content_type: Option<ContentType>,
+    brightness: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ModeSpec {
+    Off,
+    Preferred,
+    /// Size, and refresh rate in mHz.
+    Size(Vec2d<u32>, Option<u32>),
+}
+
+impl std::str::FromStr for LayoutSpec {
+    type Err = String;
+    fn from_str(s: &str) -> Result<LayoutSpec, String> {
+        let outputs = Result::from_iter(s.split_whitespace().map(parse_output))?;
+        Ok(LayoutSpec { outputs })
+    }
+}
+
+/// Spec string, parsed back to an equal spec.
+impl std::fmt::Display for LayoutSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, output) in self.outputs.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?
+            }
+            write!(f, "{}:", output.name)?;
+            match &output.mode {
+                ModeSpec::Off => f.write_str("off")?,
+                ModeSpec::Preferred => f.write_str("preferred")?,
+                ModeSpec::Size(size, refresh) => {
+                    write!(f, "{}x{}", size.x, size.y)?;
+                    if let Some(refresh) = refresh {
+                        write!(f, "@{}", Mode::format_frequency(*refresh))?
+                    }
+                }
+            }
+            if let Some(position) = output.position {
+                write!(f, "@{},{}", position.x, position.y)?
+            }
+            if let Some((relation, other)) = &output.relation {
+                let key = match relation {
+                    Relation::Adjacent(Direction::LeftOf) => "left-of",
+                    Relation::Adjacent(Direction::RightOf) => "right-of",
+                    Relation::Adjacent(Direction::Above) => "above",
+                    Relation::Adjacent(Direction::Under) => "below",
+                    Relation::SameAs => "same-as",
+                };
+                write!(f, ":{}={}", key, other)?
+            }
+            if output.primary {
+                f.write_str(":primary")?
+            }
+            if output.transform != Transform::default() {
+                write!(f, ":rotate={}", output.transform)?
+            }
+            if !output.scale.is_one() {
+                write!(f, ":scale={}", output.scale)?
+            }
+            if let Some(panning) = &output.panning {
+                write!(f, ":panning={}", panning)?
+            }
+            for (name, value) in &output.properties {
+                write!(f, ":set={}={}", name, value)?
+            }
+            match output.broadcast_rgb {
+                None => (),
+                Some(BroadcastRgb::Automatic) => f.write_str(":broadcast-rgb=auto")?,
+                Some(BroadcastRgb::Full) => f.write_str(":broadcast-rgb=full")?,
+                Some(BroadcastRgb::Limited) => f.write_str(":broadcast-rgb=limited")?,
+            }
+            if let Some(content_type) = output.content_type {
+                let value = match content_type {
+                    ContentType::NoData => "none",
+                    ContentType::Graphics => "graphics",
+                    ContentType::Photo => "photo",
+                    ContentType::Cinema => "cinema",
+                    ContentType::Game => "game",
+                };
+                write!(f, ":content-type={}", value)?
+            }
+            if let Some(percent) = output.brightness {
+                write!(f, ":brightness={}", percent)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for LayoutSpec {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<LayoutSpec, D::Error> {
+        let spec = String::deserialize(deserializer)?;
+        spec.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+fn parse_output(s: &str) -> Result<OutputSpec, String> {
+    let mut fields = s.split(':');
+    let name = fields.next().filter(|name| !name.is_empty());
+    let name = name.ok_or_else(|| format!("missing output name: {}", s))?;
+    let mode_field = fields
+        .next()
+        .ok_or_else(|| format!("missing mode for {}", name))?;
+    let mut spec = OutputSpec {
+        name: name.to_string(),
+        mode: ModeSpec::Preferred,
+        position: None,
+        relation: None,
+        primary: false,
+        transform: Transform::default(),
+        scale: Scale::ONE,
+        panning: None,
+        properties: Vec::new(),
+        broadcast_rgb: None,
+        content_type: None,
+        brightness: None,
+    };
+
+    let parse_int = |s: &str| -> Result<i64, String> {
+        s.parse()
+            .map_err(|_| format!("{}: invalid number: {}", name, s))
+    };
+    let mut mode_parts = mode_field.split('@');
+    let size = match mode_parts.next() {
+        Some("off") => {
+            spec.mode = ModeSpec::Off;
+            None
+        }
+        Some("preferred") => None,
+        Some(size) => Some(Mode::parse_size(size).map_err(|e| format!("{}: {}", name, e))?),
+        None => None,
+    };
+    let mut refresh = None;
+    for part in mode_parts {
+        match part.split_once(',') {
+            Some((x, y)) => {
+                let position = Vec2d::new(parse_int(x)?, parse_int(y)?);
+                spec.position = Some(position.map(|v| v.clamp(-(1 << 30), 1 << 30) as i32))
+            }
+            None if size.is_some() && refresh.is_none() && spec.position.is_none() => {
+                refresh = Some(Mode::parse_frequency(part).map_err(|e| format!("{}: {}", name, e))?)
+            }
+            None => return Err(format!("{}: unexpected @{}", name, part)),
+        }
+    }
+    if let Some(size) = size {
+        spec.mode = ModeSpec::Size(size, refresh)
+    }
+
+    for option in fields {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        };
+        let relation = match key {
+            "left-of" => Some(Relation::Adjacent(Direction::LeftOf)),
+            "right-of" => Some(Relation::Adjacent(Direction::RightOf)),
+            "above" => Some(Relation::Adjacent(Direction::Above)),
+            "below" => Some(Relation::Adjacent(Direction::Under)),
+            "same-as" => Some(Relation::SameAs),
+            _ => None,
+        };
+        match (key, value, relation) {
+            (_, Some(other), Some(relation)) if !other.is_empty() => {
+                spec.relation = Some((relation, other.to_string()))
+            }
+            (_, _, Some(_)) => return Err(format!("{}: {} requires an output name", name, key)),
+            ("primary", None, _) => spec.primary = true,
+            ("rotate", Some(transform), _) => {
+                spec.transform = transform.parse().map_err(|e| format!("{}: {}", name, e))?
+            }
+            ("scale", Some(scale), _) => {
+                spec.scale = scale
+                    .parse()
+                    .ok()
+                    .and_then(Scale::from_f64)
+                    .ok_or_else(|| format!("{}: invalid scale: {}", name, scale))?
+            }
+            ("panning", Some(panning), _) => {
+                spec.panning = Some(panning.parse().map_err(|e| format!("{}: {}", name, e))?)
+            }
+            ("set", Some(property), _) => match property.split_once('=') {
+                Some((property, value)) if !property.is_empty() => spec
+                    .properties
+                    .push((property.to_string(), value.to_string())),
+                _ => return Err(format!("{}: invalid property: {}", name, property)),
+            },
+            ("broadcast-rgb", Some(range), _) => {
+                spec.broadcast_rgb = Some(match range {
+                    "auto" => BroadcastRgb::Automatic,
+                    "full" => BroadcastRgb::Full,
+                    "limited" => BroadcastRgb::Limited,
+                    _ => return Err(format!("{}: invalid broadcast rgb: {}", name, range)),
+                })
+            }
+            ("content-type", Some(content_type), _) => {
+                spec.content_type = Some(match content_type {
+                    "none" => ContentType::NoData,
+                    "graphics" => ContentType::Graphics,
+                    "photo" => ContentType::Photo,
+                    "cinema" => ContentType::Cinema,
+                    "game" => ContentType::Game,
+                    _ => return Err(format!("{}: invalid content type: {}", name, content_type)),
+                })
+            }
+            ("brightness", Some(percent), _) => {
+                spec.brightness = match percent.parse() {
+                    Ok(percent) if percent <= 100 => Some(percent),
+                    _ => return Err(format!("{}: invalid brightness: {}", name, percent)),
+                }
+            }
+            _ => return Err(format!("{}: invalid option: {}", name, option)),
+        }
+    }
+    Ok(spec)
+}
+
+impl LayoutSpec {
+    /// Spec reproducing `layout` with explicit modes and positions.
+    /// Outputs are designated by their name from `names`, or by their [`OutputId`].
+    pub fn from_layout(layout: &Layout, names: &HashMap<OutputId, String>) -> LayoutSpec {
+        let name_of = |id: &OutputId| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+        let mut outputs = Vec::new();
+        for entry in layout.output_entries() {
+            let name = name_of(&entry.id);
+            let mut spec = OutputSpec {
+                name: name.clone(),
+                mode: ModeSpec::Off,
+                position: None,
+                relation: None,
+                primary: layout.primary() == Some(&entry.id),
+                transform: Transform::default(),
+                scale: Scale::ONE,
+                panning: None,
+                properties: Vec::new(),
+                broadcast_rgb: None,
+                content_type: None,
+                brightness: None,
+            };
+            if let OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                panning,
+            } = &entry.state
+            {
+                spec.mode = ModeSpec::Size(mode.size, Some(mode.millihertz()));
+                spec.position = Some(*bottom_left);
+                spec.transform = transform.clone();
+                spec.scale = *scale;
+                spec.panning = panning.clone()
+            }
+            spec.properties.extend(
+                (layout.properties().iter())
+                    .filter(|p| p.output == entry.id)
+                    .map(|p| (p.name.clone(), p.value.clone())),
+            );
+            if let Some(signal) = layout.signals().iter().find(|s| s.output == entry.id) {
+                spec.broadcast_rgb = signal.broadcast_rgb;
+                spec.content_type = signal.content_type
+            }
+            if let Some(b) = layout.brightness().iter().find(|b| b.output == entry.id) {
+                spec.brightness = Some(b.percent)
+            }
+            outputs.push(spec)
+        }
+        LayoutSpec { outputs }
+    }
+
+    /// Does the spec designate exactly all `connected` outputs ?
+    pub fn matches(&self, connected: &[OutputId], names: &HashMap<OutputId, String>) -> bool {
+        self.outputs.len() == connected.len()
+            && self.resolve_ids(connected, names).is_ok_and(|ids| {
+                let distinct =
+                    std::collections::HashSet::<&OutputId>::from_iter(ids.iter().copied());
+                distinct.len() == ids.len()
+            })
+    }
+
+    /// Whether outputs are designated by wildcards, so that one spec covers monitors never seen before,
+    /// like meeting room projectors : `@internal:preferred@0,0 @external:preferred:above=@internal`.
+    pub fn has_wildcards(&self) -> bool {
+        self.outputs
+            .iter()
+            .any(|o| wildcard_class(&o.name).is_some())
+    }
+
+    /// Connected output designated by each output spec.
+    /// Names are resolved first, then wildcards take the first unused output of their class in `connected` order.
+    fn resolve_ids<'c>(
+        &self,
+        connected: &'c [OutputId],
+        names: &HashMap<OutputId, String>,
+    ) -> Result<Vec<&'c OutputId>, String> {
+        let name_of = |id: &OutputId| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+        let mut ids = Vec::with_capacity(self.outputs.len());
+        for spec in self.outputs.iter() {
+            let id = match wildcard_class(&spec.name) {
+                Some(_) => None,
+                None => Some(
+                    connected
+                        .iter()
+                        .find(|id| {
+                            names.get(*id) == Some(&spec.name) || id.to_string() == spec.name
+                        })
+                        .ok_or_else(|| format!("no connected output named {}", spec.name))?,
+                ),
+            };
+            ids.push(id)
+        }
+        for (i, spec) in self.outputs.iter().enumerate() {
+            if let Some(internal) = wildcard_class(&spec.name) {
+                let free = connected.iter().find(|id| {
+                    !ids.contains(&Some(*id)) && is_internal_panel(&name_of(id)) == internal
+                });
+                ids[i] = Some(free.ok_or_else(|| format!("no connected output for {}", spec.name))?)
+            }
+        }
+        Ok(Vec::from_iter(ids.into_iter().flatten()))
+    }
+
+    /// Build a layout for the `connected` outputs.
+    /// Output names are looked up in `names`, and `preferred_modes` are required for `preferred` or partial modes.
+    pub fn to_layout(
+        &self,
+        connected: &[OutputId],
+        names: &HashMap<OutputId, String>,
+        preferred_modes: &HashMap<OutputId, Mode>,
+    ) -> Result<LayoutInfo, String> {
+        let ids = self.resolve_ids(connected, names)?;
+
+        // Logical sizes are needed to resolve relations
+        let mut states = Vec::with_capacity(self.outputs.len());
+        for (spec, id) in Iterator::zip(self.outputs.iter(), ids.iter()) {
+            let preferred = || {
+                preferred_modes
+                    .get(*id)
+                    .ok_or_else(|| format!("{}: unknown preferred mode", spec.name))
+            };
+            let mode = match &spec.mode {
+                ModeSpec::Off => None,
+                ModeSpec::Preferred => Some(preferred()?.clone()),
+                ModeSpec::Size(size, Some(refresh)) => Some(Mode::from_refresh(*size, *refresh)),
+                ModeSpec::Size(size, None) => match preferred() {
+                    Ok(mode) if mode.size == *size => Some(mode.clone()),
+                    _ => return Err(format!("{}: frequency required for mode", spec.name)),
+                },
+            };
+            states.push(mode.map(|mode| (mode, spec.position)))
+        }
+
+        // Resolve relative positions in passes, as relations can be given in any order
+        let transform = |spec: &OutputSpec| spec.transform.clone();
+        let size = |i: usize| -> Option<Vec2d<i32>> {
+            let (mode, _) = states[i].as_ref()?;
+            let spec = &self.outputs[i];
+            let mut size = spec.scale.logical_size(mode.size.apply(&transform(spec)));
+            if let Some(panning) = &spec.panning {
+                size = size.cwise_max(panning.size)
+            }
+            Some(size.map(|v| v as i32))
+        };
+        let sizes = Vec::from_iter((0..self.outputs.len()).map(size));
+        let enabled_count = states.iter().filter(|s| s.is_some()).count();
+        loop {
+            let mut progress = false;
+            let mut missing = None;
+            for i in 0..self.outputs.len() {
+                let spec = &self.outputs[i];
+                let placed = match &states[i] {
+                    Some((_, position)) => position.is_some(),
+                    None => continue,
+                };
+                if placed {
+                    continue;
+                }
+                let position = match &spec.relation {
+                    Some((relation, other)) => {
+                        let j = self.outputs.iter().position(|o| &o.name == other);
+                        let j =
+                            j.ok_or_else(|| format!("{}: unknown output {}", spec.name, other))?;
+                        let other_position = match &states[j] {
+                            Some((_, position)) => *position,
+                            None => return Err(format!("{}: {} is off", spec.name, other)),
+                        };
+                        other_position.map(|p| {
+                            let (own, other) = (sizes[i].unwrap(), sizes[j].unwrap());
+                            match relation {
+                                Relation::Adjacent(Direction::LeftOf) => {
+                                    Vec2d::new(p.x - own.x, p.y)
+                                }
+                                Relation::Adjacent(Direction::RightOf) => {
+                                    Vec2d::new(p.x + other.x, p.y)
+                                }
+                                Relation::Adjacent(Direction::Above) => {
+                                    Vec2d::new(p.x, p.y - own.y)
+                                }
+                                Relation::Adjacent(Direction::Under) => {
+                                    Vec2d::new(p.x, p.y + other.y)
+                                }
+                                Relation::SameAs => p,
+                            }
+                        })
+                    }
+                    None if enabled_count == 1 => Some(Vec2d::default()),
+                    None => return Err(format!("{}: position or relation required", spec.name)),
+                };
+                match position {
+                    Some(position) => {
+                        if let Some((_, p)) = &mut states[i] {
+                            *p = Some(position)
+                        }
+                        progress = true
+                    }
+                    None => missing = Some(&spec.name),
+                }
+            }
+            match (missing, progress) {
+                (None, _) => break,
+                (Some(_), true) => continue,
+                (Some(name), false) => {
+                    return Err(format!("{}: relations do not lead to a position", name))
+                }
+            }
+        }
+
+        let mut entries = Vec::from_iter(connected.iter().map(|id| OutputEntry {
+            id: id.clone(),
+            state: OutputState::Disabled { reason: None },
+        }));
+        let mut primary = None;
+        let mut properties = Vec::new();
+        let mut signals = Vec::new();
+        let mut brightness = Vec::new();
+        for ((spec, id), state) in Iterator::zip(self.outputs.iter(), ids).zip(states) {
+            if let Some(percent) = spec.brightness {
+                brightness.push(OutputBrightness {
+                    output: id.clone(),
+                    percent,
+                })
+            }
+            if spec.broadcast_rgb.is_some() || spec.content_type.is_some() {
+                signals.push(OutputSignal {
+                    output: id.clone(),
+                    broadcast_rgb: spec.broadcast_rgb,
+                    content_type: spec.content_type,
+                })
+            }
+            properties.extend(spec.properties.iter().map(|(name, value)| OutputProperty {
+                output: id.clone(),
+                name: name.clone(),
+                value: value.clone(),
+            }));
+            let entry = entries.iter_mut().find(|e| &e.id == id).expect("connected");
+            if let Some((mode, position)) = state {
+                entry.state = OutputState::Enabled {
+                    mode,
+                    transform: transform(spec),
+                    bottom_left: position.expect("resolved"),
+                    scale: spec.scale,
+                    panning: spec.panning.clone(),
+                };
+                if spec.primary {
+                    primary = Some(id.clone())
+                }
+            }
+        }
+        let mut info = LayoutInfo::from(entries, primary);
+        info.layout.set_properties(properties);
+        info.layout.set_signals(signals);
+        info.layout.set_brightness(brightness);
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_layout_spec() {
+    let spec: LayoutSpec = "eDP-1:preferred@0,0 DP-1:2560x1440@60:right-of=eDP-1:primary"
+        .parse()
+        .unwrap();
+    let (laptop, external) = (
+        OutputId::Name("eDP-1".into()),
+        OutputId::Edid(super::Edid::from(0xAB)),
+    );
+    let names = HashMap::from([(external.clone(), "DP-1".to_string())]);
+    let preferred = HashMap::from([(
+        laptop.clone(),
+        Mode {
+            size: Vec2d::new(1920, 1080),
+            frequency: 60,
+            refresh: None,
+        },
+    )]);
+    let connected = [external.clone(), laptop.clone()];
+    let info = spec.to_layout(&connected, &names, &preferred).unwrap();
+    assert!(info.unsupported_causes.is_empty());
+    assert_eq!(info.layout.primary(), Some(&external));
+    let rect = |i: usize| info.layout.output_entries()[i].state.rect().unwrap();
+    assert_eq!(rect(0).bottom_left, Vec2d::new(1920, 0));
+    assert_eq!(rect(1).bottom_left, Vec2d::new(0, 0));
+    // Relations in any order, off outputs
+    let spec: LayoutSpec =
+        "DP-1:2560x1440@60:above=eDP-1 eDP-1:1920x1080@0,0:rotate=left,reflect-y"
+            .parse()
+            .unwrap();
+    let info = spec.to_layout(&connected, &names, &preferred).unwrap();
+    assert!(info.unsupported_causes.is_empty());
+    assert_eq!(
+        info.layout.output_entries()[1].state.rect().unwrap().size,
+        Vec2d::new(1080, 1920)
+    );
+    let spec: LayoutSpec =
+        "eDP-1:off DP-1:2560x1440@60:set=audio=off:broadcast-rgb=full:brightness=70"
+            .parse()
+            .unwrap();
+    let info = spec.to_layout(&connected, &names, &preferred).unwrap();
+    assert_eq!(info.layout.output_entries()[1].state.rect(), None);
+    assert_eq!(
+        info.layout.properties(),
+        [OutputProperty {
+            output: external.clone(),
+            name: "audio".into(),
+            value: "off".into(),
+        }]
+    );
+    assert_eq!(
+        info.layout.signals()[0].broadcast_rgb,
+        Some(BroadcastRgb::Full)
+    );
+    // Round trip through a spec string
+    let spec = LayoutSpec::from_layout(&info.layout, &names);
+    assert_eq!(
+        spec.to_string(),
+        "DP-1:2560x1440@60@0,0:set=audio=off:broadcast-rgb=full:brightness=70 eDP-1:off"
+    );
+    assert_eq!(spec.to_string().parse::<LayoutSpec>(), Ok(spec));
+    let ntsc: LayoutSpec = "DP-1:1920x1080@59.94@0,0".parse().unwrap();
+    assert_eq!(ntsc.to_string(), "DP-1:1920x1080@59.94@0,0");
+    // Wildcards match any output of their class, after names
+    let spec: LayoutSpec = "@external:2560x1440@60:above=@internal @internal:preferred@0,0"
+        .parse()
+        .unwrap();
+    assert!(spec.has_wildcards());
+    assert!(spec.matches(&connected, &names));
+    let info = spec.to_layout(&connected, &names, &preferred).unwrap();
+    let rect = |i: usize| info.layout.output_entries()[i].state.rect().unwrap();
+    assert_eq!(rect(0).bottom_left, Vec2d::new(0, 0));
+    assert_eq!(rect(1).bottom_left, Vec2d::new(0, 1440));
+    let two_external: LayoutSpec = "@external:preferred@0,0 @external:preferred@0,0"
+        .parse()
+        .unwrap();
+    assert!(!two_external.matches(&connected, &names));
+    let named: LayoutSpec = "DP-1:preferred@0,0 @external:preferred@0,0"
+        .parse()
+        .unwrap();
+    assert!(!named.matches(&connected, &names));
+    // Panning area replaces the output size in the layout
+    let text = "eDP-1:1920x1080@60@0,0:panning=3840x2160/1920x1080+0+0/0/0/8/8 DP-1:2560x1440@60:right-of=eDP-1";
+    let info = text
+        .parse::<LayoutSpec>()
+        .unwrap()
+        .to_layout(&connected, &names, &preferred)
+        .unwrap();
+    assert_eq!(
+        info.layout.output_entries()[0]
+            .state
+            .rect()
+            .unwrap()
+            .bottom_left,
+        Vec2d::new(3840, 0)
+    );
+    let xrandr = info.layout.to_xrandr_args(&names).join(" ");
+    assert!(xrandr.contains("--panning 3840x2160+0+0/1920x1080+0+0/0/0/8/8"));
+    let spec = LayoutSpec::from_layout(&info.layout, &names);
+    assert!(spec
+        .to_string()
+        .contains(":panning=3840x2160/1920x1080+0+0/0/0/8/8"));
+    assert!("eDP-1:preferred:panning=3840x2160/8/8"
+        .parse::<LayoutSpec>()
+        .is_err());
+    // Clones
+    let spec: LayoutSpec = "eDP-1:1920x1080@60@0,0 DP-1:1920x1080@60:same-as=eDP-1"
+        .parse()
+        .unwrap();
+    assert_eq!(spec.to_string().parse::<LayoutSpec>(), Ok(spec.clone()));
+    let info = spec.to_layout(&connected, &names, &preferred).unwrap();
+    assert!(info.unsupported_causes.is_empty());
+    assert_eq!(info.layout.clone_groups(), [[&external, &laptop]]);
+    // Errors
+    assert!("eDP-1".parse::<LayoutSpec>().is_err());
+    assert!("eDP-1:preferred:upside".parse::<LayoutSpec>().is_err());
+    assert!("eDP-1:preferred@60".parse::<LayoutSpec>().is_err());
+    assert!("eDP-1:preferred:set=audio".parse::<LayoutSpec>().is_err());
+    assert!("eDP-1:preferred:brightness=101"
+        .parse::<LayoutSpec>()
+        .is_err());
+    assert!("eDP-1:preferred:content-type=tv"
+        .parse::<LayoutSpec>()
+        .is_err());
+    let unknown: LayoutSpec = "HDMI-1:preferred".parse().unwrap();
+    assert!(unknown.to_layout(&connected, &names, &preferred).is_err());
+    let cycle: LayoutSpec = "eDP-1:preferred:left-of=DP-1 DP-1:2560x1440@60:right-of=eDP-1"
+        .parse()
+        .unwrap();
+    assert!(cycle.to_layout(&connected, &names, &preferred).is_err());
+}
@@ -1,13 +1,175 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::os::fd::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
 
+/// Backlight brightness of outputs, from backends or sysfs.
+pub mod backlight;
+/// Daemon configuration file.
+pub mod config;
+/// Control socket of the running daemon.
+pub mod control;
+/// Diagnostic reports on crashes.
+pub mod crash;
+/// Detaching from the terminal.
+pub mod daemonize;
 /// Layout database.
 pub mod database;
+/// Database encryption.
+pub mod encryption;
+/// Export of layouts to configuration formats of other tools.
+pub mod export;
+/// Change notifications of files.
+pub mod file_watch;
 /// Basic geometric primitives.
 pub mod geometry;
+/// Tracking of repeatedly failing outputs.
+pub mod graylist;
+/// User commands run on layout events.
+pub mod hooks;
+/// Power-down of outputs when the user is idle.
+pub mod idle;
+/// Single daemon instance per display.
+pub mod instance;
+/// Conversion of kanshi configuration profiles to layouts.
+pub mod kanshi;
 /// Output layouts definitions and utils.
 pub mod layout;
+/// Text, json or journald log output, with record fields.
+pub mod logging;
+/// Scripted backend for tests.
+pub mod mock;
+/// Combination of several backends active at the same time.
+pub mod multi;
+/// Desktop notifications.
+pub mod notify;
+/// User-authored layout profiles.
+pub mod profile;
 /// Relation representation
 pub mod relation;
+/// Downloads of provisioned layouts.
+pub mod remote;
+/// Detection of system resume after suspend.
+pub mod resume;
+/// Readiness and watchdog notifications to the service manager.
+pub mod sd_notify;
+/// Session settings following the layout.
+pub mod session;
+/// Signal handling of the daemon.
+pub mod signals;
+/// Counters of the running daemon, for `slam status --stats`.
+pub mod stats;
+/// Description of the current layout for users and scripts.
+pub mod status;
+/// Stress and soak runs of the daemon loop on a simulated backend.
+pub mod stress;
+/// Waiting on several file descriptors at once.
+pub mod wait;
+
+/// Change of backend state, from [`Backend::wait_for_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendEvent {
+    /// Output plugged in.
+    OutputConnected(layout::OutputId),
+    /// Output unplugged.
+    OutputDisconnected(layout::OutputId),
+    /// State of connected outputs changed : mode, position, enabled outputs...
+    ConfigChanged,
+    /// Primary output changed, to none if [`None`].
+    PrimaryChanged(Option<layout::OutputId>),
+}
+
+impl BackendEvent {
+    /// Events from layout `old` to `new` : disconnections, connections, then configuration changes.
+    pub fn between(old: &layout::Layout, new: &layout::Layout) -> Vec<BackendEvent> {
+        let is_in = |layout: &layout::Layout, id: &layout::OutputId| {
+            layout.connected_outputs().any(|other| other == id)
+        };
+        let mut events = Vec::from_iter(
+            (old.connected_outputs())
+                .filter(|id| !is_in(new, id))
+                .map(|id| BackendEvent::OutputDisconnected(id.clone())),
+        );
+        events.extend(
+            (new.connected_outputs())
+                .filter(|id| !is_in(old, id))
+                .map(|id| BackendEvent::OutputConnected(id.clone())),
+        );
+        let old_kept = (old.output_entries().iter()).filter(|entry| is_in(new, &entry.id));
+        let new_kept = (new.output_entries().iter()).filter(|entry| is_in(old, &entry.id));
+        if !Iterator::eq(old_kept, new_kept) {
+            events.push(BackendEvent::ConfigChanged)
+        }
+        if old.primary() != new.primary() {
+            events.push(BackendEvent::PrimaryChanged(new.primary().cloned()))
+        }
+        events
+    }
+
+    /// Whether outputs were plugged or unplugged, instead of configured.
+    pub fn is_hotplug(&self) -> bool {
+        matches!(
+            self,
+            BackendEvent::OutputConnected(_) | BackendEvent::OutputDisconnected(_)
+        )
+    }
+}
+
+/// Kind of backend event, for [`ReactionDelays`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// Outputs plugged or unplugged. Monitors may keep changing during link training.
+    Hotplug,
+    /// Crtc or screen changes : modes, positions, enabled outputs.
+    Crtc,
+    /// Output property changes.
+    Property,
+}
+
+/// Time to wait for more backend events after one of each kind, before reading the new state.
+/// Waiting lets other daemons react to the change, and coalesces bursts of events into one change.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReactionDelays {
+    pub hotplug: Option<Duration>,
+    pub crtc: Option<Duration>,
+    pub property: Option<Duration>,
+}
+
+impl ReactionDelays {
+    /// Same delay for all kinds of events.
+    pub fn uniform(delay: Option<Duration>) -> ReactionDelays {
+        ReactionDelays {
+            hotplug: delay,
+            crtc: delay,
+            property: delay,
+        }
+    }
+
+    /// Delays of `config`, in milliseconds, replacing the `default` delay for their kind.
+    pub fn with_config(default: Option<Duration>, config: &config::ReactionDelaysConfig) -> Self {
+        let delay = |ms: Option<u64>| ms.map(Duration::from_millis).or(default);
+        ReactionDelays {
+            hotplug: delay(config.hotplug),
+            crtc: delay(config.crtc),
+            property: delay(config.property),
+        }
+    }
+
+    pub fn get(&self, kind: EventKind) -> Option<Duration> {
+        match kind {
+            EventKind::Hotplug => self.hotplug,
+            EventKind::Crtc => self.crtc,
+            EventKind::Property => self.property,
+        }
+    }
+
+    /// Delay for backends which cannot tell the kind of their events.
+    pub fn longest(&self) -> Option<Duration> {
+        [self.hotplug, self.crtc, self.property]
+            .into_iter()
+            .flatten()
+            .max()
+    }
+}
 
 /// Backend interface.
 ///
@@ -18,38 +180,1037 @@ pub trait Backend {
     fn current_layout(&self) -> layout::LayoutInfo;
 
     /// Wait for a change in backend layout.
-    fn wait_for_change(&mut self, reaction_delay: Option<Duration>) -> Result<(), anyhow::Error>;
+    fn wait_for_change(&mut self, reaction_delays: ReactionDelays) -> Result<(), anyhow::Error>;
+
+    /// Wait for a change in backend layout for at most `timeout`. Returns false if nothing changed.
+    /// Backends without timeout support wait for a change.
+    fn wait_for_change_until(
+        &mut self,
+        reaction_delays: ReactionDelays,
+        timeout: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let _ = timeout;
+        self.wait_for_change(reaction_delays)?;
+        Ok(true)
+    }
+
+    /// Wait for changes in backend layout, for at most `timeout` if given, and describe them.
+    /// Returns no events only if the timeout expired : a change that is not visible in the layout is a
+    /// [`BackendEvent::ConfigChanged`].
+    fn wait_for_events(
+        &mut self,
+        reaction_delays: ReactionDelays,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<BackendEvent>, anyhow::Error> {
+        let old = self.current_layout().layout;
+        match timeout {
+            Some(timeout) => {
+                if !self.wait_for_change_until(reaction_delays, timeout)? {
+                    return Ok(Vec::new());
+                }
+            }
+            None => self.wait_for_change(reaction_delays)?,
+        }
+        let mut events = BackendEvent::between(&old, &self.current_layout().layout);
+        if events.is_empty() {
+            events.push(BackendEvent::ConfigChanged)
+        }
+        Ok(events)
+    }
+
+    /// File descriptors readable when the backend may have events, for the daemon to wait on them along with
+    /// its other event sources. Events are still read by [`Backend::wait_for_events`], as some may be buffered.
+    /// Backends without any are waited on directly, and the other sources are checked periodically.
+    fn event_fds(&self) -> Vec<RawFd> {
+        Vec::new()
+    }
 
     /// Apply layout to the system using the backend.
     fn apply_layout(&mut self, layout: &layout::Layout) -> Result<(), anyhow::Error>;
+
+    /// Physical sizes in millimeters (unrotated) of connected outputs, when known.
+    fn physical_sizes(&self) -> HashMap<layout::OutputId, geometry::Vec2d<u32>> {
+        HashMap::new()
+    }
+
+    /// System names of connected outputs (like `DP-1`), when known.
+    fn output_names(&self) -> HashMap<layout::OutputId, String> {
+        HashMap::new()
+    }
+
+    /// Monitor descriptions of connected outputs (like `DELL U2720Q`), when known.
+    fn output_descriptions(&self) -> HashMap<layout::OutputId, String> {
+        HashMap::new()
+    }
+
+    /// Preferred modes of connected outputs, when known.
+    fn preferred_modes(&self) -> HashMap<layout::OutputId, layout::Mode> {
+        HashMap::new()
+    }
+
+    /// Modes advertised by connected outputs, when known.
+    fn available_modes(&self) -> HashMap<layout::OutputId, Vec<layout::Mode>> {
+        HashMap::new()
+    }
+
+    /// Backlight brightness in percent of connected outputs, for those with a backlight controlled by the backend.
+    fn brightness(&self) -> HashMap<layout::OutputId, u8> {
+        HashMap::new()
+    }
+
+    /// Set the backlight brightness of an output in percent.
+    /// Returns false if the backend does not control the backlight of this output.
+    fn set_brightness(
+        &mut self,
+        output: &layout::OutputId,
+        percent: u8,
+    ) -> Result<bool, anyhow::Error> {
+        let _ = (output, percent);
+        Ok(false)
+    }
+
+    /// Query the backend state again, for changes without events, like CRTC assignments lost across system suspend.
+    fn refresh(&mut self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Whether the connection to the display server still works, for backends using one.
+    /// A backend which lost it fails on any call, and must be started again.
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    /// Whether [`Backend::current_layout`] reports the applied state after [`Backend::refresh`], to verify applies.
+    /// Backends only updated by events sent later cannot verify.
+    fn can_verify_apply(&self) -> bool {
+        false
+    }
+
+    /// Requests the backend would send to apply `layout`, in order, for dry runs.
+    /// Empty for backends which cannot list them.
+    fn apply_requests(&self, layout: &layout::Layout) -> Result<Vec<String>, anyhow::Error> {
+        let _ = layout;
+        Ok(Vec::new())
+    }
+
+    /// Register the custom modes used by `layout` that are missing from the mode lists of outputs.
+    /// Backends without custom mode support ignore them, and fail to apply such layouts.
+    fn add_custom_modes(
+        &mut self,
+        layout: &layout::Layout,
+        modes: &[layout::modeline::CustomMode],
+    ) -> Result<(), anyhow::Error> {
+        let _ = (layout, modes);
+        Ok(())
+    }
+
+    /// Human readable summary of the backend state and versions, for crash reports.
+    fn diagnostics(&self) -> String {
+        String::new()
+    }
 }
 
 /// X backend
 #[cfg(feature = "xcb")]
 pub mod xcb;
 
+/// Wayland backend for wlroots based compositors
+#[cfg(feature = "wayland")]
+pub mod wayland;
+
+/// DRM/KMS backend for consoles without display server
+#[cfg(feature = "drm")]
+pub mod drm;
+
+/// Run hooks and send notification for a layout event. Failures are only logged.
+/// The font DPI of applied layouts is set before running hooks.
+/// `source` describes the layout in notifications and events sent to `slam watch` clients, like `profile docked`.
+pub fn report_event(
+    backend: &dyn Backend,
+    config: &config::Config,
+    event: hooks::LayoutEvent,
+    source: &str,
+    layout: &layout::Layout,
+) {
+    let outputs = Vec::from_iter(layout.connected_outputs().map(|id| id.to_string())).join(" ");
+    log::info!(
+        event = event.name(), source = source, outputs = outputs.as_str();
+        "layout {}", event
+    );
+    stats::record_layout_event(event);
+    if let (hooks::LayoutEvent::Applied, Some(dpi)) = (event, layout.font_dpi()) {
+        if let Err(e) = session::set_font_dpi(dpi) {
+            log::error!("{:#}", e)
+        }
+    }
+    for hook in config.hooks.postswitch_invocations(event, layout) {
+        if let Err(e) = hook.run() {
+            log::error!("{:#}", e)
+        }
+    }
+    if config.notifications {
+        if let Err(e) = notify::Notification::for_event(event, source, layout).send() {
+            log::error!("{:#}", e)
+        }
+    }
+    publish_event(event.name(), || {
+        let names = status::OutputNames::from_backend(backend);
+        serde_json::json!({ "source": source, "layout": status::layout_json(layout, &names) })
+    })
+}
+
+/// Send an event to `slam watch` clients : `{"event": name, "time": ..., <fields>}`.
+/// Fields are only computed if there are clients.
+fn publish_event(name: &str, fields: impl FnOnce() -> serde_json::Value) {
+    if !control::has_watchers() {
+        return;
+    }
+    let time = time::OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default();
+    let mut event = fields();
+    event["event"] = name.into();
+    event["time"] = time.into();
+    control::publish(&event)
+}
+
+/// Send `connected` and `disconnected` events for outputs of `new` not in `old`, and conversely.
+fn publish_output_changes(backend: &dyn Backend, old: &layout::Layout, new: &layout::Layout) {
+    let names = status::OutputNames::from_backend(backend);
+    for entry in new.output_entries() {
+        if !old.connected_outputs().any(|id| id == &entry.id) {
+            publish_event("connected", || {
+                let primary = new.primary() == Some(&entry.id);
+                serde_json::json!({ "output": status::output_json(entry, &names, primary) })
+            })
+        }
+    }
+    for id in old.connected_outputs() {
+        if !new.connected_outputs().any(|new_id| new_id == id) {
+            publish_event(
+                "disconnected",
+                || serde_json::json!({ "output": { "id": id.to_string(), "name": names.names.get(id) } }),
+            )
+        }
+    }
+}
+
+/// Outputs of the `requested` layout in another state in the `current` backend layout.
+/// Modes are matched like backends select them.
+fn unapplied_outputs<'l>(
+    requested: &'l layout::Layout,
+    current: &layout::Layout,
+) -> Vec<&'l layout::OutputId> {
+    let is_applied = |requested: &layout::OutputEntry| {
+        let current = match current
+            .output_entries()
+            .iter()
+            .find(|c| c.id == requested.id)
+        {
+            Some(current) => current,
+            None => return false,
+        };
+        let mut state = requested.state.clone();
+        if let (
+            layout::OutputState::Enabled { mode, .. },
+            layout::OutputState::Enabled {
+                mode: current_mode, ..
+            },
+        ) = (&mut state, &current.state)
+        {
+            if mode.match_distance(current_mode).is_some() {
+                *mode = current_mode.clone()
+            }
+        }
+        state == current.state
+    };
+    Vec::from_iter(
+        (requested.output_entries().iter())
+            .filter(|entry| !is_applied(entry))
+            .map(|entry| &entry.id),
+    )
+}
+
+/// Apply `layout`, checking the backend state afterwards as some GPUs silently reject configurations.
+/// A rejected layout is applied once more, then reported as `source`.
+///
+/// Returns the layout in effect : `layout`, or the backend state if it was not applied.
+fn apply_verified(
+    backend: &mut dyn Backend,
+    config: &config::Config,
+    source: &str,
+    layout: &layout::Layout,
+) -> Result<layout::Layout, anyhow::Error> {
+    backend.apply_layout(layout)?;
+    if !backend.can_verify_apply() {
+        return Ok(layout.clone());
+    }
+    let applied_state = |backend: &mut dyn Backend| -> Result<layout::Layout, anyhow::Error> {
+        backend.refresh()?;
+        Ok(current_layout(backend, config).layout)
+    };
+    let mut current = applied_state(backend)?;
+    if !unapplied_outputs(layout, &current).is_empty() {
+        log::warn!("layout not applied by the backend: retrying");
+        backend.apply_layout(layout)?;
+        current = applied_state(backend)?;
+    }
+    let unapplied = unapplied_outputs(layout, &current);
+    if unapplied.is_empty() {
+        return Ok(layout.clone());
+    }
+    let names = backend.output_names();
+    log::error!(
+        "layout not applied by the backend, current one is {}",
+        layout::spec::LayoutSpec::from_layout(&current, &names)
+    );
+    if config.notifications {
+        let outputs = Vec::from_iter(
+            unapplied
+                .into_iter()
+                .map(|id| names.get(id).cloned().unwrap_or_else(|| id.to_string())),
+        );
+        if let Err(e) = notify::Notification::for_failure(source, &outputs).send() {
+            log::error!("{:#}", e)
+        }
+    }
+    Ok(current)
+}
+
+/// Apply a layout chosen by the daemon or the user, running hooks before and reporting the event after.
+/// `source` describes the layout in notifications, like `profile docked`.
+///
+/// Returns the layout in effect, which differs from `layout` if the backend rejected it.
+pub fn switch_layout(
+    backend: &mut dyn Backend,
+    config: &config::Config,
+    source: &str,
+    layout: &layout::Layout,
+) -> Result<layout::Layout, anyhow::Error> {
+    for hook in config.hooks.preswitch_invocations(layout) {
+        if let Err(e) = hook.run() {
+            log::error!("{:#}", e)
+        }
+    }
+    if let Err(e) = backend.add_custom_modes(layout, &config.modes) {
+        log::warn!("cannot add custom modes: {:#}", e)
+    }
+    let applied = apply_verified(backend, config, source, layout)?;
+    for brightness in layout.brightness() {
+        let result =
+            backlight::set_output_brightness(backend, &brightness.output, brightness.percent);
+        if let Err(e) = result {
+            log::warn!("{:#}", e)
+        }
+    }
+    if config.auto_font_dpi && layout.font_dpi().is_none() {
+        let mut layout = layout.clone();
+        layout.set_font_dpi(layout::physical::font_dpi(
+            &layout,
+            &backend.physical_sizes(),
+        ));
+        report_event(
+            &*backend,
+            config,
+            hooks::LayoutEvent::Applied,
+            source,
+            &layout,
+        );
+    } else {
+        report_event(
+            &*backend,
+            config,
+            hooks::LayoutEvent::Applied,
+            source,
+            layout,
+        );
+    }
+    Ok(applied)
+}
+
+/// Automatic layout for the outputs of `layout`, with output defaults and bezels from `config`,
+/// and relations between outputs known from layouts of `database`.
+fn auto_layout(
+    backend: &dyn Backend,
+    config: &config::Config,
+    database: &database::Database,
+    layout: &layout::Layout,
+) -> Option<layout::Layout> {
+    let physical_sizes = backend.physical_sizes();
+    let names = backend.output_names();
+    let defaults = layout::auto::resolve_output_defaults(&config.output_defaults, layout, &names);
+    // Preferences come first, as the first relation found between two outputs is used
+    let mut known = layout::auto::resolve_relation_preferences(&config.relations, layout, &names);
+    known.extend(database.known_relations());
+    layout::auto::auto_layout(
+        layout,
+        &backend.preferred_modes(),
+        &defaults,
+        &known,
+        |entries| layout::bezel::resolve_bezels(&config.bezels, entries, &physical_sizes),
+    )
+}
+
+/// Layout for the outputs of `layout` adapted from the closest stored layout, whose outputs are a subset or superset.
+/// Common outputs keep their stored state, others are placed like in automatic layouts.
+fn adapted_layout(
+    backend: &dyn Backend,
+    config: &config::Config,
+    database: &database::Database,
+    layout: &layout::Layout,
+) -> Option<layout::Layout> {
+    let stored = database.closest_layout(&database::LayoutById(layout.clone()))?;
+    let entries = layout.output_entries().iter().map(|entry| {
+        let stored_entry = stored.output_entries().iter().find(|s| s.id == entry.id);
+        stored_entry
+            .cloned()
+            .unwrap_or_else(|| layout::OutputEntry {
+                id: entry.id.clone(),
+                state: layout::OutputState::Disabled { reason: None },
+            })
+    });
+    let primary = stored
+        .primary()
+        .filter(|p| layout.connected_outputs().any(|id| id == *p));
+    let base = layout::LayoutInfo::from_iter(entries, primary.cloned()).layout;
+    let adapted = auto_layout(backend, config, database, &base)?;
+    log::info!(
+        "apply layout adapted from stored layout for {}",
+        describe_outputs(backend, stored)
+    );
+    Some(adapted)
+}
+
+/// `layout` with the closest available mode for enabled outputs which no longer advertise their mode,
+/// like after a driver change or with a different cable. Outputs are placed again around the new sizes.
+fn with_available_modes(
+    backend: &dyn Backend,
+    config: &config::Config,
+    layout: layout::Layout,
+) -> layout::Layout {
+    let (available, names) = (backend.available_modes(), backend.output_names());
+    let mut substitutes = HashMap::new();
+    for entry in layout.output_entries() {
+        let (mode, available) = match (&entry.state, available.get(&entry.id)) {
+            (layout::OutputState::Enabled { mode, .. }, Some(available)) => (mode, available),
+            _ => continue,
+        };
+        if available.iter().any(|m| mode.match_distance(m).is_some()) {
+            continue;
+        }
+        if let Some(closest) = mode.closest(available) {
+            log::warn!(
+                "{}: mode {} unavailable, using {}",
+                names
+                    .get(&entry.id)
+                    .cloned()
+                    .unwrap_or_else(|| entry.id.to_string()),
+                mode,
+                closest
+            );
+            substitutes.insert(entry.id.clone(), closest.clone());
+        }
+    }
+    if substitutes.is_empty() {
+        return layout;
+    }
+    let physical_sizes = backend.physical_sizes();
+    layout::auto::with_modes(&layout, &substitutes, |entries| {
+        layout::bezel::resolve_bezels(&config.bezels, entries, &physical_sizes)
+    })
+}
+
+/// `layout` with a primary output selected by the configured rules, if it has none.
+fn with_primary_policy(
+    backend: &dyn Backend,
+    config: &config::Config,
+    mut layout: layout::Layout,
+) -> layout::Layout {
+    if layout.primary().is_none() {
+        let names = backend.output_names();
+        if let Some(primary) =
+            layout::primary_policy::select_primary(&config.primary, &layout, &names)
+        {
+            log::info!(
+                "primary output from rules: {}",
+                names
+                    .get(&primary)
+                    .cloned()
+                    .unwrap_or_else(|| primary.to_string())
+            );
+            layout.set_primary(Some(primary))
+        }
+    }
+    layout
+}
+
+/// Log a drawing of an auto-generated layout, and send it as notification if enabled.
+fn preview_autolayout(backend: &dyn Backend, config: &config::Config, layout: &layout::Layout) {
+    let diagram = layout::preview::ascii_diagram(layout, &backend.output_names());
+    log::info!("auto-generated layout:\n{}", diagram);
+    if config.notifications {
+        if let Err(e) = notify::Notification::for_autolayout(diagram).send() {
+            log::error!("{:#}", e)
+        }
+    }
+}
+
+/// Comma separated outputs of `layout`, as `name (description)` when known.
+pub fn describe_outputs(backend: &dyn Backend, layout: &layout::Layout) -> String {
+    let (names, descriptions) = (backend.output_names(), backend.output_descriptions());
+    let describe = |id: &layout::OutputId| {
+        let name = names.get(id).cloned().unwrap_or_else(|| id.to_string());
+        match descriptions.get(id) {
+            Some(description) => format!("{} ({})", name, description),
+            None => name,
+        }
+    };
+    Vec::from_iter(layout.connected_outputs().map(describe)).join(", ")
+}
+
+/// Current backend layout, with bezels and tolerance from `config`.
+pub fn current_layout(backend: &dyn Backend, config: &config::Config) -> layout::LayoutInfo {
+    let info = backend.current_layout();
+    let bezels = layout::bezel::resolve_bezels(
+        &config.bezels,
+        info.layout.output_entries(),
+        &backend.physical_sizes(),
+    );
+    info.with_bezels(bezels)
+        .with_tolerance(config.layout_tolerance.unwrap_or(0))
+}
+
+/// Layout made by the user with nearly adjacent outputs, snapped to adjacency with `snap_tolerance` from `config`.
+/// Returns [`None`] if snapping is disabled or does not make the layout supported.
+fn snapped_layout(
+    config: &config::Config,
+    layout: &layout::Layout,
+    unsupported_causes: layout::UnsupportedCauses,
+) -> Option<layout::Layout> {
+    let tolerance = config.snap_tolerance?;
+    let fixable = layout::UnsupportedCauses::GAPS | layout::UnsupportedCauses::OVERLAPS;
+    if unsupported_causes.is_empty() || !fixable.contains(unsupported_causes) {
+        return None;
+    }
+    let mut entries = layout.output_entries().to_vec();
+    let bezels = layout.bezels().to_vec();
+    if !layout::snap(&mut entries, &bezels, tolerance) {
+        return None;
+    }
+    let snapped = layout::LayoutInfo::from(entries, layout.primary().cloned())
+        .with_bezels(bezels)
+        .with_tolerance(layout.tolerance());
+    match snapped.unsupported_causes.is_empty() {
+        true => Some(snapped.layout),
+        false => None,
+    }
+}
+
+/// First profile of `config` matching the outputs of `layout`, with its layout at time `now`.
+/// Profiles designating outputs by name are preferred to those with wildcards.
+pub fn matching_profile<'c>(
+    backend: &dyn Backend,
+    config: &'c config::Config,
+    layout: &layout::Layout,
+    now: profile::TimeOfDay,
+) -> Option<(&'c profile::Profile, layout::LayoutInfo)> {
+    let connected = Vec::from_iter(layout.connected_outputs().cloned());
+    let (names, preferred_modes) = (backend.output_names(), backend.preferred_modes());
+    let (wildcards, named) =
+        (config.profiles.iter()).partition::<Vec<_>, _>(|profile| profile.layout.has_wildcards());
+    Iterator::chain(named.into_iter(), wildcards).find_map(|profile| {
+        let info = profile.layout_at(now, &connected, &names, &preferred_modes)?;
+        Some((profile, info))
+    })
+}
+
+/// Layout to apply for the outputs of `layout` at time `now`, in order of precedence :
+/// active profile variant, most recently used stored layout, profile layout.
+/// The layout is returned with a description of its source, for notifications.
+fn select_layout(
+    backend: &dyn Backend,
+    config: &config::Config,
+    database: &database::Database,
+    layout: &layout::Layout,
+    now: profile::TimeOfDay,
+) -> Option<(String, layout::Layout)> {
+    let profile = matching_profile(backend, config, layout, now);
+    if let Some((profile, info)) = &profile {
+        if let Some(variant) = profile.active_variant(now) {
+            log::info!(
+                "apply layout from profile {} ({})",
+                profile.name,
+                variant.name
+            );
+            let source = format!("profile {} ({})", profile.name, variant.name);
+            return Some((source, info.layout.clone()));
+        }
+    }
+    if let Some(stored_layout) = database.preferred_layout(&database::LayoutById(layout.clone())) {
+        let source = match stored_layout.name() {
+            Some(name) => {
+                log::info!("apply saved layout {} from database", name);
+                format!("saved layout {}", name)
+            }
+            None => {
+                log::info!("apply layout from database");
+                "stored layout".to_string()
+            }
+        };
+        return Some((source, stored_layout.clone()));
+    }
+    let (profile, info) = profile?;
+    log::info!(profile = profile.name.as_str(); "apply layout from profile {}", profile.name);
+    Some((format!("profile {}", profile.name), info.layout))
+}
+
+/// Count an apply of `layout` in the database if it comes from there. Failures are only logged.
+pub fn record_applied(database: &mut database::Database, layout: &layout::Layout) {
+    if let Err(e) = database.record_applied(layout) {
+        log::warn!("cannot record layout usage: {:#}", e)
+    }
+}
+
+/// Layout saved under `text` in the database, or built from `text` as a layout spec for the connected outputs.
+pub fn layout_from_name_or_spec(
+    backend: &dyn Backend,
+    database: &database::Database,
+    text: &str,
+) -> Result<layout::Layout, anyhow::Error> {
+    if let Some(saved) = database.saved_layout(text) {
+        return Ok(saved.clone());
+    }
+    let spec: layout::spec::LayoutSpec = text
+        .parse()
+        .map_err(|e| anyhow::Error::msg(format!("invalid layout spec: {}", e)))?;
+    let connected = Vec::from_iter(backend.current_layout().layout.connected_outputs().cloned());
+    let info = spec
+        .to_layout(
+            &connected,
+            &backend.output_names(),
+            &backend.preferred_modes(),
+        )
+        .map_err(anyhow::Error::msg)?;
+    if !info.unsupported_causes.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+            "unsupported layout: {:?}",
+            info.unsupported_causes
+        )));
+    }
+    Ok(info.layout)
+}
+
+/// Check that `layout` is for the outputs of `current`.
+pub fn check_connected_outputs(
+    layout: &layout::Layout,
+    current: &layout::Layout,
+) -> Result<(), anyhow::Error> {
+    if Iterator::eq(layout.connected_outputs(), current.connected_outputs()) {
+        return Ok(());
+    }
+    let ids = |layout: &layout::Layout| {
+        Vec::from_iter(layout.connected_outputs().map(|id| id.to_string())).join(" ")
+    };
+    Err(anyhow::Error::msg(format!(
+        "layout outputs [{}] do not match connected outputs [{}]",
+        ids(layout),
+        ids(current)
+    )))
+}
+
+/// Current layout with settings not reported by backends copied from the learned layout, for saving.
+pub fn layout_to_save(
+    backend: &dyn Backend,
+    config: &config::Config,
+    database: &database::Database,
+) -> Result<layout::Layout, anyhow::Error> {
+    let mut current = current_layout(backend, config);
+    if !current.unsupported_causes.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+            "unsupported layout: {:?}",
+            current.unsupported_causes
+        )));
+    }
+    let learned = database::LayoutById(current.layout.clone());
+    if let Some(learned) = database.get_layout(&learned) {
+        current.layout.copy_output_settings(learned)
+    }
+    Ok(current.layout)
+}
+
+/// Daemon state changed by control requests.
+struct ControlState {
+    paused: bool,
+}
+
+/// Execute a control request, returning the reply text.
+fn control_request(
+    request: control::Request,
+    state: &mut ControlState,
+    backend: &mut dyn Backend,
+    config: &config::Config,
+    database: &mut database::Database,
+    layout: &mut layout::Layout,
+) -> Result<String, anyhow::Error> {
+    log::info!("control request: {}", request);
+    match request {
+        control::Request::Status => {
+            let names = backend.output_names();
+            let spec = layout::spec::LayoutSpec::from_layout(layout, &names);
+            let learned = database.get_layout(&database::LayoutById(layout.clone()));
+            Ok(format!(
+                "outputs: {}\nlayout: {}\nlearned: {}\npaused: {}\n",
+                describe_outputs(backend, layout),
+                spec,
+                learned.is_some(),
+                state.paused
+            ))
+        }
+        control::Request::Stats => {
+            let stats = serde_json::to_string(&stats::current())?;
+            Ok(format!("{}\n", stats))
+        }
+        control::Request::Apply(text) => {
+            let selected = layout_from_name_or_spec(backend, database, &text)?;
+            check_connected_outputs(&selected, layout)?;
+            *layout = switch_layout(backend, config, &format!("layout {}", text), &selected)?;
+            if *layout == selected {
+                record_applied(database, &selected)
+            }
+            Ok(String::new())
+        }
+        control::Request::Save(name) => {
+            let saved = layout_to_save(backend, config, database)?;
+            database.save_layout(name, saved)?;
+            Ok(String::new())
+        }
+        control::Request::Reload => {
+            database.reload()?;
+            Ok(String::new())
+        }
+        control::Request::Pause => {
+            state.paused = true;
+            Ok(String::new())
+        }
+        control::Request::Resume => {
+            // Changes made while paused are not reacted to
+            state.paused = false;
+            *layout = current_layout(backend, config).layout;
+            Ok(String::new())
+        }
+        // The connection is kept by the daemon loop to send events
+        control::Request::Watch => Err(anyhow::Error::msg("watch requests have no reply")),
+    }
+}
+
+/// Profile with time variants matching the outputs of `layout`, whose boundaries the daemon waits for.
+fn scheduled_profile<'c>(
+    backend: &dyn Backend,
+    config: &'c config::Config,
+    layout: &layout::Layout,
+) -> Option<&'c profile::Profile> {
+    let (profile, _) = matching_profile(backend, config, layout, profile::TimeOfDay::now())?;
+    Some(profile).filter(|profile| !profile.variants.is_empty())
+}
+
+/// Reason to leave the daemon loop running with the current configuration.
+enum DaemonExit {
+    /// `SIGHUP` received.
+    Reload,
+    /// `SIGTERM` or `SIGINT` received.
+    Terminate,
+}
+
+/// Daemon state kept across configuration reloads.
+struct DaemonState {
+    layout: layout::Layout,
+    control: ControlState,
+}
+
+/// Run the daemon. On `SIGHUP`, the configuration file at `config_path` (if given) and the database are read again,
+/// keeping the current layout. Files which cannot be read are logged and their previous content is kept.
+///
+/// On `SIGTERM` or `SIGINT`, returns successfully once the layout being applied is done.
+/// Database changes are written as they happen, so there is nothing left to save.
+///
+/// With `database_watch` on the database file, edits by other processes are read when notified.
 pub fn run_daemon(
     backend: &mut dyn Backend,
-    reaction_delay: Option<Duration>,
+    reaction_delays: ReactionDelays,
     database: &mut database::Database,
+    config: &config::Config,
+    config_path: Option<&std::path::Path>,
+    control: Option<&control::ControlSocket>,
+    database_watch: Option<&file_watch::FileWatch>,
 ) -> Result<(), anyhow::Error> {
-    let layout::LayoutInfo { mut layout, .. } = backend.current_layout();
+    stats::record_start();
+    let mut config = config.clone();
+    let mut state = DaemonState {
+        layout: current_layout(backend, &config).layout,
+        control: ControlState { paused: false },
+    };
+    crash::set_section("backend", backend.diagnostics());
     loop {
-        dbg!(&layout);
-        backend.wait_for_change(reaction_delay)?;
+        match run_daemon_loop(
+            backend,
+            reaction_delays,
+            database,
+            &config,
+            control,
+            database_watch,
+            &mut state,
+        )? {
+            DaemonExit::Reload => {
+                sd_notify::notify("RELOADING=1");
+                if let Some(path) = config_path {
+                    match config::Config::load_or_default(path) {
+                        Ok(reloaded) => config = reloaded,
+                        Err(e) => log::error!("keeping previous configuration: {:#}", e),
+                    }
+                }
+                if let Err(e) = database.reload() {
+                    log::error!("keeping previous database: {:#}", e)
+                }
+                database.set_format(config.database_format);
+                database.set_max_layouts(config.database_max_layouts);
+                sd_notify::notify("READY=1")
+            }
+            DaemonExit::Terminate => return Ok(()),
+        }
+    }
+}
+
+/// Wait for backend events for at most `timeout`, or until one of the other event `sources` is readable.
+/// Returns no events if the timeout expired or another source is ready.
+fn wait_for_daemon_events(
+    backend: &mut dyn Backend,
+    reaction_delays: ReactionDelays,
+    timeout: Option<Duration>,
+    sources: &[RawFd],
+) -> Result<Vec<BackendEvent>, anyhow::Error> {
+    let backend_fds = backend.event_fds();
+    if backend_fds.is_empty() {
+        let timeout = match sources.is_empty() {
+            true => timeout,
+            false => Some(timeout.map_or(wait::POLL_PERIOD, |t| t.min(wait::POLL_PERIOD))),
+        };
+        return backend.wait_for_events(reaction_delays, timeout);
+    }
+    // Events may already be buffered by the backend, which would not wake up poll
+    let events = backend.wait_for_events(reaction_delays, Some(Duration::ZERO))?;
+    if !events.is_empty() {
+        return Ok(events);
+    }
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let readable = wait::wait_readable(&[&backend_fds[..], sources].concat(), deadline)?;
+    match readable.iter().any(|fd| backend_fds.contains(fd)) {
+        true => backend.wait_for_events(reaction_delays, Some(Duration::ZERO)),
+        false => Ok(Vec::new()),
+    }
+}
+
+fn run_daemon_loop(
+    backend: &mut dyn Backend,
+    reaction_delays: ReactionDelays,
+    database: &mut database::Database,
+    config: &config::Config,
+    control: Option<&control::ControlSocket>,
+    database_watch: Option<&file_watch::FileWatch>,
+    state: &mut DaemonState,
+) -> Result<DaemonExit, anyhow::Error> {
+    let mut layout = state.layout.clone();
+    let mut control_state = ControlState {
+        paused: state.control.paused,
+    };
+    let mut schedule = scheduled_profile(backend, config, &layout);
+    let mut resume = resume::ResumeDetector::default();
+    let mut idle = config
+        .idle_power_down
+        .as_ref()
+        .map(idle::IdlePowerDown::new);
+    let watchdog_period = sd_notify::watchdog_period();
+    let mut last_watchdog_ping = Instant::now();
+    let sources = Vec::from_iter(
+        [
+            control.map(|control| control.as_raw_fd()),
+            database_watch.map(|watch| watch.as_raw_fd()),
+            signals::wake_fd(),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+    loop {
+        log::debug!("current layout: {:?}", layout);
+        let switch_timeout =
+            schedule.and_then(|profile| profile.next_switch(profile::TimeOfDay::now()));
+        let idle_timeout = idle.as_ref().and_then(|idle| idle.timeout());
+        let waiting_since = Instant::now();
+        let timeouts = [
+            switch_timeout,
+            idle_timeout,
+            watchdog_period,
+            Some(resume::POLL_PERIOD),
+        ];
+        let timeout = timeouts.into_iter().flatten().min();
+        let events = wait_for_daemon_events(backend, reaction_delays, timeout, &sources)?;
+        // Before reading signal flags, so that none is missed
+        signals::clear_wake();
+        for event in &events {
+            log::debug!(event:? = event; "backend event")
+        }
+        stats::record_events(events.len());
+        let mut changed = !events.is_empty();
+        let mut hotplug = events.iter().any(BackendEvent::is_hotplug);
+        if watchdog_period.is_some_and(|period| last_watchdog_ping.elapsed() >= period) {
+            sd_notify::notify("WATCHDOG=1");
+            last_watchdog_ping = Instant::now()
+        }
+        if let Some(control) = control {
+            while let Some(mut connection) = control.accept() {
+                match connection.read_request() {
+                    Ok(control::Request::Watch) => connection.watch(),
+                    request => {
+                        let reply = request.and_then(|request| {
+                            let state = &mut control_state;
+                            control_request(request, state, backend, config, database, &mut layout)
+                        });
+                        connection.reply(reply)
+                    }
+                }
+            }
+        }
+        if let Some(watch) = database_watch {
+            // Writes of the daemon are also notified, but do not change the modification time it knows
+            let changed = watch.changed().unwrap_or_else(|e| {
+                log::error!("{:#}", e);
+                false
+            });
+            if changed && database.changed_on_disk() {
+                log::info!("database changed on disk: reloading");
+                if let Err(e) = database.reload() {
+                    log::error!("keeping previous database: {:#}", e)
+                }
+            }
+        }
+        if signals::termination_requested() {
+            // Do not leave outputs disabled by idle power-down
+            if let Some(restored) = idle.as_mut().and_then(|idle| idle.restore()) {
+                backend.apply_layout(&restored)?;
+            }
+            log::info!("terminating");
+            sd_notify::notify("STOPPING=1");
+            return Ok(DaemonExit::Terminate);
+        }
+        // Idle power-down keeps the layout to restore : wait for activity to reload
+        let powered_down = idle.as_ref().is_some_and(|idle| idle.is_powered_down());
+        if !powered_down && signals::reload_requested() {
+            log::info!("reloading configuration and database");
+            state.layout = layout;
+            state.control = control_state;
+            return Ok(DaemonExit::Reload);
+        }
+        if control_state.paused {
+            // Keep track of changes without reacting
+            layout = current_layout(backend, config).layout;
+            continue;
+        }
+        if resume.resumed() {
+            // GPUs may scramble CRTC assignments across suspend without hotplug events
+            backend.refresh()?;
+            let current = current_layout(backend, config).layout;
+            if current == layout {
+                log::info!("resumed from suspend: layout unchanged")
+            } else if Iterator::eq(current.connected_outputs(), layout.connected_outputs()) {
+                log::info!("resumed from suspend: reapplying layout");
+                layout = switch_layout(backend, config, "layout after resume", &layout)?;
+                continue;
+            } else {
+                // Outputs plugged or unplugged during suspend
+                changed = true;
+                hotplug = true
+            }
+        }
+        if !changed {
+            let elapsed = waiting_since.elapsed();
+            // Idle power-down changes are applied but never stored
+            if let Some(idle) = &mut idle {
+                let selected = match idle_timeout.is_some_and(|timeout| elapsed >= timeout) {
+                    true => idle.check(&layout, &backend.output_names()),
+                    false => None,
+                };
+                if let Some(selected) = selected {
+                    backend.apply_layout(&selected)?;
+                    layout = selected;
+                    continue;
+                }
+                if idle.is_powered_down() {
+                    continue;
+                }
+            }
+            if switch_timeout.is_some_and(|timeout| elapsed >= timeout) {
+                // profile variant boundary
+                let now = profile::TimeOfDay::now();
+                let selected = select_layout(backend, config, database, &layout, now);
+                // Compared with the current layout, which got its primary output from the policy
+                let applied = (selected.clone()).map(|(_, selected)| {
+                    let applied = with_available_modes(backend, config, selected);
+                    with_primary_policy(backend, config, applied)
+                });
+                match (selected, applied) {
+                    (Some((source, selected)), Some(applied)) if applied != layout => {
+                        layout = switch_layout(backend, config, &source, &applied)?;
+                        if layout == applied {
+                            record_applied(database, &selected)
+                        }
+                    }
+                    _ => log::debug!("time variant boundary: layout unchanged"),
+                }
+            }
+            continue;
+        }
         let layout::LayoutInfo {
-            layout: new_layout,
-            unsupported_causes,
-        } = backend.current_layout();
+            layout: mut new_layout,
+            mut unsupported_causes,
+            ..
+        } = current_layout(backend, config);
+        crash::set_section("backend", backend.diagnostics());
+        crash::set_section("layout", format!("{:?}", new_layout));
+        if new_layout != layout {
+            if let Some(idle) = &mut idle {
+                idle.forget()
+            }
+        }
         // Select behavior
         if new_layout == layout {
             // if layout is the same as last seen or requested : ignore
             log::info!("layout unchanged, ignored")
-        } else if Iterator::eq(new_layout.connected_outputs(), layout.connected_outputs()) {
-            // same outputs but changes : store to db if supported
+        } else if !hotplug
+            && Iterator::eq(new_layout.connected_outputs(), layout.connected_outputs())
+        {
+            // same outputs but changes : store to db if supported.
+            // Outputs plugged while applying a layout are seen by the backend refresh without hotplug events.
+            if let Some(snapped) = snapped_layout(config, &new_layout, unsupported_causes) {
+                log::info!("layout changed: snapping nearly adjacent outputs");
+                new_layout = switch_layout(backend, config, "snapped layout", &snapped)?;
+                unsupported_causes = layout::UnsupportedCauses::empty();
+            }
             if unsupported_causes.is_empty() {
                 log::info!("layout changed: storing to database");
-                database.store_layout(new_layout.clone())?;
+                // Outputs disabled in a layout made by the user stay disabled.
+                let mut stored_layout = new_layout.clone();
+                stored_layout.fill_disabled_reasons(layout::DisabledReason::UserDisabled);
+                // Backends do not report properties : keep the stored ones.
+                if let Some(previous) = database.get_layout(&database::LayoutById(layout.clone())) {
+                    stored_layout.copy_output_settings(previous)
+                }
+                database.store_layout(stored_layout)?;
+                report_event(
+                    &*backend,
+                    config,
+                    hooks::LayoutEvent::Stored,
+                    "new layout",
+                    &new_layout,
+                );
             } else {
                 log::warn!(
                     "layout changed: ignored because unsupported: {:?}",
@@ -59,17 +1220,42 @@ pub fn run_daemon(
             layout = new_layout
         } else {
             // new output set
-            let by_id = database::LayoutById(new_layout);
-            if let Some(stored_layout) = database.get_layout(&by_id) {
+            log::info!("outputs: {}", describe_outputs(backend, &new_layout));
+            publish_output_changes(&*backend, &layout, &new_layout);
+            schedule = scheduled_profile(backend, config, &new_layout);
+            let now = profile::TimeOfDay::now();
+            if let Some((source, selected)) =
+                select_layout(backend, config, database, &new_layout, now)
+            {
                 // apply
-                log::info!("apply layout from database");
-                backend.apply_layout(stored_layout)?;
-                layout = stored_layout.clone()
+                let applied = with_available_modes(backend, config, selected.clone());
+                let applied = with_primary_policy(backend, config, applied);
+                layout = switch_layout(backend, config, &source, &applied)?;
+                // Rejected layouts must not count as used
+                if layout == applied {
+                    record_applied(database, &selected)
+                }
+            } else if let Some((source, auto)) =
+                (adapted_layout(backend, config, database, &new_layout))
+                    .map(|adapted| ("layout adapted from a stored one", adapted))
+                    .or_else(|| {
+                        let auto = auto_layout(backend, config, database, &new_layout)?;
+                        log::info!("apply auto-generated layout");
+                        Some(("automatic layout for unknown outputs", auto))
+                    })
+            {
+                let auto = with_primary_policy(backend, config, auto);
+                if config.autolayout_preview {
+                    preview_autolayout(backend, config, &auto)
+                }
+                layout = switch_layout(backend, config, source, &auto)?;
+                // Rejected layouts must not be applied again for these outputs
+                if layout == auto {
+                    database.store_layout(auto)?
+                }
             } else {
-                // autolayout
-                log::info!("use auto-generated layout (not functionnal)");
-                let database::LayoutById(new_layout) = by_id;
-                // TODO do nothing for now
+                log::warn!("cannot generate a layout: keeping current one");
+                layout = new_layout
             }
         }
     }
@@ -0,0 +1,313 @@
+use anyhow::Context;
+use std::io::Write;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Socket of the native journald protocol.
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Socket of the system logger.
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// `LOG_DAEMON` syslog facility.
+const SYSLOG_FACILITY: u8 = 3;
+
+/// Output format of log records.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Lines on stderr : `<time> <level> [<target>] <message> <key>=<value>...`.
+    #[default]
+    Text,
+    /// One json object per line on stderr, with `time`, `level`, `target`, `message` and record fields.
+    Json,
+    /// Records sent to journald with their fields, as `SLAM_<KEY>` journal fields.
+    Journald,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            "journald" => Ok(LogFormat::Journald),
+            _ => Err(format!("unknown log format: {}", s)),
+        }
+    }
+}
+
+/// Destination of log lines in text or json format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    #[default]
+    Stderr,
+    /// System logger, through `/dev/log`.
+    Syslog,
+    /// Log file, appended.
+    File,
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stderr" => Ok(LogTarget::Stderr),
+            "syslog" => Ok(LogTarget::Syslog),
+            "file" => Ok(LogTarget::File),
+            _ => Err(format!("unknown log target: {}", s)),
+        }
+    }
+}
+
+/// Opened destination of a [`LogTarget`].
+pub enum LogOutput {
+    Stderr,
+    Syslog(UnixDatagram),
+    File(Mutex<std::fs::File>),
+}
+
+impl LogOutput {
+    pub fn syslog() -> Result<LogOutput, anyhow::Error> {
+        let socket = UnixDatagram::unbound().context("cannot create socket")?;
+        socket
+            .connect(SYSLOG_SOCKET)
+            .with_context(|| format!("cannot connect to syslog at {}", SYSLOG_SOCKET))?;
+        Ok(LogOutput::Syslog(socket))
+    }
+
+    pub fn file(path: &Path) -> Result<LogOutput, anyhow::Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("cannot create directory {}", parent.display()))?
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("cannot open log file {}", path.display()))?;
+        Ok(LogOutput::File(Mutex::new(file)))
+    }
+}
+
+/// Key-value fields of a record, like `log::info!(profile = name; "...")`.
+fn record_fields(record: &log::Record) -> Vec<(String, String)> {
+    struct Collect(Vec<(String, String)>);
+    impl<'kvs> log::kv::VisitSource<'kvs> for Collect {
+        fn visit_pair(
+            &mut self,
+            key: log::kv::Key<'kvs>,
+            value: log::kv::Value<'kvs>,
+        ) -> Result<(), log::kv::Error> {
+            self.0.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+    let mut fields = Collect(Vec::new());
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
+
+fn timestamp() -> String {
+    time::OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default()
+}
+
+/// Record as a text line, also kept for crash reports.
+fn text_line(time: &str, record: &log::Record, fields: &[(String, String)]) -> String {
+    let mut line = format!(
+        "{} {:<5} [{}] {}",
+        time,
+        record.level(),
+        record.target(),
+        record.args()
+    );
+    for (key, value) in fields {
+        line.push_str(&format!(" {}={}", key, value))
+    }
+    line
+}
+
+fn json_line(time: &str, record: &log::Record, fields: &[(String, String)]) -> String {
+    let mut json = serde_json::json!({
+        "time": time,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    for (key, value) in fields {
+        json[key] = value.as_str().into()
+    }
+    json.to_string()
+}
+
+/// Syslog priority of a level, for the `PRIORITY` journal field.
+fn priority(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => 3,
+        log::Level::Warn => 4,
+        log::Level::Info => 6,
+        log::Level::Debug | log::Level::Trace => 7,
+    }
+}
+
+/// Syslog message : `<priority>slam[pid]: [target] message`. The system logger adds the time.
+fn syslog_message(record: &log::Record, line: &str) -> String {
+    let priority = SYSLOG_FACILITY * 8 + priority(record.level());
+    format!("<{}>slam[{}]: {}", priority, std::process::id(), line)
+}
+
+/// Datagram of the native journal protocol. Values with newlines use the binary form : name, newline,
+/// little endian 64 bit length, value, newline.
+fn journal_datagram(record: &log::Record, fields: &[(String, String)]) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    let mut push = |name: &str, value: &str| {
+        datagram.extend_from_slice(name.as_bytes());
+        match value.contains('\n') {
+            true => {
+                datagram.push(b'\n');
+                datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            }
+            false => datagram.push(b'='),
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n')
+    };
+    push("MESSAGE", &record.args().to_string());
+    push("PRIORITY", &priority(record.level()).to_string());
+    push("SYSLOG_IDENTIFIER", "slam");
+    push("TARGET", record.target());
+    for (key, value) in fields {
+        // Journal field names are uppercase letters, digits and underscores
+        let key = key
+            .to_ascii_uppercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        push(&format!("SLAM_{}", key), value)
+    }
+    datagram
+}
+
+struct Logger {
+    level: log::LevelFilter,
+    format: LogFormat,
+    journal: Option<UnixDatagram>,
+    output: LogOutput,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if record.level() == log::Level::Error {
+            crate::stats::record_error(record.args().to_string())
+        }
+        let time = timestamp();
+        let fields = record_fields(record);
+        let line = text_line(&time, record, &fields);
+        let sent_to_journal = match (&self.format, &self.journal) {
+            (LogFormat::Journald, Some(journal)) => {
+                let datagram = journal_datagram(record, &fields);
+                journal.send_to(&datagram, JOURNAL_SOCKET).is_ok()
+            }
+            _ => false,
+        };
+        if !sent_to_journal {
+            let formatted = match self.format {
+                LogFormat::Json => json_line(&time, record, &fields),
+                _ => line.clone(),
+            };
+            let _ = match &self.output {
+                LogOutput::Stderr => writeln!(std::io::stderr(), "{}", formatted),
+                LogOutput::File(file) => {
+                    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                    writeln!(file, "{}", formatted)
+                }
+                LogOutput::Syslog(socket) => {
+                    // Text lines without the time, which syslog records itself
+                    let message = match self.format {
+                        LogFormat::Json => &formatted,
+                        _ => &line[time.len() + 1..],
+                    };
+                    let message = syslog_message(record, message);
+                    socket.send(message.as_bytes()).map(|_| ())
+                }
+            };
+        }
+        crate::crash::keep_log_line(line)
+    }
+
+    fn flush(&self) {
+        let _ = match &self.output {
+            LogOutput::File(file) => file.lock().map_or(Ok(()), |mut file| file.flush()),
+            _ => std::io::stderr().flush(),
+        };
+    }
+}
+
+/// Set the logger of the process, writing records of at most `level` in `format` to `output`.
+/// Records also go to `output` if journald cannot be reached. Recent lines are kept for crash reports.
+pub fn init(
+    level: log::Level,
+    format: LogFormat,
+    output: LogOutput,
+) -> Result<(), log::SetLoggerError> {
+    let journal = match format {
+        LogFormat::Journald => UnixDatagram::unbound().ok(),
+        _ => None,
+    };
+    let logger = Logger {
+        level: level.to_level_filter(),
+        format,
+        journal,
+        output,
+    };
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(level.to_level_filter());
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_record_formats() {
+    let fields = [("profile".to_string(), "docked".to_string())];
+    let args = format_args!("applied\nlayout");
+    let record = log::Record::builder()
+        .args(args)
+        .level(log::Level::Info)
+        .target("slam")
+        .build();
+    assert_eq!(
+        text_line("T", &record, &fields),
+        "T INFO  [slam] applied\nlayout profile=docked"
+    );
+    let json: serde_json::Value = serde_json::from_str(&json_line("T", &record, &fields)).unwrap();
+    assert_eq!(json["message"], "applied\nlayout");
+    assert_eq!(json["level"], "INFO");
+    assert_eq!(json["profile"], "docked");
+    let datagram = journal_datagram(&record, &fields);
+    let mut expected = b"MESSAGE\n".to_vec();
+    expected.extend_from_slice(&14_u64.to_le_bytes());
+    expected.extend_from_slice(b"applied\nlayout\nPRIORITY=6\nSYSLOG_IDENTIFIER=slam\n");
+    expected.extend_from_slice(b"TARGET=slam\nSLAM_PROFILE=docked\n");
+    assert_eq!(datagram, expected);
+
+    let kv: &[(&str, &str)] = &[("event", "applied")];
+    let record = log::Record::builder().key_values(&kv).build();
+    assert_eq!(record_fields(&record), [("event".into(), "applied".into())]);
+    assert_eq!("journald".parse(), Ok(LogFormat::Journald));
+    assert_eq!("syslog".parse(), Ok(LogTarget::Syslog));
+    let record = log::Record::builder().level(log::Level::Warn).build();
+    assert_eq!(
+        syslog_message(&record, "[slam] lost"),
+        format!("<28>slam[{}]: [slam] lost", std::process::id())
+    );
+}
@@ -1,54 +1,1331 @@
 use clap::Parser;
+use slam::export::ExportFormat;
+use slam::hooks::LayoutEvent;
+use slam::logging::{LogOutput, LogTarget};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::process::ExitCode;
 use std::time::Duration;
 
 #[derive(Debug, Parser)]
 #[clap(version, about)]
-struct DaemonOptions {
-    /// Path to database file (default: <sys_config_dir>/slam/database.json)
-    #[clap(long, parse(from_os_str), value_name = "FILE")]
+struct Options {
+    /// Path to database file (default: <sys_state_dir>/slam/database.json)
+    #[clap(long, env = "SLAM_DATABASE", parse(from_os_str), value_name = "FILE")]
     database: Option<PathBuf>,
 
+    /// Path to config file, toml or json (default: <sys_config_dir>/slam/config.toml)
+    #[clap(long, env = "SLAM_CONFIG", parse(from_os_str), value_name = "FILE")]
+    config: Option<PathBuf>,
+
     /// Sets log level: error warn info debug trace
-    #[clap(long, value_name = "LEVEL")]
+    #[clap(long, env = "SLAM_LOG_LEVEL", value_name = "LEVEL")]
     log_level: Option<log::Level>,
 
+    /// Sets log format: text json journald
+    #[clap(long, env = "SLAM_LOG_FORMAT", value_name = "FORMAT")]
+    log_format: Option<slam::logging::LogFormat>,
+
+    /// Sets log destination: stderr syslog file (see --log-file)
+    #[clap(long, env = "SLAM_LOG_TARGET", value_name = "TARGET")]
+    log_target: Option<slam::logging::LogTarget>,
+
     /// Wait for other daemons to react
-    #[clap(long, value_name = "SECONDS")]
+    #[clap(long, env = "SLAM_REACTION_DELAY", value_name = "SECONDS")]
     reaction_delay: Option<u64>,
+
+    /// Run the daemon in the background, with logs written to the log file
+    #[clap(long)]
+    daemonize: bool,
+
+    /// Log file when daemonized or logging to a file (default: <sys_state_dir>/slam/slam.log)
+    #[clap(long, parse(from_os_str), value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Pid file when daemonized, removed on exit (default: <sys_runtime_dir>/slam/slam.pid)
+    #[clap(long, alias = "pidfile", parse(from_os_str), value_name = "FILE")]
+    pid_file: Option<PathBuf>,
+
+    /// Path to the control socket of the daemon (default: <sys_runtime_dir>/slam.sock)
+    #[clap(long, env = "SLAM_SOCKET", parse(from_os_str), value_name = "FILE")]
+    socket: Option<PathBuf>,
+
+    /// Run the daemon if no command is given
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-fn run_with_logging(options: DaemonOptions) -> Result<(), anyhow::Error> {
-    let database_path = match options.database {
-        Some(path) => path,
-        None => {
-            let mut p = dirs::config_dir().ok_or(anyhow::Error::msg(
-                "no system config directory, database path must be provided",
-            ))?;
-            p.push("slam");
-            p.push("database.json");
-            log::info!("using database location {}", p.display());
-            p
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Run the daemon (same as no command)
+    Daemon,
+    /// List layouts saved by name, with their outputs and usage (does not require a backend)
+    List,
+    /// Save the current layout under a name, replacing any layout saved with this name
+    Save { name: String },
+    /// Delete a layout saved by name
+    Delete { name: String },
+    /// Send a command to the running daemon through its control socket
+    Ctl {
+        #[clap(subcommand)]
+        command: CtlCommand,
+    },
+    /// Fire configured hooks and notifications with a synthetic layout event
+    NotifyTest {
+        /// Event to simulate: applied stored
+        #[clap(long, default_value = "applied")]
+        event: LayoutEvent,
+
+        /// Print hook commands and their environment instead of running them
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Flip the current layout from left to right, then apply it and store it in the database
+    Mirror,
+    /// Print the stored layout of connected outputs in the configuration format of another tool: sway hyprland kscreen xorg kanshi xrandr
+    Export {
+        format: ExportFormat,
+
+        /// Export the layout saved under this name instead
+        #[clap(conflicts_with = "all")]
+        name: Option<String>,
+
+        /// Export all stored layouts (does not require a backend)
+        #[clap(long)]
+        all: bool,
+    },
+    /// Print the current outputs, and with --follow every later change with a timestamp
+    Events {
+        /// Keep printing changes
+        #[clap(long)]
+        follow: bool,
+    },
+    /// Print events of the running daemon as json lines: outputs connected or disconnected, layouts applied or stored
+    Watch,
+    /// Block until outputs are connected and their layout is applied (stored layout, or any supported one)
+    Wait {
+        /// Minimum number of connected outputs
+        #[clap(long, value_name = "N")]
+        outputs: Option<usize>,
+
+        /// Output that must be connected and enabled, by name or id (repeatable)
+        #[clap(long = "output", value_name = "NAME")]
+        output_names: Vec<String>,
+
+        /// Fail after this delay
+        #[clap(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+    },
+    /// Replay random hotplug sequences through the daemon loop with a simulated backend, checking invariants
+    ///
+    /// Uses a temporary database, and does not require a display server.
+    Stress {
+        /// Number of event bursts
+        #[clap(long, default_value = "1000")]
+        batches: usize,
+
+        /// Number of simulated outputs
+        #[clap(long, default_value = "4")]
+        outputs: usize,
+
+        /// Seed of the random sequence (default: from time)
+        #[clap(long)]
+        seed: Option<u64>,
+    },
+    /// Simulate long laptop usage (docking, undocking) through the daemon loop, checking resource bounds
+    ///
+    /// Uses a temporary database, and does not require a display server.
+    Soak {
+        /// Simulated duration
+        #[clap(long, default_value = "10000")]
+        hours: u64,
+
+        /// Seed of the random sequence (default: from time)
+        #[clap(long)]
+        seed: Option<u64>,
+    },
+    /// Import layouts from a url into the database, replacing stored layouts for the same outputs
+    ///
+    /// Formats: database (json list of layouts, default), kanshi (configuration file).
+    /// Downloads use curl, so http(s) and file urls are supported.
+    /// Without url, kanshi configurations are read from <sys_config_dir>/kanshi/config.
+    Import {
+        #[clap(default_value = "database")]
+        format: ImportFormat,
+
+        #[clap(long)]
+        url: Option<String>,
+
+        /// Keep running and import again with this period
+        #[clap(long, value_name = "SECONDS")]
+        every: Option<u64>,
+    },
+    /// First-run setup: create directories and save the current layout as a profile
+    Init {
+        /// Name of the profile created from the current layout
+        #[clap(long, default_value = "initial")]
+        profile: String,
+
+        /// Also generate and install a systemd user unit running the daemon with the graphical session
+        #[clap(long)]
+        systemd: bool,
+    },
+    /// Print the current layout: outputs with their mode and position, primary output, and unsupported causes
+    Status {
+        /// Print a json object instead of text, for scripts
+        #[clap(long)]
+        json: bool,
+
+        /// Also print counters of the running daemon: events processed, layouts applied and stored, last error
+        #[clap(long)]
+        stats: bool,
+    },
+    /// Print what applying a layout would change for each output: mode, position, transform, enabled or disabled
+    Diff {
+        /// Saved layout name or layout spec (default: the stored layout of connected outputs)
+        name: Option<String>,
+    },
+    /// Draw the enabled outputs of a layout to a png image, and print the color of each output
+    #[cfg(feature = "preview")]
+    Preview {
+        /// `current`, a saved layout name, or a layout spec
+        layout: String,
+
+        /// Png file to write
+        #[clap(short, long, value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// Print a short stable fingerprint of the connected outputs, and the matching profile name if any
+    Fingerprint,
+    /// Print or set the font DPI (Xft.dpi) of the stored layout of connected outputs
+    ///
+    /// The daemon sets it with xrdb when applying the layout, and gives it to hooks as SLAM_FONT_DPI.
+    FontDpi {
+        /// New font DPI, applied immediately
+        #[clap(conflicts_with = "clear")]
+        dpi: Option<u32>,
+
+        /// Remove the font DPI of the layout
+        #[clap(long)]
+        clear: bool,
+    },
+    /// Print the backlight brightness of outputs, or set the brightness of an output in the stored layout
+    ///
+    /// The brightness is set immediately, and again each time the layout is applied.
+    Brightness {
+        /// Output name
+        #[clap(requires = "percent")]
+        output: Option<String>,
+
+        /// Brightness in percent
+        percent: Option<u8>,
+    },
+    /// Validate and apply a saved layout by name, or a layout from a json file or a one-line spec
+    ///
+    /// Layouts from a file or spec do not use the database.
+    /// Spec format: space separated `NAME:MODE[@X,Y][:OPTION]...` for each output.
+    /// MODE is one of: preferred WxH WxH@HZ off.
+    /// OPTION is one of: left-of=NAME right-of=NAME above=NAME below=NAME same-as=NAME primary rotate=TRANSFORM scale=FACTOR
+    /// panning=WxH[/TWxTH+TX+TY[/L/T/R/B]] set=PROPERTY=VALUE broadcast-rgb=auto|full|limited
+    /// content-type=none|graphics|photo|cinema|game brightness=PERCENT.
+    /// TRANSFORM is comma separated among: normal left inverted right reflect-x reflect-y.
+    /// Example: "eDP-1:preferred@0,0 DP-1:2560x1440@60:right-of=eDP-1:primary"
+    Apply {
+        /// Saved layout name, or layout spec
+        #[clap(required_unless_present = "file", conflicts_with = "file")]
+        spec: Option<String>,
+
+        /// Layout file (same json format as database entries)
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        file: Option<PathBuf>,
+
+        /// Only validate the layout against connected outputs, and print the requests that would apply it (X backend)
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ImportFormat {
+    Database,
+    Kanshi,
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ImportFormat, String> {
+        match s {
+            "database" => Ok(ImportFormat::Database),
+            "kanshi" => Ok(ImportFormat::Kanshi),
+            _ => Err(format!("unknown import format: {}", s)),
         }
-    };
+    }
+}
 
-    let reaction_delay = options.reaction_delay.map(Duration::from_secs);
-    let mut database = slam::database::Database::load_or_empty(database_path)?;
+#[derive(Debug, clap::Subcommand)]
+enum CtlCommand {
+    /// Print the current layout, and whether it is learned and the daemon paused
+    Status,
+    /// Print counters of the daemon since it started, as json
+    Stats,
+    /// Apply a saved layout by name, or a layout spec (see `slam help apply`)
+    Apply { layout: String },
+    /// Save the current layout under a name
+    Save { name: String },
+    /// Read the database file again
+    Reload,
+    /// Stop storing and applying layouts
+    Pause,
+    /// Resume storing and applying layouts, from the current layout
+    Resume,
+}
+
+impl From<CtlCommand> for slam::control::Request {
+    fn from(command: CtlCommand) -> Self {
+        use slam::control::Request;
+        match command {
+            CtlCommand::Status => Request::Status,
+            CtlCommand::Stats => Request::Stats,
+            CtlCommand::Apply { layout } => Request::Apply(layout),
+            CtlCommand::Save { name } => Request::Save(name),
+            CtlCommand::Reload => Request::Reload,
+            CtlCommand::Pause => Request::Pause,
+            CtlCommand::Resume => Request::Resume,
+        }
+    }
+}
 
+/// Default location of slam files: `<dir>/slam/<file_name>`.
+fn default_path(
+    dir: Option<PathBuf>,
+    dir_kind: &str,
+    file_name: &str,
+) -> Result<PathBuf, anyhow::Error> {
+    let mut p = dir.ok_or_else(|| {
+        anyhow::Error::msg(format!(
+            "no system {} directory, {} path must be provided",
+            dir_kind, file_name
+        ))
+    })?;
+    p.push("slam");
+    p.push(file_name);
+    Ok(p)
+}
+
+/// User configuration goes to `XDG_CONFIG_HOME`, as `config.toml`.
+/// A `config.json` from before toml support is still used if there is no `config.toml`.
+fn default_config_path() -> Result<PathBuf, anyhow::Error> {
+    let path = default_path(dirs::config_dir(), "config", "config.toml")?;
+    let legacy_path = path.with_extension("json");
+    match !path.exists() && legacy_path.exists() {
+        true => Ok(legacy_path),
+        false => Ok(path),
+    }
+}
+
+/// Learned layouts are mutable state and go to `XDG_STATE_HOME` (or local data dir on other systems).
+/// The database is moved there from its previous default location in the config directory if needed.
+fn default_database_path() -> Result<PathBuf, anyhow::Error> {
+    use anyhow::Context;
+    let path = default_path(
+        dirs::state_dir().or_else(dirs::data_local_dir),
+        "state",
+        "database.json",
+    )?;
+    let legacy_path = dirs::config_dir().map(|dir| dir.join("slam").join("database.json"));
+    if let Some(legacy_path) = legacy_path.filter(|p| p.exists() && !path.exists()) {
+        log::warn!(
+            "moving database from {} to {}",
+            legacy_path.display(),
+            path.display()
+        );
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("cannot create directory {}", parent.display()))?
+        }
+        // Rename fails across filesystems
+        if std::fs::rename(&legacy_path, &path).is_err() {
+            std::fs::copy(&legacy_path, &path)
+                .with_context(|| format!("cannot copy database to {}", path.display()))?;
+            std::fs::remove_file(&legacy_path).with_context(|| {
+                format!("cannot remove legacy database {}", legacy_path.display())
+            })?
+        }
+    }
+    Ok(path)
+}
+
+/// The control socket is `$XDG_RUNTIME_DIR/slam.sock`, which is private to the user.
+fn default_socket_path() -> Result<PathBuf, anyhow::Error> {
+    let dir = dirs::runtime_dir().ok_or_else(|| {
+        anyhow::Error::msg("no system runtime directory, socket path must be provided")
+    })?;
+    Ok(dir.join("slam.sock"))
+}
+
+/// Crash reports go to the state directory, or the temporary directory.
+fn crash_report_dir() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("slam")
+}
+
+/// Instance locks go to the runtime directory, which is private to the user.
+fn instance_lock_dir() -> PathBuf {
+    dirs::runtime_dir()
+        .or_else(dirs::state_dir)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("slam")
+}
+
+fn start_backend() -> Result<Box<dyn slam::Backend>, anyhow::Error> {
+    // Before xcb, which would connect to Xwayland
+    #[cfg(feature = "wayland")]
+    match slam::wayland::WaylandBackend::start() {
+        Ok(backend) => return Ok(Box::new(backend)),
+        Err(e) => log::info!("cannot start Wayland backend: {}", e),
+    }
     #[cfg(feature = "xcb")]
     match slam::xcb::XcbBackend::start() {
-        Ok(mut backend) => return slam::run_daemon(&mut backend, reaction_delay, &mut database),
+        Ok(backend) => return Ok(Box::new(backend)),
         Err(e) => log::info!("cannot start Xcb backend: {}", e),
     }
+    // Last, as it also starts below display servers but cannot apply layouts there
+    #[cfg(feature = "drm")]
+    match slam::drm::DrmBackend::start() {
+        Ok(backend) => return Ok(Box::new(backend)),
+        Err(e) => log::info!("cannot start DRM backend: {}", e),
+    }
     Err(anyhow::Error::msg("no working available backend"))
 }
 
+/// Delays between attempts to start the backend again after losing the display server connection.
+/// They double from the first one, and the daemon stops after the last one (about 5 minutes in total).
+const RECONNECT_FIRST_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+const RECONNECT_ATTEMPTS: u32 = 10;
+
+/// Start the backend again with exponential backoff, like while a display manager restarts.
+/// Returns [`None`] if termination was requested meanwhile.
+fn restart_backend() -> Result<Option<Box<dyn slam::Backend>>, anyhow::Error> {
+    let mut delay = RECONNECT_FIRST_DELAY;
+    for attempt in 1..=RECONNECT_ATTEMPTS {
+        let deadline = std::time::Instant::now() + delay;
+        while std::time::Instant::now() < deadline {
+            if slam::signals::termination_requested() {
+                return Ok(None);
+            }
+            slam::sd_notify::ping_watchdog();
+            // Woken up by signals, and in time for the next watchdog ping
+            let next_ping =
+                slam::sd_notify::watchdog_period().map(|period| std::time::Instant::now() + period);
+            let wake = Vec::from_iter(slam::signals::wake_fd());
+            slam::wait::wait_readable(
+                &wake,
+                Some(next_ping.map_or(deadline, |t| t.min(deadline))),
+            )?;
+            slam::signals::clear_wake()
+        }
+        match start_backend() {
+            Ok(backend) => {
+                log::info!("backend started again");
+                return Ok(Some(backend));
+            }
+            Err(e) => log::warn!("attempt {} to start backend: {:#}", attempt, e),
+        }
+        delay = std::cmp::min(2 * delay, RECONNECT_MAX_DELAY)
+    }
+    Err(anyhow::Error::msg(format!(
+        "cannot start backend after {} attempts",
+        RECONNECT_ATTEMPTS
+    )))
+}
+
+/// Load config from `--config`, `SLAM_CONFIG` or the default location, returned with the config.
+fn load_config(options: &Options) -> Result<(PathBuf, slam::config::Config), anyhow::Error> {
+    let config_path = match &options.config {
+        Some(path) => path.clone(),
+        None => default_config_path()?,
+    };
+    let config = slam::config::Config::load_or_default(&config_path)?;
+    Ok((config_path, config))
+}
+
+/// Command line (or environment) options take precedence over the config file.
+fn run_with_logging(options: Options, config: slam::config::Config) -> Result<(), anyhow::Error> {
+    let database_path = || match options.database.clone().or_else(|| config.database.clone()) {
+        Some(path) => Ok(path),
+        None => default_database_path(),
+    };
+
+    let config_path = || match &options.config {
+        Some(path) => Ok(path.clone()),
+        None => default_config_path(),
+    };
+
+    let load_database = || {
+        let path = database_path()?;
+        log::info!("using database {}", path.display());
+        slam::crash::set_database_path(path.clone());
+        let mut database =
+            slam::database::Database::load_or_empty(path, config.database_encryption.clone())?;
+        database.set_format(config.database_format);
+        database.set_max_layouts(config.database_max_layouts);
+        Ok::<_, anyhow::Error>(database)
+    };
+
+    if options.daemonize && !matches!(options.command, None | Some(Command::Daemon)) {
+        return Err(anyhow::Error::msg("--daemonize only applies to the daemon"));
+    }
+
+    match options.command {
+        None | Some(Command::Daemon) => {
+            let reaction_delay = options
+                .reaction_delay
+                .or(config.reaction_delay)
+                .map(Duration::from_secs);
+            let reaction_delays =
+                slam::ReactionDelays::with_config(reaction_delay, &config.reaction_delays);
+            let mut database = load_database()?;
+            // Before detaching, to report a running daemon in the terminal
+            let _instance_lock = slam::instance::InstanceLock::acquire(
+                &instance_lock_dir(),
+                &slam::instance::current_display(),
+            )?;
+            // Kept until the daemon stops, to remove the pid file
+            let _pid_file = if options.daemonize {
+                let log_file = match options.log_file {
+                    Some(path) => path,
+                    None => default_path(dirs::state_dir(), "state", "slam.log")?,
+                };
+                let pid_file = match options.pid_file {
+                    Some(path) => path,
+                    None => {
+                        let runtime_dir = dirs::runtime_dir().or_else(dirs::state_dir);
+                        default_path(runtime_dir, "runtime", "slam.pid")?
+                    }
+                };
+                Some(slam::daemonize::daemonize(&log_file, &pid_file)?)
+            } else {
+                None
+            };
+            let crash_dir = crash_report_dir();
+            slam::crash::install_panic_hook(crash_dir.clone());
+            let mut backend = start_backend()?;
+            // The daemon works without control socket
+            let control = (options.socket.map_or_else(default_socket_path, Ok))
+                .and_then(slam::control::ControlSocket::bind);
+            let control = match control {
+                Ok(control) => Some(control),
+                Err(e) => {
+                    log::warn!("{:#} ; control socket disabled", e);
+                    None
+                }
+            };
+            let database_watch = slam::file_watch::FileWatch::new(database.path())
+                .map_err(|e| log::warn!("{:#} ; database edits need a reload", e))
+                .ok();
+            slam::signals::install_handlers()?;
+            slam::sd_notify::notify("READY=1");
+            let result = loop {
+                let result = slam::run_daemon(
+                    &mut *backend,
+                    reaction_delays,
+                    &mut database,
+                    &config,
+                    config_path().ok().as_deref(),
+                    control.as_ref(),
+                    database_watch.as_ref(),
+                );
+                match result {
+                    Err(e) if !backend.is_connected() => {
+                        log::warn!("{:#} ; display server connection lost", e);
+                        match restart_backend()? {
+                            Some(restarted) => backend = restarted,
+                            None => break Ok(()),
+                        }
+                    }
+                    result => break result,
+                }
+            };
+            if let Err(e) = &result {
+                match slam::crash::write_report(&crash_dir, &format!("{:#}", e)) {
+                    Ok(path) => log::error!("crash report written to {}", path.display()),
+                    Err(report_error) => log::error!("cannot write crash report: {}", report_error),
+                }
+            }
+            result
+        }
+        Some(Command::List) => {
+            let database = load_database()?;
+            list(&database)
+        }
+        Some(Command::Save { name }) => {
+            let mut database = load_database()?;
+            let backend = start_backend()?;
+            let layout = slam::layout_to_save(&*backend, &config, &database)?;
+            database.save_layout(name, layout)
+        }
+        Some(Command::Delete { name }) => {
+            let mut database = load_database()?;
+            match database.delete_saved_layout(&name)? {
+                true => Ok(()),
+                false => Err(anyhow::Error::msg(format!(
+                    "no saved layout named {}",
+                    name
+                ))),
+            }
+        }
+        Some(Command::Ctl { command }) => {
+            let path = match options.socket {
+                Some(path) => path,
+                None => default_socket_path()?,
+            };
+            let reply = slam::control::send(&path, &command.into())?;
+            print!("{}", reply);
+            Ok(())
+        }
+        Some(Command::NotifyTest { event, dry_run }) => notify_test(&config, event, dry_run),
+        Some(Command::Mirror) => {
+            let mut database = load_database()?;
+            mirror(&config, &mut database)
+        }
+        Some(Command::Export { format, name, all }) => {
+            let database = load_database()?;
+            export(&database, format, name, all)
+        }
+        Some(Command::Events { follow }) => events(follow),
+        Some(Command::Watch) => {
+            let path = match options.socket {
+                Some(path) => path,
+                None => default_socket_path()?,
+            };
+            for event in slam::control::watch(&path)? {
+                println!("{}", event?)
+            }
+            Ok(())
+        }
+        Some(Command::Stress {
+            batches,
+            outputs,
+            seed,
+        }) => {
+            if outputs == 0 {
+                return Err(anyhow::Error::msg("at least one output is required"));
+            }
+            with_temporary_database("stress", seed, |path, seed| {
+                let report = slam::stress::stress(path, batches, outputs, seed)?;
+                Ok(report.to_string())
+            })
+        }
+        Some(Command::Soak { hours, seed }) => {
+            with_temporary_database("soak", seed, |path, seed| {
+                Ok(slam::stress::soak(path, hours, seed)?.to_string())
+            })
+        }
+        Some(Command::Import { format, url, every }) => {
+            let mut database = load_database()?;
+            import(&mut database, format, url, every.map(Duration::from_secs))
+        }
+        Some(Command::Init { profile, systemd }) => {
+            init(&config_path()?, &database_path()?, &profile, systemd)
+        }
+        Some(Command::FontDpi { dpi, clear }) => {
+            let mut database = load_database()?;
+            font_dpi(&config, &mut database, dpi, clear)
+        }
+        Some(Command::Brightness { output, percent }) => {
+            let mut database = load_database()?;
+            brightness(&config, &mut database, output.zip(percent))
+        }
+        Some(Command::Status { json, stats }) => {
+            let daemon_stats = match stats {
+                true => {
+                    let path = match options.socket {
+                        Some(path) => path,
+                        None => default_socket_path()?,
+                    };
+                    let reply = slam::control::send(&path, &slam::control::Request::Stats)?;
+                    let stats: slam::stats::DaemonStats = serde_json::from_str(&reply)
+                        .map_err(|e| anyhow::Error::msg(format!("invalid daemon stats: {}", e)))?;
+                    Some(stats)
+                }
+                false => None,
+            };
+            let backend = start_backend()?;
+            let info = slam::current_layout(&*backend, &config);
+            let names = slam::status::OutputNames::from_backend(&*backend);
+            match json {
+                true => {
+                    let mut json = slam::status::to_json(&info, &names);
+                    if let Some(stats) = daemon_stats {
+                        json["stats"] = serde_json::to_value(stats)?
+                    }
+                    println!("{}", json)
+                }
+                false => {
+                    print!("{}", slam::status::to_text(&info, &names));
+                    if let Some(stats) = daemon_stats {
+                        print!("{}", stats.to_text())
+                    }
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Diff { name }) => {
+            let database = load_database()?;
+            let backend = start_backend()?;
+            let current = backend.current_layout().layout;
+            let target = match name {
+                Some(text) => slam::layout_from_name_or_spec(&*backend, &database, &text)?,
+                None => (database.get_layout(&slam::database::LayoutById(current.clone())))
+                    .cloned()
+                    .ok_or_else(|| anyhow::Error::msg("no stored layout for connected outputs"))?,
+            };
+            let names = slam::status::OutputNames::from_backend(&*backend);
+            print!("{}", slam::status::diff_text(&current, &target, &names));
+            Ok(())
+        }
+        #[cfg(feature = "preview")]
+        Some(Command::Preview { layout, output }) => {
+            use anyhow::Context;
+            let database = load_database()?;
+            let backend = start_backend()?;
+            let layout = match layout.as_str() {
+                "current" => backend.current_layout().layout,
+                text => slam::layout_from_name_or_spec(&*backend, &database, text)?,
+            };
+            let drawing = slam::layout::preview::png_drawing(&layout)?;
+            std::fs::write(&output, drawing.png)
+                .with_context(|| format!("cannot write {}", output.display()))?;
+            let names = backend.output_names();
+            for (id, color) in drawing.colors {
+                let name = names.get(&id).cloned().unwrap_or_else(|| id.to_string());
+                println!("{} {}", color, name)
+            }
+            Ok(())
+        }
+        Some(Command::Fingerprint) => {
+            let backend = start_backend()?;
+            let layout = backend.current_layout().layout;
+            match slam::matching_profile(
+                &*backend,
+                &config,
+                &layout,
+                slam::profile::TimeOfDay::now(),
+            ) {
+                Some((profile, _)) => println!("{} {}", layout.fingerprint(), profile.name),
+                None => println!("{}", layout.fingerprint()),
+            }
+            Ok(())
+        }
+        Some(Command::Wait {
+            outputs,
+            output_names,
+            timeout,
+        }) => {
+            let database = load_database()?;
+            let timeout = timeout.map(Duration::from_secs);
+            let config = config.clone();
+            wait(
+                config,
+                database,
+                outputs.unwrap_or(0),
+                output_names,
+                timeout,
+            )
+        }
+        Some(Command::Apply {
+            spec,
+            file,
+            dry_run,
+        }) => {
+            let mut database = load_database()?;
+            apply(&config, &mut database, spec, file, dry_run)
+        }
+    }
+}
+
+/// Saved layout names take precedence over specs.
+fn apply(
+    config: &slam::config::Config,
+    database: &mut slam::database::Database,
+    spec: Option<String>,
+    file: Option<PathBuf>,
+    dry_run: bool,
+) -> Result<(), anyhow::Error> {
+    use anyhow::Context;
+    let mut backend = start_backend()?;
+    let current = backend.current_layout();
+    let (source, layout): (String, slam::layout::Layout) = match (spec, file) {
+        (Some(text), _) => (
+            format!("layout {}", text),
+            slam::layout_from_name_or_spec(&*backend, database, &text)?,
+        ),
+        (None, Some(file)) => {
+            let content = std::fs::read(&file)
+                .with_context(|| format!("cannot read layout {}", file.display()))?;
+            // Deserialization checks that the layout is supported
+            let layout = serde_json::from_slice(&content)
+                .with_context(|| format!("invalid layout {}", file.display()))?;
+            (format!("layout from {}", file.display()), layout)
+        }
+        (None, None) => unreachable!("required by clap"),
+    };
+    slam::check_connected_outputs(&layout, &current.layout)?;
+    if dry_run {
+        println!("layout is valid for connected outputs");
+        for request in backend.apply_requests(&layout)? {
+            println!("{}", request)
+        }
+        return Ok(());
+    }
+    slam::switch_layout(&mut *backend, config, &source, &layout)?;
+    slam::record_applied(database, &layout);
+    Ok(())
+}
+
+/// Outputs are designated by their name if a backend is available, or by their id.
+fn list(database: &slam::database::Database) -> Result<(), anyhow::Error> {
+    let names = match start_backend() {
+        Ok(backend) => backend.output_names(),
+        Err(_) => Default::default(),
+    };
+    let date = |secs: u64| match time::OffsetDateTime::from_unix_timestamp(secs as i64) {
+        Ok(time) => time.date().to_string(),
+        Err(_) => "?".into(),
+    };
+    for layout in database.saved_layouts() {
+        let spec = slam::layout::spec::LayoutSpec::from_layout(layout, &names);
+        let usage = match layout.usage() {
+            Some(usage) => format!(
+                "created {}, applied {} times, last {}",
+                date(usage.created),
+                usage.apply_count,
+                usage.last_applied.map_or("never".into(), date)
+            ),
+            None => "no usage data".into(),
+        };
+        println!("{}\t{}\t{}", layout.name().unwrap_or_default(), spec, usage)
+    }
+    Ok(())
+}
+
+/// Uses its own backend connection, and never changes the layout.
+fn events(follow: bool) -> Result<(), anyhow::Error> {
+    let mut backend = start_backend()?;
+    let mut layout = backend.current_layout().layout;
+    let mut names = backend.output_names();
+    let mut descriptions = backend.output_descriptions();
+    print_layout_changes(None, &layout, &names, &descriptions);
+    if !follow {
+        return Ok(());
+    }
+    loop {
+        backend.wait_for_events(Default::default(), None)?;
+        let new_layout = backend.current_layout().layout;
+        // Keep names of disconnected outputs, as the backend does not know them anymore
+        names.extend(backend.output_names());
+        descriptions.extend(backend.output_descriptions());
+        print_layout_changes(Some(&layout), &new_layout, &names, &descriptions);
+        layout = new_layout
+    }
+}
+
+/// The backend is used in a separate thread, so that waiting for changes can be interrupted by the timeout.
+fn wait(
+    config: slam::config::Config,
+    database: slam::database::Database,
+    min_outputs: usize,
+    output_names: Vec<String>,
+    timeout: Option<Duration>,
+) -> Result<(), anyhow::Error> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let result = (|| -> Result<(), anyhow::Error> {
+            let mut backend = start_backend()?;
+            loop {
+                let current = slam::current_layout(&*backend, &config);
+                let names = backend.output_names();
+                let is_enabled = |name: &String| {
+                    current.layout.output_entries().iter().any(|entry| {
+                        let named =
+                            names.get(&entry.id) == Some(name) || &entry.id.to_string() == name;
+                        named && entry.state.rect().is_some()
+                    })
+                };
+                let applied = match database
+                    .get_layout(&slam::database::LayoutById(current.layout.clone()))
+                {
+                    Some(stored) => stored == &current.layout,
+                    None => current.unsupported_causes.is_empty(),
+                };
+                if current.layout.connected_outputs().len() >= min_outputs
+                    && output_names.iter().all(is_enabled)
+                    && applied
+                {
+                    return Ok(());
+                }
+                backend.wait_for_events(Default::default(), None)?;
+            }
+        })();
+        // Receiver is gone on timeout
+        let _ = sender.send(result);
+    });
+    match timeout {
+        Some(timeout) => match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::Error::msg("timeout waiting for outputs")),
+        },
+        None => receiver.recv().expect("waiting thread sends result"),
+    }
+}
+
+/// Print one line per output : `+` connected, `-` disconnected, `*` changed.
+/// Unchanged outputs are printed without marker, to show the full state.
+fn print_layout_changes(
+    old: Option<&slam::layout::Layout>,
+    new: &slam::layout::Layout,
+    names: &HashMap<slam::layout::OutputId, String>,
+    descriptions: &HashMap<slam::layout::OutputId, String>,
+) {
+    use slam::layout::{Layout, OutputId, OutputState};
+    let now = time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+    let timestamp = now
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default();
+    let name = |id: &OutputId| match (names.get(id), descriptions.get(id)) {
+        (Some(name), Some(description)) => format!("{} ({}, {})", name, description, id),
+        (Some(name), None) => format!("{} ({})", name, id),
+        (None, Some(description)) => format!("{} ({})", id, description),
+        (None, None) => id.to_string(),
+    };
+    fn state_of<'l>(layout: &'l Layout, id: &OutputId) -> Option<&'l OutputState> {
+        let entry = layout.output_entries().iter().find(|e| &e.id == id);
+        entry.map(|e| &e.state)
+    }
+
+    let mut ids = Vec::from_iter(new.connected_outputs());
+    if let Some(old) = old {
+        ids.extend(old.connected_outputs());
+        ids.sort();
+        ids.dedup();
+    }
+    for id in ids {
+        let old_state = old.and_then(|old| state_of(old, id));
+        let (symbol, state) = match (old_state, state_of(new, id)) {
+            (None, Some(state)) if old.is_some() => ("+", state),
+            (Some(state), None) => ("-", state),
+            (Some(old_state), Some(state)) if old_state != state => ("*", state),
+            (_, Some(state)) => (" ", state),
+            (None, None) => continue,
+        };
+        println!("{} {} {}: {}", timestamp, symbol, name(id), state)
+    }
+    if old.map(|old| old.primary()) != Some(new.primary()) {
+        if let Some(primary) = new.primary() {
+            println!("{} * primary: {}", timestamp, name(primary))
+        }
+    }
+}
+
+fn init(
+    config_path: &std::path::Path,
+    database_path: &std::path::Path,
+    profile_name: &str,
+    systemd: bool,
+) -> Result<(), anyhow::Error> {
+    use anyhow::Context;
+    fn create_parent_dir(path: &std::path::Path) -> Result<(), anyhow::Error> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("cannot create directory {}", dir.display()))?;
+            println!("created {}", dir.display())
+        }
+        Ok(())
+    }
+    fn write_new_file(path: &std::path::Path, content: &str) -> Result<(), anyhow::Error> {
+        create_parent_dir(path)?;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .with_context(|| format!("cannot create {}", path.display()))?;
+        std::io::Write::write_all(&mut file, content.as_bytes())
+            .with_context(|| format!("cannot write {}", path.display()))?;
+        println!("wrote {}", path.display());
+        Ok(())
+    }
+
+    if profile_name.is_empty() || profile_name.contains(std::path::is_separator) {
+        return Err(anyhow::Error::msg(format!(
+            "invalid profile name: {}",
+            profile_name
+        )));
+    }
+    let backend = start_backend()?;
+    let diagnostics = backend.diagnostics();
+    println!(
+        "backend: {}",
+        diagnostics.lines().next().unwrap_or("available")
+    );
+
+    let current = backend.current_layout();
+    if !current.unsupported_causes.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+            "current layout is unsupported: {:?}",
+            current.unsupported_causes
+        )));
+    }
+    let spec =
+        slam::layout::spec::LayoutSpec::from_layout(&current.layout, &backend.output_names());
+    create_parent_dir(database_path)?;
+    let profile_path = match config_path.parent() {
+        Some(dir) => dir
+            .join("profiles.d")
+            .join(format!("{}.json", profile_name)),
+        None => return Err(anyhow::Error::msg("config path has no directory")),
+    };
+    let profile = serde_json::json!({ "name": profile_name, "layout": spec.to_string() });
+    write_new_file(&profile_path, &format!("{:#}\n", profile))?;
+
+    if systemd {
+        let unit_dir = dirs::config_dir()
+            .ok_or_else(|| anyhow::Error::msg("no config directory for systemd units"))?;
+        let exe = std::env::current_exe().context("cannot locate slam executable")?;
+        let unit = format!(
+            "[Unit]\n\
+             Description=Screen layout automatic manager\n\
+             PartOf=graphical-session.target\n\
+             After=graphical-session.target\n\
+             \n\
+             [Service]\n\
+             Type=notify\n\
+             ExecStart={}\n\
+             WatchdogSec=30\n\
+             Restart=on-failure\n\
+             \n\
+             [Install]\n\
+             WantedBy=graphical-session.target\n",
+            exe.display()
+        );
+        write_new_file(&unit_dir.join("systemd/user/slam.service"), &unit)?;
+        println!("enable it with: systemctl --user daemon-reload && systemctl --user enable --now slam.service")
+    }
+    Ok(())
+}
+
+/// With a refresh period, import failures are logged and retried at the next period.
+fn import(
+    database: &mut slam::database::Database,
+    format: ImportFormat,
+    url: Option<String>,
+    every: Option<Duration>,
+) -> Result<(), anyhow::Error> {
+    use anyhow::Context;
+    let url = match (url, format) {
+        (Some(url), _) => url,
+        (None, ImportFormat::Kanshi) => {
+            let dir = dirs::config_dir().ok_or_else(|| {
+                anyhow::Error::msg("no system config directory, kanshi config url must be provided")
+            })?;
+            format!(
+                "file://{}",
+                dir.join(slam::kanshi::DEFAULT_CONFIG).display()
+            )
+        }
+        (None, ImportFormat::Database) => {
+            return Err(anyhow::Error::msg("--url is required to import a database"))
+        }
+    };
+    let mut import_once = || -> Result<(), anyhow::Error> {
+        let content = slam::remote::fetch(&url)?;
+        let layouts = match format {
+            ImportFormat::Database => slam::database::parse_layouts(&content)?,
+            ImportFormat::Kanshi => {
+                let text = std::str::from_utf8(&content).context("kanshi config is not utf-8")?;
+                slam::kanshi::parse_config(text)?
+            }
+        };
+        let count = layouts.len();
+        let changed = database.import_layouts(layouts)?;
+        log::info!(
+            "imported {} layouts from {}: {} new or changed",
+            count,
+            url,
+            changed
+        );
+        Ok(())
+    };
+    let period = match every {
+        None => return import_once(),
+        Some(period) => period,
+    };
+    loop {
+        if let Err(e) = import_once() {
+            log::error!("{:#}", e)
+        }
+        std::thread::sleep(period)
+    }
+}
+
+/// Run a simulation with a database in a temporary directory, printing the seed first for replays.
+fn with_temporary_database<F>(name: &str, seed: Option<u64>, run: F) -> Result<(), anyhow::Error>
+where
+    F: FnOnce(&std::path::Path, u64) -> Result<String, anyhow::Error>,
+{
+    use anyhow::Context;
+    let seed = seed.unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+        now.map_or(0, |d| d.as_nanos() as u64)
+    });
+    println!("seed {}", seed);
+    let dir = std::env::temp_dir().join(format!("slam-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("cannot create directory {}", dir.display()))?;
+    let result = run(&dir.join("database.json"), seed);
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        log::warn!("cannot remove {}: {}", dir.display(), e)
+    }
+    println!("{}", result?);
+    Ok(())
+}
+
+fn export(
+    database: &slam::database::Database,
+    format: ExportFormat,
+    name: Option<String>,
+    all: bool,
+) -> Result<(), anyhow::Error> {
+    let backend = start_backend();
+    let names = match &backend {
+        Ok(backend) => backend.output_names(),
+        Err(_) => Default::default(),
+    };
+    if let Some(name) = name {
+        let saved = database
+            .saved_layout(&name)
+            .ok_or_else(|| anyhow::Error::msg(format!("no saved layout named {}", name)))?;
+        print!("{}", slam::export::export(saved, format, &names));
+        return Ok(());
+    }
+    if all {
+        for layout in database.layouts().chain(database.saved_layouts()) {
+            if let Some(prefix) = format.comment_prefix() {
+                let ids = Vec::from_iter(layout.connected_outputs().map(|id| id.to_string()));
+                println!("{} {}", prefix, ids.join(" "));
+            }
+            print!("{}", slam::export::export(layout, format, &names));
+        }
+        return Ok(());
+    }
+    let current = backend?.current_layout();
+    let stored = database
+        .get_layout(&slam::database::LayoutById(current.layout))
+        .ok_or_else(|| anyhow::Error::msg("no stored layout for connected outputs"))?;
+    print!("{}", slam::export::export(stored, format, &names));
+    Ok(())
+}
+
+fn font_dpi(
+    config: &slam::config::Config,
+    database: &mut slam::database::Database,
+    dpi: Option<u32>,
+    clear: bool,
+) -> Result<(), anyhow::Error> {
+    let backend = start_backend()?;
+    let current = slam::current_layout(&*backend, config);
+    let mut stored = database
+        .get_layout(&slam::database::LayoutById(current.layout))
+        .ok_or_else(|| anyhow::Error::msg("no stored layout for connected outputs"))?
+        .clone();
+    if dpi.is_none() && !clear {
+        match stored.font_dpi() {
+            Some(dpi) => println!("{}", dpi),
+            None => println!("none"),
+        }
+        return Ok(());
+    }
+    if dpi == Some(0) {
+        return Err(anyhow::Error::msg("font dpi must be positive"));
+    }
+    stored.set_font_dpi(dpi);
+    database.store_layout(stored)?;
+    if let Some(dpi) = dpi {
+        slam::session::set_font_dpi(dpi)?
+    }
+    Ok(())
+}
+
+fn brightness(
+    config: &slam::config::Config,
+    database: &mut slam::database::Database,
+    setting: Option<(String, u8)>,
+) -> Result<(), anyhow::Error> {
+    let mut backend = start_backend()?;
+    let names = backend.output_names();
+    let current = slam::current_layout(&*backend, config);
+    let stored = database
+        .get_layout(&slam::database::LayoutById(current.layout))
+        .cloned();
+    let (name, percent) = match setting {
+        Some(setting) => setting,
+        None => {
+            let levels = slam::backlight::output_brightness(&*backend);
+            let mut lines = Vec::from_iter(levels.iter().map(|(id, percent)| {
+                let name = names.get(id).cloned().unwrap_or_else(|| id.to_string());
+                let preferred = stored
+                    .iter()
+                    .flat_map(|layout| layout.brightness())
+                    .find(|b| &b.output == id);
+                match preferred {
+                    Some(preferred) => {
+                        format!("{}\t{}%\t(layout {}%)", name, percent, preferred.percent)
+                    }
+                    None => format!("{}\t{}%", name, percent),
+                }
+            }));
+            lines.sort();
+            for line in lines {
+                println!("{}", line)
+            }
+            return Ok(());
+        }
+    };
+    if percent > 100 {
+        return Err(anyhow::Error::msg("brightness must be at most 100%"));
+    }
+    let id = names
+        .iter()
+        .find(|(id, n)| **n == name || id.to_string() == name)
+        .map(|(id, _)| id.clone())
+        .ok_or_else(|| anyhow::Error::msg(format!("no connected output named {}", name)))?;
+    slam::backlight::set_output_brightness(&mut *backend, &id, percent)?;
+    let mut stored =
+        stored.ok_or_else(|| anyhow::Error::msg("no stored layout for connected outputs"))?;
+    let mut settings = stored.brightness().to_vec();
+    settings.retain(|b| b.output != id);
+    settings.push(slam::layout::OutputBrightness {
+        output: id,
+        percent,
+    });
+    stored.set_brightness(settings);
+    database.store_layout(stored)
+}
+
+fn mirror(
+    config: &slam::config::Config,
+    database: &mut slam::database::Database,
+) -> Result<(), anyhow::Error> {
+    let mut backend = start_backend()?;
+    let current = slam::current_layout(&*backend, config);
+    if !current.unsupported_causes.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+            "cannot mirror unsupported layout: {:?}",
+            current.unsupported_causes
+        )));
+    }
+    let mut mirrored = current.layout.mirrored_horizontally();
+    slam::switch_layout(&mut *backend, config, "mirrored layout", &mirrored)?;
+    mirrored.fill_disabled_reasons(slam::layout::DisabledReason::UserDisabled);
+    database.store_layout(mirrored)
+}
+
+/// Use a fixed layout so that hooks can be tested without any backend.
+fn notify_test(
+    config: &slam::config::Config,
+    event: LayoutEvent,
+    dry_run: bool,
+) -> Result<(), anyhow::Error> {
+    use slam::geometry::{Transform, Vec2d};
+    use slam::layout::{LayoutInfo, Mode, OutputEntry, OutputId, OutputState, Scale};
+    let enabled = |x, y, size: Vec2d<u32>| OutputState::Enabled {
+        mode: Mode {
+            size,
+            frequency: 60,
+            refresh: None,
+        },
+        transform: Transform::default(),
+        bottom_left: Vec2d::new(x, y),
+        scale: Scale::ONE,
+        panning: None,
+    };
+    let internal = OutputId::Name("eDP-1".into());
+    let LayoutInfo { layout, .. } = LayoutInfo::from_iter(
+        [
+            OutputEntry {
+                id: internal.clone(),
+                state: enabled(0, 0, Vec2d::new(1920, 1080)),
+            },
+            OutputEntry {
+                id: OutputId::Name("DP-1".into()),
+                state: enabled(1920, 0, Vec2d::new(2560, 1440)),
+            },
+        ],
+        Some(internal),
+    );
+
+    let mut hooks = Vec::new();
+    if event == LayoutEvent::Applied {
+        hooks = config.hooks.preswitch_invocations(&layout)
+    }
+    hooks.extend(config.hooks.postswitch_invocations(event, &layout));
+    if hooks.is_empty() {
+        println!("no hooks configured");
+    }
+    for hook in hooks {
+        println!("{}", hook);
+        if !dry_run {
+            match hook.run() {
+                Ok(()) => println!("-> success"),
+                Err(e) => println!("-> {:#}", e),
+            }
+        }
+    }
+    let notification = slam::notify::Notification::for_event(event, "test layout", &layout);
+    match (config.notifications, dry_run) {
+        (false, _) => println!("notifications disabled"),
+        (true, true) => println!("notification: {:?}", notification),
+        (true, false) => notification.send()?,
+    }
+    Ok(())
+}
+
 fn main() -> ExitCode {
-    let options = DaemonOptions::parse();
-    simple_logger::init_with_level(options.log_level.unwrap_or(log::Level::Warn))
-        .expect("first logger set");
-    match run_with_logging(options) {
+    let options = Options::parse();
+    // Config is needed for the log level, so errors and paths are reported after logger setup
+    let loaded = load_config(&options);
+    let config = loaded.as_ref().map(|(_, config)| config);
+    let config_log_level = config.as_ref().ok().and_then(|config| config.log_level);
+    let log_level = options.log_level.or(config_log_level);
+    let config_log_format = config.as_ref().ok().and_then(|config| config.log_format);
+    let log_format = options.log_format.or(config_log_format).unwrap_or_default();
+    let config_log_target = config.as_ref().ok().and_then(|config| config.log_target);
+    let log_output = match options.log_target.or(config_log_target).unwrap_or_default() {
+        LogTarget::Stderr => Ok(LogOutput::Stderr),
+        LogTarget::Syslog => LogOutput::syslog(),
+        LogTarget::File => (options.log_file.clone())
+            .map_or_else(|| default_path(dirs::state_dir(), "state", "slam.log"), Ok)
+            .and_then(|path| LogOutput::file(&path)),
+    };
+    let (log_output, log_output_error) = match log_output {
+        Ok(output) => (output, None),
+        Err(e) => (LogOutput::Stderr, Some(e)),
+    };
+    slam::logging::init(
+        log_level.unwrap_or(log::Level::Warn),
+        log_format,
+        log_output,
+    )
+    .expect("first logger set");
+    if let Some(e) = log_output_error {
+        log::error!("logging to stderr: {:#}", e)
+    }
+    let result = loaded.and_then(|(config_path, config)| {
+        log::info!("using configuration {}", config_path.display());
+        run_with_logging(options, config)
+    });
+    match result {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
             log::error!("{}", e);
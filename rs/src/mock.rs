@@ -0,0 +1,410 @@
+use crate::layout::{Layout, LayoutInfo, Mode, OutputEntry, OutputId, OutputState};
+use crate::{Backend, ReactionDelays};
+use std::collections::{HashMap, VecDeque};
+
+/// Change of the simulated system, from hardware or from another tool.
+#[derive(Debug, Clone)]
+pub enum MockEvent {
+    /// Output plugged in. It starts disabled, like on X.
+    Connect(OutputId),
+    Disconnect(OutputId),
+    /// Layout set by another tool. Ignored if its outputs are not the connected ones.
+    Change(Layout),
+    /// Whole system state replaced at once, connected outputs included, like after a resume.
+    Snapshot(LayoutInfo),
+}
+
+/// Number of applied layouts kept by [`MockBackend`] for inspection.
+const APPLIED_HISTORY: usize = 64;
+
+/// Returned by [`MockBackend::wait_for_change`] when the script is exhausted, to stop the daemon loop.
+#[derive(Debug)]
+pub struct EndOfScript;
+
+impl std::fmt::Display for EndOfScript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("end of mock event script")
+    }
+}
+impl std::error::Error for EndOfScript {}
+
+/// Backend simulating a system from a script of [`MockEvent`], without display server.
+///
+/// Applying a layout generates a change event, as on real systems where the daemon sees its own updates.
+/// With a reaction delay, consecutive connections and disconnections are seen as one change,
+/// like a dock plugging several outputs at once.
+pub struct MockBackend {
+    /// Outputs that can be connected, with their preferred mode.
+    preferred_modes: HashMap<OutputId, Mode>,
+    /// Connected outputs, sorted by id.
+    outputs: Vec<OutputEntry>,
+    primary: Option<OutputId>,
+    script: VecDeque<MockEvent>,
+    /// Events processed by the next successful apply.
+    on_apply: VecDeque<MockEvent>,
+    pending_change: bool,
+    applied_layouts: usize,
+    /// Last applied layouts, oldest first. Bounded for long simulations.
+    applied: VecDeque<Layout>,
+    /// Backlight of outputs, which all have one.
+    brightness: HashMap<OutputId, u8>,
+    /// Next applies to ignore, like GPUs silently rejecting configurations.
+    rejected_applies: usize,
+}
+
+impl MockBackend {
+    /// Backend without connected outputs.
+    pub fn new(preferred_modes: HashMap<OutputId, Mode>) -> MockBackend {
+        MockBackend {
+            preferred_modes,
+            outputs: Vec::new(),
+            primary: None,
+            script: VecDeque::new(),
+            on_apply: VecDeque::new(),
+            pending_change: false,
+            applied_layouts: 0,
+            applied: VecDeque::new(),
+            brightness: HashMap::new(),
+            rejected_applies: 0,
+        }
+    }
+
+    /// Queue an event, processed by a later [`Backend::wait_for_change`].
+    pub fn push_event(&mut self, event: MockEvent) {
+        self.script.push_back(event)
+    }
+
+    /// Queue an event processed right after the next successful [`Backend::apply_layout`],
+    /// like an output plugged while the daemon applies a layout : no change reports it as a hotplug.
+    pub fn push_event_on_apply(&mut self, event: MockEvent) {
+        self.on_apply.push_back(event)
+    }
+
+    /// Ignore the next `count` applies without error.
+    pub fn reject_applies(&mut self, count: usize) {
+        self.rejected_applies = count
+    }
+
+    /// Number of layouts applied with [`Backend::apply_layout`].
+    pub fn applied_layouts(&self) -> usize {
+        self.applied_layouts
+    }
+
+    /// Last layouts applied with [`Backend::apply_layout`], oldest first.
+    pub fn applied(&self) -> impl Iterator<Item = &Layout> {
+        self.applied.iter()
+    }
+
+    fn process_event(&mut self, event: MockEvent) {
+        match event {
+            MockEvent::Connect(id) => {
+                if self.preferred_modes.contains_key(&id) && self.outputs.iter().all(|o| o.id != id)
+                {
+                    self.outputs.push(OutputEntry {
+                        id,
+                        state: OutputState::Disabled { reason: None },
+                    });
+                    self.outputs.sort()
+                }
+            }
+            MockEvent::Disconnect(id) => self.outputs.retain(|o| o.id != id),
+            MockEvent::Change(layout) => {
+                if !self.set_layout(&layout) {
+                    log::debug!("mock: ignored change for other outputs")
+                }
+            }
+            MockEvent::Snapshot(info) => {
+                self.outputs = info.layout.output_entries().to_vec();
+                self.primary = info.layout.primary().cloned()
+            }
+        }
+    }
+
+    fn set_layout(&mut self, layout: &Layout) -> bool {
+        let connected = Vec::from_iter(self.outputs.iter().map(|o| &o.id));
+        if !Iterator::eq(layout.connected_outputs(), connected) {
+            return false;
+        }
+        self.outputs = layout.output_entries().to_vec();
+        self.primary = layout.primary().cloned();
+        true
+    }
+}
+
+impl Backend for MockBackend {
+    fn current_layout(&self) -> LayoutInfo {
+        let is_enabled = |id: &OutputId| {
+            let entry = self.outputs.iter().find(|o| &o.id == id);
+            entry.is_some_and(|o| o.state.rect().is_some())
+        };
+        let primary = self.primary.clone().filter(is_enabled);
+        LayoutInfo::from(self.outputs.clone(), primary)
+            .with_metadata(&self.output_names(), &self.preferred_modes())
+    }
+
+    fn wait_for_change(&mut self, reaction_delays: ReactionDelays) -> Result<(), anyhow::Error> {
+        if std::mem::take(&mut self.pending_change) {
+            return Ok(());
+        }
+        let event = self.script.pop_front().ok_or(EndOfScript)?;
+        let is_hotplug = |event: &MockEvent| !matches!(event, MockEvent::Change(_));
+        let coalesce = reaction_delays.hotplug.is_some() && is_hotplug(&event);
+        self.process_event(event);
+        while coalesce && self.script.front().is_some_and(is_hotplug) {
+            let next = self.script.pop_front().expect("non empty");
+            self.process_event(next)
+        }
+        Ok(())
+    }
+
+    fn apply_layout(&mut self, layout: &Layout) -> Result<(), anyhow::Error> {
+        if self.rejected_applies > 0 {
+            self.rejected_applies -= 1;
+            log::debug!("mock: rejected layout");
+            return Ok(());
+        }
+        if self.set_layout(layout) {
+            self.applied_layouts += 1;
+            if self.applied.len() == APPLIED_HISTORY {
+                self.applied.pop_front();
+            }
+            self.applied.push_back(layout.clone());
+            for event in std::mem::take(&mut self.on_apply) {
+                self.process_event(event)
+            }
+            self.pending_change = true
+        } else {
+            log::warn!("mock: could not apply layout for other outputs")
+        }
+        Ok(())
+    }
+
+    fn can_verify_apply(&self) -> bool {
+        true
+    }
+
+    fn output_names(&self) -> HashMap<OutputId, String> {
+        HashMap::from_iter(
+            self.outputs
+                .iter()
+                .map(|o| (o.id.clone(), o.id.to_string())),
+        )
+    }
+
+    fn brightness(&self) -> HashMap<OutputId, u8> {
+        HashMap::from_iter(self.outputs.iter().map(|o| {
+            let percent = self.brightness.get(&o.id).copied().unwrap_or(100);
+            (o.id.clone(), percent)
+        }))
+    }
+
+    fn set_brightness(&mut self, output: &OutputId, percent: u8) -> Result<bool, anyhow::Error> {
+        let connected = self.outputs.iter().any(|o| &o.id == output);
+        if connected {
+            self.brightness.insert(output.clone(), percent);
+        }
+        Ok(connected)
+    }
+
+    fn preferred_modes(&self) -> HashMap<OutputId, Mode> {
+        HashMap::from_iter(
+            self.outputs
+                .iter()
+                .filter_map(|o| Some((o.id.clone(), self.preferred_modes.get(&o.id)?.clone()))),
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_daemon_decisions() {
+    use crate::config::Config;
+    use crate::database::{Database, LayoutById};
+    use crate::geometry::Vec2d;
+    let mode = || Mode {
+        size: Vec2d::new(1920, 1080),
+        frequency: 60,
+        refresh: None,
+    };
+    let (a, b) = (OutputId::Name("a".into()), OutputId::Name("b".into()));
+    let modes = HashMap::from([(a.clone(), mode()), (b.clone(), mode())]);
+    let mut backend = MockBackend::new(modes);
+    let enabled = |name: &str, x| {
+        OutputEntry::enabled_for_test(name, Vec2d::new(1920, 1080), Vec2d::new(x, 0))
+    };
+    let b_left_of_a = LayoutInfo::from_iter([enabled("a", 1920), enabled("b", 0)], None).layout;
+    for event in [
+        MockEvent::Connect(a.clone()), // autolayout, applied and stored
+        MockEvent::Connect(b.clone()), // autolayout, applied and stored
+        MockEvent::Change(b_left_of_a.clone()), // stored
+        MockEvent::Disconnect(b.clone()), // stored layout applied
+        MockEvent::Connect(b.clone()), // stored layout applied
+    ] {
+        backend.push_event(event)
+    }
+
+    let dir = std::env::temp_dir().join(format!("slam-test-mock-{}", std::process::id()));
+    let mut database = Database::load_or_empty(dir.join("database.json"), None).unwrap();
+    let config = Config::default();
+    let result = crate::run_daemon(
+        &mut backend,
+        Default::default(),
+        &mut database,
+        &config,
+        None,
+        None,
+        None,
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(result.unwrap_err().is::<EndOfScript>());
+
+    assert_eq!(database.layouts().count(), 2);
+    let stored = database.get_layout(&LayoutById(b_left_of_a.clone()));
+    assert_eq!(stored, Some(&b_left_of_a));
+    let applied = Vec::from_iter(backend.applied());
+    assert_eq!(applied.len(), 4);
+    assert_eq!(applied[2].connected_outputs().len(), 1);
+    assert_eq!(applied[3], &b_left_of_a);
+    assert_eq!(backend.current_layout().layout, b_left_of_a);
+
+    // Applies are verified : retried once, then the backend state is kept
+    let a_left_of_b = b_left_of_a.mirrored_horizontally();
+    backend.reject_applies(1);
+    let switched = crate::switch_layout(&mut backend, &config, "test", &a_left_of_b).unwrap();
+    assert_eq!(switched, a_left_of_b);
+    backend.reject_applies(2);
+    let switched = crate::switch_layout(&mut backend, &config, "test", &b_left_of_a).unwrap();
+    assert_eq!(switched, a_left_of_b);
+}
+
+#[cfg(test)]
+#[test]
+fn test_outputs_changed_during_apply() {
+    use crate::config::Config;
+    use crate::database::Database;
+    use crate::geometry::Vec2d;
+    let mode = || Mode {
+        size: Vec2d::new(1920, 1080),
+        frequency: 60,
+        refresh: None,
+    };
+    let (a, b) = (OutputId::Name("a".into()), OutputId::Name("b".into()));
+    let mut backend = MockBackend::new(HashMap::from([(a.clone(), mode()), (b.clone(), mode())]));
+    backend.push_event(MockEvent::Connect(a.clone()));
+    // Plugged while the layout for a alone is applied
+    backend.push_event_on_apply(MockEvent::Connect(b.clone()));
+
+    let dir = std::env::temp_dir().join(format!("slam-test-mock-apply-{}", std::process::id()));
+    let mut database = Database::load_or_empty(dir.join("database.json"), None).unwrap();
+    let config = Config::default();
+    let result = crate::run_daemon(
+        &mut backend,
+        Default::default(),
+        &mut database,
+        &config,
+        None,
+        None,
+        None,
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(result.unwrap_err().is::<EndOfScript>());
+
+    // The new output is laid out instead of being stored as disabled by the user
+    let all_enabled = |layout: &Layout| {
+        let entries = layout.output_entries();
+        entries.len() == 2 && entries.iter().all(|o| o.state.rect().is_some())
+    };
+    let applied = Vec::from_iter(backend.applied());
+    assert_eq!(applied.len(), 2);
+    assert!(all_enabled(applied[1]));
+    let stored = Vec::from_iter(
+        database
+            .layouts()
+            .filter(|l| l.connected_outputs().len() == 2),
+    );
+    assert_eq!(stored.len(), 1);
+    assert!(all_enabled(stored[0]));
+}
+
+#[cfg(test)]
+#[test]
+fn test_backend_events() {
+    use crate::geometry::Vec2d;
+    use crate::{BackendEvent, EventKind};
+    use std::time::Duration;
+    let mode = || Mode {
+        size: Vec2d::new(1920, 1080),
+        frequency: 60,
+        refresh: None,
+    };
+    let (a, b) = (OutputId::Name("a".into()), OutputId::Name("b".into()));
+    let mut backend = MockBackend::new(HashMap::from([(a.clone(), mode()), (b.clone(), mode())]));
+    let enabled = |name: &str, x| {
+        OutputEntry::enabled_for_test(name, Vec2d::new(1920, 1080), Vec2d::new(x, 0))
+    };
+    let a_left_of_b =
+        LayoutInfo::from_iter([enabled("a", 0), enabled("b", 1920)], Some(a.clone())).layout;
+    for event in [
+        MockEvent::Connect(a.clone()),
+        MockEvent::Connect(b.clone()),
+        MockEvent::Change(a_left_of_b.clone()),
+        MockEvent::Change(a_left_of_b),
+        MockEvent::Disconnect(a.clone()),
+    ] {
+        backend.push_event(event)
+    }
+    let mut next = || backend.wait_for_events(Default::default(), None).unwrap();
+    assert_eq!(next(), [BackendEvent::OutputConnected(a.clone())]);
+    assert_eq!(next(), [BackendEvent::OutputConnected(b.clone())]);
+    assert_eq!(
+        next(),
+        [
+            BackendEvent::ConfigChanged,
+            BackendEvent::PrimaryChanged(Some(a.clone()))
+        ]
+    );
+    // Changes without visible effect are still reported
+    assert_eq!(next(), [BackendEvent::ConfigChanged]);
+    let events = next();
+    assert_eq!(
+        events,
+        [
+            BackendEvent::OutputDisconnected(a.clone()),
+            BackendEvent::PrimaryChanged(None)
+        ]
+    );
+    assert!(events[0].is_hotplug() && !events[1].is_hotplug());
+
+    // Hotplugs are coalesced with a hotplug delay only
+    let config = serde_json::from_str(r#"{"hotplug": 2000}"#).unwrap();
+    let delays = ReactionDelays::with_config(Some(Duration::from_millis(100)), &config);
+    assert_eq!(delays.get(EventKind::Hotplug), Some(Duration::from_secs(2)));
+    assert_eq!(
+        delays.get(EventKind::Crtc),
+        Some(Duration::from_millis(100))
+    );
+    assert_eq!(delays.longest(), Some(Duration::from_secs(2)));
+    for event in [
+        MockEvent::Connect(a.clone()),
+        MockEvent::Disconnect(b.clone()),
+        MockEvent::Disconnect(a.clone()),
+    ] {
+        backend.push_event(event)
+    }
+    let without_hotplug_delay = ReactionDelays {
+        hotplug: None,
+        ..delays
+    };
+    let events = backend
+        .wait_for_events(without_hotplug_delay, None)
+        .unwrap();
+    assert_eq!(events, [BackendEvent::OutputConnected(a.clone())]);
+    let events = backend.wait_for_events(delays, None).unwrap();
+    assert_eq!(
+        events,
+        [
+            BackendEvent::OutputDisconnected(a),
+            BackendEvent::OutputDisconnected(b)
+        ]
+    );
+}
@@ -0,0 +1,293 @@
+use crate::geometry::Vec2d;
+use crate::layout::modeline::CustomMode;
+use crate::layout::{Layout, LayoutInfo, Mode, OutputEntry, OutputId, OutputState};
+use crate::{Backend, ReactionDelays};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+/// Wait time on each backend when waiting for a change on any of them.
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+
+/// Backend combining several backends active at the same time, like two X screens.
+///
+/// The combined layout places the outputs of all backends in one logical space.
+/// Each backend has an offset in this space : backends are initially placed left to right,
+/// and offsets are updated by applied layouts.
+/// Layouts are split into one piece per backend, and applied transactionally.
+///
+/// Backends are waited on in turn with [`Backend::wait_for_change_until`], so they must support timeouts.
+pub struct MultiBackend {
+    backends: Vec<Box<dyn Backend>>,
+    offsets: Vec<Vec2d<i32>>,
+}
+
+impl MultiBackend {
+    pub fn new(backends: Vec<Box<dyn Backend>>) -> MultiBackend {
+        let mut offsets = Vec::new();
+        let mut x = 0;
+        for backend in &backends {
+            offsets.push(Vec2d::new(x, 0));
+            let width = backend.current_layout().layout.bounding_rect_size().x;
+            x += i32::try_from(width).unwrap_or(i32::MAX)
+        }
+        MultiBackend { backends, offsets }
+    }
+
+    /// Split `layout` into a layout and offset for each backend.
+    /// Fails if the connected outputs of `layout` are not exactly those of the backends.
+    fn split_layout(&self, layout: &Layout) -> Result<Vec<(Layout, Vec2d<i32>)>, anyhow::Error> {
+        let mut remaining = Vec::from_iter(layout.output_entries().iter());
+        let mut pieces = Vec::new();
+        for (i, backend) in self.backends.iter().enumerate() {
+            let current = backend.current_layout().layout;
+            let owned = Vec::from_iter(current.connected_outputs().cloned());
+            let (mut entries, others): (Vec<&OutputEntry>, _) =
+                remaining.into_iter().partition(|e| owned.contains(&e.id));
+            remaining = others;
+            if entries.len() != owned.len() {
+                return Err(anyhow::Error::msg(format!(
+                    "layout does not describe all outputs of backend {}",
+                    i
+                )));
+            }
+            entries.sort();
+            let offset = entries
+                .iter()
+                .filter_map(|e| e.state.rect())
+                .map(|rect| rect.bottom_left)
+                .reduce(Vec2d::cwise_min)
+                .unwrap_or_default();
+            let entries = Vec::from_iter(entries.into_iter().map(|entry| {
+                let mut entry = entry.clone();
+                if let OutputState::Enabled { bottom_left, .. } = &mut entry.state {
+                    *bottom_left -= offset
+                }
+                entry
+            }));
+            let primary = layout.primary().filter(|id| owned.contains(id)).cloned();
+            let mut piece = LayoutInfo::from(entries, primary).layout;
+            piece.copy_output_settings(layout);
+            pieces.push((piece, offset))
+        }
+        if let Some(entry) = remaining.first() {
+            return Err(anyhow::Error::msg(format!(
+                "output {} does not belong to any backend",
+                entry.id
+            )));
+        }
+        Ok(pieces)
+    }
+}
+
+impl Backend for MultiBackend {
+    fn current_layout(&self) -> LayoutInfo {
+        let mut entries = Vec::new();
+        let mut primary = None;
+        for (backend, offset) in Iterator::zip(self.backends.iter(), self.offsets.iter()) {
+            let layout = backend.current_layout().layout;
+            primary = primary.or_else(|| layout.primary().cloned());
+            entries.extend(layout.output_entries().iter().map(|entry| {
+                let mut entry = entry.clone();
+                if let OutputState::Enabled { bottom_left, .. } = &mut entry.state {
+                    *bottom_left += *offset
+                }
+                entry
+            }))
+        }
+        LayoutInfo::from(entries, primary)
+            .with_metadata(&self.output_names(), &self.preferred_modes())
+    }
+
+    fn wait_for_change(&mut self, reaction_delays: ReactionDelays) -> Result<(), anyhow::Error> {
+        loop {
+            for backend in self.backends.iter_mut() {
+                if backend.wait_for_change_until(reaction_delays, POLL_PERIOD)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn wait_for_change_until(
+        &mut self,
+        reaction_delays: ReactionDelays,
+        timeout: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Each backend is checked at least once, even without timeout
+            for backend in self.backends.iter_mut() {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if backend.wait_for_change_until(reaction_delays, remaining.min(POLL_PERIOD))? {
+                    return Ok(true);
+                }
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Only if all backends have some : otherwise they are polled in turn.
+    fn event_fds(&self) -> Vec<RawFd> {
+        let fds = Vec::from_iter(self.backends.iter().map(|backend| backend.event_fds()));
+        match fds.iter().all(|fds| !fds.is_empty()) {
+            true => fds.concat(),
+            false => Vec::new(),
+        }
+    }
+
+    /// Apply each piece in turn. If one fails, previous pieces are reverted to their prior layouts.
+    fn apply_layout(&mut self, layout: &Layout) -> Result<(), anyhow::Error> {
+        let pieces = self.split_layout(layout)?;
+        let mut previous_layouts = Vec::new();
+        for (i, (piece, _)) in pieces.iter().enumerate() {
+            let previous = self.backends[i].current_layout().layout;
+            if let Err(e) = self.backends[i].apply_layout(piece) {
+                for (j, previous) in previous_layouts.into_iter().enumerate().rev() {
+                    if let Err(e) = self.backends[j].apply_layout(&previous) {
+                        log::error!("cannot revert backend {}: {:#}", j, e)
+                    }
+                }
+                return Err(e).with_context(|| format!("backend {} failed, layout reverted", i));
+            }
+            previous_layouts.push(previous)
+        }
+        self.offsets = Vec::from_iter(pieces.into_iter().map(|(_, offset)| offset));
+        Ok(())
+    }
+
+    fn physical_sizes(&self) -> HashMap<OutputId, Vec2d<u32>> {
+        HashMap::from_iter(self.backends.iter().flat_map(|b| b.physical_sizes()))
+    }
+
+    fn output_names(&self) -> HashMap<OutputId, String> {
+        HashMap::from_iter(self.backends.iter().flat_map(|b| b.output_names()))
+    }
+
+    fn output_descriptions(&self) -> HashMap<OutputId, String> {
+        HashMap::from_iter(self.backends.iter().flat_map(|b| b.output_descriptions()))
+    }
+
+    fn preferred_modes(&self) -> HashMap<OutputId, Mode> {
+        HashMap::from_iter(self.backends.iter().flat_map(|b| b.preferred_modes()))
+    }
+
+    fn available_modes(&self) -> HashMap<OutputId, Vec<Mode>> {
+        HashMap::from_iter(self.backends.iter().flat_map(|b| b.available_modes()))
+    }
+
+    fn brightness(&self) -> HashMap<OutputId, u8> {
+        HashMap::from_iter(self.backends.iter().flat_map(|b| b.brightness()))
+    }
+
+    fn set_brightness(&mut self, output: &OutputId, percent: u8) -> Result<bool, anyhow::Error> {
+        for backend in self.backends.iter_mut() {
+            if backend.set_brightness(output, percent)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn refresh(&mut self) -> Result<(), anyhow::Error> {
+        for backend in self.backends.iter_mut() {
+            backend.refresh()?
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.backends.iter().all(|b| b.is_connected())
+    }
+
+    fn can_verify_apply(&self) -> bool {
+        self.backends.iter().all(|b| b.can_verify_apply())
+    }
+
+    fn apply_requests(&self, layout: &Layout) -> Result<Vec<String>, anyhow::Error> {
+        let mut requests = Vec::new();
+        for (i, (piece, _)) in self.split_layout(layout)?.iter().enumerate() {
+            let backend_requests = self.backends[i].apply_requests(piece)?;
+            requests.extend(
+                (backend_requests.into_iter()).map(|request| format!("backend {}: {}", i, request)),
+            )
+        }
+        Ok(requests)
+    }
+
+    fn add_custom_modes(
+        &mut self,
+        layout: &Layout,
+        modes: &[CustomMode],
+    ) -> Result<(), anyhow::Error> {
+        for backend in self.backends.iter_mut() {
+            backend.add_custom_modes(layout, modes)?
+        }
+        Ok(())
+    }
+
+    fn diagnostics(&self) -> String {
+        let sections =
+            self.backends.iter().enumerate().map(|(i, backend)| {
+                format!("backend {}:\n{}", i, backend.diagnostics().trim_end())
+            });
+        Vec::from_iter(sections).join("\n")
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_multi_backend() {
+    use crate::mock::{MockBackend, MockEvent};
+
+    let mode = Mode {
+        size: Vec2d::new(1920, 1080),
+        frequency: 60,
+        refresh: None,
+    };
+    let mock = |name: &str| {
+        let id = OutputId::Name(name.into());
+        let mut backend = MockBackend::new(HashMap::from([(id.clone(), mode.clone())]));
+        backend.push_event(MockEvent::Connect(id));
+        backend.wait_for_change(Default::default()).unwrap();
+        Box::new(backend) as Box<dyn Backend>
+    };
+    let layout = |positions: &[(&str, i32)]| {
+        let entries = positions
+            .iter()
+            .map(|(name, x)| OutputEntry::enabled_for_test(name, mode.size, Vec2d::new(*x, 0)));
+        LayoutInfo::from_iter(entries, None).layout
+    };
+
+    let mut multi = MultiBackend::new(vec![mock("a"), mock("b")]);
+    // Outputs are disabled on connection
+    assert_eq!(multi.current_layout().layout.connected_outputs().count(), 2);
+    let side_by_side = layout(&[("a", 1920), ("b", 0)]);
+    multi.apply_layout(&side_by_side).unwrap();
+    assert_eq!(multi.current_layout().layout, side_by_side);
+    // Outputs of unknown backends are rejected
+    assert!(multi
+        .apply_layout(&layout(&[("a", 0), ("b", 1920), ("c", 3840)]))
+        .is_err());
+
+    // A failing backend reverts the others
+    struct FailingBackend(Box<dyn Backend>);
+    impl Backend for FailingBackend {
+        fn current_layout(&self) -> LayoutInfo {
+            self.0.current_layout()
+        }
+        fn wait_for_change(&mut self, delay: ReactionDelays) -> Result<(), anyhow::Error> {
+            self.0.wait_for_change(delay)
+        }
+        fn apply_layout(&mut self, _: &Layout) -> Result<(), anyhow::Error> {
+            Err(anyhow::Error::msg("failure"))
+        }
+    }
+    let mut multi = MultiBackend::new(vec![mock("a"), Box::new(FailingBackend(mock("b")))]);
+    let before = multi.current_layout().layout;
+    assert!(multi.apply_layout(&side_by_side).is_err());
+    assert_eq!(multi.current_layout().layout, before);
+}
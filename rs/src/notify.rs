@@ -0,0 +1,80 @@
+use crate::hooks::LayoutEvent;
+use crate::layout::Layout;
+use anyhow::Context;
+use std::process::Command;
+
+/// Desktop notification, sent with the `notify-send` command from libnotify.
+/// This avoids a dbus dependency for a rarely used feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+}
+
+impl Notification {
+    /// Summary from the event and the `source` of the layout, like `Applied profile docked`.
+    pub fn for_event(event: LayoutEvent, source: &str, layout: &Layout) -> Notification {
+        let action = match event {
+            LayoutEvent::Applied => "Applied",
+            LayoutEvent::Stored => "Stored",
+        };
+        let body = Vec::from_iter(layout.connected_outputs().map(|id| id.to_string())).join(", ");
+        Notification {
+            summary: format!("{} {}", action, source),
+            body,
+        }
+    }
+
+    /// Layout not applied by the backend, with the outputs in another state.
+    pub fn for_failure(source: &str, outputs: &[String]) -> Notification {
+        Notification {
+            summary: format!("Apply failed: {}", source),
+            body: format!(
+                "outputs not configured as requested: {}",
+                outputs.join(", ")
+            ),
+        }
+    }
+
+    /// Show the decision of the daemon for an unknown output set, with a drawing of the layout.
+    pub fn for_autolayout(diagram: String) -> Notification {
+        Notification {
+            summary: "Auto-generated layout".into(),
+            body: diagram,
+        }
+    }
+
+    pub fn send(&self) -> Result<(), anyhow::Error> {
+        let status = Command::new("notify-send")
+            .arg("--app-name=slam")
+            .arg(&self.summary)
+            .arg(&self.body)
+            .status()
+            .with_context(|| "cannot run notify-send")?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(anyhow::Error::msg(format!(
+                "notify-send failed: {}",
+                status
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_notification_for_event() {
+    use crate::geometry::Vec2d;
+    use crate::layout::{LayoutInfo, OutputEntry};
+    let layout = LayoutInfo::from_iter(
+        [
+            OutputEntry::enabled_for_test("a", Vec2d::new(1920, 1080), Vec2d::new(0, 0)),
+            OutputEntry::enabled_for_test("b", Vec2d::new(1920, 1080), Vec2d::new(1920, 0)),
+        ],
+        None,
+    )
+    .layout;
+    let notification = Notification::for_event(LayoutEvent::Applied, "profile docked", &layout);
+    assert_eq!(notification.summary, "Applied profile docked");
+    assert_eq!(notification.body, "a, b");
+}
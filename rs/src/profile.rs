@@ -0,0 +1,268 @@
+use crate::layout::spec::LayoutSpec;
+use crate::layout::{LayoutInfo, Mode, OutputId};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// User-authored layout for a set of outputs, like kanshi profiles.
+/// Layouts learned from manual changes (database) take precedence over profiles.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    /// Outputs are matched by name or EDID, see [`LayoutSpec`].
+    pub layout: LayoutSpec,
+    /// Layouts replacing [`Profile::layout`] during time windows. The first active one is used.
+    #[serde(default)]
+    pub variants: Vec<ProfileVariant>,
+}
+
+/// Alternative layout of a [`Profile`] during a daily time window, like `night` after 22:00.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileVariant {
+    pub name: String,
+    /// Start of the window, local time.
+    pub from: TimeOfDay,
+    /// End of the window, excluded. Windows can wrap over midnight, like `22:00` to `07:00`.
+    /// A window ending at its start is empty.
+    pub until: TimeOfDay,
+    /// Must describe the same outputs as [`Profile::layout`].
+    pub layout: LayoutSpec,
+}
+
+impl ProfileVariant {
+    pub fn is_active(&self, now: TimeOfDay) -> bool {
+        match self.from <= self.until {
+            true => self.from <= now && now < self.until,
+            false => self.from <= now || now < self.until,
+        }
+    }
+}
+
+impl Profile {
+    /// Layout of the profile if it describes exactly the `connected` outputs.
+    pub fn layout_for(
+        &self,
+        connected: &[OutputId],
+        names: &HashMap<OutputId, String>,
+        preferred_modes: &HashMap<OutputId, Mode>,
+    ) -> Option<LayoutInfo> {
+        if !self.layout.matches(connected, names) {
+            return None;
+        }
+        self.spec_layout(&self.layout, connected, names, preferred_modes)
+    }
+
+    /// Like [`Profile::layout_for`], using the layout of the variant active at `now` if any.
+    pub fn layout_at(
+        &self,
+        now: TimeOfDay,
+        connected: &[OutputId],
+        names: &HashMap<OutputId, String>,
+        preferred_modes: &HashMap<OutputId, Mode>,
+    ) -> Option<LayoutInfo> {
+        if !self.layout.matches(connected, names) {
+            return None;
+        }
+        let spec = match self.active_variant(now) {
+            Some(variant) => &variant.layout,
+            None => &self.layout,
+        };
+        self.spec_layout(spec, connected, names, preferred_modes)
+    }
+
+    /// First variant active at `now`.
+    pub fn active_variant(&self, now: TimeOfDay) -> Option<&ProfileVariant> {
+        self.variants.iter().find(|v| v.is_active(now))
+    }
+
+    /// Time from `now` to the next start or end of a variant window, [`None`] without variants.
+    pub fn next_switch(&self, now: TimeOfDay) -> Option<Duration> {
+        let boundaries = self.variants.iter().flat_map(|v| [v.from, v.until]);
+        boundaries.map(|boundary| now.until(boundary)).min()
+    }
+
+    fn spec_layout(
+        &self,
+        spec: &LayoutSpec,
+        connected: &[OutputId],
+        names: &HashMap<OutputId, String>,
+        preferred_modes: &HashMap<OutputId, Mode>,
+    ) -> Option<LayoutInfo> {
+        match spec.to_layout(connected, names, preferred_modes) {
+            Ok(info) if info.unsupported_causes.is_empty() => Some(info),
+            Ok(info) => {
+                log::warn!(
+                    "profile {}: unsupported layout: {:?}",
+                    self.name,
+                    info.unsupported_causes
+                );
+                None
+            }
+            Err(e) => {
+                log::warn!("profile {}: {}", self.name, e);
+                None
+            }
+        }
+    }
+}
+
+/// Time of day with second precision, written `HH:MM` in configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay {
+    seconds: u32,
+}
+
+impl TimeOfDay {
+    const DAY: u32 = 24 * 3600;
+
+    pub fn new(hour: u8, minute: u8, second: u8) -> Option<TimeOfDay> {
+        match hour < 24 && minute < 60 && second < 60 {
+            true => Some(TimeOfDay {
+                seconds: u32::from(hour) * 3600 + u32::from(minute) * 60 + u32::from(second),
+            }),
+            false => None,
+        }
+    }
+
+    /// Local time, or UTC if the local offset cannot be determined.
+    pub fn now() -> TimeOfDay {
+        let now = time::OffsetDateTime::now_local().unwrap_or_else(|e| {
+            log::debug!("using UTC time: {}", e);
+            time::OffsetDateTime::now_utc()
+        });
+        TimeOfDay {
+            seconds: u32::from(now.hour()) * 3600
+                + u32::from(now.minute()) * 60
+                + u32::from(now.second()),
+        }
+    }
+
+    /// Time until the next occurrence of `other`, a full day if equal.
+    pub fn until(self, other: TimeOfDay) -> Duration {
+        let seconds = (other.seconds + Self::DAY - self.seconds - 1) % Self::DAY + 1;
+        Duration::from_secs(seconds.into())
+    }
+}
+
+impl std::str::FromStr for TimeOfDay {
+    type Err = String;
+    fn from_str(s: &str) -> Result<TimeOfDay, String> {
+        let invalid = || format!("invalid time: {} (expected HH:MM)", s);
+        let (hour, minute) = s.split_once(':').ok_or_else(invalid)?;
+        let (hour, minute) = (
+            hour.parse().map_err(|_| invalid())?,
+            minute.parse().map_err(|_| invalid())?,
+        );
+        TimeOfDay::new(hour, minute, 0).ok_or_else(invalid)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<TimeOfDay, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Load profiles from `*.json` files of `dir` in file name order, each containing one profile.
+/// A missing directory has no profiles.
+pub fn load_profile_dir(dir: &Path) -> Result<Vec<Profile>, anyhow::Error> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("cannot read {}", dir.display())),
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("cannot read {}", dir.display()))?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            paths.push(path)
+        }
+    }
+    paths.sort();
+    Result::from_iter(paths.iter().map(|path| -> Result<Profile, anyhow::Error> {
+        let content =
+            std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+        serde_json::from_slice(&content)
+            .with_context(|| format!("error parsing profile {}", path.display()))
+    }))
+}
+
+#[cfg(test)]
+#[test]
+fn test_profile_matching() {
+    use crate::geometry::Vec2d;
+    let profile: Profile = serde_json::from_str(
+        r#"{"name": "desk", "layout": "eDP-1:1920x1080@60@0,0 DP-1:2560x1440@60:right-of=eDP-1"}"#,
+    )
+    .unwrap();
+    let (laptop, external) = (
+        OutputId::Name("eDP-1".into()),
+        OutputId::Name("DP-1".into()),
+    );
+    let (names, modes) = (HashMap::new(), HashMap::new());
+    let info = profile
+        .layout_for(&[external.clone(), laptop.clone()], &names, &modes)
+        .unwrap();
+    let rect = info.layout.output_entries()[0].state.rect().unwrap();
+    assert_eq!(rect.bottom_left, Vec2d::new(1920, 0));
+    // Exact set of outputs is required
+    assert!(profile
+        .layout_for(std::slice::from_ref(&laptop), &names, &modes)
+        .is_none());
+    let other = OutputId::Name("HDMI-1".into());
+    assert!(profile
+        .layout_for(&[external, laptop, other], &names, &modes)
+        .is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn test_profile_variants() {
+    let profile: Profile = serde_json::from_str(
+        r#"{
+            "name": "desk",
+            "layout": "eDP-1:1920x1080@60@0,0 DP-1:2560x1440@144:right-of=eDP-1",
+            "variants": [{
+                "name": "night",
+                "from": "22:00",
+                "until": "07:30",
+                "layout": "eDP-1:1920x1080@60@0,0 DP-1:off"
+            }]
+        }"#,
+    )
+    .unwrap();
+    let time = |h, m| TimeOfDay::new(h, m, 0).unwrap();
+    let connected = [
+        OutputId::Name("DP-1".into()),
+        OutputId::Name("eDP-1".into()),
+    ];
+    let (names, modes) = (HashMap::new(), HashMap::new());
+    let enabled_outputs = |now| {
+        let info = profile.layout_at(now, &connected, &names, &modes).unwrap();
+        let entries = info.layout.output_entries().to_vec();
+        entries.iter().filter(|e| e.state.rect().is_some()).count()
+    };
+    assert_eq!(enabled_outputs(time(12, 0)), 2);
+    assert_eq!(enabled_outputs(time(22, 0)), 1);
+    assert_eq!(enabled_outputs(time(3, 0)), 1);
+    assert_eq!(enabled_outputs(time(7, 30)), 2);
+    assert_eq!(
+        profile.next_switch(time(21, 0)),
+        Some(Duration::from_secs(3600))
+    );
+    assert_eq!(
+        profile.next_switch(time(23, 30)),
+        Some(Duration::from_secs(8 * 3600))
+    );
+    assert_eq!(
+        time(7, 30).until(time(7, 30)),
+        Duration::from_secs(24 * 3600)
+    );
+    assert!("24:00".parse::<TimeOfDay>().is_err());
+}
@@ -0,0 +1,26 @@
+use anyhow::Context;
+use std::process::Command;
+
+/// Maximum duration of a download, so that a periodic refresh cannot hang.
+const FETCH_TIMEOUT_SECS: u32 = 60;
+
+/// Download `url` using the `curl` command line tool, which handles HTTP(S), proxies and `file://` urls.
+/// HTTP error statuses are errors.
+pub fn fetch(url: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--max-time", &FETCH_TIMEOUT_SECS.to_string()])
+        .arg("--")
+        .arg(url)
+        .output()
+        .context("cannot run curl")?;
+    if !output.status.success() {
+        return Err(anyhow::Error::msg(format!(
+            "cannot fetch {} ({}): {}",
+            url,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
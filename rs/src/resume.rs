@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+/// Period of checks for a system resume by the daemon, as backends may not report any event.
+pub const POLL_PERIOD: Duration = Duration::from_secs(2);
+
+/// Shorter clock jumps are scheduling noise, not suspends.
+const MIN_SUSPEND: Duration = Duration::from_secs(1);
+
+fn clock(id: libc::clockid_t) -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Cannot fail for clocks supported since linux 2.6.39
+    unsafe { libc::clock_gettime(id, &mut time) };
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+/// Total time spent in system suspend since boot : the boot time clock counts it, the monotonic clock does not.
+pub fn suspended_time() -> Duration {
+    clock(libc::CLOCK_BOOTTIME).saturating_sub(clock(libc::CLOCK_MONOTONIC))
+}
+
+/// Detect system resumes by increases of the suspended time.
+/// This avoids depending on logind `PrepareForSleep` signals through dbus.
+pub struct ResumeDetector {
+    suspended: Duration,
+}
+
+impl Default for ResumeDetector {
+    fn default() -> Self {
+        ResumeDetector {
+            suspended: suspended_time(),
+        }
+    }
+}
+
+impl ResumeDetector {
+    /// Whether the system was suspended since the last call.
+    pub fn resumed(&mut self) -> bool {
+        self.update(suspended_time())
+    }
+
+    fn update(&mut self, suspended: Duration) -> bool {
+        let previous = std::mem::replace(&mut self.suspended, suspended);
+        suspended.saturating_sub(previous) >= MIN_SUSPEND
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_resume_detector() {
+    let mut detector = ResumeDetector::default();
+    assert!(!detector.resumed());
+    let base = detector.suspended;
+    assert!(!detector.update(base + Duration::from_millis(20)));
+    assert!(detector.update(base + Duration::from_secs(60)));
+    assert!(!detector.update(base + Duration::from_secs(60)));
+}
@@ -0,0 +1,76 @@
+use anyhow::Context;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+/// Send a state change like `READY=1` to the service manager, for `Type=notify` systemd services.
+/// Does nothing if not started by a service manager waiting for notifications. Failures are only logged.
+pub fn notify(state: &str) {
+    if let Some(socket) = std::env::var_os("NOTIFY_SOCKET") {
+        if let Err(e) = send(&socket, state) {
+            log::warn!("cannot notify service manager: {:#}", e)
+        }
+    }
+}
+
+/// Socket paths starting with `@` are in the abstract namespace.
+fn send(socket: &OsStr, state: &str) -> Result<(), anyhow::Error> {
+    let address = match socket.as_bytes() {
+        [b'@', name @ ..] => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name)
+        }
+        path => SocketAddr::from_pathname(OsStr::from_bytes(path)),
+    }
+    .with_context(|| format!("invalid socket {}", socket.to_string_lossy()))?;
+    let datagram = UnixDatagram::unbound().context("cannot create socket")?;
+    datagram
+        .send_to_addr(state.as_bytes(), &address)
+        .with_context(|| format!("cannot send to {}", socket.to_string_lossy()))?;
+    Ok(())
+}
+
+/// Period of `WATCHDOG=1` pings, if the service manager supervises this process with a watchdog.
+/// Pings are sent twice per watchdog timeout, as recommended by `sd_watchdog_enabled(3)`.
+pub fn watchdog_period() -> Option<Duration> {
+    let pid = std::env::var("WATCHDOG_PID").ok();
+    if pid.is_some_and(|pid| pid.parse() != Ok(std::process::id())) {
+        return None;
+    }
+    let timeout: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(timeout / 2)).filter(|period| !period.is_zero())
+}
+
+/// Tell the service manager that the process is alive, if it supervises it with a watchdog.
+pub fn ping_watchdog() {
+    if watchdog_period().is_some() {
+        notify("WATCHDOG=1")
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_send() {
+    let dir = std::env::temp_dir().join(format!("slam-test-notify-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("notify.sock");
+    let receiver = UnixDatagram::bind(&path).unwrap();
+    let sent = send(path.as_os_str(), "READY=1");
+    let mut buffer = [0_u8; 16];
+    let received = receiver.recv(&mut buffer).map(|len| buffer[..len].to_vec());
+    let missing = send(dir.join("missing.sock").as_os_str(), "READY=1");
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(sent.is_ok());
+    assert_eq!(received.unwrap(), b"READY=1");
+    assert!(missing.is_err());
+
+    let name = format!("slam-test-notify-{}", std::process::id());
+    let receiver = {
+        use std::os::linux::net::SocketAddrExt;
+        UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap()
+    };
+    send(OsStr::new(&format!("@{}", name)), "WATCHDOG=1").unwrap();
+    let len = receiver.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"WATCHDOG=1");
+}
@@ -0,0 +1,24 @@
+use anyhow::Context;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Set `Xft.dpi` in the X resources database with `xrdb -merge`.
+/// Applications read it at startup, so running ones keep their font size.
+pub fn set_font_dpi(dpi: u32) -> Result<(), anyhow::Error> {
+    let mut child = Command::new("xrdb")
+        .arg("-merge")
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| "cannot run xrdb")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "Xft.dpi: {}", dpi).with_context(|| "cannot write to xrdb")?
+    }
+    let status = child.wait().with_context(|| "cannot run xrdb")?;
+    match status.success() {
+        true => {
+            log::info!("font dpi set to {}", dpi);
+            Ok(())
+        }
+        false => Err(anyhow::Error::msg(format!("xrdb failed: {}", status))),
+    }
+}
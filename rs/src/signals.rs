@@ -0,0 +1,107 @@
+use anyhow::Context;
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+static RELOAD: AtomicBool = AtomicBool::new(false);
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+/// Self-pipe written by handlers after setting their flag, to wake up the daemon waiting on file descriptors.
+/// Negative until handlers are installed.
+static WAKE_READ: AtomicI32 = AtomicI32::new(-1);
+static WAKE_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+/// Only async-signal-safe calls : errno is restored for the interrupted code.
+fn wake() {
+    let fd = WAKE_WRITE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe {
+            let errno = *libc::__errno_location();
+            // A full pipe already wakes up the daemon
+            libc::write(fd, b"s".as_ptr() as *const libc::c_void, 1);
+            *libc::__errno_location() = errno
+        }
+    }
+}
+
+extern "C" fn request_reload(_signal: libc::c_int) {
+    RELOAD.store(true, Ordering::SeqCst);
+    wake()
+}
+
+extern "C" fn request_termination(_signal: libc::c_int) {
+    TERMINATE.store(true, Ordering::SeqCst);
+    wake()
+}
+
+/// Handlers only set flags, and interrupted system calls are restarted.
+fn set_handler(
+    signal: libc::c_int,
+    handler: extern "C" fn(libc::c_int),
+) -> Result<(), anyhow::Error> {
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = handler as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("cannot set handler for signal {}", signal));
+    }
+    Ok(())
+}
+
+/// Handle `SIGHUP` as a reload request, instead of terminating the process.
+/// `SIGTERM` and `SIGINT` request termination, letting the daemon finish applying a layout before exiting.
+pub fn install_handlers() -> Result<(), anyhow::Error> {
+    if WAKE_READ.load(Ordering::SeqCst) < 0 {
+        let mut fds = [-1; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+            return Err(std::io::Error::last_os_error()).context("cannot create signal pipe");
+        }
+        WAKE_READ.store(fds[0], Ordering::SeqCst);
+        WAKE_WRITE.store(fds[1], Ordering::SeqCst);
+    }
+    set_handler(libc::SIGHUP, request_reload)?;
+    set_handler(libc::SIGTERM, request_termination)?;
+    set_handler(libc::SIGINT, request_termination)?;
+    Ok(())
+}
+
+/// File descriptor readable after a signal was received, once handlers are installed.
+/// Flags must be checked after [`clear_wake`], so that no signal is missed.
+pub fn wake_fd() -> Option<RawFd> {
+    let fd = WAKE_READ.load(Ordering::SeqCst);
+    (fd >= 0).then_some(fd)
+}
+
+/// Empty the pipe of [`wake_fd`], before checking flags.
+pub fn clear_wake() {
+    if let Some(fd) = wake_fd() {
+        let mut buffer = [0_u8; 64];
+        while unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) } > 0
+        {
+        }
+    }
+}
+
+/// Whether `SIGHUP` was received since the last call.
+pub fn reload_requested() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
+
+/// Whether `SIGTERM` or `SIGINT` was received.
+pub fn termination_requested() -> bool {
+    TERMINATE.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+#[test]
+fn test_signal_handlers() {
+    // Daemon flags are process wide and read by daemon tests : use a separate signal
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+    extern "C" fn received(_signal: libc::c_int) {
+        RECEIVED.store(true, Ordering::SeqCst)
+    }
+    set_handler(libc::SIGUSR1, received).unwrap();
+    assert!(!RECEIVED.load(Ordering::SeqCst));
+    assert_eq!(unsafe { libc::raise(libc::SIGUSR1) }, 0);
+    assert!(RECEIVED.load(Ordering::SeqCst));
+    assert!(set_handler(libc::SIGKILL, received).is_err());
+}
@@ -0,0 +1,133 @@
+use crate::hooks::LayoutEvent;
+use std::sync::Mutex;
+
+/// Counters of the daemon process, for `slam status --stats`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DaemonStats {
+    /// Start time of the daemon, in RFC 3339 format.
+    pub started: Option<String>,
+    /// Backend events processed.
+    pub events: u64,
+    /// Layouts applied.
+    pub applies: u64,
+    /// Layouts stored to the database.
+    pub stored: u64,
+    /// Last error logged.
+    pub last_error: Option<LoggedError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LoggedError {
+    /// RFC 3339 format.
+    pub time: String,
+    pub message: String,
+}
+
+static STATS: Mutex<DaemonStats> = Mutex::new(DaemonStats {
+    started: None,
+    events: 0,
+    applies: 0,
+    stored: 0,
+    last_error: None,
+});
+
+fn update(f: impl FnOnce(&mut DaemonStats)) {
+    f(&mut STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()))
+}
+
+fn now() -> String {
+    time::OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default()
+}
+
+/// Record the start of the daemon. Later calls, like after a backend restart, keep the first time.
+pub fn record_start() {
+    update(|stats| {
+        stats.started.get_or_insert_with(now);
+    })
+}
+
+pub fn record_events(count: usize) {
+    update(|stats| stats.events += count as u64)
+}
+
+pub fn record_layout_event(event: LayoutEvent) {
+    update(|stats| match event {
+        LayoutEvent::Applied => stats.applies += 1,
+        LayoutEvent::Stored => stats.stored += 1,
+    })
+}
+
+pub fn record_error(message: String) {
+    update(|stats| {
+        stats.last_error = Some(LoggedError {
+            time: now(),
+            message,
+        })
+    })
+}
+
+pub fn current() -> DaemonStats {
+    STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+impl DaemonStats {
+    /// One line per counter : `events: 12`.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(started) = &self.started {
+            text.push_str(&format!("started: {}\n", started))
+        }
+        text.push_str(&format!(
+            "events: {}\napplies: {}\nstored: {}\n",
+            self.events, self.applies, self.stored
+        ));
+        if let Some(error) = &self.last_error {
+            text.push_str(&format!("last error: {} {}\n", error.time, error.message))
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_stats() {
+    // Daemon tests running at the same time also count
+    let before = current();
+    record_start();
+    record_events(2);
+    record_layout_event(LayoutEvent::Applied);
+    record_layout_event(LayoutEvent::Stored);
+    record_error("cannot apply layout".into());
+    let after = current();
+    assert!(after.started.is_some());
+    assert!(after.events >= before.events + 2);
+    assert!(after.applies > before.applies && after.stored > before.stored);
+    assert!(after.last_error.is_some());
+    record_start();
+    assert_eq!(current().started, after.started);
+
+    let stats = DaemonStats {
+        started: Some("2024-01-01T00:00:00Z".into()),
+        events: 12,
+        applies: 4,
+        stored: 2,
+        last_error: Some(LoggedError {
+            time: "2024-01-01T01:00:00Z".into(),
+            message: "cannot apply layout".into(),
+        }),
+    };
+    assert_eq!(
+        stats.to_text(),
+        "started: 2024-01-01T00:00:00Z\nevents: 12\napplies: 4\nstored: 2\n\
+         last error: 2024-01-01T01:00:00Z cannot apply layout\n"
+    );
+    let json = serde_json::to_string(&stats).unwrap();
+    assert_eq!(serde_json::from_str::<DaemonStats>(&json).unwrap(), stats);
+}
@@ -0,0 +1,318 @@
+use crate::layout::{Layout, LayoutInfo, OutputEntry, OutputId, OutputState, UnsupportedCauses};
+use crate::Backend;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Names of output sets, to describe outputs in plain text or json.
+pub struct OutputNames {
+    /// System names, like `DP-1`.
+    pub names: HashMap<OutputId, String>,
+    /// Monitor descriptions, like `DELL U2720Q`.
+    pub descriptions: HashMap<OutputId, String>,
+}
+
+impl OutputNames {
+    pub fn from_backend(backend: &dyn Backend) -> OutputNames {
+        OutputNames {
+            names: backend.output_names(),
+            descriptions: backend.output_descriptions(),
+        }
+    }
+
+    pub fn name(&self, id: &OutputId) -> String {
+        self.names
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_string())
+    }
+}
+
+/// Lowercase names of unsupported causes, as in json output.
+pub fn unsupported_cause_names(causes: UnsupportedCauses) -> Vec<&'static str> {
+    let names = [
+        (UnsupportedCauses::OVERLAPS, "overlaps"),
+        (UnsupportedCauses::GAPS, "gaps"),
+        (UnsupportedCauses::DUPLICATE_EDID, "duplicate_edid"),
+    ];
+    Vec::from_iter(
+        names
+            .into_iter()
+            .filter(|(cause, _)| causes.contains(*cause))
+            .map(|(_, name)| name),
+    )
+}
+
+/// Json object for an output : `{"id": ..., "name": "DP-1", "enabled": true, "mode": {"width": 1920, ...}, ...}`.
+/// Fields of the enabled state are `null` for disabled outputs.
+pub fn output_json(entry: &OutputEntry, names: &OutputNames, primary: bool) -> serde_json::Value {
+    let mut output = json!({
+        "id": entry.id.to_string(),
+        "name": names.names.get(&entry.id),
+        "description": names.descriptions.get(&entry.id),
+        "enabled": entry.state.rect().is_some(),
+        "primary": primary,
+    });
+    let (mode, position, size, transform, scale) = match (&entry.state, entry.state.rect()) {
+        (
+            OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                ..
+            },
+            Some(rect),
+        ) => (
+            json!({ "width": mode.size.x, "height": mode.size.y, "frequency": mode.frequency }),
+            json!({ "x": bottom_left.x, "y": bottom_left.y }),
+            json!({ "width": rect.size.x, "height": rect.size.y }),
+            json!(transform.to_string()),
+            json!(scale.to_f64()),
+        ),
+        _ => Default::default(),
+    };
+    output["mode"] = mode;
+    output["position"] = position;
+    output["size"] = size;
+    output["transform"] = transform;
+    output["scale"] = scale;
+    output
+}
+
+/// Layout as json : outputs and primary output name.
+pub fn layout_json(layout: &Layout, names: &OutputNames) -> serde_json::Value {
+    let outputs = Vec::from_iter(layout.output_entries().iter().map(|entry| {
+        let primary = layout.primary() == Some(&entry.id);
+        output_json(entry, names, primary)
+    }));
+    json!({
+        "outputs": outputs,
+        "primary": layout.primary().map(|id| names.name(id)),
+    })
+}
+
+/// Current layout as json : outputs with their connector type and preferred mode, primary output name,
+/// and unsupported causes.
+pub fn to_json(info: &LayoutInfo, names: &OutputNames) -> serde_json::Value {
+    let mut json = layout_json(&info.layout, names);
+    let outputs = json["outputs"].as_array_mut().into_iter().flatten();
+    // Outputs of layout_json() are in entry order
+    for (output, entry) in Iterator::zip(outputs, info.layout.output_entries()) {
+        let metadata = info.metadata.get(&entry.id).cloned().unwrap_or_default();
+        output["connector"] = json!(metadata.connector);
+        output["preferred_mode"] = match metadata.preferred_mode {
+            Some(mode) => {
+                json!({ "width": mode.size.x, "height": mode.size.y, "frequency": mode.frequency })
+            }
+            None => serde_json::Value::Null,
+        };
+    }
+    json["unsupported"] = json!(unsupported_cause_names(info.unsupported_causes));
+    json
+}
+
+/// Current layout as text, one line per output : `DP-1 (DELL U2720Q): 1920x1080@60+0+0 primary`.
+pub fn to_text(info: &LayoutInfo, names: &OutputNames) -> String {
+    let mut text = String::new();
+    for entry in info.layout.output_entries() {
+        text.push_str(&names.name(&entry.id));
+        if let Some(description) = names.descriptions.get(&entry.id) {
+            text.push_str(&format!(" ({})", description))
+        }
+        text.push_str(&format!(": {}", entry.state));
+        if info.layout.primary() == Some(&entry.id) {
+            text.push_str(" primary")
+        }
+        text.push('\n')
+    }
+    let unsupported = unsupported_cause_names(info.unsupported_causes);
+    if !unsupported.is_empty() {
+        text.push_str(&format!("unsupported: {}\n", unsupported.join(", ")))
+    }
+    text
+}
+
+/// Changes of each output from `current` to `target`, one line per output :
+/// `DP-1: mode 1920x1080x60Hz -> 2560x1440x60Hz, position +0+0 -> +1920+0`.
+pub fn diff_text(current: &Layout, target: &Layout, names: &OutputNames) -> String {
+    fn state_of<'l>(layout: &'l Layout, id: &OutputId) -> Option<&'l OutputState> {
+        let entry = layout.output_entries().iter().find(|e| &e.id == id);
+        entry.map(|e| &e.state)
+    }
+    let mut ids = Vec::from_iter(
+        current
+            .connected_outputs()
+            .chain(target.connected_outputs()),
+    );
+    ids.sort();
+    ids.dedup();
+    let mut text = String::new();
+    for id in ids {
+        let change = match (state_of(current, id), state_of(target, id)) {
+            (Some(current), Some(target)) => output_diff(current, target),
+            (Some(_), None) => "not in layout".into(),
+            (None, Some(_)) => "not connected".into(),
+            (None, None) => continue,
+        };
+        text.push_str(&format!("{}: {}\n", names.name(id), change))
+    }
+    if current.primary() != target.primary() {
+        let name = |id: Option<&OutputId>| id.map_or_else(|| "none".into(), |id| names.name(id));
+        text.push_str(&format!(
+            "primary: {} -> {}\n",
+            name(current.primary()),
+            name(target.primary())
+        ))
+    }
+    text
+}
+
+fn output_diff(current: &OutputState, target: &OutputState) -> String {
+    match (current, target) {
+        (
+            OutputState::Enabled {
+                mode,
+                transform,
+                bottom_left,
+                scale,
+                panning,
+            },
+            OutputState::Enabled {
+                mode: target_mode,
+                transform: target_transform,
+                bottom_left: target_bottom_left,
+                scale: target_scale,
+                panning: target_panning,
+            },
+        ) => {
+            let mut changes = Vec::new();
+            if mode != target_mode {
+                changes.push(format!("mode {} -> {}", mode, target_mode))
+            }
+            if bottom_left != target_bottom_left {
+                changes.push(format!(
+                    "position +{}+{} -> +{}+{}",
+                    bottom_left.x, bottom_left.y, target_bottom_left.x, target_bottom_left.y
+                ))
+            }
+            if transform != target_transform {
+                changes.push(format!("transform {} -> {}", transform, target_transform))
+            }
+            if scale != target_scale {
+                changes.push(format!("scale {} -> {}", scale, target_scale))
+            }
+            if panning != target_panning {
+                let text = |panning: &Option<_>| match panning {
+                    Some(panning) => format!("{}", panning),
+                    None => "none".into(),
+                };
+                changes.push(format!(
+                    "panning {} -> {}",
+                    text(panning),
+                    text(target_panning)
+                ))
+            }
+            match changes.is_empty() {
+                true => "unchanged".into(),
+                false => changes.join(", "),
+            }
+        }
+        (OutputState::Disabled { .. }, OutputState::Enabled { .. }) => {
+            format!("enabled as {}", target)
+        }
+        (OutputState::Enabled { .. }, OutputState::Disabled { .. }) => {
+            format!("disabled, was {}", current)
+        }
+        (OutputState::Disabled { .. }, OutputState::Disabled { .. }) => {
+            "unchanged (disabled)".into()
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_status() {
+    use crate::geometry::{Transform, Vec2d};
+    use crate::layout::{Mode, Scale};
+    let id = |name: &str| OutputId::Name(name.into());
+    let info = LayoutInfo::from_iter(
+        [
+            OutputEntry {
+                id: id("DP-1"),
+                state: OutputState::Enabled {
+                    mode: Mode {
+                        size: Vec2d::new(1920, 1080),
+                        frequency: 60,
+                        refresh: None,
+                    },
+                    transform: "left".parse::<Transform>().unwrap(),
+                    bottom_left: Vec2d::new(0, 0),
+                    scale: Scale::ONE,
+                    panning: None,
+                },
+            },
+            OutputEntry {
+                id: id("HDMI-1"),
+                state: OutputState::Disabled { reason: None },
+            },
+        ],
+        Some(id("DP-1")),
+    );
+    let names = OutputNames {
+        names: HashMap::from([(id("DP-1"), "DP-1".to_string())]),
+        descriptions: HashMap::from([(id("DP-1"), "DELL U2720Q".to_string())]),
+    };
+    let preferred = Mode {
+        size: Vec2d::new(2560, 1440),
+        frequency: 60,
+        refresh: None,
+    };
+    let info = info.with_metadata(&names.names, &HashMap::from([(id("DP-1"), preferred)]));
+    let text = to_text(&info, &names);
+    assert_eq!(
+        text,
+        "DP-1 (DELL U2720Q): 1920x1080x60Hz+0+0 90 primary\nHDMI-1: disabled\n"
+    );
+    let json = to_json(&info, &names);
+    assert_eq!(json["primary"], "DP-1");
+    assert_eq!(json["unsupported"], json!([]));
+    assert_eq!(
+        json["outputs"][0]["size"],
+        json!({"width": 1080, "height": 1920})
+    );
+    assert_eq!(json["outputs"][0]["transform"], "left");
+    assert_eq!(json["outputs"][0]["mode"]["frequency"], 60);
+    assert_eq!(json["outputs"][0]["description"], "DELL U2720Q");
+    assert_eq!(json["outputs"][0]["connector"], "DP");
+    assert_eq!(json["outputs"][0]["preferred_mode"]["width"], 2560);
+    assert_eq!(json["outputs"][1]["enabled"], false);
+    assert_eq!(json["outputs"][1]["connector"], serde_json::Value::Null);
+    assert_eq!(json["outputs"][1]["name"], serde_json::Value::Null);
+    assert_eq!(json["outputs"][1]["mode"], serde_json::Value::Null);
+    assert_eq!(
+        unsupported_cause_names(UnsupportedCauses::OVERLAPS | UnsupportedCauses::GAPS),
+        ["overlaps", "gaps"]
+    );
+
+    let target = LayoutInfo::from_iter(
+        [
+            OutputEntry::enabled_for_test("DP-1", Vec2d::new(2560, 1440), Vec2d::new(1920, 0)),
+            OutputEntry {
+                id: id("eDP-1"),
+                state: OutputState::Disabled { reason: None },
+            },
+        ],
+        None,
+    );
+    assert_eq!(
+        diff_text(&info.layout, &target.layout, &names),
+        "DP-1: mode 1920x1080x60Hz -> 2560x1440x60Hz, position +0+0 -> +1920+0, transform left -> normal\n\
+         HDMI-1: not in layout\n\
+         eDP-1: not connected\n\
+         primary: DP-1 -> none\n"
+    );
+    assert_eq!(
+        diff_text(&info.layout, &info.layout, &names),
+        "DP-1: unchanged\nHDMI-1: unchanged (disabled)\n"
+    );
+}
@@ -0,0 +1,376 @@
+use crate::config::Config;
+use crate::database::{Database, LayoutById};
+use crate::geometry::{Transform, Vec2d};
+use crate::layout::{LayoutInfo, Mode, OutputEntry, OutputId, OutputState, Scale};
+use crate::mock::{EndOfScript, MockBackend, MockEvent};
+use crate::ReactionDelays;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// Summary of a stress run.
+#[derive(Debug, Default)]
+pub struct StressReport {
+    pub batches: usize,
+    pub events: usize,
+    pub applied_layouts: usize,
+    pub stored_layouts: usize,
+}
+
+impl std::fmt::Display for StressReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} batches, {} events, {} layouts applied, {} layouts stored",
+            self.batches, self.events, self.applied_layouts, self.stored_layouts
+        )
+    }
+}
+
+/// Replay randomized bursts of connections, disconnections and external layout changes
+/// through [`crate::run_daemon`] with a [`MockBackend`], using a database at `database_path`.
+///
+/// After each burst, checks that :
+/// - the database file contains the same layouts as the daemon database ;
+/// - the stored layout of connected outputs, if any, is the current layout.
+///
+/// The same `seed` replays the same sequence.
+pub fn stress(
+    database_path: &Path,
+    batches: usize,
+    output_count: usize,
+    seed: u64,
+) -> Result<StressReport, anyhow::Error> {
+    let mut rng = XorShift::new(seed);
+    let modes = [(1920, 1080), (2560, 1440), (3840, 2160), (1280, 1024)];
+    let preferred_modes = HashMap::from_iter((0..output_count).map(|i| {
+        let (x, y) = modes[i % modes.len()];
+        let mode = Mode {
+            size: Vec2d::new(x, y),
+            frequency: 60,
+            refresh: None,
+        };
+        (OutputId::Name(format!("MOCK-{}", i)), mode)
+    }));
+    let ids = Vec::from_iter((0..output_count).map(|i| OutputId::Name(format!("MOCK-{}", i))));
+
+    let config = Config::default();
+    let mut database = Database::load_or_empty(database_path.to_path_buf(), None)?;
+    let mut backend = MockBackend::new(preferred_modes.clone());
+    let mut connected: Vec<OutputId> = Vec::new();
+    let mut report = StressReport::default();
+    for batch in 0..batches {
+        for _ in 0..1 + rng.below(8) {
+            let id = &ids[rng.below(ids.len())];
+            let event = match rng.below(3) {
+                0 if !connected.contains(id) => {
+                    connected.push(id.clone());
+                    MockEvent::Connect(id.clone())
+                }
+                1 if connected.contains(id) => {
+                    connected.retain(|c| c != id);
+                    MockEvent::Disconnect(id.clone())
+                }
+                _ if !connected.is_empty() => {
+                    MockEvent::Change(random_layout(&mut rng, &connected, &preferred_modes))
+                }
+                _ => continue,
+            };
+            backend.push_event(event);
+            report.events += 1
+        }
+        run_script(&mut backend, Default::default(), &mut database, &config)?;
+        connected.sort();
+        report.stored_layouts = check_invariants(&backend, &database, database_path, &connected)
+            .map_err(|e| e.context(format!("batch {}", batch)))?;
+        report.batches += 1
+    }
+    report.applied_layouts = backend.applied_layouts();
+    Ok(report)
+}
+
+/// Summary of a soak run.
+#[derive(Debug, Default)]
+pub struct SoakReport {
+    pub days: usize,
+    pub stored_layouts: usize,
+    pub database_bytes: u64,
+    /// Resident memory growth after the first simulated week, in KiB, if measurable.
+    pub memory_growth: Option<u64>,
+}
+
+impl std::fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} days, {} layouts stored, database {} bytes",
+            self.days, self.stored_layouts, self.database_bytes
+        )?;
+        match self.memory_growth {
+            Some(growth) => write!(f, ", memory growth {} KiB", growth),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Maximum resident memory growth during a soak run, after the warm-up week.
+const SOAK_MEMORY_GROWTH_LIMIT_KIB: u64 = 16 * 1024;
+/// Reaction delay of soak runs, so that the outputs of a dock are connected together.
+const DOCK_REACTION_DELAY: Duration = Duration::from_millis(500);
+/// Serialized layouts are a few hundred bytes.
+const SOAK_BYTES_PER_LAYOUT_LIMIT: u64 = 4096;
+
+/// Run [`crate::run_daemon`] with a [`MockBackend`] for `hours` of simulated laptop usage.
+///
+/// Each simulated day, the laptop output is docked at one of a few places in the morning,
+/// optionally rearranged by the user, and undocked in the evening.
+/// After each day, the [`stress`] invariants are checked, along with bounds on :
+/// - stored layouts : at most one per output set seen ;
+/// - database file size relative to the number of layouts, and no temporary file left ;
+/// - resident memory growth after the first week (Linux only).
+pub fn soak(database_path: &Path, hours: u64, seed: u64) -> Result<SoakReport, anyhow::Error> {
+    let mut rng = XorShift::new(seed);
+    let laptop = OutputId::Name("MOCK-0".into());
+    // Outputs docked together at each place, after the laptop
+    let places: [&[usize]; 4] = [&[], &[1], &[2, 3], &[4]];
+    let modes = [
+        (1920, 1200),
+        (2560, 1440),
+        (1920, 1080),
+        (1920, 1080),
+        (3840, 2160),
+    ];
+    let id = |i: usize| OutputId::Name(format!("MOCK-{}", i));
+    let preferred_modes = HashMap::from_iter(modes.iter().enumerate().map(|(i, &(x, y))| {
+        let mode = Mode {
+            size: Vec2d::new(x, y),
+            frequency: 60,
+            refresh: None,
+        };
+        (id(i), mode)
+    }));
+
+    let config = Config::default();
+    let mut database = Database::load_or_empty(database_path.to_path_buf(), None)?;
+    let mut backend = MockBackend::new(preferred_modes.clone());
+    backend.push_event(MockEvent::Connect(laptop.clone()));
+    let mut output_sets = std::collections::HashSet::new();
+    let mut report = SoakReport::default();
+    let mut baseline_memory = None;
+    for day in 0..hours / 24 {
+        let docked = Vec::from_iter(places[rng.below(places.len())].iter().map(|&i| id(i)));
+        let mut connected = vec![laptop.clone()];
+        for output in &docked {
+            backend.push_event(MockEvent::Connect(output.clone()));
+            connected.push(output.clone())
+        }
+        connected.sort();
+        if rng.below(4) == 0 {
+            backend.push_event(MockEvent::Change(random_layout(
+                &mut rng,
+                &connected,
+                &preferred_modes,
+            )))
+        }
+        run_script(
+            &mut backend,
+            ReactionDelays::uniform(Some(DOCK_REACTION_DELAY)),
+            &mut database,
+            &config,
+        )?;
+        output_sets.insert(connected.clone());
+        let context = || format!("day {}", day);
+        check_invariants(&backend, &database, database_path, &connected)
+            .map_err(|e| e.context(context()))?;
+        for output in docked {
+            backend.push_event(MockEvent::Disconnect(output))
+        }
+        run_script(
+            &mut backend,
+            ReactionDelays::uniform(Some(DOCK_REACTION_DELAY)),
+            &mut database,
+            &config,
+        )?;
+        output_sets.insert(vec![laptop.clone()]);
+        let stored_layouts = check_invariants(
+            &backend,
+            &database,
+            database_path,
+            std::slice::from_ref(&laptop),
+        )
+        .map_err(|e| e.context(context()))?;
+
+        let fail = |msg: String| Err(anyhow::Error::msg(msg).context(context()));
+        if stored_layouts > output_sets.len() {
+            return fail(format!(
+                "{} layouts stored for {} output sets",
+                stored_layouts,
+                output_sets.len()
+            ));
+        }
+        let database_bytes = std::fs::metadata(database_path).map_or(0, |m| m.len());
+        if database_bytes > SOAK_BYTES_PER_LAYOUT_LIMIT * stored_layouts as u64 {
+            return fail(format!("database too large: {} bytes", database_bytes));
+        }
+        let mut tmp_path = database_path.to_path_buf();
+        tmp_path.set_extension("json.tmp");
+        if tmp_path.exists() {
+            return fail(format!("temporary file left: {}", tmp_path.display()));
+        }
+        if let Some(memory) = resident_memory_kib() {
+            match baseline_memory {
+                None if day >= 7 => baseline_memory = Some(memory),
+                None => (),
+                Some(baseline) => {
+                    let growth = memory.saturating_sub(baseline);
+                    if growth > SOAK_MEMORY_GROWTH_LIMIT_KIB {
+                        return fail(format!("memory grew by {} KiB", growth));
+                    }
+                    report.memory_growth = Some(growth)
+                }
+            }
+        }
+        report.days += 1;
+        report.stored_layouts = stored_layouts;
+        report.database_bytes = database_bytes
+    }
+    Ok(report)
+}
+
+/// Process events until the script of `backend` is exhausted.
+fn run_script(
+    backend: &mut MockBackend,
+    reaction_delays: ReactionDelays,
+    database: &mut Database,
+    config: &Config,
+) -> Result<(), anyhow::Error> {
+    match crate::run_daemon(backend, reaction_delays, database, config, None, None, None) {
+        Err(e) if e.is::<EndOfScript>() => Ok(()),
+        Err(e) => Err(e),
+        Ok(()) => unreachable!("daemon only stops on errors"),
+    }
+}
+
+/// Check consistency of the backend and database after events, and return the number of stored layouts.
+fn check_invariants(
+    backend: &MockBackend,
+    database: &Database,
+    database_path: &Path,
+    connected: &[OutputId],
+) -> Result<usize, anyhow::Error> {
+    let current = crate::Backend::current_layout(backend).layout;
+    if !Iterator::eq(current.connected_outputs(), connected.iter()) {
+        return Err(anyhow::Error::msg("connected outputs differ from events"));
+    }
+    if let Some(stored) = database.get_layout(&LayoutById(current.clone())) {
+        if stored != &current {
+            return Err(anyhow::Error::msg("current layout is not the stored one"));
+        }
+    }
+    let serialized = |database: &Database| {
+        let mut layouts = Vec::from_iter(
+            database
+                .layouts()
+                .map(|l| serde_json::to_string(l).expect("serializable")),
+        );
+        layouts.sort();
+        layouts
+    };
+    let reloaded = Database::load_or_empty(database_path.to_path_buf(), None)?;
+    let in_memory = serialized(database);
+    if serialized(&reloaded) != in_memory {
+        return Err(anyhow::Error::msg("database file differs from memory"));
+    }
+    Ok(in_memory.len())
+}
+
+/// Resident memory of the process from `/proc`, in KiB.
+fn resident_memory_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Supported layout : random subset of outputs enabled side by side, in random order.
+fn random_layout(
+    rng: &mut XorShift,
+    connected: &[OutputId],
+    preferred_modes: &HashMap<OutputId, Mode>,
+) -> crate::layout::Layout {
+    let mut order = connected.to_vec();
+    for i in (1..order.len()).rev() {
+        order.swap(i, rng.below(i + 1))
+    }
+    let disabled = match order.len() > 1 && rng.below(4) == 0 {
+        true => Some(order.pop().expect("not empty")),
+        false => None,
+    };
+    let mut x = 0;
+    let mut entries = Vec::from_iter(order.iter().map(|id| {
+        let mode = preferred_modes[id].clone();
+        let bottom_left = Vec2d::new(x, 0);
+        x += mode.size.x as i32;
+        OutputEntry {
+            id: id.clone(),
+            state: OutputState::Enabled {
+                mode,
+                transform: Transform::default(),
+                bottom_left,
+                scale: Scale::ONE,
+                panning: None,
+            },
+        }
+    }));
+    entries.extend(disabled.map(|id| OutputEntry {
+        id,
+        state: OutputState::Disabled { reason: None },
+    }));
+    let primary = match rng.below(2) {
+        0 => Some(order[rng.below(order.len())].clone()),
+        _ => None,
+    };
+    LayoutInfo::from(entries, primary).layout
+}
+
+/// Small deterministic generator, good enough to shuffle events.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> XorShift {
+        // State must not be zero
+        XorShift((seed ^ 0x9e37_79b9_7f4a_7c15).max(1))
+    }
+
+    /// Uniform-ish in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_stress() {
+    let dir = std::env::temp_dir().join(format!("slam-test-stress-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let report = stress(&dir.join("database.json"), 200, 3, 42);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let report = report.unwrap();
+    assert_eq!(report.batches, 200);
+    assert!(report.stored_layouts > 0);
+    assert!(report.applied_layouts > 0);
+}
+
+#[cfg(test)]
+#[test]
+fn test_soak() {
+    let dir = std::env::temp_dir().join(format!("slam-test-soak-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let report = soak(&dir.join("database.json"), 24 * 60, 7);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let report = report.unwrap();
+    assert_eq!(report.days, 60);
+    assert!(report.stored_layouts <= 4);
+}
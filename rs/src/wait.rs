@@ -0,0 +1,65 @@
+use anyhow::Context;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+/// Period of checks of other event sources, for backends without file descriptors to wait on.
+pub const POLL_PERIOD: Duration = Duration::from_millis(500);
+
+/// Wait until one of `fds` is readable, or until `deadline` if any. Returns the readable ones.
+/// Returns none if the deadline passed or a signal interrupted the wait : the caller checks its flags again.
+/// Closed or failing file descriptors are readable, so that their owner reports the error.
+pub fn wait_readable(
+    fds: &[RawFd],
+    deadline: Option<Instant>,
+) -> Result<Vec<RawFd>, anyhow::Error> {
+    let mut pollfds = Vec::from_iter(fds.iter().map(|fd| libc::pollfd {
+        fd: *fd,
+        events: libc::POLLIN,
+        revents: 0,
+    }));
+    // Rounded up to not wake up just before the deadline
+    let timeout_ms = deadline.map_or(-1, |deadline| {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        remaining.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+    });
+    let count = unsafe {
+        libc::poll(
+            pollfds.as_mut_ptr(),
+            pollfds.len() as libc::nfds_t,
+            timeout_ms,
+        )
+    };
+    if count < 0 {
+        let error = std::io::Error::last_os_error();
+        return match error.kind() {
+            std::io::ErrorKind::Interrupted => Ok(Vec::new()),
+            _ => Err(error).context("poll failed"),
+        };
+    }
+    Ok(Vec::from_iter(
+        (pollfds.iter())
+            .filter(|pollfd| pollfd.revents != 0)
+            .map(|pollfd| pollfd.fd),
+    ))
+}
+
+#[cfg(test)]
+#[test]
+fn test_wait_readable() {
+    use std::io::Write;
+    use std::os::fd::AsRawFd;
+    let (first, second) = (
+        std::os::unix::net::UnixStream::pair().unwrap(),
+        std::os::unix::net::UnixStream::pair().unwrap(),
+    );
+    let fds = [first.0.as_raw_fd(), second.0.as_raw_fd()];
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(20);
+    assert!(wait_readable(&fds, Some(deadline)).unwrap().is_empty());
+    assert!(Instant::now() >= deadline);
+    (&second.1).write_all(b"x").unwrap();
+    assert_eq!(wait_readable(&fds, None).unwrap(), [fds[1]]);
+    drop(first.1);
+    assert_eq!(wait_readable(&fds, Some(start)).unwrap(), fds);
+    assert!(wait_readable(&[], Some(start)).unwrap().is_empty());
+}
@@ -0,0 +1,830 @@
+use crate::geometry::{Rotation, Transform, Vec2d};
+use crate::layout::{self, Edid};
+use crate::{Backend, ReactionDelays};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Highest used version of `zwlr_output_manager_v1`.
+/// Version 2 adds make, model and serial number of heads.
+/// Version 3 requires releasing heads and modes, which is not needed here.
+const OUTPUT_MANAGER_VERSION: u32 = 2;
+
+/// Backend for wlroots based Wayland compositors (sway, hyprland, river), using the `wlr-output-management-unstable-v1` protocol.
+/// Protocol definition : <https://gitlab.freedesktop.org/wlroots/wlr-protocols/-/blob/master/unstable/wlr-output-management-unstable-v1.xml>.
+///
+/// The few messages needed are encoded directly on the Wayland socket, without libwayland.
+/// The compositor describes each connected output as a _head_ with a list of _modes_.
+/// Each batch of changes ends with a `done` event, whose serial is required to apply configurations.
+///
+/// Things that are not handled :
+/// - heads do not expose EDID : outputs are identified by make, model and serial number if available, or by name.
+/// - Wayland has no primary output.
+/// - output properties and signal settings are X specific.
+pub struct WaylandBackend {
+    connection: Connection,
+    manager: u32,
+    manager_version: u32,
+    /// Serial of the last `done` event.
+    serial: u32,
+    /// Head and mode events were received after the last `done` event.
+    pending_changes: bool,
+    heads: HashMap<u32, Head>,
+    modes: HashMap<u32, HeadMode>,
+}
+
+impl WaylandBackend {
+    pub fn start() -> Result<Self, anyhow::Error> {
+        let mut connection = Connection::connect()?;
+
+        // List globals, synchronized by a wl_display.sync callback
+        let registry = connection.new_id();
+        connection.send(Message::new(DISPLAY, DISPLAY_GET_REGISTRY).uint(registry))?;
+        let callback = connection.new_id();
+        connection.send(Message::new(DISPLAY, DISPLAY_SYNC).uint(callback))?;
+        let mut manager_global = None;
+        loop {
+            let message = connection.receive()?;
+            match (message.object, message.opcode) {
+                (DISPLAY, _) => check_display_event(&message)?,
+                (object, REGISTRY_GLOBAL) if object == registry => {
+                    let mut args = message.arguments();
+                    let (name, interface, version) = (args.uint()?, args.string()?, args.uint()?);
+                    if interface == "zwlr_output_manager_v1" {
+                        manager_global = Some((name, version))
+                    }
+                }
+                (object, CALLBACK_DONE) if object == callback => break,
+                _ => (),
+            }
+        }
+        let (name, version) = manager_global.ok_or_else(|| {
+            anyhow::Error::msg("compositor does not support wlr-output-management")
+        })?;
+
+        let manager = connection.new_id();
+        let manager_version = version.min(OUTPUT_MANAGER_VERSION);
+        connection.send(
+            Message::new(registry, REGISTRY_BIND)
+                .uint(name)
+                .string("zwlr_output_manager_v1")
+                .uint(manager_version)
+                .uint(manager),
+        )?;
+        let mut backend = WaylandBackend {
+            connection,
+            manager,
+            manager_version,
+            serial: 0,
+            pending_changes: false,
+            heads: HashMap::new(),
+            modes: HashMap::new(),
+        };
+        // Initial state is sent after binding
+        backend.wait_for_done(None)?;
+        Ok(backend)
+    }
+
+    /// Process events until a `done` event, or until `deadline`. Returns false on timeout.
+    fn wait_for_done(&mut self, deadline: Option<Instant>) -> Result<bool, anyhow::Error> {
+        loop {
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if !self.connection.poll(remaining)? {
+                    return Ok(false);
+                }
+            }
+            let message = self.connection.receive()?;
+            if let Some(Event::Done) = self.dispatch(message)? {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// After a `done` event, also process changes happening during the reaction delay.
+    fn absorb_changes(&mut self, reaction_delays: ReactionDelays) -> Result<(), anyhow::Error> {
+        if let Some(delay) = reaction_delays.longest() {
+            std::thread::sleep(delay);
+            while self.connection.poll(Duration::ZERO)? {
+                let message = self.connection.receive()?;
+                self.dispatch(message)?;
+            }
+            // Do not stop in the middle of a batch of changes
+            if self.pending_changes {
+                self.wait_for_done(None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Update state from an event.
+    fn dispatch(&mut self, message: Message) -> Result<Option<Event>, anyhow::Error> {
+        let mut args = message.arguments();
+        let object = message.object;
+        if object == DISPLAY {
+            check_display_event(&message)?;
+        } else if object == self.manager {
+            match message.opcode {
+                MANAGER_HEAD => {
+                    self.heads.insert(args.uint()?, Head::default());
+                    self.pending_changes = true
+                }
+                MANAGER_DONE => {
+                    self.serial = args.uint()?;
+                    self.pending_changes = false;
+                    return Ok(Some(Event::Done));
+                }
+                MANAGER_FINISHED => {
+                    return Err(anyhow::Error::msg("output manager stopped by compositor"))
+                }
+                _ => (),
+            }
+        } else if let Some(head) = self.heads.get_mut(&object) {
+            self.pending_changes = true;
+            match message.opcode {
+                HEAD_NAME => head.name = args.string()?,
+                HEAD_DESCRIPTION => head.description = args.string()?,
+                HEAD_PHYSICAL_SIZE => head.physical_size = Vec2d::new(args.int()?, args.int()?),
+                HEAD_MODE => {
+                    let mode = args.uint()?;
+                    head.modes.push(mode);
+                    self.modes.insert(mode, HeadMode::default());
+                }
+                HEAD_ENABLED => head.enabled = args.int()? != 0,
+                HEAD_CURRENT_MODE => head.current_mode = Some(args.uint()?),
+                HEAD_POSITION => head.position = Vec2d::new(args.int()?, args.int()?),
+                HEAD_TRANSFORM => head.transform = transform_from_wayland(args.int()?),
+                HEAD_SCALE => head.scale = args.fixed()?,
+                HEAD_FINISHED => {
+                    self.heads.remove(&object);
+                }
+                HEAD_MAKE => head.make = args.string()?,
+                HEAD_MODEL => head.model = args.string()?,
+                HEAD_SERIAL_NUMBER => head.serial_number = args.string()?,
+                _ => (),
+            }
+        } else if let Some(mode) = self.modes.get_mut(&object) {
+            self.pending_changes = true;
+            match message.opcode {
+                MODE_SIZE => mode.size = Vec2d::new(args.int()?, args.int()?),
+                MODE_REFRESH => mode.refresh = args.int()?,
+                MODE_PREFERRED => mode.preferred = true,
+                MODE_FINISHED => {
+                    self.modes.remove(&object);
+                    for head in self.heads.values_mut() {
+                        head.modes.retain(|m| *m != object);
+                        if head.current_mode == Some(object) {
+                            head.current_mode = None
+                        }
+                    }
+                }
+                _ => (),
+            }
+        } else {
+            // Configuration results. Other objects (callbacks, configuration heads) have no used events.
+            let outcome = match message.opcode {
+                CONFIGURATION_SUCCEEDED => ConfigurationOutcome::Succeeded,
+                CONFIGURATION_FAILED => ConfigurationOutcome::Failed,
+                CONFIGURATION_CANCELLED => ConfigurationOutcome::Cancelled,
+                _ => return Ok(None),
+            };
+            return Ok(Some(Event::Configuration(object, outcome)));
+        }
+        Ok(None)
+    }
+
+    fn connected_heads(&self) -> impl Iterator<Item = (&u32, &Head)> {
+        self.heads.iter().filter(|(_, head)| !head.name.is_empty())
+    }
+
+    fn head_mode(&self, head: &Head, requested: &layout::Mode) -> Option<u32> {
+        let distance = |id: &u32| requested.match_distance(&self.modes.get(id)?.to_mode());
+        head.modes
+            .iter()
+            .filter_map(|id| Some((distance(id)?, *id)))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, id)| id)
+    }
+}
+
+impl Backend for WaylandBackend {
+    fn current_layout(&self) -> layout::LayoutInfo {
+        let entries = self.connected_heads().map(|(_, head)| {
+            let current_mode = head.current_mode.and_then(|id| self.modes.get(&id));
+            let state = match (head.enabled, current_mode) {
+                (true, Some(mode)) => layout::OutputState::Enabled {
+                    mode: mode.to_mode(),
+                    transform: head.transform.clone(),
+                    bottom_left: head.position,
+                    scale: layout::Scale::from_f64(head.scale).unwrap_or(layout::Scale::ONE),
+                    panning: None,
+                },
+                _ => layout::OutputState::Disabled { reason: None },
+            };
+            layout::OutputEntry {
+                id: head.id(),
+                state,
+            }
+        });
+        layout::LayoutInfo::from_iter(entries, None)
+            .with_metadata(&self.output_names(), &self.preferred_modes())
+    }
+
+    fn wait_for_change(&mut self, reaction_delays: ReactionDelays) -> Result<(), anyhow::Error> {
+        self.wait_for_done(None)?;
+        self.absorb_changes(reaction_delays)
+    }
+
+    fn wait_for_change_until(
+        &mut self,
+        reaction_delays: ReactionDelays,
+        timeout: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        if !self.wait_for_done(Some(Instant::now() + timeout))? {
+            return Ok(false);
+        }
+        self.absorb_changes(reaction_delays)?;
+        Ok(true)
+    }
+
+    fn event_fds(&self) -> Vec<RawFd> {
+        vec![self.connection.socket.as_raw_fd()]
+    }
+
+    fn apply_layout(&mut self, layout: &layout::Layout) -> Result<(), anyhow::Error> {
+        let mut requests = Vec::new();
+        for entry in layout.output_entries() {
+            let (head_id, head) = match self.connected_heads().find(|(_, h)| h.id() == entry.id) {
+                Some(head) => head,
+                None => {
+                    log::warn!("could not apply layout: output {} not found", entry.id);
+                    return Ok(());
+                }
+            };
+            requests.push((*head_id, entry.state.clone(), head.clone()));
+        }
+        if !layout.backend_properties().is_empty() {
+            log::warn!("output properties are not supported by the wayland backend")
+        }
+
+        let configuration = self.connection.new_id();
+        self.connection.send(
+            Message::new(self.manager, MANAGER_CREATE_CONFIGURATION)
+                .uint(configuration)
+                .uint(self.serial),
+        )?;
+        for (head_id, state, head) in requests {
+            match state {
+                layout::OutputState::Disabled { .. } => self
+                    .connection
+                    .send(Message::new(configuration, CONFIGURATION_DISABLE_HEAD).uint(head_id))?,
+                layout::OutputState::Enabled {
+                    mode,
+                    transform,
+                    bottom_left,
+                    scale,
+                    ..
+                } => {
+                    let config_head = self.connection.new_id();
+                    self.connection.send(
+                        Message::new(configuration, CONFIGURATION_ENABLE_HEAD)
+                            .uint(config_head)
+                            .uint(head_id),
+                    )?;
+                    let mode_request = match self.head_mode(&head, &mode) {
+                        Some(mode_id) => {
+                            Message::new(config_head, CONFIGURATION_HEAD_SET_MODE).uint(mode_id)
+                        }
+                        None => Message::new(config_head, CONFIGURATION_HEAD_SET_CUSTOM_MODE)
+                            .int(mode.size.x as i32)
+                            .int(mode.size.y as i32)
+                            .int(mode.millihertz() as i32),
+                    };
+                    self.connection.send(mode_request)?;
+                    self.connection.send(
+                        Message::new(config_head, CONFIGURATION_HEAD_SET_POSITION)
+                            .int(bottom_left.x)
+                            .int(bottom_left.y),
+                    )?;
+                    self.connection.send(
+                        Message::new(config_head, CONFIGURATION_HEAD_SET_TRANSFORM)
+                            .int(transform_to_wayland(&transform)),
+                    )?;
+                    self.connection.send(
+                        Message::new(config_head, CONFIGURATION_HEAD_SET_SCALE)
+                            .fixed(scale.to_f64()),
+                    )?;
+                }
+            }
+        }
+        self.connection
+            .send(Message::new(configuration, CONFIGURATION_APPLY))?;
+
+        // Other events may come before the result
+        let outcome = loop {
+            let message = self.connection.receive()?;
+            match self.dispatch(message)? {
+                Some(Event::Configuration(object, outcome)) if object == configuration => {
+                    break outcome
+                }
+                _ => (),
+            }
+        };
+        self.connection
+            .send(Message::new(configuration, CONFIGURATION_DESTROY))?;
+        match outcome {
+            ConfigurationOutcome::Succeeded => (),
+            ConfigurationOutcome::Failed => {
+                log::warn!("could not apply layout: rejected by compositor")
+            }
+            ConfigurationOutcome::Cancelled => {
+                log::warn!("could not apply layout: outputs changed during apply")
+            }
+        }
+        Ok(())
+    }
+
+    fn physical_sizes(&self) -> HashMap<layout::OutputId, Vec2d<u32>> {
+        HashMap::from_iter(self.connected_heads().filter_map(|(_, head)| {
+            let size = head.physical_size.try_map(u32::try_from).ok()?;
+            match size.x > 0 && size.y > 0 {
+                true => Some((head.id(), size)),
+                false => None,
+            }
+        }))
+    }
+
+    fn output_names(&self) -> HashMap<layout::OutputId, String> {
+        HashMap::from_iter(
+            self.connected_heads()
+                .map(|(_, head)| (head.id(), head.name.clone())),
+        )
+    }
+
+    /// Heads have no model name field : the compositor description also contains make, serial and connector.
+    fn output_descriptions(&self) -> HashMap<layout::OutputId, String> {
+        HashMap::from_iter(self.connected_heads().filter_map(|(_, head)| {
+            let description = match head.model.is_empty() {
+                false => head.model.clone(),
+                true if !head.description.is_empty() => head.description.clone(),
+                true => return None,
+            };
+            Some((head.id(), description))
+        }))
+    }
+
+    fn preferred_modes(&self) -> HashMap<layout::OutputId, layout::Mode> {
+        HashMap::from_iter(self.connected_heads().filter_map(|(_, head)| {
+            let preferred = head
+                .modes
+                .iter()
+                .filter_map(|id| self.modes.get(id))
+                .find(|mode| mode.preferred)?;
+            Some((head.id(), preferred.to_mode()))
+        }))
+    }
+
+    fn available_modes(&self) -> HashMap<layout::OutputId, Vec<layout::Mode>> {
+        HashMap::from_iter(self.connected_heads().map(|(_, head)| {
+            let modes = head.modes.iter().filter_map(|id| self.modes.get(id));
+            (head.id(), Vec::from_iter(modes.map(|mode| mode.to_mode())))
+        }))
+    }
+
+    fn diagnostics(&self) -> String {
+        use std::fmt::Write;
+        let mut summary = format!(
+            "wayland backend, zwlr_output_manager_v1 version {}, serial {}\n",
+            self.manager_version, self.serial
+        );
+        let mut heads = Vec::from_iter(self.heads.iter());
+        heads.sort_by_key(|(id, _)| **id);
+        for (id, head) in heads {
+            let current_mode = head.current_mode.and_then(|id| self.modes.get(&id));
+            let _ = writeln!(
+                summary,
+                "head {} {:#x} ({}): {:?}, {}, mode {}, position {}x{}, transform {:?}, scale {}, {} modes",
+                head.name,
+                id,
+                head.id(),
+                head.description,
+                match head.enabled {
+                    true => "enabled",
+                    false => "disabled",
+                },
+                current_mode.map_or_else(|| "none".to_string(), |m| m.to_mode().to_string()),
+                head.position.x,
+                head.position.y,
+                head.transform,
+                head.scale,
+                head.modes.len()
+            );
+        }
+        summary
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Head {
+    name: String,
+    description: String,
+    /// Millimeters, 0 if unknown.
+    physical_size: Vec2d<i32>,
+    modes: Vec<u32>,
+    enabled: bool,
+    current_mode: Option<u32>,
+    position: Vec2d<i32>,
+    transform: Transform,
+    scale: f64,
+    make: String,
+    model: String,
+    serial_number: String,
+}
+
+impl Default for Head {
+    fn default() -> Self {
+        Head {
+            name: String::new(),
+            description: String::new(),
+            physical_size: Vec2d::default(),
+            modes: Vec::new(),
+            enabled: false,
+            current_mode: None,
+            position: Vec2d::default(),
+            transform: Transform::default(),
+            scale: 1.,
+            make: String::new(),
+            model: String::new(),
+            serial_number: String::new(),
+        }
+    }
+}
+
+impl Head {
+    /// Use the monitor identification if it has a serial number, to recognize it on any connector.
+    fn id(&self) -> layout::OutputId {
+        match self.serial_number.as_str() {
+            "" | "Unknown" => layout::OutputId::Name(self.name.clone()),
+            serial => {
+                layout::OutputId::Edid(Edid::from_identification(&self.make, &self.model, serial))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct HeadMode {
+    size: Vec2d<i32>,
+    /// mHz, 0 if unknown.
+    refresh: i32,
+    preferred: bool,
+}
+
+impl HeadMode {
+    fn to_mode(&self) -> layout::Mode {
+        let size = self.size.map(|i| i.max(0) as u32);
+        match u32::try_from(self.refresh) {
+            Ok(refresh) if refresh > 0 => layout::Mode::from_refresh(size, refresh),
+            _ => layout::Mode {
+                size,
+                frequency: 0,
+                refresh: None,
+            },
+        }
+    }
+}
+
+/// `wl_output.transform` : rotations are counter-clockwise, flips are along the vertical axis before rotation.
+fn transform_from_wayland(value: i32) -> Transform {
+    Transform {
+        reflect: value & 4 != 0,
+        rotation: match value & 3 {
+            0 => Rotation::R0,
+            1 => Rotation::R90,
+            2 => Rotation::R180,
+            _ => Rotation::R270,
+        },
+    }
+}
+
+fn transform_to_wayland(transform: &Transform) -> i32 {
+    transform.rotation as i32 + if transform.reflect { 4 } else { 0 }
+}
+
+enum Event {
+    Done,
+    Configuration(u32, ConfigurationOutcome),
+}
+
+enum ConfigurationOutcome {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Wire protocol
+
+const DISPLAY: u32 = 1;
+const DISPLAY_SYNC: u16 = 0;
+const DISPLAY_GET_REGISTRY: u16 = 1;
+const DISPLAY_ERROR: u16 = 0;
+const REGISTRY_BIND: u16 = 0;
+const REGISTRY_GLOBAL: u16 = 0;
+const CALLBACK_DONE: u16 = 0;
+
+const MANAGER_CREATE_CONFIGURATION: u16 = 0;
+const MANAGER_HEAD: u16 = 0;
+const MANAGER_DONE: u16 = 1;
+const MANAGER_FINISHED: u16 = 2;
+
+const HEAD_NAME: u16 = 0;
+const HEAD_DESCRIPTION: u16 = 1;
+const HEAD_PHYSICAL_SIZE: u16 = 2;
+const HEAD_MODE: u16 = 3;
+const HEAD_ENABLED: u16 = 4;
+const HEAD_CURRENT_MODE: u16 = 5;
+const HEAD_POSITION: u16 = 6;
+const HEAD_TRANSFORM: u16 = 7;
+const HEAD_SCALE: u16 = 8;
+const HEAD_FINISHED: u16 = 9;
+const HEAD_MAKE: u16 = 10;
+const HEAD_MODEL: u16 = 11;
+const HEAD_SERIAL_NUMBER: u16 = 12;
+
+const MODE_SIZE: u16 = 0;
+const MODE_REFRESH: u16 = 1;
+const MODE_PREFERRED: u16 = 2;
+const MODE_FINISHED: u16 = 3;
+
+const CONFIGURATION_ENABLE_HEAD: u16 = 0;
+const CONFIGURATION_DISABLE_HEAD: u16 = 1;
+const CONFIGURATION_APPLY: u16 = 2;
+const CONFIGURATION_DESTROY: u16 = 4;
+const CONFIGURATION_SUCCEEDED: u16 = 0;
+const CONFIGURATION_FAILED: u16 = 1;
+const CONFIGURATION_CANCELLED: u16 = 2;
+
+const CONFIGURATION_HEAD_SET_MODE: u16 = 0;
+const CONFIGURATION_HEAD_SET_CUSTOM_MODE: u16 = 1;
+const CONFIGURATION_HEAD_SET_POSITION: u16 = 2;
+const CONFIGURATION_HEAD_SET_TRANSFORM: u16 = 3;
+const CONFIGURATION_HEAD_SET_SCALE: u16 = 4;
+
+fn check_display_event(message: &Message) -> Result<(), anyhow::Error> {
+    if message.opcode == DISPLAY_ERROR {
+        let mut args = message.arguments();
+        let (object, code, description) = (args.uint()?, args.uint()?, args.string()?);
+        return Err(anyhow::Error::msg(format!(
+            "wayland protocol error on object {} (code {}): {}",
+            object, code, description
+        )));
+    }
+    Ok(()) // delete_id : object ids are never reused
+}
+
+struct Connection {
+    socket: UnixStream,
+    /// Received bytes not yet decoded.
+    buffer: Vec<u8>,
+    next_id: u32,
+}
+
+impl Connection {
+    /// Connect to `$WAYLAND_DISPLAY`, relative to `$XDG_RUNTIME_DIR`.
+    fn connect() -> Result<Connection, anyhow::Error> {
+        let display = std::env::var_os("WAYLAND_DISPLAY")
+            .ok_or_else(|| anyhow::Error::msg("WAYLAND_DISPLAY is not set"))?;
+        let mut path = PathBuf::from(display);
+        if path.is_relative() {
+            let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+                .ok_or_else(|| anyhow::Error::msg("XDG_RUNTIME_DIR is not set"))?;
+            path = PathBuf::from(runtime_dir).join(path)
+        }
+        let socket = UnixStream::connect(&path)
+            .with_context(|| format!("cannot connect to {}", path.display()))?;
+        Ok(Connection {
+            socket,
+            buffer: Vec::new(),
+            next_id: DISPLAY + 1,
+        })
+    }
+
+    fn new_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn send(&mut self, message: Message) -> Result<(), anyhow::Error> {
+        self.socket
+            .write_all(&message.encode())
+            .with_context(|| "cannot write to wayland socket")
+    }
+
+    /// Next message, waiting for it if needed.
+    fn receive(&mut self) -> Result<Message, anyhow::Error> {
+        loop {
+            if let Some(message) = Message::decode(&mut self.buffer)? {
+                return Ok(message);
+            }
+            let mut bytes = [0; 4096];
+            let size = self
+                .socket
+                .read(&mut bytes)
+                .with_context(|| "cannot read from wayland socket")?;
+            if size == 0 {
+                return Err(anyhow::Error::msg(
+                    "wayland compositor closed the connection",
+                ));
+            }
+            self.buffer.extend_from_slice(&bytes[..size])
+        }
+    }
+
+    /// Wait until data can be received, up to `timeout`. Returns false on timeout.
+    fn poll(&self, timeout: Duration) -> Result<bool, anyhow::Error> {
+        if Message::is_complete(&self.buffer) {
+            return Ok(true);
+        }
+        let mut fd = libc::pollfd {
+            fd: self.socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+        match unsafe { libc::poll(&mut fd, 1, timeout_ms) } {
+            n if n < 0 => {
+                let error = std::io::Error::last_os_error();
+                match error.kind() {
+                    std::io::ErrorKind::Interrupted => Ok(false),
+                    _ => Err(error).with_context(|| "poll on wayland socket failed"),
+                }
+            }
+            n => Ok(n > 0),
+        }
+    }
+}
+
+/// Wayland message : header with object id, size and opcode, then arguments as native endian 32-bit words.
+/// File descriptor arguments are not supported, they are not used by the output management protocol.
+#[derive(Debug, PartialEq, Eq)]
+struct Message {
+    object: u32,
+    opcode: u16,
+    arguments: Vec<u8>,
+}
+
+impl Message {
+    const HEADER_SIZE: usize = 8;
+
+    fn new(object: u32, opcode: u16) -> Message {
+        Message {
+            object,
+            opcode,
+            arguments: Vec::new(),
+        }
+    }
+
+    /// Also used for `new_id` and `object` arguments.
+    fn uint(mut self, value: u32) -> Message {
+        self.arguments.extend_from_slice(&value.to_ne_bytes());
+        self
+    }
+    fn int(self, value: i32) -> Message {
+        self.uint(value as u32)
+    }
+    /// Signed 24.8 fixed point.
+    fn fixed(self, value: f64) -> Message {
+        self.int((value * 256.).round() as i32)
+    }
+    /// Length including the nul terminator, then bytes padded to 32 bits.
+    fn string(mut self, value: &str) -> Message {
+        self = self.uint(value.len() as u32 + 1);
+        self.arguments.extend_from_slice(value.as_bytes());
+        let padded = (value.len() + 1 + 3) & !3;
+        self.arguments
+            .resize(self.arguments.len() + padded - value.len(), 0);
+        self
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let size = (Self::HEADER_SIZE + self.arguments.len()) as u32;
+        let mut bytes = Vec::with_capacity(size as usize);
+        bytes.extend_from_slice(&self.object.to_ne_bytes());
+        bytes.extend_from_slice(&((size << 16) | u32::from(self.opcode)).to_ne_bytes());
+        bytes.extend_from_slice(&self.arguments);
+        bytes
+    }
+
+    fn size(buffer: &[u8]) -> Option<usize> {
+        let word = buffer.get(4..8)?;
+        Some((u32::from_ne_bytes(word.try_into().unwrap()) >> 16) as usize)
+    }
+
+    fn is_complete(buffer: &[u8]) -> bool {
+        Message::size(buffer).is_some_and(|size| buffer.len() >= size)
+    }
+
+    /// Remove the first message from `buffer` if complete.
+    fn decode(buffer: &mut Vec<u8>) -> Result<Option<Message>, anyhow::Error> {
+        let size = match Message::size(buffer) {
+            Some(size) if size < Self::HEADER_SIZE || size % 4 != 0 => {
+                return Err(anyhow::Error::msg(format!(
+                    "invalid wayland message size: {}",
+                    size
+                )))
+            }
+            Some(size) if buffer.len() >= size => size,
+            _ => return Ok(None),
+        };
+        let word = |i: usize| u32::from_ne_bytes(buffer[i..i + 4].try_into().unwrap());
+        let message = Message {
+            object: word(0),
+            opcode: (word(4) & 0xFFFF) as u16,
+            arguments: buffer[Self::HEADER_SIZE..size].to_vec(),
+        };
+        buffer.drain(..size);
+        Ok(Some(message))
+    }
+
+    fn arguments(&self) -> Arguments<'_> {
+        Arguments {
+            bytes: &self.arguments,
+        }
+    }
+}
+
+/// Reader for message arguments, in order.
+struct Arguments<'m> {
+    bytes: &'m [u8],
+}
+
+impl<'m> Arguments<'m> {
+    fn take(&mut self, size: usize) -> Result<&'m [u8], anyhow::Error> {
+        if self.bytes.len() < size {
+            return Err(anyhow::Error::msg("truncated wayland message"));
+        }
+        let (taken, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn uint(&mut self) -> Result<u32, anyhow::Error> {
+        Ok(u32::from_ne_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn int(&mut self) -> Result<i32, anyhow::Error> {
+        Ok(self.uint()? as i32)
+    }
+    fn fixed(&mut self) -> Result<f64, anyhow::Error> {
+        Ok(f64::from(self.int()?) / 256.)
+    }
+    /// Null strings are returned as empty.
+    fn string(&mut self) -> Result<String, anyhow::Error> {
+        let length = self.uint()? as usize;
+        let padded = (length + 3) & !3;
+        let bytes = self.take(padded)?;
+        let content = bytes[..length.saturating_sub(1)].to_vec();
+        String::from_utf8(content).with_context(|| "invalid utf8 in wayland string")
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_wayland_wire() {
+    let message = Message::new(3, 1)
+        .uint(42)
+        .string("zwlr_output_manager_v1")
+        .int(-5)
+        .fixed(1.5)
+        .string("");
+    let mut buffer = message.encode();
+    assert_eq!(buffer.len() % 4, 0);
+    assert_eq!(Message::size(&buffer), Some(buffer.len()));
+    // Incomplete messages are kept in the buffer
+    let mut partial = buffer[..buffer.len() - 4].to_vec();
+    assert_eq!(Message::decode(&mut partial).unwrap(), None);
+    assert!(!partial.is_empty());
+    buffer.extend(Message::new(1, 0).encode());
+    let decoded = Message::decode(&mut buffer).unwrap().unwrap();
+    assert_eq!(decoded, message);
+    assert_eq!(buffer.len(), Message::HEADER_SIZE);
+    let mut args = decoded.arguments();
+    assert_eq!(args.uint().unwrap(), 42);
+    assert_eq!(args.string().unwrap(), "zwlr_output_manager_v1");
+    assert_eq!(args.int().unwrap(), -5);
+    assert_eq!(args.fixed().unwrap(), 1.5);
+    assert_eq!(args.string().unwrap(), "");
+    assert!(args.uint().is_err());
+
+    for value in 0..8 {
+        assert_eq!(transform_to_wayland(&transform_from_wayland(value)), value)
+    }
+    assert_eq!(
+        transform_from_wayland(5),
+        Transform::default().reflect_x().rotate(Rotation::R90)
+    );
+}
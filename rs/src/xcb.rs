@@ -1,13 +1,19 @@
-use crate::geometry::{Rotation, Transform, Vec2d};
+use crate::backlight;
+use crate::geometry::{Rect, Rotation, Transform, Vec2d};
+use crate::graylist::GrayList;
 use crate::layout::{self, Edid};
-use crate::Backend;
+use crate::{Backend, EventKind, ReactionDelays};
 use anyhow::Context;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 use xcb::Xid;
 
-const MM_PER_INCH: f64 = 25.4;
+use layout::physical::MM_PER_INCH;
+
+/// Outputs failing `SetCrtcConfig` this many times in a row are not enabled for a while.
+const GRAY_LIST_MAX_FAILURES: u32 = 3;
+const GRAY_LIST_COOLDOWN: Duration = Duration::from_secs(10 * 60);
 
 /// Backend for X server, using xcb bindings with randr extension.
 /// Useful documentation : `/usr/share/doc/xorgproto/randrproto.txt`.
@@ -16,21 +22,43 @@ const MM_PER_INCH: f64 = 25.4;
 /// This consists of mapping _outputs_ to viewports on the rectangle _screen_.
 /// These outputs are mapped through _crtc_ that set _modeinfo_ and rotations.
 ///
+/// With RandR 1.5 and up, _monitors_ group outputs into logical monitors for window managers.
+/// Monitors created by the server follow their outputs, but user-defined ones (`xrandr --setmonitor`,
+/// usually for tiled or MST displays driven by several outputs) are moved and resized after each layout change.
+///
+/// Output scales use the homogeneous transform matrix of crtcs, restricted to uniform scaling (`xrandr --scale`).
+/// Output panning uses the crtc panning area, and the position of the output is the one of its panned area.
+/// Custom modes from the configuration are created and added to outputs when a layout uses them.
+///
+/// If the DPMS extension is available, disabling outputs cycles the screen through DPMS off and on.
+/// Only outputs with a crtc are woken up, so disabled monitors go to sleep instead of showing "no signal".
+///
 /// Things that are not handled and assumed left default :
-/// - homogeneous transform matrix on crtc : cool toy, but handling by window managers is mostly broken.
+/// - other transform matrices on crtc (keystone, shear) : cool toy, but handling by window managers is mostly broken.
 /// - rotations at the screen level : legacy, superseeded by current mode.
 /// - any recent provider stuff.
 pub struct XcbBackend {
     connection: xcb::Connection,
     root_window: xcb::x::Window,
     edid_atom: xcb::x::Atom,
+    /// `Backlight` output property, if defined by a driver.
+    backlight_atom: Option<xcb::x::Atom>,
+    /// `(major, minor)` version of the RandR extension of the server.
+    randr_version: (u32, u32),
+    /// DPMS extension is available and usable by the server.
+    dpms: bool,
     output_set_state: OutputSetState,
+    /// Flaky outputs (broken adapters) that we stopped trying to enable.
+    failing_outputs: GrayList<layout::OutputId>,
 }
 
 impl XcbBackend {
     pub fn start() -> Result<Self, anyhow::Error> {
-        let (connection, screen_id) =
-            xcb::Connection::connect_with_extensions(None, &[xcb::Extension::RandR], &[])?;
+        let (connection, screen_id) = xcb::Connection::connect_with_extensions(
+            None,
+            &[xcb::Extension::RandR],
+            &[xcb::Extension::Dpms],
+        )?;
         let root_window = {
             let setup = connection.get_setup();
             let screen = setup
@@ -40,6 +68,15 @@ impl XcbBackend {
             screen.root()
         };
 
+        let randr_version = {
+            let cookie = connection.send_request(&xcb::randr::QueryVersion {
+                major_version: 1,
+                minor_version: 6,
+            });
+            let reply = connection.wait_for_reply(cookie)?;
+            (reply.major_version(), reply.minor_version())
+        };
+
         // Register for randr events
         connection.send_and_check_request(&xcb::randr::SelectInput {
             window: root_window,
@@ -63,22 +100,120 @@ impl XcbBackend {
             }
         };
 
-        let output_set_state = OutputSetState::query(&connection, root_window, edid_atom)?;
+        let backlight_atom = {
+            let cookie = connection.send_request(&xcb::x::InternAtom {
+                only_if_exists: true,
+                name: b"Backlight",
+            });
+            filter_xid(connection.wait_for_reply(cookie)?.atom())
+        };
+
+        let dpms = connection
+            .active_extensions()
+            .any(|e| e == xcb::Extension::Dpms)
+            && {
+                let cookie = connection.send_request(&xcb::dpms::Capable {});
+                connection.wait_for_reply(cookie)?.capable()
+            };
+
+        let output_set_state =
+            OutputSetState::query(&connection, root_window, edid_atom, randr_version >= (1, 5))?;
         Ok(XcbBackend {
             connection,
             root_window,
             edid_atom,
+            backlight_atom,
+            randr_version,
+            dpms,
             output_set_state,
+            failing_outputs: GrayList::new(GRAY_LIST_MAX_FAILURES, GRAY_LIST_COOLDOWN),
         })
     }
+
+    /// Monitors are available since RandR 1.5.
+    fn has_monitors(&self) -> bool {
+        self.randr_version >= (1, 5)
+    }
+
+    /// Backlight of an output as `(value, min, max)`, if it has one.
+    fn backlight(
+        &self,
+        output: xcb::randr::Output,
+    ) -> Result<Option<(i32, i32, i32)>, anyhow::Error> {
+        let atom = match self.backlight_atom {
+            Some(atom) => atom,
+            None => return Ok(None),
+        };
+        let value_cookie = self
+            .connection
+            .send_request(&xcb::randr::GetOutputProperty {
+                output,
+                property: atom,
+                r#type: xcb::x::ATOM_INTEGER,
+                long_offset: 0,
+                long_length: 1,
+                delete: false,
+                pending: false,
+            });
+        let range_cookie = self
+            .connection
+            .send_request(&xcb::randr::QueryOutputProperty {
+                output,
+                property: atom,
+            });
+        let value_reply = self.connection.wait_for_reply(value_cookie)?;
+        let range_reply = self.connection.wait_for_reply(range_cookie);
+        let value = match (value_reply.r#type(), value_reply.format()) {
+            (xcb::x::ATOM_INTEGER, 32) => value_reply.data::<u32>().first().map(|v| *v as i32),
+            _ => None,
+        };
+        // QueryOutputProperty fails with a BadName error if the output has no backlight
+        match (value, range_reply) {
+            (Some(value), Ok(range)) if range.range() => match range.valid_values() {
+                [min, max] if min < max => Ok(Some((value, *min, *max))),
+                _ => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Reload state after randr events of `kinds`.
+    fn reload_after_randr_event(
+        &mut self,
+        reaction_delays: ReactionDelays,
+        mut kinds: Vec<EventKind>,
+    ) -> Result<(), anyhow::Error> {
+        // If delay is requested, also flush all randr events during the delay.
+        // Events of a kind with a longer delay extend it.
+        let delay_of = |kinds: &[EventKind]| {
+            let delays = kinds.iter().filter_map(|kind| reaction_delays.get(*kind));
+            delays.max()
+        };
+        let mut waited = Duration::ZERO;
+        while let Some(delay) = delay_of(&kinds).filter(|delay| *delay > waited) {
+            std::thread::sleep(delay - waited);
+            waited = delay;
+            while let Some(event) = self.connection.poll_for_event()? {
+                kinds.extend(check_randr_event(event))
+            }
+        }
+        self.output_set_state = OutputSetState::query(
+            &self.connection,
+            self.root_window,
+            self.edid_atom,
+            self.has_monitors(),
+        )?;
+        Ok(())
+    }
 }
 
 impl Backend for XcbBackend {
     fn current_layout(&self) -> layout::LayoutInfo {
         convert_to_layout(&self.output_set_state)
+            .with_metadata(&self.output_names(), &self.preferred_modes())
     }
 
-    fn wait_for_change(&mut self, reaction_delay: Option<Duration>) -> Result<(), anyhow::Error> {
+    fn wait_for_change(&mut self, reaction_delays: ReactionDelays) -> Result<(), anyhow::Error> {
         // Wait for any randr event, then reload entire randr state.
         // Initial version used poll_for_queued_event() after one poll() for efficiency.
         // Changes were missed due to that so this was reverted to active poll.
@@ -90,47 +225,364 @@ impl Backend for XcbBackend {
         // See https://gitlab.gnome.org/GNOME/mutter/-/blob/main/src/backends/x11/meta-monitor-manager-xrandr.c
         loop {
             // Wait for event, flush all events, and determine if it was randr related
-            let mut had_randr_event = false;
             let event = self.connection.wait_for_event()?;
-            had_randr_event |= check_randr_event(event);
+            let mut kinds = Vec::from_iter(check_randr_event(event));
             while let Some(event) = self.connection.poll_for_event()? {
-                had_randr_event |= check_randr_event(event)
+                kinds.extend(check_randr_event(event))
             }
-            if had_randr_event {
-                // If delay is requested, also flush all randr events during the delay
-                if let Some(delay) = reaction_delay {
-                    std::thread::sleep(delay);
-                    while let Some(event) = self.connection.poll_for_event()? {
-                        check_randr_event(event);
-                    }
+            if !kinds.is_empty() {
+                return self.reload_after_randr_event(reaction_delays, kinds);
+            }
+        }
+    }
+
+    fn wait_for_change_until(
+        &mut self,
+        reaction_delays: ReactionDelays,
+        timeout: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        use std::os::unix::io::AsRawFd;
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Same as wait_for_change, but sleep in poll() to stop at the deadline
+            let mut kinds = Vec::new();
+            while let Some(event) = self.connection.poll_for_event()? {
+                kinds.extend(check_randr_event(event))
+            }
+            if !kinds.is_empty() {
+                self.reload_after_randr_event(reaction_delays, kinds)?;
+                return Ok(true);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            let mut fd = libc::pollfd {
+                fd: self.connection.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout_ms = remaining.as_millis().clamp(1, i32::MAX as u128) as i32;
+            if unsafe { libc::poll(&mut fd, 1, timeout_ms) } < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(error).with_context(|| "poll on X connection failed");
                 }
-                self.output_set_state =
-                    OutputSetState::query(&self.connection, self.root_window, self.edid_atom)?;
-                return Ok(());
             }
         }
     }
 
+    fn event_fds(&self) -> Vec<std::os::unix::io::RawFd> {
+        use std::os::unix::io::AsRawFd;
+        vec![self.connection.as_raw_fd()]
+    }
+
+    fn refresh(&mut self) -> Result<(), anyhow::Error> {
+        self.output_set_state = OutputSetState::query(
+            &self.connection,
+            self.root_window,
+            self.edid_atom,
+            self.has_monitors(),
+        )?;
+        Ok(())
+    }
+
+    fn can_verify_apply(&self) -> bool {
+        true
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.has_error().is_ok()
+    }
+
+    fn physical_sizes(&self) -> HashMap<layout::OutputId, Vec2d<u32>> {
+        self.output_set_state.physical_sizes()
+    }
+
+    fn output_names(&self) -> HashMap<layout::OutputId, String> {
+        HashMap::from_iter(
+            self.output_set_state
+                .outputs
+                .values()
+                .filter(|state| state.is_connected())
+                .map(|state| (state.id(), state.name.clone())),
+        )
+    }
+
+    fn output_descriptions(&self) -> HashMap<layout::OutputId, String> {
+        HashMap::from_iter(
+            self.output_set_state
+                .outputs
+                .values()
+                .filter(|state| state.is_connected())
+                .filter_map(|state| Some((state.id(), state.edid_info.as_ref()?.to_string()))),
+        )
+    }
+
+    fn preferred_modes(&self) -> HashMap<layout::OutputId, layout::Mode> {
+        let state = &self.output_set_state;
+        HashMap::from_iter(state.outputs.values().filter_map(|output| {
+            // The first num_preferred modes are preferred
+            let preferred = match output.info.num_preferred() {
+                0 => None,
+                _ => output.info.modes().first(),
+            };
+            let mode = state.get_mode(*preferred?)?;
+            match output.is_connected() {
+                true => Some((output.id(), mode.clone())),
+                false => None,
+            }
+        }))
+    }
+
+    fn available_modes(&self) -> HashMap<layout::OutputId, Vec<layout::Mode>> {
+        let state = &self.output_set_state;
+        HashMap::from_iter(
+            state
+                .outputs
+                .values()
+                .filter(|output| output.is_connected())
+                .map(|output| {
+                    let modes = output.info.modes().iter();
+                    let modes = modes.filter_map(|id| state.get_mode(*id).cloned());
+                    (output.id(), Vec::from_iter(modes))
+                }),
+        )
+    }
+
+    fn brightness(&self) -> HashMap<layout::OutputId, u8> {
+        let mut brightness = HashMap::new();
+        for (output, state) in self.output_set_state.outputs.iter() {
+            if !state.is_connected() {
+                continue;
+            }
+            match self.backlight(*output) {
+                Ok(Some((value, min, max))) => {
+                    let percent = backlight::to_percent((value - min) as u32, (max - min) as u32);
+                    brightness.insert(state.id(), percent);
+                }
+                Ok(None) => (),
+                Err(e) => log::debug!("{}: cannot read backlight: {}", state.name, e),
+            }
+        }
+        brightness
+    }
+
+    fn set_brightness(
+        &mut self,
+        output: &layout::OutputId,
+        percent: u8,
+    ) -> Result<bool, anyhow::Error> {
+        let (output, atom) = match (
+            self.output_set_state.connected_output_mapping.get(output),
+            self.backlight_atom,
+        ) {
+            (Some(output), Some(atom)) => (*output, atom),
+            _ => return Ok(false),
+        };
+        let (min, max) = match self.backlight(output)? {
+            Some((_, min, max)) => (min, max),
+            None => return Ok(false),
+        };
+        let value = min + backlight::from_percent(percent, (max - min) as u32) as i32;
+        self.connection
+            .send_and_check_request(&xcb::randr::ChangeOutputProperty {
+                output,
+                property: atom,
+                r#type: xcb::x::ATOM_INTEGER,
+                mode: xcb::x::PropMode::Replace,
+                data: &[value as u32],
+            })
+            .with_context(|| "ChangeOutputProperty(Backlight)")?;
+        Ok(true)
+    }
+
+    fn add_custom_modes(
+        &mut self,
+        layout: &layout::Layout,
+        modes: &[layout::modeline::CustomMode],
+    ) -> Result<(), anyhow::Error> {
+        let state = &self.output_set_state;
+        let mut added = false;
+        for entry in layout.output_entries() {
+            let (requested, output) =
+                match (&entry.state, state.connected_output_mapping.get(&entry.id)) {
+                    (layout::OutputState::Enabled { mode, .. }, Some(output)) => (mode, *output),
+                    _ => continue,
+                };
+            let output_state = &state.outputs[&output];
+            let listed = output_state
+                .info
+                .modes()
+                .iter()
+                .any(|id| state.get_mode(*id) == Some(requested));
+            let custom = modes.iter().find(|custom| {
+                custom.output == output_state.name && &custom.modeline.mode() == requested
+            });
+            let modeline = match (listed, custom) {
+                (false, Some(custom)) => &custom.modeline,
+                _ => continue,
+            };
+            let mode_info = mode_info_from_modeline(modeline);
+            // Modes are shared between outputs : reuse one with the same timings, as names must be unique
+            let existing = state
+                .ressources
+                .modes()
+                .iter()
+                .find(|info| same_timings(info, &mode_info));
+            let mode = match existing {
+                // Safety : id of a mode from the server
+                Some(info) => unsafe { <xcb::randr::Mode as xcb::XidNew>::new(info.id) },
+                None => {
+                    let name = modeline.name();
+                    let cookie = self.connection.send_request(&xcb::randr::CreateMode {
+                        window: self.root_window,
+                        mode_info,
+                        name: name.as_bytes(),
+                    });
+                    self.connection
+                        .wait_for_reply(cookie)
+                        .with_context(|| format!("CreateMode({})", name))?
+                        .mode()
+                }
+            };
+            self.connection
+                .send_and_check_request(&xcb::randr::AddOutputMode { output, mode })
+                .with_context(|| format!("AddOutputMode({})", output_state.name))?;
+            log::info!(
+                "added mode {} ({}) to output {}",
+                requested,
+                modeline,
+                output_state.name
+            );
+            added = true
+        }
+        if added {
+            self.output_set_state = OutputSetState::query(
+                &self.connection,
+                self.root_window,
+                self.edid_atom,
+                self.has_monitors(),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn diagnostics(&self) -> String {
+        use std::fmt::Write;
+        let state = &self.output_set_state;
+        let mut summary = format!(
+            "xcb backend, RandR {}.{}, DPMS {}\nscreen {}x{}\n",
+            self.randr_version.0,
+            self.randr_version.1,
+            self.dpms,
+            state.screen_size.x,
+            state.screen_size.y
+        );
+        let mut outputs = Vec::from_iter(state.outputs.iter());
+        outputs.sort_by_key(|(output, _)| output.resource_id());
+        for (output, output_state) in outputs {
+            let info = &output_state.info;
+            let _ = writeln!(
+                summary,
+                "output {} {:#x} ({}): {:?}, crtc {:#x}, {} modes, {} crtcs{}",
+                output_state.name,
+                output.resource_id(),
+                output_state.id(),
+                info.connection(),
+                info.crtc().resource_id(),
+                info.modes().len(),
+                info.crtcs().len(),
+                match state.primary == Some(*output) {
+                    true => ", primary",
+                    false => "",
+                }
+            );
+        }
+        let mut crtcs = Vec::from_iter(state.crtcs.iter());
+        crtcs.sort_by_key(|(crtc, _)| crtc.resource_id());
+        for (crtc, info) in crtcs {
+            let _ = writeln!(
+                summary,
+                "crtc {:#x}: {}x{}+{}+{} {:?}, mode {:#x}, {} outputs",
+                crtc.resource_id(),
+                info.width(),
+                info.height(),
+                info.x(),
+                info.y(),
+                info.rotation(),
+                info.mode().resource_id(),
+                info.outputs().len()
+            );
+        }
+        for monitor in &state.monitors {
+            let info = &monitor.info;
+            let outputs =
+                Vec::from_iter(info.outputs().iter().map(
+                    |output| match state.outputs.get(output) {
+                        Some(output_state) => output_state.name.clone(),
+                        None => format!("{:#x}", output.resource_id()),
+                    },
+                ));
+            let _ = writeln!(
+                summary,
+                "monitor {}: {}x{}+{}+{}, outputs [{}]{}{}",
+                monitor.name,
+                info.width(),
+                info.height(),
+                info.x(),
+                info.y(),
+                outputs.join(", "),
+                match info.automatic() {
+                    true => ", automatic",
+                    false => "",
+                },
+                match info.primary() {
+                    true => ", primary",
+                    false => "",
+                }
+            );
+        }
+        let _ = writeln!(summary, "gray listed: {:?}", self.failing_outputs);
+        summary
+    }
+
     fn apply_layout(&mut self, layout: &layout::Layout) -> Result<(), anyhow::Error> {
-        // Does not update output_set_state
         match apply_layout(self, layout) {
-            Ok(()) => Ok(()),
-            Err(ApplyLayoutError::Fatal(e)) => Err(e),
+            Ok(()) => (),
+            Err(ApplyLayoutError::Fatal(e)) => return Err(e),
             Err(ApplyLayoutError::Recoverable(msg)) => {
-                log::warn!("could not apply layout: {}", msg);
-                Ok(())
+                log::warn!("could not apply layout: {}", msg)
             }
         }
+        // Crtc allocations and current_layout() must not use the state from before the changes
+        self.refresh()
+    }
+
+    fn apply_requests(&self, layout: &layout::Layout) -> Result<Vec<String>, anyhow::Error> {
+        describe_apply_requests(self, layout).map_err(|e| match e {
+            ApplyLayoutError::Fatal(e) => e,
+            ApplyLayoutError::Recoverable(msg) => anyhow::Error::msg(msg),
+        })
     }
 }
 
-fn check_randr_event(event: xcb::Event) -> bool {
+/// Kind of randr events, [`None`] for other events.
+/// Output changes include connection changes, and are treated as hotplugs.
+fn check_randr_event(event: xcb::Event) -> Option<EventKind> {
     match event {
         xcb::Event::RandR(e) => {
             log::debug!("[event] {:?}", e);
-            true
+            match e {
+                xcb::randr::Event::Notify(notify) => match notify.u() {
+                    xcb::randr::NotifyData::Oc(_) => Some(EventKind::Hotplug),
+                    xcb::randr::NotifyData::Op(_) => Some(EventKind::Property),
+                    _ => Some(EventKind::Crtc),
+                },
+                xcb::randr::Event::ScreenChangeNotify(_) => Some(EventKind::Crtc),
+            }
         }
-        _ => false,
+        _ => None,
     }
 }
 
@@ -142,9 +594,15 @@ struct OutputSetState {
     ressources: xcb::randr::GetScreenResourcesReply,
     mode_by_id: HashMap<u32, layout::Mode>,
     crtcs: HashMap<xcb::randr::Crtc, xcb::randr::GetCrtcInfoReply>,
+    /// Current transform matrix of each crtc.
+    crtc_transforms: HashMap<xcb::randr::Crtc, xcb::randr::Transform>,
+    /// Crtcs with panning enabled.
+    crtc_pannings: HashMap<xcb::randr::Crtc, CrtcPanning>,
     outputs: HashMap<xcb::randr::Output, OutputState>,
     connected_output_mapping: HashMap<layout::OutputId, xcb::randr::Output>,
     primary: Option<xcb::randr::Output>,
+    /// Empty before RandR 1.5.
+    monitors: Vec<MonitorState>,
 }
 
 #[derive(Debug)]
@@ -152,6 +610,24 @@ struct OutputState {
     info: xcb::randr::GetOutputInfoReply,
     name: String,
     edid: Option<Edid>,
+    edid_info: Option<layout::EdidInfo>,
+    /// Millimeters from EDID, which is more precise than the output info (rounded to centimeters by some drivers).
+    edid_physical_size: Option<Vec2d<u32>>,
+}
+
+/// Panning of a crtc in screen coordinates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CrtcPanning {
+    area: Rect,
+    tracking: Rect,
+    border: [i16; 4],
+}
+
+/// Logical monitor covering one or more outputs.
+#[derive(Debug)]
+struct MonitorState {
+    name: String,
+    info: xcb::randr::MonitorInfoBuf,
 }
 
 impl OutputSetState {
@@ -159,6 +635,7 @@ impl OutputSetState {
         conn: &xcb::Connection,
         root_window: xcb::x::Window,
         edid_atom: xcb::x::Atom,
+        query_monitors: bool,
     ) -> Result<OutputSetState, anyhow::Error> {
         // Some replies have an additional status field.
         // These bad status codes never happened in the read state part so treat them as errors.
@@ -183,6 +660,13 @@ impl OutputSetState {
         let screen_size_request = conn.send_request(&xcb::x::GetGeometry {
             drawable: xcb::x::Drawable::Window(root_window),
         });
+        let monitors_request = match query_monitors {
+            true => Some(conn.send_request(&xcb::randr::GetMonitors {
+                window: root_window,
+                get_active: false,
+            })),
+            false => None,
+        };
         let ressources = conn.wait_for_reply(ressources_req)?;
         let config_timestamp = ressources.config_timestamp();
 
@@ -192,13 +676,26 @@ impl OutputSetState {
                 crtc,
                 config_timestamp,
             });
-            (crtc, req)
-        };
-        let process_crtc_reply = |(crtc, request)| -> Result<_, anyhow::Error> {
-            let reply: xcb::randr::GetCrtcInfoReply = conn.wait_for_reply(request)?;
-            check_status(reply.status()).with_context(|| "GetCrtcInfo")?;
-            Ok((crtc, reply))
+            let transform_req = conn.send_request(&xcb::randr::GetCrtcTransform { crtc });
+            let panning_req = conn.send_request(&xcb::randr::GetPanning { crtc });
+            (crtc, req, transform_req, panning_req)
         };
+        let process_crtc_reply =
+            |(crtc, request, transform_req, panning_req)| -> Result<_, anyhow::Error> {
+                let reply: xcb::randr::GetCrtcInfoReply = conn.wait_for_reply(request)?;
+                check_status(reply.status()).with_context(|| "GetCrtcInfo")?;
+                let transform_reply: xcb::randr::GetCrtcTransformReply =
+                    conn.wait_for_reply(transform_req)?;
+                let panning_reply: xcb::randr::GetPanningReply =
+                    conn.wait_for_reply(panning_req)?;
+                check_status(panning_reply.status()).with_context(|| "GetPanning")?;
+                let panning = CrtcPanning::from_reply(&panning_reply).map(|p| (crtc, p));
+                Ok((
+                    (crtc, reply),
+                    (crtc, transform_reply.current_transform()),
+                    panning,
+                ))
+            };
 
         let make_output_requests = |&output| {
             let info_req = conn.send_request(&xcb::randr::GetOutputInfo {
@@ -221,31 +718,47 @@ impl OutputSetState {
             check_status(info.status()).with_context(|| "GetOutputInfo")?;
             let name = String::from_utf8_lossy(info.name()).to_string();
             let edid_reply: xcb::randr::GetOutputPropertyReply = conn.wait_for_reply(edid_req)?;
-            let edid = match edid_reply.r#type() {
+            let (edid, edid_info) = match edid_reply.r#type() {
                 xcb::x::ATOM_INTEGER => match Edid::try_from(edid_reply.data()) {
-                    Ok(edid) => Some(edid),
+                    Ok(edid) => (Some(edid), layout::EdidInfo::parse(edid_reply.data()).ok()),
                     Err(e) => {
                         log::debug!("{}: {}", name, e);
-                        None
+                        (None, None)
                     }
                 },
-                xcb::x::ATOM_NONE => None,
+                xcb::x::ATOM_NONE => (None, None),
                 atom => {
                     // Fail for other atoms, but decode and log them anyway for debugging
                     let atom_name_req = conn.send_request(&xcb::x::GetAtomName { atom });
                     let atom_name_reply = conn.wait_for_reply(atom_name_req)?;
                     let atom_name = atom_name_reply.name();
                     log::debug!("{}: unexpected type for Edid: {}", name, atom_name);
-                    None
+                    (None, None)
                 }
             };
-            let state = OutputState { info, name, edid };
+            let edid_physical_size =
+                edid.and_then(|_| layout::edid_physical_size(edid_reply.data()));
+            let state = OutputState {
+                info,
+                name,
+                edid,
+                edid_info,
+                edid_physical_size,
+            };
             Ok((output, state))
         };
 
         let crtc_requests = Vec::from_iter(ressources.crtcs().iter().map(make_crtc_request));
         let output_requests = Vec::from_iter(ressources.outputs().iter().map(make_output_requests));
-        let crtcs = Result::from_iter(crtc_requests.into_iter().map(process_crtc_reply))?;
+        let mut crtcs = HashMap::new();
+        let mut crtc_transforms = HashMap::new();
+        let mut crtc_pannings = HashMap::new();
+        for request in crtc_requests {
+            let (info, transform, panning) = process_crtc_reply(request)?;
+            crtcs.extend([info]);
+            crtc_transforms.extend([transform]);
+            crtc_pannings.extend(panning);
+        }
         let outputs: HashMap<_, _> =
             Result::from_iter(output_requests.into_iter().map(process_output_replies))?;
 
@@ -254,40 +767,91 @@ impl OutputSetState {
         let primary = filter_xid(primary_reply.output());
         let screen_size_reply = conn.wait_for_reply(screen_size_request)?;
         let screen_size = Vec2d::new(screen_size_reply.width(), screen_size_reply.height());
+        let monitors = match monitors_request {
+            Some(request) => query_monitor_states(conn, conn.wait_for_reply(request)?)?,
+            None => Vec::new(),
+        };
 
         Ok(OutputSetState {
             mode_by_id: HashMap::from_iter(
                 ressources
                     .modes()
-                    .into_iter()
+                    .iter()
                     .map(|m| (m.id, layout::Mode::from(m))),
             ),
             connected_output_mapping: HashMap::from_iter(
                 outputs
                     .iter()
                     .filter(|(_id, state)| state.is_connected())
-                    .map(|(id, state)| (state.id(), id.clone())),
+                    .map(|(id, state)| (state.id(), *id)),
             ),
             screen_size,
             ressources,
             crtcs,
+            crtc_transforms,
+            crtc_pannings,
             outputs,
             primary,
+            monitors,
         })
     }
 
+    /// Physical sizes of connected outputs, from EDID or output info.
+    fn physical_sizes(&self) -> HashMap<layout::OutputId, Vec2d<u32>> {
+        HashMap::from_iter(
+            self.outputs
+                .values()
+                .filter(|state| state.is_connected())
+                .filter_map(|state| {
+                    let info_size = Vec2d::new(state.info.mm_width(), state.info.mm_height());
+                    let size = state.edid_physical_size.unwrap_or(info_size);
+                    match size.x > 0 && size.y > 0 {
+                        true => Some((state.id(), size)),
+                        false => None,
+                    }
+                }),
+        )
+    }
+
     fn get_mode(&self, id: xcb::randr::Mode) -> Option<&layout::Mode> {
         let id = filter_xid(id)?;
         self.mode_by_id.get(&id.resource_id())
     }
 }
 
+/// Copy monitor infos from the reply, with their names decoded from atoms.
+fn query_monitor_states(
+    conn: &xcb::Connection,
+    reply: xcb::randr::GetMonitorsReply,
+) -> Result<Vec<MonitorState>, anyhow::Error> {
+    let requests = Vec::from_iter(reply.monitors().map(|info| {
+        let name_req = conn.send_request(&xcb::x::GetAtomName { atom: info.name() });
+        (info, name_req)
+    }));
+    Result::from_iter(requests.into_iter().map(|(info, name_req)| {
+        let name = conn.wait_for_reply(name_req)?.name().to_string();
+        let info = xcb::randr::MonitorInfoBuf::new(
+            info.name(),
+            info.primary(),
+            info.automatic(),
+            info.x(),
+            info.y(),
+            info.width(),
+            info.height(),
+            info.width_in_millimeters(),
+            info.height_in_millimeters(),
+            info.outputs(),
+        );
+        Ok(MonitorState { name, info })
+    }))
+}
+
 impl OutputState {
     /// Consider an output connected only if really usable : has crtcs, modes.
     fn is_connected(&self) -> bool {
         self.info.connection() == xcb::randr::Connection::Connected
-            && self.info.modes().len() > 0
-            && self.info.crtcs().len() > 0
+            && !self.info.modes().is_empty()
+            && !self.info.crtcs().is_empty()
     }
 
     fn id(&self) -> layout::OutputId {
@@ -305,16 +869,36 @@ fn convert_to_layout(output_states: &OutputSetState) -> layout::LayoutInfo {
     let convert_output_state = |xcb_state: &OutputState| -> layout::OutputState {
         let assigned_crtc = match output_states.crtcs.get(&xcb_state.info.crtc()) {
             Some(crtc) => crtc,
-            None => return layout::OutputState::Disabled,
+            None => return layout::OutputState::Disabled { reason: None },
         };
         let valid_mode = match output_states.get_mode(assigned_crtc.mode()) {
             Some(mode) => mode.clone(),
-            None => return layout::OutputState::Disabled,
+            None => return layout::OutputState::Disabled { reason: None },
+        };
+        let crtc_transform = &output_states.crtc_transforms[&xcb_state.info.crtc()];
+        let scale = match scale_from_crtc_transform(crtc_transform) {
+            Some(scale) => scale,
+            None => {
+                log::debug!(
+                    "{}: unsupported crtc transform {:?}",
+                    xcb_state.name,
+                    crtc_transform
+                );
+                layout::Scale::ONE
+            }
+        };
+        let crtc_position = Vec2d::new(assigned_crtc.x().into(), assigned_crtc.y().into());
+        let (bottom_left, panning) = match output_states.crtc_pannings.get(&xcb_state.info.crtc()) {
+            // The crtc position is the viewport, which moves with the mouse
+            Some(panning) => (panning.area.bottom_left, Some(panning.to_layout())),
+            None => (crtc_position, None),
         };
         layout::OutputState::Enabled {
             mode: valid_mode,
             transform: Transform::from(assigned_crtc.rotation()),
-            bottom_left: Vec2d::new(assigned_crtc.x().into(), assigned_crtc.y().into()),
+            bottom_left,
+            scale,
+            panning,
         }
     };
     let primary_id = output_states
@@ -346,23 +930,132 @@ impl From<anyhow::Error> for ApplyLayoutError {
     }
 }
 
+/// Crtc assignments for `layout`. Outputs of the gray list are left disabled.
+fn layout_crtc_mapping(
+    backend: &XcbBackend,
+    layout: &layout::Layout,
+    now: Instant,
+) -> Result<HashMap<xcb::randr::Crtc, Option<EnabledOutputConfiguration>>, ApplyLayoutError> {
+    let mut enabled_outputs = compute_enabled_output_configs(layout, &backend.output_set_state)?;
+    enabled_outputs.retain(|output, _| {
+        let state = &backend.output_set_state.outputs[output];
+        let grayed = backend.failing_outputs.is_grayed(&state.id(), now);
+        if grayed {
+            log::warn!("output {} keeps failing, left disabled", state.name)
+        }
+        !grayed
+    });
+    share_clone_crtcs(layout, &backend.output_set_state, &mut enabled_outputs);
+    allocate_crtcs(&backend.output_set_state, enabled_outputs)
+}
+
+/// Requests of [`apply_layout`] for `layout`, without sending them.
+/// Output properties, user-defined monitors and DPMS changes made after the crtc changes are not listed.
+fn describe_apply_requests(
+    backend: &XcbBackend,
+    layout: &layout::Layout,
+) -> Result<Vec<String>, ApplyLayoutError> {
+    let state = &backend.output_set_state;
+    let new_screen_size = target_layout_screen_size(layout, state)?;
+    let crtc_mapping = layout_crtc_mapping(backend, layout, Instant::now())?;
+    let output_names = |outputs: &[xcb::randr::Output]| {
+        Vec::from_iter(
+            outputs
+                .iter()
+                .map(|output| state.outputs[output].name.as_str()),
+        )
+        .join(",")
+    };
+    let mut requests = vec![String::from("GrabServer")];
+    for change in crtc_changes(state, &crtc_mapping, &new_screen_size.pixel) {
+        match change {
+            CrtcChange::ResizeScreen(size) => requests.push(format!(
+                "SetScreenSize {}x{} ({}x{}mm)",
+                size.x, size.y, new_screen_size.physical.x, new_screen_size.physical.y
+            )),
+            CrtcChange::SetCrtc(crtc, allocation) => {
+                let crtc_name = format!("crtc 0x{:x}", crtc.resource_id());
+                match allocation {
+                    Some(config) => {
+                        requests.push(format!(
+                            "SetCrtcTransform {}: scale {}",
+                            crtc_name, config.scale
+                        ));
+                        requests.push(format!(
+                            "SetCrtcConfig {}: {} mode {} (0x{:x}) at +{}+{} rotation {:?}",
+                            crtc_name,
+                            output_names(&config.outputs()),
+                            state.mode_by_id[&config.mode.resource_id()],
+                            config.mode.resource_id(),
+                            config.bottom_left.x,
+                            config.bottom_left.y,
+                            config.rotation
+                        ))
+                    }
+                    None => requests.push(format!("SetCrtcConfig {}: disabled", crtc_name)),
+                }
+                let panning = allocation.as_ref().and_then(|c| c.panning.as_ref());
+                if panning.is_some() || state.crtc_pannings.contains_key(&crtc) {
+                    requests.push(format!("SetPanning {}: {:?}", crtc_name, panning))
+                }
+            }
+        }
+    }
+    if let Some(primary) = layout.primary() {
+        let output = &state.outputs[&state.connected_output_mapping[primary]];
+        requests.push(format!("SetOutputPrimary {}", output.name))
+    }
+    requests.push("UngrabServer".into());
+    Ok(requests)
+}
+
 fn apply_layout(backend: &mut XcbBackend, layout: &layout::Layout) -> Result<(), ApplyLayoutError> {
-    let new_screen_size = target_layout_screen_size(layout, &backend.output_set_state);
-    let enabled_outputs = compute_enabled_output_configs(layout, &backend.output_set_state)?;
-    let crtc_mapping = allocate_crtcs(&backend.output_set_state, enabled_outputs)?;
+    let new_screen_size = target_layout_screen_size(layout, &backend.output_set_state)?;
+    let now = Instant::now();
+    let crtc_mapping = layout_crtc_mapping(backend, layout, now)?;
+    let (disables_outputs, enables_outputs) =
+        output_power_changes(&backend.output_set_state, &crtc_mapping);
 
     // Grab server while modifying state, to make the crtc changes atomic for other listeners.
     // Notifications are not sent to other listeners while grabbed.
     backend.connection.send_request(&xcb::x::GrabServer {});
-    match try_apply_crtc_configuration(backend, &crtc_mapping, &new_screen_size) {
-        Ok(()) => (),
-        Err(ApplyLayoutError::Recoverable(msg)) => {
-            log::warn!("could not apply layout ; reverting: {}", msg);
-            todo!("revert")
+    let mut crtc_outcomes = Vec::new();
+    let result =
+        try_apply_crtc_configuration(backend, &crtc_mapping, &new_screen_size, &mut crtc_outcomes);
+    for (output, success) in crtc_outcomes {
+        let state = &backend.output_set_state.outputs[&output];
+        if success {
+            backend.failing_outputs.record_success(&state.id())
+        } else if backend.failing_outputs.record_failure(state.id(), now) {
+            log::warn!(
+                "output {} failed {} consecutive times: not enabled for {:?}",
+                state.name,
+                GRAY_LIST_MAX_FAILURES,
+                backend.failing_outputs.cooldown()
+            )
         }
-        Err(ApplyLayoutError::Fatal(_e)) => {
-            todo!("try revert ? abort ?")
+    }
+    if let Err(error) = result {
+        // Restore the state captured before changes, so that a failure does not leave a half configured screen.
+        // A fatal error is from the connection itself, so the revert is attempted but likely fails too.
+        let revert = revert_crtc_configuration(backend, &new_screen_size);
+        if let Err(e) = &revert {
+            log::error!("cannot revert to previous layout: {:#}", e)
         }
+        let ungrab = backend
+            .connection
+            .send_and_check_request(&xcb::x::UngrabServer {})
+            .with_context(|| "UngrabServer");
+        return Err(match (error, revert) {
+            (ApplyLayoutError::Recoverable(msg), Ok(())) => {
+                ungrab?;
+                ApplyLayoutError::Recoverable(format!("{} ; previous layout restored", msg))
+            }
+            (ApplyLayoutError::Recoverable(msg), Err(e)) => {
+                ApplyLayoutError::Fatal(e.context(msg).context("revert failed"))
+            }
+            (ApplyLayoutError::Fatal(e), _) => ApplyLayoutError::Fatal(e),
+        });
     }
 
     if let Some(primary) = layout.primary() {
@@ -373,11 +1066,133 @@ fn apply_layout(backend: &mut XcbBackend, layout: &layout::Layout) -> Result<(),
                 output: backend.output_set_state.connected_output_mapping[primary],
             });
     }
+    for property in layout.backend_properties() {
+        if let Err(e) = set_output_property(backend, &property) {
+            log::warn!("cannot set output property {}: {:#}", property.name, e)
+        }
+    }
+    if let Err(e) = update_user_monitors(backend, layout) {
+        log::warn!("cannot update monitors: {:#}", e)
+    }
 
     backend
         .connection
         .send_and_check_request(&xcb::x::UngrabServer {})
         .with_context(|| "UngrabServer")?;
+
+    if backend.dpms && (disables_outputs || enables_outputs) {
+        if let Err(e) = update_dpms(backend, disables_outputs) {
+            log::warn!("cannot update DPMS state: {:#}", e)
+        }
+    }
+    Ok(())
+}
+
+/// Whether the crtc mapping disables outputs that are currently enabled, and enables disabled ones.
+fn output_power_changes(
+    state: &OutputSetState,
+    crtc_mapping: &HashMap<xcb::randr::Crtc, Option<EnabledOutputConfiguration>>,
+) -> (bool, bool) {
+    let enabled: HashSet<xcb::randr::Output> = crtc_mapping
+        .values()
+        .flatten()
+        .flat_map(|config| config.outputs())
+        .collect();
+    let was_enabled = |output: &xcb::randr::Output| !state.outputs[output].info.crtc().is_none();
+    let disables = state
+        .outputs
+        .keys()
+        .any(|output| was_enabled(output) && !enabled.contains(output));
+    let enables = enabled.iter().any(|output| !was_enabled(output));
+    (disables, enables)
+}
+
+/// DPMS is screen wide in X : force it off then on after disabling outputs.
+/// Drivers only wake up outputs driven by a crtc, so monitors of disabled outputs stay asleep.
+/// Forcing it on also wakes newly enabled outputs if the screen was asleep.
+fn update_dpms(backend: &XcbBackend, disables_outputs: bool) -> Result<(), anyhow::Error> {
+    if disables_outputs {
+        backend
+            .connection
+            .send_and_check_request(&xcb::dpms::ForceLevel {
+                power_level: xcb::dpms::DpmsMode::Off,
+            })
+            .with_context(|| "DPMS ForceLevel Off")?;
+    }
+    backend
+        .connection
+        .send_and_check_request(&xcb::dpms::ForceLevel {
+            power_level: xcb::dpms::DpmsMode::On,
+        })
+        .with_context(|| "DPMS ForceLevel On")?;
+    Ok(())
+}
+
+/// Move user-defined monitors to cover the new rects of their outputs, or delete them if all their outputs are disabled.
+/// Monitors made by the server are updated by the server itself.
+fn update_user_monitors(
+    backend: &XcbBackend,
+    layout: &layout::Layout,
+) -> Result<(), anyhow::Error> {
+    let state = &backend.output_set_state;
+    let rect_of = |output: &xcb::randr::Output| {
+        let id = state.outputs.get(output)?.id();
+        let entry = layout.output_entries().iter().find(|e| e.id == id)?;
+        entry.state.rect()
+    };
+    for monitor in state.monitors.iter().filter(|m| !m.info.automatic()) {
+        let info = &monitor.info;
+        let rects = Vec::from_iter(info.outputs().iter().filter_map(rect_of));
+        let bounds = match rects.iter().cloned().reduce(|a, b| a.union_bounds(&b)) {
+            Some(bounds) => bounds,
+            None => {
+                log::info!("monitor {}: no enabled output, deleted", monitor.name);
+                backend
+                    .connection
+                    .send_and_check_request(&xcb::randr::DeleteMonitor {
+                        window: backend.root_window,
+                        name: info.name(),
+                    })
+                    .with_context(|| format!("DeleteMonitor({})", monitor.name))?;
+                continue;
+            }
+        };
+        if rects.len() < info.outputs().len() {
+            log::warn!("monitor {}: some outputs are disabled", monitor.name)
+        }
+        let size = bounds.size;
+        let position = bounds.bottom_left.try_map(i16::try_from)?;
+        let pixel_size = size.try_map(u16::try_from)?;
+        // Keep the physical density of the monitor
+        let millimeters = |mm: u32, old: u16, new: u32| match old {
+            0 => mm,
+            old => div_round(mm * new, u32::from(old)),
+        };
+        let primary = layout.primary().is_some_and(|primary| {
+            info.outputs().iter().any(|output| {
+                state.outputs.get(output).map(OutputState::id).as_ref() == Some(primary)
+            })
+        });
+        let new_info = xcb::randr::MonitorInfoBuf::new(
+            info.name(),
+            primary,
+            false,
+            position.x,
+            position.y,
+            pixel_size.x,
+            pixel_size.y,
+            millimeters(info.width_in_millimeters(), info.width(), size.x),
+            millimeters(info.height_in_millimeters(), info.height(), size.y),
+            info.outputs(),
+        );
+        backend
+            .connection
+            .send_and_check_request(&xcb::randr::SetMonitor {
+                window: backend.root_window,
+                monitorinfo: &new_info,
+            })
+            .with_context(|| format!("SetMonitor({})", monitor.name))?;
+    }
     Ok(())
 }
 
@@ -388,49 +1203,72 @@ struct XcbScreenSize {
 }
 
 /// SetScreenSize requires a physical size for legacy reasons.
-/// This physical size is meaningless for multiple outputs in a screen (since randr 1.2).
-/// A fake dpi value is used to fill these required useless values from screen pixel size.
-fn target_layout_screen_size(layout: &layout::Layout, state: &OutputSetState) -> XcbScreenSize {
-    let pixel = layout
-        .bounding_rect_size()
-        .map(|i| u16::try_from(i).expect("size integer overflows u16 xcb limit"));
-
-    let fake_dpi = {
-        // Compute fake dpi as an average of outputs dpi, weighted by pixel area
-        let mut dpi_weighted_sum: f64 = 0.;
-        let mut dpi_weight_sum: f64 = 0.;
-        for entry in layout.output_entries() {
-            if let layout::OutputState::Enabled { mode, .. } = &entry.state {
-                let output = &state.outputs[&state.connected_output_mapping[&entry.id]];
-                if output.info.mm_width() > 0 && output.info.mm_height() > 0 {
-                    let dpmm_x = f64::from(mode.size.x) / f64::from(output.info.mm_width());
-                    let dpmm_y = f64::from(mode.size.y) / f64::from(output.info.mm_height());
-                    let weight = f64::from(output.info.mm_width() * output.info.mm_height());
-                    dpi_weighted_sum += weight * (MM_PER_INCH * 0.5) * (dpmm_x + dpmm_y);
-                    dpi_weight_sum += weight;
-                }
-            }
-        }
-        if dpi_weight_sum > 0. {
-            dpi_weighted_sum / dpi_weight_sum
-        } else {
-            96.
-        }
-    };
-    log::debug!("using fake DPI of {}", fake_dpi);
-    let physical = pixel
-        .clone()
-        .map(|i| (f64::from(i) * MM_PER_INCH / fake_dpi) as u32);
+/// It is meaningless for multiple outputs in a screen (since randr 1.2), but applications use it to compute the DPI.
+/// It is derived from the area-weighted DPI of outputs (EDID sizes), or 96 if unknown.
+fn target_layout_screen_size(
+    layout: &layout::Layout,
+    state: &OutputSetState,
+) -> Result<XcbScreenSize, ApplyLayoutError> {
+    let pixel = to_xcb_integers(layout.bounding_rect_size(), "screen size")?;
+
+    let dpi = layout::physical::screen_dpi(layout, &state.physical_sizes()).unwrap_or(96.);
+    log::debug!("using screen DPI of {}", dpi);
+    let physical = pixel.map(|i| (f64::from(i) * MM_PER_INCH / dpi) as u32);
 
-    XcbScreenSize { pixel, physical }
+    Ok(XcbScreenSize { pixel, physical })
+}
+
+/// Layout coordinates or sizes as the 16 bit integers of the X protocol.
+/// Layouts exceeding them cannot be applied, but must not bring the daemon down.
+fn to_xcb_integers<T, U>(v: Vec2d<T>, what: &str) -> Result<Vec2d<U>, ApplyLayoutError>
+where
+    T: Copy + std::fmt::Display,
+    U: TryFrom<T>,
+{
+    v.try_map(U::try_from).map_err(|_| {
+        ApplyLayoutError::Recoverable(format!(
+            "{} {}x{} exceeds the 16 bit limits of X",
+            what, v.x, v.y
+        ))
+    })
 }
 
 #[derive(Debug, Clone)]
 struct EnabledOutputConfiguration {
     output: xcb::randr::Output,
+    /// Clones of `output` driven by the same crtc.
+    clones: Vec<xcb::randr::Output>,
     bottom_left: Vec2d<i16>,
     mode: xcb::randr::Mode,
     rotation: xcb::randr::Rotation,
+    scale: layout::Scale,
+    panning: Option<CrtcPanning>,
+}
+
+impl EnabledOutputConfiguration {
+    /// Outputs driven by the crtc.
+    fn outputs(&self) -> Vec<xcb::randr::Output> {
+        let mut outputs = vec![self.output];
+        outputs.extend_from_slice(&self.clones);
+        outputs
+    }
+
+    /// Same crtc configuration, so that both outputs could be driven by one crtc.
+    fn same_crtc_config(&self, other: &Self) -> bool {
+        (
+            self.bottom_left,
+            self.mode,
+            self.rotation,
+            self.scale,
+            &self.panning,
+        ) == (
+            other.bottom_left,
+            other.mode,
+            other.rotation,
+            other.scale,
+            &other.panning,
+        )
+    }
 }
 
 /// Extract the list of enabled outputs, convert layout config to xcb structs
@@ -439,34 +1277,54 @@ fn compute_enabled_output_configs(
     state: &OutputSetState,
 ) -> Result<HashMap<xcb::randr::Output, EnabledOutputConfiguration>, ApplyLayoutError> {
     let scan_mode_list = |list: &[xcb::randr::Mode], requested_mode: &layout::Mode| {
-        list.into_iter()
-            .find(|id| requested_mode == &state.mode_by_id[&id.resource_id()])
-            .cloned()
+        list.iter()
+            .filter_map(|id| {
+                let distance =
+                    requested_mode.match_distance(&state.mode_by_id[&id.resource_id()])?;
+                Some((distance, *id))
+            })
+            .min_by_key(|(distance, _)| *distance)
     };
     layout
         .output_entries()
-        .into_iter()
+        .iter()
         .filter_map(|entry| match &entry.state {
-            layout::OutputState::Disabled => None,
+            layout::OutputState::Disabled { .. } => None,
             layout::OutputState::Enabled {
                 mode: requested_mode,
                 transform,
                 bottom_left,
+                scale,
+                panning,
             } => {
                 let output_id = &state.connected_output_mapping[&entry.id];
                 let output = &state.outputs[output_id];
                 let entry = match scan_mode_list(output.info.modes(), requested_mode) {
-                    Some(mode_id) => Ok((
-                        output_id.clone(),
-                        EnabledOutputConfiguration {
-                            output: output_id.clone(),
-                            bottom_left: bottom_left
-                                .clone()
-                                .map(|i| i.try_into().expect("bottom_left coordinate overflow")),
-                            mode: mode_id,
-                            rotation: transform.into(),
-                        },
-                    )),
+                    Some((distance, mode_id)) => {
+                        if distance > 0 {
+                            log::info!(
+                                "output {}: no mode {}, using {}",
+                                output.name,
+                                requested_mode,
+                                state.mode_by_id[&mode_id.resource_id()]
+                            )
+                        }
+                        let position = format!("output {} position", output.name);
+                        to_xcb_integers(*bottom_left, &position).map(|xcb_bottom_left| {
+                            let config = EnabledOutputConfiguration {
+                                output: *output_id,
+                                clones: Vec::new(),
+                                bottom_left: xcb_bottom_left,
+                                mode: mode_id,
+                                rotation: transform.into(),
+                                scale: *scale,
+                                panning: panning
+                                    .as_ref()
+                                    .map(|panning| CrtcPanning::from_layout(*bottom_left, panning)),
+                            };
+                            (*output_id, config)
+                        })
+                    }
                     None => Err(ApplyLayoutError::Recoverable(format!(
                         "no mode matching {} found in output {}",
                         requested_mode, output.name
@@ -478,24 +1336,64 @@ fn compute_enabled_output_configs(
         .collect()
 }
 
+/// Drive each clone group of `layout` with a single crtc when the hardware allows it, as crtcs are scarce.
+/// Clones with different modes or transforms, or that cannot be clones in hardware, get their own crtcs at the same position.
+fn share_clone_crtcs(
+    layout: &layout::Layout,
+    state: &OutputSetState,
+    enabled_outputs: &mut HashMap<xcb::randr::Output, EnabledOutputConfiguration>,
+) {
+    for group in layout.clone_groups() {
+        let outputs = Vec::from_iter(
+            group
+                .iter()
+                .filter_map(|id| state.connected_output_mapping.get(*id))
+                .filter(|output| enabled_outputs.contains_key(*output))
+                .copied(),
+        );
+        let (lead, clones) = match outputs.split_first() {
+            Some((lead, clones)) if !clones.is_empty() => (*lead, clones),
+            _ => continue,
+        };
+        let clonable = clones.iter().all(|clone| {
+            let same_config = enabled_outputs[&lead].same_crtc_config(&enabled_outputs[clone]);
+            same_config && state.outputs[&lead].info.clones().contains(clone)
+        });
+        let fits_a_crtc = state.crtcs.values().any(|crtc| {
+            outputs
+                .iter()
+                .all(|output| crtc.possible().contains(output))
+        });
+        if clonable && fits_a_crtc {
+            for clone in clones {
+                enabled_outputs.remove(clone);
+            }
+            enabled_outputs.get_mut(&lead).unwrap().clones = clones.to_vec()
+        }
+    }
+}
+
 fn allocate_crtcs(
     state: &OutputSetState,
     mut enabled_outputs: HashMap<xcb::randr::Output, EnabledOutputConfiguration>,
 ) -> Result<HashMap<xcb::randr::Crtc, Option<EnabledOutputConfiguration>>, ApplyLayoutError> {
     let can_allocate_crtc = |crtc: &xcb::randr::Crtc, config: &EnabledOutputConfiguration| {
         let crtc_info = &state.crtcs[crtc];
-        let can_fit_output = crtc_info.possible().contains(&config.output);
+        let can_fit_output = config
+            .outputs()
+            .iter()
+            .all(|output| crtc_info.possible().contains(output));
         let can_fit_transform = crtc_info.rotations().contains(config.rotation);
         can_fit_output && can_fit_transform
     };
-    let mut output_by_crtc = HashMap::from_iter(state.crtcs.keys().map(|k| (k.clone(), None)));
+    let mut output_by_crtc = HashMap::from_iter(state.crtcs.keys().map(|k| (*k, None)));
 
     // For already enabled outputs, see if we can keep the same crtc.
     // This avoids "resetting" the screen like xrandr does.
     for (output, state) in state.outputs.iter() {
         if let (Some(crtc), Entry::Occupied(config)) = (
             filter_xid(state.info.crtc()),
-            enabled_outputs.entry(output.clone()),
+            enabled_outputs.entry(*output),
         ) {
             let allocation = output_by_crtc.get_mut(&crtc).unwrap();
             if allocation.is_none() && can_allocate_crtc(&crtc, config.get()) {
@@ -503,14 +1401,19 @@ fn allocate_crtcs(
             }
         }
     }
-    // Find Crtc for all remaining requested outputs
-    for (output, config) in enabled_outputs.into_iter() {
-        let allocated_entry = output_by_crtc
-            .iter_mut()
-            .find(|(crtc, allocation)| allocation.is_none() && can_allocate_crtc(crtc, &config));
-        match allocated_entry {
-            Some((_crtc, allocation)) => {
-                *allocation = Some(config);
+    // Find Crtc for all remaining requested outputs.
+    // In id order, so that a dry run describes the allocation of the apply.
+    let mut remaining = Vec::from_iter(enabled_outputs);
+    remaining.sort_by_key(|(output, _)| output.resource_id());
+    let mut crtcs = Vec::from_iter(state.crtcs.keys().copied());
+    crtcs.sort_by_key(|crtc| crtc.resource_id());
+    for (output, config) in remaining {
+        let allocated_crtc = crtcs
+            .iter()
+            .find(|crtc| output_by_crtc[*crtc].is_none() && can_allocate_crtc(crtc, &config));
+        match allocated_crtc {
+            Some(crtc) => {
+                *output_by_crtc.get_mut(crtc).unwrap() = Some(config);
             }
             None => {
                 return Err(ApplyLayoutError::Recoverable(format!(
@@ -524,10 +1427,12 @@ fn allocate_crtcs(
 }
 
 // outer Error is fatal (xcb connection level), inner is set_crtc
+// Success status of SetCrtcConfig for enabled outputs is pushed to `outcomes`.
 fn try_apply_crtc_configuration(
     backend: &XcbBackend,
     crtc_mapping: &HashMap<xcb::randr::Crtc, Option<EnabledOutputConfiguration>>,
     new_screen_size: &XcbScreenSize,
+    outcomes: &mut Vec<(xcb::randr::Output, bool)>,
 ) -> Result<(), ApplyLayoutError> {
     let config_timestamp = backend.output_set_state.ressources.config_timestamp();
     let mut timestamp = backend.output_set_state.ressources.timestamp();
@@ -547,19 +1452,24 @@ fn try_apply_crtc_configuration(
     let mut set_crtc = |crtc: &xcb::randr::Crtc,
                         allocation: &Option<EnabledOutputConfiguration>|
      -> Result<(), ApplyLayoutError> {
+        if let Some(config) = allocation {
+            // The pending transform is used by the next SetCrtcConfig
+            set_crtc_transform(backend, *crtc, &crtc_transform_from_scale(config.scale))?;
+        }
+        let outputs = allocation.as_ref().map(|config| config.outputs());
         let request = match allocation {
             Some(config) => xcb::randr::SetCrtcConfig {
-                crtc: crtc.clone(),
+                crtc: *crtc,
                 timestamp,
                 config_timestamp,
                 x: config.bottom_left.x,
                 y: config.bottom_left.y,
                 mode: config.mode,
                 rotation: config.rotation,
-                outputs: std::slice::from_ref(&config.output),
+                outputs: outputs.as_deref().unwrap_or_default(),
             },
             None => xcb::randr::SetCrtcConfig {
-                crtc: crtc.clone(),
+                crtc: *crtc,
                 timestamp,
                 config_timestamp,
                 x: 0,
@@ -573,7 +1483,13 @@ fn try_apply_crtc_configuration(
         let reply = backend
             .connection
             .wait_for_reply(cookie)
-            .with_context(|| format!("SetCrtcConfig({:?})", request))?;
+            .with_context(|| format!("SetCrtcConfig({:?})", request));
+        let status = reply.as_ref().ok().map(|reply| reply.status());
+        outcomes.extend(set_crtc_outcomes(
+            outputs.as_deref().unwrap_or_default(),
+            status,
+        ));
+        let reply = reply?;
 
         use xcb::randr::SetConfig;
         let fail_msg = match reply.status() {
@@ -581,6 +1497,11 @@ fn try_apply_crtc_configuration(
                 // Update to newest timestamp representing our change.
                 // This is required by following set_crtc, hence the sequential wait_for_reply().
                 timestamp = reply.timestamp();
+                let panning = allocation.as_ref().and_then(|c| c.panning.as_ref());
+                let had_panning = backend.output_set_state.crtc_pannings.contains_key(crtc);
+                if panning.is_some() || had_panning {
+                    timestamp = set_crtc_panning(backend, *crtc, timestamp, panning)?;
+                }
                 return Ok(());
             }
             SetConfig::InvalidTime => "invalid timestamp",
@@ -593,47 +1514,428 @@ fn try_apply_crtc_configuration(
         )))
     };
 
+    let changes = crtc_changes(
+        &backend.output_set_state,
+        crtc_mapping,
+        &new_screen_size.pixel,
+    );
+    for change in changes {
+        match change {
+            CrtcChange::ResizeScreen(size) => resize_screen(&size)?,
+            CrtcChange::SetCrtc(crtc, allocation) => set_crtc(&crtc, allocation)?,
+        }
+    }
+    Ok(())
+}
+
+/// Request changing the crtc configuration.
+enum CrtcChange<'a> {
+    ResizeScreen(Vec2d<u16>),
+    SetCrtc(xcb::randr::Crtc, &'a Option<EnabledOutputConfiguration>),
+}
+
+/// Requests to go from the current crtc configuration to `crtc_mapping`, in the order they must be sent.
+fn crtc_changes<'a>(
+    state: &OutputSetState,
+    crtc_mapping: &'a HashMap<xcb::randr::Crtc, Option<EnabledOutputConfiguration>>,
+    new_screen_size: &Vec2d<u16>,
+) -> Vec<CrtcChange<'a>> {
     // The overall randr state need to be valid between each SetCrtc call.
     // Resize screen to the maximum needed for all operations.
-    let temporary_screen_size = Vec2d::cwise_max(
-        backend.output_set_state.screen_size.clone(),
-        new_screen_size.pixel.clone(),
-    );
-    resize_screen(&temporary_screen_size)?;
+    let temporary_screen_size = Vec2d::cwise_max(state.screen_size, *new_screen_size);
+    let mut changes = vec![CrtcChange::ResizeScreen(temporary_screen_size)];
 
     // Crtc changes are sequential, each intermediate state must be valid.
     // Having an outputs mapped to 2 crtcs would be an error.
     // So do crtcs changes in a very specific order to prevent this.
+    let mut crtcs = Vec::from_iter(crtc_mapping.iter());
+    crtcs.sort_by_key(|(crtc, _)| crtc.resource_id());
+    let current_outputs = |crtc: &xcb::randr::Crtc| state.crtcs[crtc].outputs().len();
 
     // Disable newly unused crtcs
-    for (crtc, allocation) in crtc_mapping.iter() {
-        if allocation.is_none() && backend.output_set_state.crtcs[&crtc].outputs().len() > 0 {
-            set_crtc(crtc, &None)?;
+    for (crtc, allocation) in crtcs.iter() {
+        if allocation.is_none() && current_outputs(crtc) > 0 {
+            changes.push(CrtcChange::SetCrtc(**crtc, allocation))
         }
     }
     // Reassign cloned crtcs first to detach them from many outputs
-    for (crtc, allocation) in crtc_mapping.iter() {
-        if allocation.is_some() && backend.output_set_state.crtcs[&crtc].outputs().len() > 1 {
-            set_crtc(crtc, allocation)?;
+    for (crtc, allocation) in crtcs.iter() {
+        if allocation.is_some() && current_outputs(crtc) > 1 {
+            changes.push(CrtcChange::SetCrtc(**crtc, allocation))
         }
     }
     // Set remaning crtcs
-    for (crtc, allocation) in crtc_mapping.iter() {
-        if allocation.is_some() && backend.output_set_state.crtcs[&crtc].outputs().len() <= 1 {
-            set_crtc(crtc, allocation)?;
+    for (crtc, allocation) in crtcs.iter() {
+        if allocation.is_some() && current_outputs(crtc) <= 1 {
+            changes.push(CrtcChange::SetCrtc(**crtc, allocation))
         }
     }
     // Left untouched : crtc disabled (== with no outputs) before & after.
 
     // Resize to final dimensions
-    if temporary_screen_size != new_screen_size.pixel {
-        resize_screen(&new_screen_size.pixel)?;
+    if temporary_screen_size != *new_screen_size {
+        changes.push(CrtcChange::ResizeScreen(*new_screen_size))
+    }
+    changes
+}
+
+/// Re-apply the crtc assignments and screen size of `backend.output_set_state`, after a partial failure.
+/// `new_screen_size` is the target of the failed apply, which bounds the current screen size.
+fn revert_crtc_configuration(
+    backend: &XcbBackend,
+    new_screen_size: &XcbScreenSize,
+) -> Result<(), anyhow::Error> {
+    let state = &backend.output_set_state;
+    let config_timestamp = state.ressources.config_timestamp();
+    let previous_physical_size =
+        match target_layout_screen_size(&convert_to_layout(state).layout, state) {
+            Ok(size) => size.physical,
+            Err(ApplyLayoutError::Recoverable(msg)) => return Err(anyhow::Error::msg(msg)),
+            Err(ApplyLayoutError::Fatal(e)) => return Err(e),
+        };
+
+    let resize_screen = |size: &Vec2d<u16>| {
+        backend
+            .connection
+            .send_and_check_request(&xcb::randr::SetScreenSize {
+                window: backend.root_window,
+                width: size.x,
+                height: size.y,
+                mm_width: previous_physical_size.x,
+                mm_height: previous_physical_size.y,
+            })
+            .with_context(|| format!("SetScreenSize({:?})", size))
+    };
+    // Timestamps of the failed apply are not tracked : use the current time, which is always accepted.
+    let set_crtc = |crtc: &xcb::randr::Crtc, info: Option<&xcb::randr::GetCrtcInfoReply>| {
+        if info.is_some() {
+            set_crtc_transform(backend, *crtc, &state.crtc_transforms[crtc])?;
+        }
+        let request = match info {
+            Some(info) => xcb::randr::SetCrtcConfig {
+                crtc: *crtc,
+                timestamp: xcb::x::CURRENT_TIME,
+                config_timestamp,
+                x: info.x(),
+                y: info.y(),
+                mode: info.mode(),
+                rotation: info.rotation(),
+                outputs: info.outputs(),
+            },
+            None => xcb::randr::SetCrtcConfig {
+                crtc: *crtc,
+                timestamp: xcb::x::CURRENT_TIME,
+                config_timestamp,
+                x: 0,
+                y: 0,
+                mode: Xid::none(),
+                rotation: xcb::randr::Rotation::ROTATE_0,
+                outputs: &[],
+            },
+        };
+        let cookie = backend.connection.send_request(&request);
+        let reply = backend
+            .connection
+            .wait_for_reply(cookie)
+            .with_context(|| format!("SetCrtcConfig({:?})", request))?;
+        match reply.status() {
+            xcb::randr::SetConfig::Success => Ok(()),
+            status => Err(anyhow::Error::msg(format!(
+                "SetCrtcConfig({:?}): {:?}",
+                request, status
+            ))),
+        }
+    };
+
+    // Same ordering constraints as the apply : valid screen size, and no output on 2 crtcs.
+    // The screen may still have the temporary size of the apply, which contains both sizes.
+    let temporary_screen_size = Vec2d::cwise_max(state.screen_size, new_screen_size.pixel);
+    resize_screen(&temporary_screen_size)?;
+    // Disable all crtcs first, as their current outputs are unknown after the failure
+    for crtc in state.crtcs.keys() {
+        set_crtc(crtc, None)?;
+    }
+    for (crtc, info) in state.crtcs.iter() {
+        if !info.outputs().is_empty() {
+            set_crtc(crtc, Some(info))?;
+        }
+    }
+    for (crtc, panning) in state.crtc_pannings.iter() {
+        match set_crtc_panning(backend, *crtc, xcb::x::CURRENT_TIME, Some(panning)) {
+            Ok(_) => (),
+            Err(ApplyLayoutError::Recoverable(msg)) => return Err(anyhow::Error::msg(msg)),
+            Err(ApplyLayoutError::Fatal(e)) => return Err(e),
+        }
+    }
+    if temporary_screen_size != state.screen_size {
+        resize_screen(&state.screen_size)?;
     }
     Ok(())
 }
 
+/// Set an existing output property, converting the value to its current type (like `xrandr --set`).
+fn set_output_property(
+    backend: &XcbBackend,
+    property: &layout::OutputProperty,
+) -> Result<(), anyhow::Error> {
+    let conn = &backend.connection;
+    let output = match backend
+        .output_set_state
+        .connected_output_mapping
+        .get(&property.output)
+    {
+        Some(output) => *output,
+        None => return Ok(()),
+    };
+    let intern = |name: &str, only_if_exists: bool| -> Result<xcb::x::Atom, anyhow::Error> {
+        let cookie = conn.send_request(&xcb::x::InternAtom {
+            only_if_exists,
+            name: name.as_bytes(),
+        });
+        Ok(conn.wait_for_reply(cookie)?.atom())
+    };
+    let atom = match intern(&property.name, true)? {
+        xcb::x::ATOM_NONE => return Err(anyhow::Error::msg("unknown property")),
+        atom => atom,
+    };
+    let cookie = conn.send_request(&xcb::randr::GetOutputProperty {
+        output,
+        property: atom,
+        r#type: xcb::x::GETPROPERTYTYPE_ANY,
+        long_offset: 0,
+        long_length: 1,
+        delete: false,
+        pending: false,
+    });
+    let current = conn.wait_for_reply(cookie)?;
+    let integer = || -> Result<i64, anyhow::Error> {
+        property
+            .value
+            .parse()
+            .with_context(|| format!("invalid integer: {}", property.value))
+    };
+    let r#type = current.r#type();
+    let request = match (r#type, current.format()) {
+        (xcb::x::ATOM_NONE, _) => return Err(anyhow::Error::msg("not defined for this output")),
+        (xcb::x::ATOM_ATOM, 32) => {
+            let value = intern(&property.value, false)?;
+            conn.send_and_check_request(&xcb::randr::ChangeOutputProperty {
+                output,
+                property: atom,
+                r#type,
+                mode: xcb::x::PropMode::Replace,
+                data: &[value],
+            })
+        }
+        (xcb::x::ATOM_INTEGER | xcb::x::ATOM_CARDINAL, 32) => {
+            conn.send_and_check_request(&xcb::randr::ChangeOutputProperty {
+                output,
+                property: atom,
+                r#type,
+                mode: xcb::x::PropMode::Replace,
+                data: &[integer()? as u32],
+            })
+        }
+        (xcb::x::ATOM_INTEGER | xcb::x::ATOM_CARDINAL, 16) => {
+            conn.send_and_check_request(&xcb::randr::ChangeOutputProperty {
+                output,
+                property: atom,
+                r#type,
+                mode: xcb::x::PropMode::Replace,
+                data: &[integer()? as u16],
+            })
+        }
+        (xcb::x::ATOM_INTEGER | xcb::x::ATOM_CARDINAL | xcb::x::ATOM_STRING, 8) => {
+            let data = match r#type {
+                xcb::x::ATOM_STRING => property.value.as_bytes().to_vec(),
+                _ => vec![integer()? as u8],
+            };
+            conn.send_and_check_request(&xcb::randr::ChangeOutputProperty {
+                output,
+                property: atom,
+                r#type,
+                mode: xcb::x::PropMode::Replace,
+                data: &data,
+            })
+        }
+        (_, format) => {
+            return Err(anyhow::Error::msg(format!(
+                "unsupported property type (format {})",
+                format
+            )))
+        }
+    };
+    request.with_context(|| format!("ChangeOutputProperty({})", property.value))
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 
+impl CrtcPanning {
+    /// [`None`] if panning is disabled (empty area).
+    fn from_reply(reply: &xcb::randr::GetPanningReply) -> Option<CrtcPanning> {
+        let rect = |left: u16, top: u16, width: u16, height: u16| Rect {
+            bottom_left: Vec2d::new(left.into(), top.into()),
+            size: Vec2d::new(width.into(), height.into()),
+        };
+        let area = rect(reply.left(), reply.top(), reply.width(), reply.height());
+        if area.size.x == 0 || area.size.y == 0 {
+            return None;
+        }
+        let tracking = match (reply.track_width(), reply.track_height()) {
+            (0, _) | (_, 0) => area.clone(),
+            (width, height) => rect(reply.track_left(), reply.track_top(), width, height),
+        };
+        let border = [
+            reply.border_left(),
+            reply.border_top(),
+            reply.border_right(),
+            reply.border_bottom(),
+        ];
+        Some(CrtcPanning {
+            area,
+            tracking,
+            border,
+        })
+    }
+
+    fn from_layout(bottom_left: Vec2d<i32>, panning: &layout::Panning) -> CrtcPanning {
+        let area = Rect {
+            bottom_left,
+            size: panning.size,
+        };
+        let tracking = match &panning.tracking {
+            Some(tracking) => tracking.offset(bottom_left),
+            None => area.clone(),
+        };
+        CrtcPanning {
+            area,
+            tracking,
+            border: panning.border,
+        }
+    }
+
+    fn to_layout(&self) -> layout::Panning {
+        let tracking = match self.tracking == self.area {
+            true => None,
+            false => Some(self.tracking.offset(Vec2d::new(
+                -self.area.bottom_left.x,
+                -self.area.bottom_left.y,
+            ))),
+        };
+        layout::Panning {
+            size: self.area.size,
+            tracking,
+            border: self.border,
+        }
+    }
+}
+
+/// Set or disable (with [`None`]) the panning of a crtc. Returns the new timestamp.
+fn set_crtc_panning(
+    backend: &XcbBackend,
+    crtc: xcb::randr::Crtc,
+    timestamp: xcb::x::Timestamp,
+    panning: Option<&CrtcPanning>,
+) -> Result<xcb::x::Timestamp, ApplyLayoutError> {
+    let empty = Rect {
+        bottom_left: Vec2d::new(0, 0),
+        size: Vec2d::new(0, 0),
+    };
+    let (area, tracking, border) = match panning {
+        Some(panning) => (&panning.area, &panning.tracking, panning.border),
+        None => (&empty, &empty, [0; 4]),
+    };
+    let (area_position, area_size, track_position, track_size): (Vec2d<u16>, _, Vec2d<u16>, _) = (
+        to_xcb_integers(area.bottom_left, "panning area position")?,
+        to_xcb_integers(area.size, "panning area size")?,
+        to_xcb_integers(tracking.bottom_left, "panning tracking position")?,
+        to_xcb_integers(tracking.size, "panning tracking size")?,
+    );
+    let request = xcb::randr::SetPanning {
+        crtc,
+        timestamp,
+        left: area_position.x,
+        top: area_position.y,
+        width: area_size.x,
+        height: area_size.y,
+        track_left: track_position.x,
+        track_top: track_position.y,
+        track_width: track_size.x,
+        track_height: track_size.y,
+        border_left: border[0],
+        border_top: border[1],
+        border_right: border[2],
+        border_bottom: border[3],
+    };
+    let cookie = backend.connection.send_request(&request);
+    let reply = backend
+        .connection
+        .wait_for_reply(cookie)
+        .with_context(|| format!("SetPanning({:?})", request))?;
+    match reply.status() {
+        xcb::randr::SetConfig::Success => Ok(reply.timestamp()),
+        status => Err(ApplyLayoutError::Recoverable(format!(
+            "SetPanning({:?}): {:?}",
+            request, status
+        ))),
+    }
+}
+
+/// Transform matrices are in 16.16 fixed point.
+const FIXED_ONE: i32 = 1 << 16;
+
+/// The crtc transform maps output pixels to screen pixels : a diagonal of `d` shows `d` times more of the screen.
+/// Our scale has the Wayland semantics (content `scale` times bigger), so the diagonal is `1/scale`, like `xrandr --scale`.
+fn crtc_transform_from_scale(scale: layout::Scale) -> xcb::randr::Transform {
+    let diagonal = (scale.to_f64().recip() * f64::from(FIXED_ONE)).round() as i32;
+    xcb::randr::Transform {
+        matrix11: diagonal,
+        matrix12: 0,
+        matrix13: 0,
+        matrix21: 0,
+        matrix22: diagonal,
+        matrix23: 0,
+        matrix31: 0,
+        matrix32: 0,
+        matrix33: FIXED_ONE,
+    }
+}
+
+/// Scale of a uniform scaling transform, [`None`] for other transforms.
+fn scale_from_crtc_transform(t: &xcb::randr::Transform) -> Option<layout::Scale> {
+    let uniform = t.matrix11 == t.matrix22
+        && t.matrix11 > 0
+        && [
+            t.matrix12, t.matrix13, t.matrix21, t.matrix23, t.matrix31, t.matrix32,
+        ] == [0; 6]
+        && t.matrix33 == FIXED_ONE;
+    match uniform {
+        true => layout::Scale::from_f64(f64::from(FIXED_ONE) / f64::from(t.matrix11)),
+        false => None,
+    }
+}
+
+/// Set the pending transform of a crtc, with bilinear filtering if scaled.
+fn set_crtc_transform(
+    backend: &XcbBackend,
+    crtc: xcb::randr::Crtc,
+    transform: &xcb::randr::Transform,
+) -> Result<(), anyhow::Error> {
+    let filter_name: &[u8] = match scale_from_crtc_transform(transform) {
+        Some(scale) if scale.is_one() => b"nearest",
+        _ => b"bilinear",
+    };
+    backend
+        .connection
+        .send_and_check_request(&xcb::randr::SetCrtcTransform {
+            crtc,
+            transform: *transform,
+            filter_name,
+            filter_params: &[],
+        })
+        .with_context(|| format!("SetCrtcTransform({:?})", transform))
+}
+
 /// xcb Rotation : apply reflect_x/y then a rotation. Stored as bitmask.
 impl From<xcb::randr::Rotation> for Transform {
     // xcb representation is not unique, thus a conversion is needed.
@@ -685,15 +1987,70 @@ impl From<&'_ xcb::randr::ModeInfo> for layout::Mode {
         let size = Vec2d::new(xcb_mode.width.into(), xcb_mode.height.into());
         let dots = u32::from(xcb_mode.htotal) * u32::from(xcb_mode.vtotal);
         assert_ne!(dots, 0, "invalid xcb::ModeInfo");
-        let frequency = div_round(xcb_mode.dot_clock, dots);
-        layout::Mode { size, frequency }
+        let millihertz =
+            (u64::from(xcb_mode.dot_clock) * 1000 + u64::from(dots / 2)) / u64::from(dots);
+        layout::Mode::from_refresh(size, millihertz as u32)
     }
 }
 
+fn mode_info_from_modeline(modeline: &layout::modeline::Modeline) -> xcb::randr::ModeInfo {
+    use layout::modeline::Polarity;
+    let [width, hsync_start, hsync_end, htotal] = modeline.horizontal;
+    let [height, vsync_start, vsync_end, vtotal] = modeline.vertical;
+    let mut mode_flags = xcb::randr::ModeFlag::empty();
+    match modeline.hsync {
+        Some(Polarity::Positive) => mode_flags |= xcb::randr::ModeFlag::HSYNC_POSITIVE,
+        Some(Polarity::Negative) => mode_flags |= xcb::randr::ModeFlag::HSYNC_NEGATIVE,
+        None => (),
+    }
+    match modeline.vsync {
+        Some(Polarity::Positive) => mode_flags |= xcb::randr::ModeFlag::VSYNC_POSITIVE,
+        Some(Polarity::Negative) => mode_flags |= xcb::randr::ModeFlag::VSYNC_NEGATIVE,
+        None => (),
+    }
+    xcb::randr::ModeInfo {
+        id: 0,
+        width,
+        height,
+        dot_clock: modeline.clock * 1000,
+        hsync_start,
+        hsync_end,
+        htotal,
+        hskew: 0,
+        vsync_start,
+        vsync_end,
+        vtotal,
+        name_len: modeline.name().len() as u16,
+        mode_flags,
+    }
+}
+
+/// Mode infos with equal timings, ignoring ids and names.
+fn same_timings(lhs: &xcb::randr::ModeInfo, rhs: &xcb::randr::ModeInfo) -> bool {
+    let timings = |m: &xcb::randr::ModeInfo| {
+        (
+            (m.width, m.hsync_start, m.hsync_end, m.htotal, m.hskew),
+            (m.height, m.vsync_start, m.vsync_end, m.vtotal),
+            (m.dot_clock, m.mode_flags.bits()),
+        )
+    };
+    timings(lhs) == timings(rhs)
+}
+
 fn div_round(lhs: u32, rhs: u32) -> u32 {
     (lhs + rhs / 2) / rhs
 }
 
+/// Success of a SetCrtcConfig for each of its `outputs`, with the `status` of the reply.
+/// X errors (no status), like `BadMatch` for a mode the outputs do not support, are failures too.
+fn set_crtc_outcomes(
+    outputs: &[xcb::randr::Output],
+    status: Option<xcb::randr::SetConfig>,
+) -> Vec<(xcb::randr::Output, bool)> {
+    let success = status == Some(xcb::randr::SetConfig::Success);
+    Vec::from_iter(outputs.iter().map(|output| (*output, success)))
+}
+
 fn filter_xid<T: Xid>(id: T) -> Option<T> {
     if id.is_none() {
         None
@@ -701,3 +2058,44 @@ fn filter_xid<T: Xid>(id: T) -> Option<T> {
         Some(id)
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_to_xcb_integers() {
+    use layout::{LayoutInfo, OutputEntry};
+    let entry = |name: &str, x| {
+        OutputEntry::enabled_for_test(name, Vec2d::new(7680, 4320), Vec2d::new(x, 0))
+    };
+    let layout =
+        LayoutInfo::from_iter((0..4).map(|i| entry(&i.to_string(), i * 7680)), None).layout;
+    let size: Vec2d<u16> = to_xcb_integers(layout.bounding_rect_size(), "screen size")
+        .ok()
+        .unwrap();
+    assert_eq!(size, Vec2d::new(30720, 4320));
+    let huge = LayoutInfo::from_iter((0..10).map(|i| entry(&i.to_string(), i * 7680)), None).layout;
+    let size = to_xcb_integers::<_, u16>(huge.bounding_rect_size(), "screen size");
+    assert!(
+        matches!(size, Err(ApplyLayoutError::Recoverable(msg)) if msg == "screen size 76800x4320 exceeds the 16 bit limits of X")
+    );
+    let position = to_xcb_integers::<_, i16>(Vec2d::new(38400, 0), "output 5 position");
+    assert!(matches!(position, Err(ApplyLayoutError::Recoverable(_))));
+    assert_eq!(
+        to_xcb_integers::<i32, i16>(Vec2d::new(-10, 32767), "").ok(),
+        Some(Vec2d::new(-10, 32767))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_set_crtc_outcomes() {
+    use xcb::randr::SetConfig;
+    let outputs = [1, 2].map(|id| unsafe { <xcb::randr::Output as xcb::XidNew>::new(id) });
+    let success = set_crtc_outcomes(&outputs, Some(SetConfig::Success));
+    assert_eq!(success, [(outputs[0], true), (outputs[1], true)]);
+    let failed = set_crtc_outcomes(&outputs, Some(SetConfig::Failed));
+    assert_eq!(failed, [(outputs[0], false), (outputs[1], false)]);
+    // Recorded in the gray list before the X error is propagated
+    let x_error = set_crtc_outcomes(&outputs, None);
+    assert_eq!(x_error, [(outputs[0], false), (outputs[1], false)]);
+    assert_eq!(set_crtc_outcomes(&[], None), []);
+}